@@ -15,13 +15,18 @@
 // along with Parity-Bridge.  If not, see <http://www.gnu.org/licenses/>.
 
 use contracts;
+use database::{RelayCost, RelayDirection};
 use error::{self, ResultExt};
+use futures::future::Join;
 use futures::{Async, Future, Poll};
-use helpers::{self, AsyncCall, AsyncTransaction};
+use helpers::{self, AsyncCall, AsyncGasEstimate};
 use main_contract::MainContract;
-use relay_stream::LogToFuture;
+use relay_log::{Chain, RelayLog};
+use relay_stream::{LogToFuture, RelayOutcome};
+use send_tx_with_receipt::SendTransactionWithReceipt;
 use side_contract::SideContract;
-use web3::types::{Address, Log, H256};
+use transaction::AsyncFees;
+use web3::types::{Address, Log, H256, U256};
 use web3::Transport;
 
 #[derive(Clone)]
@@ -33,7 +38,7 @@ pub struct LogToAcceptMessageFromMain<T> {
 impl<T: Transport> LogToFuture for LogToAcceptMessageFromMain<T> {
 	type Future = AcceptMessageFromMain<T>;
 
-	fn log_to_future(&self, log: &Log) -> Self::Future {
+	fn log_to_future(&self, log: &Log) -> error::Result<Self::Future> {
 		AcceptMessageFromMain::new(log, self.side.clone(), self.main.clone())
 	}
 }
@@ -47,7 +52,14 @@ enum State<T: Transport> {
 			contracts::side::functions::has_authority_accepted_message_from_main::Decoder,
 		>,
 	},
-	AwaitTxSent(AsyncTransaction<T>),
+	AwaitGasEstimate {
+		message: Vec<u8>,
+		future: Join<AsyncGasEstimate<T>, AsyncFees<T>>,
+	},
+	AwaitReceipt {
+		future: SendTransactionWithReceipt<T>,
+		gas_price: U256,
+	},
 }
 
 pub struct AcceptMessageFromMain<T: Transport> {
@@ -56,39 +68,43 @@ pub struct AcceptMessageFromMain<T: Transport> {
 	sender: Address,
 	recipient: Address,
 	side: SideContract<T>,
+	relay_log: RelayLog,
 }
 
 impl<T: Transport> AcceptMessageFromMain<T> {
-	pub fn new(raw_log: &Log, side: SideContract<T>, main: MainContract<T>) -> Self {
+	pub fn new(raw_log: &Log, side: SideContract<T>, main: MainContract<T>) -> error::Result<Self> {
 		let main_tx_hash = raw_log
 			.transaction_hash
-			.expect("`log` must be mined and contain `transaction_hash`. q.e.d.");
+			.ok_or_else(|| "`log` must be mined and contain `transaction_hash`")?;
 
 		let log = helpers::parse_log(contracts::main::events::relay_message::parse_log, raw_log)
-			.expect("`log` must be for a relay message. q.e.d.");
+			.chain_err(|| "`log` must be for a relay message")?;
 
 		let sender = log.sender;
 		let recipient = log.recipient;
 
-		info!(
-			"{:?} - step 1/4 - fetch message using message_id",
-			main_tx_hash
+		let relay_log = RelayLog::new(
+			RelayDirection::MainToSide,
+			Some(main_tx_hash),
+			Some(log.message_id),
 		);
+		relay_log.step("1/4", Chain::Main, "fetch message using message_id");
 		let future = main.relayed_message_by_id(log.message_id);
 		let state = State::AwaitMessage(future);
 
-		AcceptMessageFromMain {
+		Ok(AcceptMessageFromMain {
 			state,
 			main_tx_hash,
 			sender,
 			recipient,
 			side,
-		}
+			relay_log,
+		})
 	}
 }
 
 impl<T: Transport> Future for AcceptMessageFromMain<T> {
-	type Item = Option<H256>;
+	type Item = RelayOutcome;
 	type Error = error::Error;
 
 	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
@@ -99,9 +115,10 @@ impl<T: Transport> Future for AcceptMessageFromMain<T> {
 						.poll()
 						.chain_err(|| "AcceptMessageFromMain: failed to fetch the message"));
 
-					info!(
-						"{:?} - 2/4 - checking if the message is already signed",
-						self.main_tx_hash
+					self.relay_log.step(
+						"2/4",
+						Chain::Side,
+						"checking if the message is already signed",
 					);
 					State::AwaitAlreadyAccepted {
 						message: message.clone(),
@@ -121,26 +138,79 @@ impl<T: Transport> Future for AcceptMessageFromMain<T> {
 						"AcceptMessageFromMain: failed to check if already accepted"
 					}));
 					if has_already_accepted {
-						info!("{:?} - DONE - already accepted", self.main_tx_hash);
-						return Ok(Async::Ready(None));
+						self.relay_log.step("done", Chain::Side, "already accepted");
+						return Ok(Async::Ready(RelayOutcome::AlreadyRelayed));
 					}
 
-					info!("{:?} - 3/4 - accepting the message", self.main_tx_hash);
-					State::AwaitTxSent(self.side.accept_message_from_main(
-						self.main_tx_hash,
-						message.clone(),
-						self.sender,
-						self.recipient,
-					))
+					self.relay_log
+						.step("3/4", Chain::Side, "estimating gas and fees");
+					State::AwaitGasEstimate {
+						message: message.clone(),
+						future: self
+							.side
+							.estimate_accept_message_from_main_gas(
+								self.main_tx_hash,
+								message.clone(),
+								self.sender,
+								self.recipient,
+							)
+							.join(self.side.accept_message_from_main_fees()),
+					}
+				}
+				State::AwaitGasEstimate {
+					ref message,
+					ref mut future,
+				} => {
+					let main_tx_hash = self.main_tx_hash;
+					let context = || {
+						format!(
+							"AcceptMessageFromMain: estimating gas to accept message from {} failed",
+							main_tx_hash
+						)
+					};
+					let (gas, fees) =
+						try_ready!(helpers::chain_estimate_err(future.poll(), context));
+
+					self.relay_log.step(
+						"4/4",
+						Chain::Side,
+						format!("accepting the message with gas {} and fees {:?}", gas, fees),
+					);
+					State::AwaitReceipt {
+						gas_price: fees.expected_gas_price(),
+						future: self.side.accept_message_from_main(
+							self.main_tx_hash,
+							message.clone(),
+							self.sender,
+							self.recipient,
+							gas,
+							fees,
+						),
+					}
 				}
-				State::AwaitTxSent(ref mut future) => {
+				State::AwaitReceipt {
+					ref mut future,
+					gas_price,
+				} => {
 					let main_tx_hash = self.main_tx_hash;
-					let side_tx_hash = try_ready!(future.poll().chain_err(|| format!(
-						"AcceptMessageFromMain: checking whether {} was relayed failed",
+					let receipt = try_ready!(future.poll().chain_err(|| format!(
+						"AcceptMessageFromMain: accepting message from {} failed",
 						main_tx_hash
 					)));
-					info!("{:?} - DONE - accepted", self.main_tx_hash);
-					return Ok(Async::Ready(Some(side_tx_hash)));
+					self.relay_log.sent(
+						"done",
+						Chain::Side,
+						receipt.transaction_hash,
+						"accepted in",
+					);
+					return Ok(Async::Ready(RelayOutcome::Relayed(
+						RelayCost::from_receipt(
+							RelayDirection::MainToSide,
+							self.sender,
+							&receipt,
+							gas_price,
+						),
+					)));
 				}
 			};
 			self.state = next_state;
@@ -151,6 +221,7 @@ impl<T: Transport> Future for AcceptMessageFromMain<T> {
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use config::TransactionConfig;
 	use contracts;
 	use ethabi;
 	use rustc_hex::ToHex;
@@ -238,25 +309,70 @@ mod tests {
 					"to": format!("0x{:x}", side_contract_address),
 				}, "latest"]),
 				res => json!(format!("0x{}", ethabi::encode(&[ethabi::Token::Bool(false)]).to_hex::<String>()));
+			"eth_estimateGas" =>
+				req => json!([{
+					"data": format!("0x{}", accept_message_call_data.to_hex::<String>()),
+					"from": "0x0000000000000000000000000000000000000001",
+					"to": format!("0x{:x}", side_contract_address),
+				}]),
+				res => json!("0x64");
+			"eth_feeHistory" =>
+				req => json!(["0x5", "latest", [50.0]]),
+				res => json!({
+					"oldestBlock": "0x100b",
+					"baseFeePerGas": ["0x40", "0x48", "0x50", "0x50", "0x48", "0x50"],
+					"gasUsedRatio": [0.6, 0.6, 0.5, 0.4, 0.6],
+					"reward": [["0x8"], ["0x10"], ["0x10"], ["0x20"], ["0x10"]],
+				});
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x1010");
 			"eth_sendTransaction" =>
 				req => json!([{
 					"data": format!("0x{}", accept_message_call_data.to_hex::<String>()),
 					"from": "0x0000000000000000000000000000000000000001",
-					"gas": "0xfd",
-					"gasPrice": "0xa0",
+					"gas": "0x78",
+					"maxFeePerGas": "0xb0",
+					"maxPriorityFeePerGas": "0x10",
 					"to": format!("0x{:x}", side_contract_address),
+					"type": "0x2",
 				}]),
 				res => json!(format!("0x{}", tx_hash));
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x1011");
+			"eth_getTransactionReceipt" =>
+				req => json!([format!("0x{}", tx_hash)]),
+				res => json!({
+					"blockHash": "0xe0bdcf35b14a292d2998308d9b3fdea93a8c3d9c0b6c824c633fb9b15f9c3919",
+					"blockNumber": "0x1011",
+					"contractAddress": null,
+					"cumulativeGasUsed": "0x1c1999",
+					"gasUsed": "0x5208",
+					"logs": [],
+					"logsBloom": format!("0x{}", "0".repeat(512)),
+					"root": null,
+					"status": "0x1",
+					"transactionHash": format!("0x{}", tx_hash),
+					"transactionIndex": "0x4"
+				});
 		);
 
 		let main_contract = MainContract {
 			transport: main_transport.clone(),
 			contract_address: main_contract_address,
 			authority_address,
-			submit_collected_signatures_gas: 0.into(),
+			submit_collected_signatures_tx: TransactionConfig {
+				gas: 0.into(),
+				gas_price: 0.into(),
+				..Default::default()
+			},
+			gas_estimate_multiplier: 1.2,
+			max_relay_cost: None,
 			request_timeout: ::std::time::Duration::from_millis(0),
 			logs_poll_interval: ::std::time::Duration::from_millis(0),
 			required_log_confirmations: 0,
+			eip1559_support: Default::default(),
 		};
 
 		let side_contract = SideContract {
@@ -267,17 +383,34 @@ mod tests {
 			request_timeout: ::std::time::Duration::from_millis(0),
 			logs_poll_interval: ::std::time::Duration::from_millis(0),
 			required_log_confirmations: 0,
-			sign_main_to_side_gas: 0xfd.into(),
-			sign_main_to_side_gas_price: 0xa0.into(),
-			sign_side_to_main_gas: 0.into(),
-			sign_side_to_main_gas_price: 0.into(),
+			gas_estimate_multiplier: 1.2,
+			sign_main_to_side_tx: TransactionConfig {
+				gas: 0xfd.into(),
+				gas_price: 0xa0.into(),
+				..Default::default()
+			},
+			sign_side_to_main_tx: TransactionConfig {
+				gas: 0.into(),
+				gas_price: 0.into(),
+				..Default::default()
+			},
+			eip1559_support: Default::default(),
 		};
 
-		let future = AcceptMessageFromMain::new(&raw_log, side_contract, main_contract);
+		let future = AcceptMessageFromMain::new(&raw_log, side_contract, main_contract).unwrap();
 
 		let mut event_loop = Core::new().unwrap();
 		let result = event_loop.run(future).unwrap();
-		assert_eq!(result, Some(tx_hash.parse().unwrap()));
+		assert_eq!(
+			result,
+			RelayOutcome::Relayed(RelayCost {
+				direction: RelayDirection::MainToSide,
+				sender: log.sender,
+				transaction_hash: tx_hash.parse().unwrap(),
+				gas_used: 0x5208.into(),
+				gas_price: 0x60.into(),
+			})
+		);
 
 		assert_eq!(
 			side_transport.actual_requests(),
@@ -368,10 +501,17 @@ mod tests {
 			transport: main_transport.clone(),
 			contract_address: main_contract_address,
 			authority_address,
-			submit_collected_signatures_gas: 0.into(),
+			submit_collected_signatures_tx: TransactionConfig {
+				gas: 0.into(),
+				gas_price: 0.into(),
+				..Default::default()
+			},
+			gas_estimate_multiplier: 1.2,
+			max_relay_cost: None,
 			request_timeout: ::std::time::Duration::from_millis(0),
 			logs_poll_interval: ::std::time::Duration::from_millis(0),
 			required_log_confirmations: 0,
+			eip1559_support: Default::default(),
 		};
 
 		let side_contract = SideContract {
@@ -382,17 +522,25 @@ mod tests {
 			request_timeout: ::std::time::Duration::from_millis(0),
 			logs_poll_interval: ::std::time::Duration::from_millis(0),
 			required_log_confirmations: 0,
-			sign_main_to_side_gas: 0xfd.into(),
-			sign_main_to_side_gas_price: 0xa0.into(),
-			sign_side_to_main_gas: 0.into(),
-			sign_side_to_main_gas_price: 0.into(),
+			gas_estimate_multiplier: 1.2,
+			sign_main_to_side_tx: TransactionConfig {
+				gas: 0xfd.into(),
+				gas_price: 0xa0.into(),
+				..Default::default()
+			},
+			sign_side_to_main_tx: TransactionConfig {
+				gas: 0.into(),
+				gas_price: 0.into(),
+				..Default::default()
+			},
+			eip1559_support: Default::default(),
 		};
 
-		let future = AcceptMessageFromMain::new(&raw_log, side_contract, main_contract);
+		let future = AcceptMessageFromMain::new(&raw_log, side_contract, main_contract).unwrap();
 
 		let mut event_loop = Core::new().unwrap();
 		let result = event_loop.run(future).unwrap();
-		assert_eq!(result, None);
+		assert_eq!(result, RelayOutcome::AlreadyRelayed);
 
 		assert_eq!(
 			side_transport.actual_requests(),
@@ -0,0 +1,374 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity-Bridge.
+
+// Parity-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! simulation of several authorities that each run a `Bridge`
+//! against the same `SimulatedChains`.
+//!
+//! every authority has its own secret key that its bridge signs with
+//! through `eth_sign`, and the chains execute the `Main` and `Side`
+//! contracts, so signatures of non authorities and duplicate relays
+//! are rejected like on a real chain.
+//!
+//! a scripted workload sends messages and runs the bridges of
+//! the authorities that are online. authorities that are offline
+//! resume from their own state once they are online again.
+//! afterwards every message must have been relayed exactly once,
+//! which is checked against the state of the contracts.
+
+use database::State;
+use error;
+use message_to_main::MessageToMain;
+use relay_log::Chain;
+use simulated_chains::{bridge_contracts, SimulatedChains, SteppedBridge};
+use std::collections::HashSet;
+use web3::types::{Address, H256};
+
+/// a step of a scripted workload
+#[derive(Debug, Clone)]
+pub enum Step {
+	SendMessageToSide(Vec<u8>),
+	SendMessageToMain(Vec<u8>),
+	/// runs the bridges of the authorities with the given indexes for `STEPS_PER_RUN` steps.
+	/// the other authorities are offline
+	Run(Vec<usize>),
+}
+
+/// an authority with the state of its bridge
+#[derive(Debug)]
+struct Authority {
+	address: Address,
+	state: State,
+}
+
+/// steps of the simulated chains per `Step::Run`
+const STEPS_PER_RUN: usize = 30;
+
+/// authorities that run their bridges against the same simulated chains
+pub struct AuthoritySimulation {
+	pub chains: SimulatedChains,
+	required_signatures: u32,
+	authorities: Vec<Authority>,
+	/// hashes of the transactions on main that sent messages to side and their data
+	messages_to_side: Vec<(H256, Vec<u8>)>,
+	messages_to_main: Vec<MessageToMain>,
+}
+
+impl AuthoritySimulation {
+	pub fn new(authorities: usize, required_signatures: u32) -> Self {
+		let chains = SimulatedChains::new(authorities, required_signatures);
+		let authorities = chains
+			.authorities()
+			.iter()
+			.map(|&address| Authority {
+				address,
+				state: State::default(),
+			})
+			.collect();
+		Self {
+			chains,
+			required_signatures,
+			authorities,
+			messages_to_side: Vec::new(),
+			messages_to_main: Vec::new(),
+		}
+	}
+
+	pub fn authority(&self, index: usize) -> Address {
+		self.authorities[index].address
+	}
+
+	pub fn run_script(&mut self, script: &[Step]) {
+		for step in script {
+			match *step {
+				Step::SendMessageToSide(ref data) => {
+					let hash = self.chains.send_message_to_side(data.clone());
+					self.messages_to_side.push((hash, data.clone()));
+				}
+				Step::SendMessageToMain(ref data) => {
+					let message = self.chains.send_message_to_main(data.clone());
+					self.messages_to_main.push(message);
+				}
+				Step::Run(ref online) => {
+					if let Some(err) = self.run(online) {
+						panic!("bridge of authorities {:?} failed: {:?}", online, err);
+					}
+				}
+			}
+		}
+	}
+
+	/// runs the bridges of the `online` authorities concurrently.
+	/// every step each bridge is polled until it stalls.
+	/// the state of each is updated like its database would be
+	fn run(&mut self, online: &[usize]) -> Option<error::Error> {
+		let mut bridges: Vec<(usize, SteppedBridge<_>)> = online
+			.iter()
+			.map(|&index| {
+				let authority = &self.authorities[index];
+				let (main_contract, side_contract) = bridge_contracts(
+					authority.address,
+					self.required_signatures,
+					0,
+					self.chains.main(),
+					self.chains.side(),
+				);
+				let bridge =
+					SteppedBridge::new(authority.state.clone(), main_contract, side_contract);
+				(index, bridge)
+			})
+			.collect();
+
+		for step in 0..=STEPS_PER_RUN {
+			if step > 0 {
+				self.chains.advance();
+			}
+			for &mut (_, ref mut bridge) in &mut bridges {
+				bridge.poll_until_stalled();
+			}
+			if bridges
+				.iter()
+				.any(|&(_, ref bridge)| bridge.error.is_some())
+			{
+				break;
+			}
+		}
+
+		let mut error = None;
+		for (index, bridge) in bridges {
+			self.authorities[index].state = bridge.state;
+			error = error.or(bridge.error);
+		}
+		error
+	}
+
+	/// authorities whose acceptance of the message sent to side in `hash`
+	/// with `data` the side contract stores
+	fn accepted_on_side(&self, hash: H256, data: &[u8]) -> Vec<Address> {
+		self.authorities
+			.iter()
+			.map(|authority| authority.address)
+			.filter(|&authority| {
+				self.chains
+					.has_authority_accepted_on_side(hash, data.to_vec(), authority)
+			})
+			.collect()
+	}
+
+	/// whether the contracts accepted every message sent so far
+	pub fn is_relayed(&self) -> bool {
+		self.messages_to_side.iter().all(|&(hash, ref data)| {
+			self.accepted_on_side(hash, data).len() >= self.required_signatures as usize
+		}) && self
+			.messages_to_main
+			.iter()
+			.all(|message| self.chains.is_accepted_on_main(message))
+	}
+
+	/// asserts that every message sent so far was relayed exactly once:
+	/// no transaction of an authority was reverted by the contracts,
+	/// the side contract stores the acceptances and signatures of
+	/// the required number of distinct authorities, and only the authority
+	/// whose signature completed them relayed a side to main message to main
+	pub fn assert_relayed_exactly_once(&self) {
+		let required_signatures = self.required_signatures as usize;
+
+		for &chain in &[Chain::Main, Chain::Side] {
+			let reverted = self.chains.reverted_transactions(chain);
+			assert!(
+				reverted.is_empty(),
+				"transactions reverted on {:?}: {:?}",
+				chain,
+				reverted
+			);
+		}
+
+		for &(hash, ref data) in &self.messages_to_side {
+			let acceptances = self.chains.side_acceptances(hash);
+			assert_no_duplicates(&acceptances, "accepted message", &hash);
+			let accepted = self.accepted_on_side(hash, data);
+			assert!(
+				accepted.len() >= required_signatures,
+				"message sent in {:?} was accepted by {:?} only",
+				hash,
+				accepted
+			);
+			assert_eq!(
+				acceptances.into_iter().collect::<HashSet<_>>(),
+				accepted.into_iter().collect::<HashSet<_>>(),
+				"acceptances of message sent in {:?} mined and stored by the side contract",
+				hash
+			);
+		}
+
+		for message in &self.messages_to_main {
+			let signers = self.chains.side_signatures(message);
+			assert_no_duplicates(&signers, "signed message", &message.side_tx_hash);
+			for signer in &signers {
+				assert!(
+					self.chains.authorities().contains(signer),
+					"message sent in {:?} has a signature of {:?} which is no authority",
+					message.side_tx_hash,
+					signer
+				);
+			}
+			assert!(
+				signers.len() >= required_signatures,
+				"message sent in {:?} was signed by {:?} only",
+				message.side_tx_hash,
+				signers
+			);
+			let authority_responsible_for_relay = signers[required_signatures - 1];
+			assert_eq!(
+				self.chains.main_acceptances(message),
+				vec![authority_responsible_for_relay],
+				"message sent in {:?}",
+				message.side_tx_hash
+			);
+			assert!(
+				self.chains.is_accepted_on_main(message),
+				"message sent in {:?} was not accepted by the main contract",
+				message.side_tx_hash
+			);
+		}
+	}
+}
+
+fn assert_no_duplicates(authorities: &[Address], action: &str, hash: &H256) {
+	let unique: HashSet<_> = authorities.iter().collect();
+	assert_eq!(
+		unique.len(),
+		authorities.len(),
+		"an authority {} sent in {:?} more than once: {:?}",
+		action,
+		hash,
+		authorities
+	);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use contracts;
+
+	#[test]
+	fn test_all_authorities_online() {
+		let mut simulation = AuthoritySimulation::new(3, 2);
+		simulation.run_script(&[
+			Step::SendMessageToSide(vec![1]),
+			Step::SendMessageToMain(vec![2]),
+			Step::SendMessageToSide(vec![3]),
+			Step::SendMessageToMain(vec![4]),
+			Step::Run(vec![0, 1, 2]),
+		]);
+		assert!(simulation.is_relayed());
+		simulation.assert_relayed_exactly_once();
+	}
+
+	#[test]
+	fn test_authorities_going_offline() {
+		let mut simulation = AuthoritySimulation::new(3, 2);
+		simulation.run_script(&[
+			Step::SendMessageToSide(vec![1]),
+			Step::SendMessageToMain(vec![2]),
+			Step::Run(vec![0, 1, 2]),
+			// authority 2 goes offline
+			Step::SendMessageToSide(vec![3]),
+			Step::SendMessageToMain(vec![4]),
+			Step::Run(vec![0, 1]),
+		]);
+		assert!(simulation.is_relayed());
+		simulation.assert_relayed_exactly_once();
+
+		// without a quorum messages to main can't be relayed
+		simulation.run_script(&[
+			Step::SendMessageToMain(vec![5]),
+			Step::SendMessageToSide(vec![6]),
+			Step::Run(vec![0]),
+		]);
+		assert!(!simulation.is_relayed());
+
+		// authority 2 comes back and catches up with the messages it missed
+		simulation.run_script(&[Step::Run(vec![0, 2])]);
+		assert!(simulation.is_relayed());
+		simulation.assert_relayed_exactly_once();
+		// its signature completed the quorum so it relayed the message to main
+		assert_eq!(
+			simulation
+				.chains
+				.main_acceptances(&simulation.messages_to_main[2]),
+			vec![simulation.authority(2)]
+		);
+
+		simulation.run_script(&[Step::Run(vec![0, 1, 2])]);
+		simulation.assert_relayed_exactly_once();
+		for message in &simulation.messages_to_main {
+			assert_eq!(simulation.chains.side_signatures(message).len(), 3);
+		}
+	}
+
+	#[test]
+	fn test_authorities_taking_turns() {
+		let mut simulation = AuthoritySimulation::new(4, 3);
+		let mut script = Vec::new();
+		for round in 0..4u8 {
+			script.push(Step::SendMessageToSide(vec![round]));
+			script.push(Step::SendMessageToMain(vec![round]));
+			// a different authority is offline every round
+			let online = (0..4).filter(|&index| index != round as usize).collect();
+			script.push(Step::Run(online));
+		}
+		simulation.run_script(&script);
+		assert!(simulation.is_relayed());
+		simulation.assert_relayed_exactly_once();
+	}
+
+	#[test]
+	#[should_panic(expected = "transactions reverted on Side")]
+	fn test_signing_twice_is_rejected_by_side() {
+		let mut simulation = AuthoritySimulation::new(3, 2);
+		simulation.run_script(&[Step::SendMessageToMain(vec![1]), Step::Run(vec![0, 1, 2])]);
+		simulation.assert_relayed_exactly_once();
+
+		let message = simulation.messages_to_main[0].clone();
+		let signers = simulation.chains.side_signatures(&message);
+		let signature: Vec<u8> = simulation
+			.chains
+			.call(
+				Chain::Side,
+				contracts::side::functions::signature::call(message.keccak256(), 0u32),
+			)
+			.unwrap();
+		// the first signer submits its signature again
+		let hash = simulation.chains.send_transaction(
+			Chain::Side,
+			signers[0],
+			contracts::side::functions::submit_signed_message::encode_input(
+				signature,
+				message.to_bytes(),
+			),
+		);
+		assert!(
+			!simulation
+				.chains
+				.transaction(Chain::Side, hash)
+				.unwrap()
+				.success
+		);
+		assert_eq!(simulation.chains.side_signatures(&message), signers);
+
+		simulation.assert_relayed_exactly_once();
+	}
+}
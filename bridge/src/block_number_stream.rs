@@ -64,6 +64,12 @@ impl<T: Transport> BlockNumberStream<T> {
 			state: State::AwaitInterval,
 		}
 	}
+
+	/// used for requests and intervals started after this call
+	pub fn set_timings(&mut self, request_timeout: Duration, poll_interval: Duration) {
+		self.request_timeout = request_timeout;
+		self.poll_interval = self.timer.interval(poll_interval);
+	}
 }
 
 impl<T: Transport> Stream for BlockNumberStream<T> {
@@ -17,11 +17,13 @@ use futures::{Async, Poll, Stream};
 use web3::Transport;
 
 use accept_message_from_main;
+use config::Config;
+use config_watcher::ConfigWatcher;
 use database::State;
 use error::{self, ResultExt};
-use log_stream::LogStream;
+use log_stream::{LogPosition, LogStream};
 use main_contract::MainContract;
-use relay_stream::RelayStream;
+use relay_stream::{RelayOutcome, RelayStream};
 use side_contract::SideContract;
 use side_to_main_sign;
 use side_to_main_signatures;
@@ -33,7 +35,8 @@ use side_to_main_signatures;
 /// if polled polls all relay streams which causes them fetch
 /// all pending relays and relay them
 /// updates the database with results returned from relay streams.
-/// yields new state that should be persisted
+/// yields new state that should be persisted.
+/// applies configs yielded by the `ConfigWatcher` (if any) to relays that haven't started yet.
 pub struct Bridge<T: Transport> {
 	accept_message_from_main:
 		RelayStream<LogStream<T>, accept_message_from_main::LogToAcceptMessageFromMain<T>>,
@@ -41,6 +44,7 @@ pub struct Bridge<T: Transport> {
 	side_to_main_signatures:
 		RelayStream<LogStream<T>, side_to_main_signatures::LogToSideToMainSignatures<T>>,
 	state: State,
+	config_watcher: Option<ConfigWatcher>,
 }
 
 impl<T: Transport> Bridge<T> {
@@ -48,32 +52,48 @@ impl<T: Transport> Bridge<T> {
 		initial_state: State,
 		main_contract: MainContract<T>,
 		side_contract: SideContract<T>,
+		max_in_flight_relays: usize,
 	) -> Self {
+		let (after, relayed_until) = resume_from(
+			initial_state.last_main_to_side_sign_at_block,
+			initial_state.last_main_to_side_sign_at_log_index,
+		);
 		let accept_message_from_main = RelayStream::new(
-			main_contract.main_to_side_log_stream(initial_state.last_main_to_side_sign_at_block),
+			main_contract.main_to_side_log_stream(after),
 			accept_message_from_main::LogToAcceptMessageFromMain {
 				main: main_contract.clone(),
 				side: side_contract.clone(),
 			},
+			max_in_flight_relays,
+			relayed_until,
 		);
 
+		let (after, relayed_until) = resume_from(
+			initial_state.last_side_to_main_sign_at_block,
+			initial_state.last_side_to_main_sign_at_log_index,
+		);
 		let side_to_main_sign = RelayStream::new(
-			side_contract
-				.side_to_main_sign_log_stream(initial_state.last_side_to_main_sign_at_block),
+			side_contract.side_to_main_sign_log_stream(after),
 			side_to_main_sign::LogToSideToMainSign {
 				side: side_contract.clone(),
 			},
+			max_in_flight_relays,
+			relayed_until,
 		);
 
+		let (after, relayed_until) = resume_from(
+			initial_state.last_side_to_main_signatures_at_block,
+			initial_state.last_side_to_main_signatures_at_log_index,
+		);
 		let side_to_main_signatures = RelayStream::new(
-			side_contract.side_to_main_signatures_log_stream(
-				initial_state.last_side_to_main_signatures_at_block,
-				main_contract.authority_address,
-			),
+			side_contract
+				.side_to_main_signatures_log_stream(after, main_contract.authority_address),
 			side_to_main_signatures::LogToSideToMainSignatures {
 				main: main_contract.clone(),
 				side: side_contract.clone(),
 			},
+			max_in_flight_relays,
+			relayed_until,
 		);
 
 		Self {
@@ -81,6 +101,96 @@ impl<T: Transport> Bridge<T> {
 			side_to_main_sign,
 			side_to_main_signatures,
 			state: initial_state,
+			config_watcher: None,
+		}
+	}
+
+	/// apply the configs yielded by `config_watcher` while running
+	pub fn with_config_watcher(mut self, config_watcher: ConfigWatcher) -> Self {
+		self.config_watcher = Some(config_watcher);
+		self
+	}
+
+	/// applies the options of `config` that can change while the bridge is running.
+	/// relays that have already started keep using the old options
+	pub fn apply_config(&mut self, config: &Config) {
+		{
+			let mut log_to_future = self.accept_message_from_main.log_to_future_mut();
+			log_to_future.main.apply_config(config);
+			log_to_future.side.apply_config(config);
+		}
+		self.accept_message_from_main
+			.stream_of_logs_mut()
+			.set_timings(config.main.request_timeout, config.main.poll_interval);
+
+		self.side_to_main_sign
+			.log_to_future_mut()
+			.side
+			.apply_config(config);
+		self.side_to_main_sign
+			.stream_of_logs_mut()
+			.set_timings(config.side.request_timeout, config.side.poll_interval);
+
+		{
+			let mut log_to_future = self.side_to_main_signatures.log_to_future_mut();
+			log_to_future.main.apply_config(config);
+			log_to_future.side.apply_config(config);
+		}
+		self.side_to_main_signatures
+			.stream_of_logs_mut()
+			.set_timings(config.side.request_timeout, config.side.poll_interval);
+	}
+}
+
+/// from a checkpoint in `State` returns the block after which the log stream
+/// has to start and the position of the last log that has already been relayed
+pub fn resume_from(last_block: u64, last_log_index: Option<u64>) -> (u64, Option<LogPosition>) {
+	match last_log_index {
+		// the block was only checked up to `log_index` and has to be fetched again
+		Some(log_index) => (
+			last_block.saturating_sub(1),
+			Some(LogPosition {
+				block_number: last_block,
+				log_index,
+			}),
+		),
+		None => (last_block, None),
+	}
+}
+
+/// keeps track of relay costs and held messages in `state`
+pub fn record_outcome(state: &mut State, outcome: RelayOutcome) {
+	match outcome {
+		RelayOutcome::AlreadyRelayed => {}
+		RelayOutcome::Relayed(relay_cost) => {
+			info!(
+				"relay transaction {:?} cost {} wei",
+				relay_cost.transaction_hash,
+				relay_cost.cost()
+			);
+			state.add_relay_cost(&relay_cost);
+			for (direction, total) in state.relay_cost_by_direction() {
+				info!("total relay cost {:?}: {} wei", direction, total);
+			}
+		}
+		RelayOutcome::Held(held_message) => {
+			warn!(
+				"holding message {:?} from side transaction {:?} for manual release",
+				held_message.message_hash, held_message.side_tx_hash
+			);
+			// a restart before the checkpoint was persisted can hold a message twice
+			if !state
+				.held_messages
+				.iter()
+				.any(|x| x.message_hash == held_message.message_hash)
+			{
+				state.held_messages.push(held_message);
+			}
+		}
+		RelayOutcome::Quarantined(quarantined_log) => {
+			if !state.quarantined_logs.contains(&quarantined_log) {
+				state.quarantined_logs.push(quarantined_log);
+			}
 		}
 	}
 }
@@ -91,6 +201,18 @@ impl<T: Transport> Stream for Bridge<T> {
 
 	fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
 		loop {
+			let maybe_config = match self.config_watcher {
+				Some(ref mut config_watcher) => try_maybe_stream!(config_watcher
+					.poll()
+					.chain_err(|| "Bridge: polling config watcher failed")),
+				None => None,
+			};
+			if let Some(config) = maybe_config {
+				self.apply_config(&config);
+				// poll the watcher again so it gets notified of further changes
+				continue;
+			}
+
 			let maybe_main_to_side_sign = try_maybe_stream!(self
 				.accept_message_from_main
 				.poll()
@@ -106,28 +228,34 @@ impl<T: Transport> Stream for Bridge<T> {
 
 			let mut has_state_changed = false;
 
-			if let Some(main_to_side_sign) = maybe_main_to_side_sign {
+			if let Some((position, outcome)) = maybe_main_to_side_sign {
 				info!(
-					"last block checked for main to side sign is now {}",
-					main_to_side_sign
+					"last log relayed for main to side sign is now {:?}",
+					position
 				);
-				self.state.last_main_to_side_sign_at_block = main_to_side_sign;
+				self.state.last_main_to_side_sign_at_block = position.block_number;
+				self.state.last_main_to_side_sign_at_log_index = Some(position.log_index);
+				record_outcome(&mut self.state, outcome);
 				has_state_changed = true;
 			}
-			if let Some(side_to_main_sign) = maybe_side_to_main_sign {
+			if let Some((position, outcome)) = maybe_side_to_main_sign {
 				info!(
-					"last block checked for side to main sign is now {}",
-					side_to_main_sign
+					"last log relayed for side to main sign is now {:?}",
+					position
 				);
-				self.state.last_side_to_main_sign_at_block = side_to_main_sign;
+				self.state.last_side_to_main_sign_at_block = position.block_number;
+				self.state.last_side_to_main_sign_at_log_index = Some(position.log_index);
+				record_outcome(&mut self.state, outcome);
 				has_state_changed = true;
 			}
-			if let Some(side_to_main_signatures) = maybe_side_to_main_signatures {
+			if let Some((position, outcome)) = maybe_side_to_main_signatures {
 				info!(
-					"last block checked for side to main signatures is now {}",
-					side_to_main_signatures
+					"last log relayed for side to main signatures is now {:?}",
+					position
 				);
-				self.state.last_side_to_main_signatures_at_block = side_to_main_signatures;
+				self.state.last_side_to_main_signatures_at_block = position.block_number;
+				self.state.last_side_to_main_signatures_at_log_index = Some(position.log_index);
+				record_outcome(&mut self.state, outcome);
 				has_state_changed = true;
 			}
 
@@ -139,3 +267,157 @@ impl<T: Transport> Stream for Bridge<T> {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use config::TransactionConfig;
+	use contracts;
+	use ethabi;
+	use rustc_hex::ToHex;
+	use std::time::Duration;
+	use tempfile;
+	use tokio_core::reactor::Core;
+	use transport_recording::{RecordingTransport, ReplayOrder, ReplayTransport};
+	use web3::types::{Address, H256};
+
+	/// the state after the first relay of a `Bridge` started from `state`.
+	/// the side relays don't poll for logs before that
+	fn first_state<T: Transport>(state: State, main: T, side: T) -> State {
+		let authority_address: Address =
+			"0000000000000000000000000000000000000001".parse().unwrap();
+		let main_contract = MainContract {
+			transport: main,
+			contract_address: "0000000000000000000000000000000000000dd2".parse().unwrap(),
+			authority_address,
+			submit_collected_signatures_tx: TransactionConfig::default(),
+			gas_estimate_multiplier: 1.2,
+			max_relay_cost: None,
+			request_timeout: Duration::from_secs(1),
+			logs_poll_interval: Duration::from_secs(1),
+			required_log_confirmations: 0,
+			eip1559_support: Default::default(),
+		};
+		let side_contract = SideContract {
+			transport: side,
+			contract_address: "0000000000000000000000000000000000000dd1".parse().unwrap(),
+			authority_address,
+			required_signatures: 1,
+			request_timeout: Duration::from_secs(1),
+			logs_poll_interval: Duration::from_secs(300),
+			required_log_confirmations: 0,
+			gas_estimate_multiplier: 1.2,
+			sign_main_to_side_tx: TransactionConfig::default(),
+			sign_side_to_main_tx: TransactionConfig::default(),
+			eip1559_support: Default::default(),
+		};
+
+		let bridge = Bridge::new(state, main_contract, side_contract, 1);
+		let mut event_loop = Core::new().unwrap();
+		let (state, _) = event_loop
+			.run(bridge.into_future())
+			.map_err(|(err, _)| err)
+			.unwrap();
+		state.unwrap()
+	}
+
+	#[test]
+	fn test_bridge_replays_recorded_run() {
+		let topic = contracts::main::events::relay_message::filter().topic0;
+		let authority_address: Address =
+			"0000000000000000000000000000000000000001".parse().unwrap();
+		let message_id: H256 = "1db8f385535c0d178b8f40016048f3a3cffee8f94e68978ea4b277f57b638f0b"
+			.parse()
+			.unwrap();
+		let sender: Address = "aff3454fce5edbc8cca8697c15331677e6ebdddd".parse().unwrap();
+		let recipient: Address = "aff3454fce5edbc8cca8697c15331677e6ebcccc".parse().unwrap();
+		let log_tx_hash: H256 = "884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364"
+			.parse()
+			.unwrap();
+		let data: Vec<u8> = vec![0x12, 0x34];
+
+		let log_data = ethabi::encode(&[
+			ethabi::Token::FixedBytes(message_id.as_bytes().to_vec()),
+			ethabi::Token::Address(sender),
+			ethabi::Token::Address(recipient),
+		]);
+		let get_message_call_data =
+			contracts::main::functions::relayed_messages::encode_input(message_id);
+		let has_accepted_call_data =
+			contracts::side::functions::has_authority_accepted_message_from_main::encode_input(
+				log_tx_hash,
+				data.clone(),
+				sender,
+				recipient,
+				authority_address,
+			);
+
+		let main_transport = mock_transport!(
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x12");
+			"eth_getLogs" =>
+				req => json!([{
+					"address": "0x0000000000000000000000000000000000000dd2",
+					"fromBlock": "0x11",
+					"toBlock": "0x12",
+					"topics": [topic]
+				}]),
+				res => json!([{
+					"address": "0x0000000000000000000000000000000000000dd2",
+					"topics": [topic],
+					"data": format!("0x{}", log_data.to_hex::<String>()),
+					"blockNumber": "0x11",
+					"logIndex": "0x0",
+					"transactionHash": format!("0x{:x}", log_tx_hash),
+				}]);
+			"eth_call" =>
+				req => json!([{
+					"data": format!("0x{}", get_message_call_data.to_hex::<String>()),
+					"to": "0x0000000000000000000000000000000000000dd2",
+				}, "latest"]),
+				res => json!(format!("0x{}", ethabi::encode(&[ethabi::Token::Bytes(data.clone())]).to_hex::<String>()));
+		);
+		let side_transport = mock_transport!(
+			"eth_call" =>
+				req => json!([{
+					"data": format!("0x{}", has_accepted_call_data.to_hex::<String>()),
+					"to": "0x0000000000000000000000000000000000000dd1",
+				}, "latest"]),
+				res => json!(format!("0x{}", ethabi::encode(&[ethabi::Token::Bool(true)]).to_hex::<String>()));
+		);
+
+		let mut initial_state = State::default();
+		initial_state.last_main_to_side_sign_at_block = 0x10;
+
+		// record a run against the mocked nodes like `parity-bridge --record <dir>`
+		let dir = tempfile::tempdir().unwrap();
+		let main_fixture = dir.path().join("main.jsonl");
+		let side_fixture = dir.path().join("side.jsonl");
+		let recorded_state = first_state(
+			initial_state.clone(),
+			RecordingTransport::new(main_transport.clone(), &main_fixture).unwrap(),
+			RecordingTransport::new(side_transport.clone(), &side_fixture).unwrap(),
+		);
+		assert_eq!(
+			main_transport.actual_requests(),
+			main_transport.expected_requests()
+		);
+		assert_eq!(
+			side_transport.actual_requests(),
+			side_transport.expected_requests()
+		);
+		assert_eq!(recorded_state.last_main_to_side_sign_at_block, 0x11);
+		assert_eq!(recorded_state.last_main_to_side_sign_at_log_index, Some(0));
+
+		// then run the bridge again against nothing but the fixture files
+		let main_replay =
+			ReplayTransport::from_fixture(&main_fixture, ReplayOrder::Strict).unwrap();
+		let side_replay =
+			ReplayTransport::from_fixture(&side_fixture, ReplayOrder::Strict).unwrap();
+		let replayed_state = first_state(initial_state, main_replay.clone(), side_replay.clone());
+		assert_eq!(replayed_state, recorded_state);
+		assert_eq!(main_replay.unreplayed(), vec![]);
+		assert_eq!(side_replay.unreplayed(), vec![]);
+	}
+}
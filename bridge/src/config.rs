@@ -16,6 +16,7 @@
 
 //! concerning reading configuration from toml files
 
+use contracts;
 use error::{Error, ResultExt};
 use ethereum_types::U256;
 use rustc_hex::FromHex;
@@ -26,11 +27,22 @@ use std::time::Duration;
 use toml;
 use web3::types::{Address, Bytes};
 
+/// prefix of environment variables that override config values
+pub const ENV_PREFIX: &str = "PARITY_BRIDGE_";
+
 const DEFAULT_POLL_INTERVAL: u64 = 1;
 const DEFAULT_TIMEOUT: u64 = 5;
 
 const DEFAULT_CONFIRMATIONS: u32 = 12;
 
+const DEFAULT_GAS_ESTIMATE_MULTIPLIER: f64 = 1.2;
+
+/// matches the number of parallel requests of the http transports
+const DEFAULT_MAX_IN_FLIGHT_RELAYS: usize = 10;
+
+/// more confirmations than this are most likely a typo
+const MAX_REQUIRED_CONFIRMATIONS: u32 = 1000;
+
 /// Application config.
 #[derive(Debug, PartialEq, Clone)]
 pub struct Config {
@@ -42,14 +54,183 @@ pub struct Config {
 	pub estimated_gas_cost_of_withdraw: U256,
 	pub max_total_main_contract_balance: U256,
 	pub max_single_deposit_value: U256,
+	/// relays to `main` whose estimated cost in wei exceeds this are held
+	/// for manual release instead of being sent
+	pub max_main_relay_cost: Option<U256>,
+	/// `eth_estimateGas` results for relay transactions are multiplied by this.
+	/// the configured `gas` of a relay transaction is the upper cap.
+	pub gas_estimate_multiplier: f64,
+	/// maximum number of relays of each kind that are running at the same time.
+	/// relays for further logs are queued.
+	pub max_in_flight_relays: usize,
 }
 
 impl Config {
 	pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, Error> {
-		let mut file = fs::File::open(path).chain_err(|| "Cannot open config")?;
+		Self::load_with_overrides(path, &[])
+	}
+
+	/// loads the config file at `path` and replaces values in it by `overrides`.
+	/// later overrides take precedence over earlier ones.
+	pub fn load_with_overrides<P: AsRef<Path>>(
+		path: P,
+		overrides: &[ConfigOverride],
+	) -> Result<Config, Error> {
+		let mut file = fs::File::open(&path)
+			.chain_err(|| format!("Cannot open config {:?}", path.as_ref()))?;
 		let mut buffer = String::new();
-		file.read_to_string(&mut buffer).expect("TODO");
-		Self::load_from_str(&buffer)
+		file.read_to_string(&mut buffer)
+			.chain_err(|| format!("Cannot read config {:?}", path.as_ref()))?;
+		Self::load_from_str_with_overrides(&buffer, overrides)
+			.chain_err(|| format!("Cannot load config {:?}", path.as_ref()))
+	}
+
+	fn load_from_str_with_overrides(
+		s: &str,
+		overrides: &[ConfigOverride],
+	) -> Result<Config, Error> {
+		if overrides.is_empty() {
+			return Self::load_from_str(s);
+		}
+
+		let mut value: toml::Value = toml::from_str(s).chain_err(|| "Cannot parse config file")?;
+		for config_override in overrides {
+			config_override.apply(&mut value)?;
+		}
+
+		// round trip through a string so errors contain the key of the bad value
+		let merged = toml::to_string(&value).chain_err(|| "Cannot serialize config")?;
+		let config: load::Config = toml::from_str(&merged).map_err(|err| {
+			let message = err.to_string();
+			// the last override of a key is the one that ended up in the config
+			match overrides
+				.iter()
+				.rev()
+				.find(|x| message.contains(&format!("`{}`", x.key)))
+			{
+				Some(x) => Error::from(format!(
+					"Invalid value for `{}` from {}: {}",
+					x.key, x.source, message
+				)),
+				None => Error::with_chain(err, "Cannot parse config"),
+			}
+		})?;
+		Config::from_load_struct(config)
+	}
+
+	/// checks the config for values that are syntactically valid
+	/// but can't work. returns all problems found.
+	pub fn validate(&self) -> Vec<String> {
+		let mut problems = Vec::new();
+
+		if self.authorities.accounts.is_empty() {
+			problems.push("authorities.accounts must not be empty".into());
+		}
+		if self.authorities.required_signatures == 0 {
+			problems.push("authorities.required_signatures must be greater than 0".into());
+		}
+		if self.authorities.required_signatures as usize > self.authorities.accounts.len() {
+			problems.push(format!(
+				"authorities.required_signatures = {} is greater than the number of authorities.accounts ({})",
+				self.authorities.required_signatures,
+				self.authorities.accounts.len()
+			));
+		}
+		if !self.authorities.accounts.contains(&self.address) {
+			problems.push(format!(
+				"address {:?} is not one of authorities.accounts",
+				self.address
+			));
+		}
+
+		if self.estimated_gas_cost_of_withdraw.is_zero() {
+			problems.push("estimated_gas_cost_of_withdraw must be greater than 0".into());
+		}
+		for (name, tx) in self.txs.by_name() {
+			if let (Some(max_fee), Some(tip)) = (tx.max_fee_per_gas, tx.max_priority_fee_per_gas) {
+				if tip > max_fee {
+					problems.push(format!(
+						"transactions.{}.max_priority_fee_per_gas = {} is greater than max_fee_per_gas = {}",
+						name, tip, max_fee
+					));
+				}
+			}
+		}
+		for (name, tx) in self.txs.gas_capped_relays_by_name() {
+			if tx.gas.is_zero() {
+				problems.push(format!("transactions.{}.gas must be greater than 0", name));
+			}
+		}
+
+		for (name, node) in &[("main", &self.main), ("side", &self.side)] {
+			if node.required_confirmations > MAX_REQUIRED_CONFIRMATIONS {
+				problems.push(format!(
+					"{}.required_confirmations = {} is more than {}",
+					name, node.required_confirmations, MAX_REQUIRED_CONFIRMATIONS
+				));
+			}
+			if node.request_timeout == Duration::from_secs(0) {
+				problems.push(format!("{}.request_timeout must be greater than 0", name));
+			}
+		}
+
+		problems
+	}
+
+	/// names of the options that differ between `self` and `new` but
+	/// can't be changed while the bridge is running.
+	/// `poll_interval`, `request_timeout` and the fees of transactions can.
+	pub fn immutable_changes(&self, new: &Config) -> Vec<String> {
+		let mut changes = Vec::new();
+		{
+			let mut check = |name: String, changed: bool| {
+				if changed {
+					changes.push(name);
+				}
+			};
+			check("address".into(), self.address != new.address);
+			for (name, old, new) in vec![
+				("main", &self.main, &new.main),
+				("side", &self.side, &new.side),
+			] {
+				check(format!("{}.http", name), old.http != new.http);
+				check(format!("{}.contract", name), old.contract != new.contract);
+				check(
+					format!("{}.required_confirmations", name),
+					old.required_confirmations != new.required_confirmations,
+				);
+				check(format!("{}.chain_id", name), old.chain_id != new.chain_id);
+			}
+			check("authorities".into(), self.authorities != new.authorities);
+			for ((name, old), (_, new)) in self.txs.by_name().into_iter().zip(new.txs.by_name()) {
+				check(format!("transactions.{}.gas", name), old.gas != new.gas);
+			}
+			check(
+				"estimated_gas_cost_of_withdraw".into(),
+				self.estimated_gas_cost_of_withdraw != new.estimated_gas_cost_of_withdraw,
+			);
+			check(
+				"max_total_main_contract_balance".into(),
+				self.max_total_main_contract_balance != new.max_total_main_contract_balance,
+			);
+			check(
+				"max_single_deposit_value".into(),
+				self.max_single_deposit_value != new.max_single_deposit_value,
+			);
+			check(
+				"max_main_relay_cost".into(),
+				self.max_main_relay_cost != new.max_main_relay_cost,
+			);
+			check(
+				"gas_estimate_multiplier".into(),
+				self.gas_estimate_multiplier != new.gas_estimate_multiplier,
+			);
+			check(
+				"max_in_flight_relays".into(),
+				self.max_in_flight_relays != new.max_in_flight_relays,
+			);
+		}
+		changes
 	}
 
 	fn load_from_str(s: &str) -> Result<Config, Error> {
@@ -58,10 +239,27 @@ impl Config {
 	}
 
 	fn from_load_struct(config: load::Config) -> Result<Config, Error> {
+		let gas_estimate_multiplier = config
+			.gas_estimate_multiplier
+			.unwrap_or(DEFAULT_GAS_ESTIMATE_MULTIPLIER);
+		if gas_estimate_multiplier.is_nan() || gas_estimate_multiplier < 1.0 {
+			bail!(
+				"gas_estimate_multiplier must be at least 1.0 but is {}",
+				gas_estimate_multiplier
+			);
+		}
+
+		let max_in_flight_relays = config
+			.max_in_flight_relays
+			.unwrap_or(DEFAULT_MAX_IN_FLIGHT_RELAYS);
+		if max_in_flight_relays == 0 {
+			bail!("max_in_flight_relays must be greater than 0");
+		}
+
 		let result = Config {
 			address: config.address,
-			main: NodeConfig::from_load_struct(config.main)?,
-			side: NodeConfig::from_load_struct(config.side)?,
+			main: NodeConfig::from_load_struct(config.main, contracts::MAIN_BIN)?,
+			side: NodeConfig::from_load_struct(config.side, contracts::SIDE_BIN)?,
 			authorities: Authorities {
 				accounts: config.authorities.accounts,
 				required_signatures: config.authorities.required_signatures,
@@ -73,12 +271,123 @@ impl Config {
 			estimated_gas_cost_of_withdraw: config.estimated_gas_cost_of_withdraw,
 			max_total_main_contract_balance: config.max_total_main_contract_balance,
 			max_single_deposit_value: config.max_single_deposit_value,
+			max_main_relay_cost: config.max_main_relay_cost,
+			gas_estimate_multiplier,
+			max_in_flight_relays,
 		};
 
 		Ok(result)
 	}
 }
 
+/// a value that replaces the value at `key` in the config file
+#[derive(Debug, PartialEq, Clone)]
+pub struct ConfigOverride {
+	/// dot separated path. example: `main.http`
+	pub key: String,
+	pub value: String,
+	/// where the override comes from. used in error messages
+	pub source: String,
+}
+
+impl ConfigOverride {
+	/// overrides from all `PARITY_BRIDGE_*` variables in `vars`.
+	/// `__` separates the parts of the key:
+	/// `PARITY_BRIDGE_TRANSACTIONS__WITHDRAW_RELAY__GAS_PRICE` sets `transactions.withdraw_relay.gas_price`
+	pub fn from_env<I: IntoIterator<Item = (String, String)>>(vars: I) -> Vec<Self> {
+		let mut overrides = vars
+			.into_iter()
+			.filter(|(name, _)| name.starts_with(ENV_PREFIX))
+			.map(|(name, value)| ConfigOverride {
+				key: name[ENV_PREFIX.len()..]
+					.to_lowercase()
+					.split("__")
+					.collect::<Vec<_>>()
+					.join("."),
+				value,
+				source: format!("environment variable {}", name),
+			})
+			.collect::<Vec<_>>();
+		// environment variables come in no particular order
+		overrides.sort_by(|a, b| a.source.cmp(&b.source));
+		overrides
+	}
+
+	/// override from a `key=value` command line flag
+	pub fn from_flag(flag: &str) -> Result<Self, Error> {
+		let mut parts = flag.splitn(2, '=');
+		match (parts.next(), parts.next()) {
+			(Some(key), Some(value)) if !key.trim().is_empty() => Ok(ConfigOverride {
+				key: key.trim().into(),
+				value: value.into(),
+				source: format!("command line flag --set {}", flag),
+			}),
+			_ => bail!(
+				"command line flag --set {} must have the form key=value",
+				flag
+			),
+		}
+	}
+
+	/// replaces the value at `key` in `config`.
+	/// `value` is converted to the type of the value it replaces.
+	/// if there is none it's parsed as a toml value and used as a string if that fails.
+	fn apply(&self, config: &mut toml::Value) -> Result<(), Error> {
+		let mut parts = self.key.split('.').collect::<Vec<_>>();
+		let last = parts
+			.pop()
+			.expect("split always yields at least one part; qed");
+
+		let mut table = config;
+		for part in parts {
+			table = table
+				.as_table_mut()
+				.ok_or_else(|| format!("{}: `{}` is not a table", self.source, self.key))?
+				.entry(part.to_string())
+				.or_insert_with(|| toml::Value::Table(Default::default()));
+		}
+		let table = table
+			.as_table_mut()
+			.ok_or_else(|| format!("{}: `{}` is not a table", self.source, self.key))?;
+
+		let value = match table.get(last) {
+			Some(toml::Value::String(_)) => Some(toml::Value::String(self.value.clone())),
+			Some(toml::Value::Integer(_)) => {
+				self.value.trim().parse().ok().map(toml::Value::Integer)
+			}
+			Some(toml::Value::Float(_)) => self.value.trim().parse().ok().map(toml::Value::Float),
+			Some(toml::Value::Boolean(_)) => {
+				self.value.trim().parse().ok().map(toml::Value::Boolean)
+			}
+			Some(existing) => self.parse_toml().filter(|value| value.same_type(existing)),
+			None => Some(
+				self.parse_toml()
+					.unwrap_or_else(|| toml::Value::String(self.value.clone())),
+			),
+		};
+
+		match value {
+			Some(value) => {
+				table.insert(last.to_string(), value);
+				Ok(())
+			}
+			None => bail!(
+				"{}: expected {} for `{}` but got `{}`",
+				self.source,
+				table[last].type_str(),
+				self.key,
+				self.value
+			),
+		}
+	}
+
+	fn parse_toml(&self) -> Option<toml::Value> {
+		toml::from_str::<toml::Value>(&format!("value = {}", self.value))
+			.ok()
+			.and_then(|mut table| table.as_table_mut().and_then(|table| table.remove("value")))
+	}
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct NodeConfig {
 	pub contract: ContractConfig,
@@ -86,28 +395,39 @@ pub struct NodeConfig {
 	pub request_timeout: Duration,
 	pub poll_interval: Duration,
 	pub required_confirmations: u32,
+	/// if set the chain id reported by the node must match
+	pub chain_id: Option<u64>,
 }
 
 impl NodeConfig {
-	fn from_load_struct(node: load::NodeConfig) -> Result<NodeConfig, Error> {
+	/// `default_bin` is the hex encoded bytecode used if `contract.bin` is not set
+	fn from_load_struct(node: load::NodeConfig, default_bin: &str) -> Result<NodeConfig, Error> {
 		let result = Self {
 			contract: ContractConfig {
-				bin: {
-					let mut read = String::new();
-					let mut file = fs::File::open(&node.contract.bin).chain_err(|| {
-						format!(
-							"Cannot open compiled contract file at {}",
-							node.contract.bin.to_string_lossy()
-						)
-					})?;
-					file.read_to_string(&mut read)?;
-					Bytes(read.from_hex()?)
+				bin: match node.contract.bin {
+					Some(ref path) => {
+						let mut read = String::new();
+						let mut file = fs::File::open(path).chain_err(|| {
+							format!(
+								"Cannot open compiled contract file at {}",
+								path.to_string_lossy()
+							)
+						})?;
+						file.read_to_string(&mut read)?;
+						Bytes(read.from_hex()?)
+					}
+					None => Bytes(
+						default_bin
+							.from_hex()
+							.expect("compiled contracts are valid hex; qed"),
+					),
 				},
 			},
 			http: node.http,
 			request_timeout: Duration::from_secs(node.request_timeout.unwrap_or(DEFAULT_TIMEOUT)),
 			poll_interval: Duration::from_secs(node.poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL)),
 			required_confirmations: node.required_confirmations.unwrap_or(DEFAULT_CONFIRMATIONS),
+			chain_id: node.chain_id,
 		};
 
 		Ok(result)
@@ -124,6 +444,26 @@ pub struct Transactions {
 }
 
 impl Transactions {
+	/// all transaction configs together with their name in the config file
+	pub fn by_name(&self) -> Vec<(&'static str, &TransactionConfig)> {
+		vec![
+			("main_deploy", &self.main_deploy),
+			("side_deploy", &self.side_deploy),
+			("deposit_relay", &self.deposit_relay),
+			("withdraw_confirm", &self.withdraw_confirm),
+			("withdraw_relay", &self.withdraw_relay),
+		]
+	}
+
+	/// the configs whose `gas` caps the relay transactions `parity-bridge` sends.
+	/// it never deploys and sends `withdraw_relay` with `estimated_gas_cost_of_withdraw`
+	pub fn gas_capped_relays_by_name(&self) -> Vec<(&'static str, &TransactionConfig)> {
+		vec![
+			("deposit_relay", &self.deposit_relay),
+			("withdraw_confirm", &self.withdraw_confirm),
+		]
+	}
+
 	fn from_load_struct(cfg: load::Transactions) -> Self {
 		Transactions {
 			main_deploy: cfg
@@ -153,20 +493,41 @@ impl Transactions {
 #[derive(Debug, PartialEq, Default, Clone)]
 pub struct TransactionConfig {
 	pub gas: U256,
+	/// used on chains that don't support EIP-1559
 	pub gas_price: U256,
+	/// if `None` estimated from `eth_feeHistory` on chains that support EIP-1559
+	pub max_fee_per_gas: Option<U256>,
+	/// if `None` estimated from `eth_feeHistory` on chains that support EIP-1559
+	pub max_priority_fee_per_gas: Option<U256>,
 }
 
 impl TransactionConfig {
+	/// replaces the fees of `self` by the fees of `other`. keeps `gas`
+	pub fn set_fees_from(&mut self, other: &TransactionConfig) {
+		self.gas_price = other.gas_price;
+		self.max_fee_per_gas = other.max_fee_per_gas;
+		self.max_priority_fee_per_gas = other.max_priority_fee_per_gas;
+	}
+
+	/// the most a single transaction can cost in wei with fees that are not estimated
+	pub fn max_cost(&self) -> U256 {
+		self.gas
+			.saturating_mul(self.max_fee_per_gas.unwrap_or(self.gas_price))
+	}
+
 	fn from_load_struct(cfg: load::TransactionConfig) -> Self {
 		TransactionConfig {
 			gas: cfg.gas,
 			gas_price: cfg.gas_price,
+			max_fee_per_gas: cfg.max_fee_per_gas,
+			max_priority_fee_per_gas: cfg.max_priority_fee_per_gas,
 		}
 	}
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct ContractConfig {
+	/// contract creation code without constructor arguments
 	pub bin: Bytes,
 }
 
@@ -181,7 +542,7 @@ pub struct Authorities {
 /// in application.
 mod load {
 	use ethereum_types::U256;
-	use helpers::deserialize_u256;
+	use helpers::{deserialize_optional_u256, deserialize_u256};
 	use std::path::PathBuf;
 	use web3::types::Address;
 
@@ -199,16 +560,22 @@ mod load {
 		pub max_total_main_contract_balance: U256,
 		#[serde(deserialize_with = "deserialize_u256")]
 		pub max_single_deposit_value: U256,
+		#[serde(default, deserialize_with = "deserialize_optional_u256")]
+		pub max_main_relay_cost: Option<U256>,
+		pub gas_estimate_multiplier: Option<f64>,
+		pub max_in_flight_relays: Option<usize>,
 	}
 
 	#[derive(Deserialize)]
 	#[serde(deny_unknown_fields)]
 	pub struct NodeConfig {
+		#[serde(default)]
 		pub contract: ContractConfig,
 		pub http: String,
 		pub request_timeout: Option<u64>,
 		pub poll_interval: Option<u64>,
 		pub required_confirmations: Option<u32>,
+		pub chain_id: Option<u64>,
 	}
 
 	#[derive(Deserialize)]
@@ -228,12 +595,17 @@ mod load {
 		pub gas: U256,
 		#[serde(deserialize_with = "deserialize_u256")]
 		pub gas_price: U256,
+		#[serde(default, deserialize_with = "deserialize_optional_u256")]
+		pub max_fee_per_gas: Option<U256>,
+		#[serde(default, deserialize_with = "deserialize_optional_u256")]
+		pub max_priority_fee_per_gas: Option<U256>,
 	}
 
-	#[derive(Deserialize)]
+	#[derive(Deserialize, Default)]
 	#[serde(deny_unknown_fields)]
 	pub struct ContractConfig {
-		pub bin: PathBuf,
+		/// compiled into the binaries if not set
+		pub bin: Option<PathBuf>,
 	}
 
 	#[derive(Deserialize)]
@@ -246,7 +618,10 @@ mod load {
 
 #[cfg(test)]
 mod tests {
-	use super::{Authorities, Config, ContractConfig, NodeConfig, TransactionConfig, Transactions};
+	use super::{
+		Authorities, Config, ConfigOverride, ContractConfig, NodeConfig, TransactionConfig,
+		Transactions,
+	};
 	use ethereum_types::U256;
 	use rustc_hex::FromHex;
 	use std::time::Duration;
@@ -258,11 +633,15 @@ address = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
 estimated_gas_cost_of_withdraw = "100000"
 max_total_main_contract_balance = "10000000000000000000"
 max_single_deposit_value = "1000000000000000000"
+max_main_relay_cost = "2000000000000000"
+gas_estimate_multiplier = 1.5
+max_in_flight_relays = 4
 
 [main]
 http = "http://localhost:8545"
 poll_interval = 2
 required_confirmations = 100
+chain_id = 1
 
 [main.contract]
 bin = "../compiled_contracts/Main.bin"
@@ -283,6 +662,7 @@ required_signatures = 2
 
 [transactions]
 main_deploy = { gas = "20", gas_price = "0" }
+deposit_relay = { gas = "30", gas_price = "10", max_fee_per_gas = "100", max_priority_fee_per_gas = "2" }
 "#;
 
 		let mut expected = Config {
@@ -299,6 +679,7 @@ main_deploy = { gas = "20", gas_price = "0" }
 				poll_interval: Duration::from_secs(2),
 				request_timeout: Duration::from_secs(5),
 				required_confirmations: 100,
+				chain_id: Some(1),
 			},
 			side: NodeConfig {
 				contract: ContractConfig {
@@ -311,6 +692,7 @@ main_deploy = { gas = "20", gas_price = "0" }
 				poll_interval: Duration::from_secs(1),
 				request_timeout: Duration::from_secs(5),
 				required_confirmations: 12,
+				chain_id: None,
 			},
 			authorities: Authorities {
 				accounts: vec![
@@ -323,11 +705,22 @@ main_deploy = { gas = "20", gas_price = "0" }
 			estimated_gas_cost_of_withdraw: U256::from_dec_str("100000").unwrap(),
 			max_total_main_contract_balance: U256::from_dec_str("10000000000000000000").unwrap(),
 			max_single_deposit_value: U256::from_dec_str("1000000000000000000").unwrap(),
+			max_main_relay_cost: Some(U256::from_dec_str("2000000000000000").unwrap()),
+			gas_estimate_multiplier: 1.5,
+			max_in_flight_relays: 4,
 		};
 
 		expected.txs.main_deploy = TransactionConfig {
 			gas: 20.into(),
 			gas_price: 0.into(),
+			max_fee_per_gas: None,
+			max_priority_fee_per_gas: None,
+		};
+		expected.txs.deposit_relay = TransactionConfig {
+			gas: 30.into(),
+			gas_price: 10.into(),
+			max_fee_per_gas: Some(100.into()),
+			max_priority_fee_per_gas: Some(2.into()),
 		};
 
 		let config = Config::load_from_str(toml).unwrap();
@@ -376,6 +769,7 @@ required_signatures = 2
 				poll_interval: Duration::from_secs(1),
 				request_timeout: Duration::from_secs(5),
 				required_confirmations: 12,
+				chain_id: None,
 			},
 			side: NodeConfig {
 				http: "".into(),
@@ -388,6 +782,7 @@ required_signatures = 2
 				poll_interval: Duration::from_secs(1),
 				request_timeout: Duration::from_secs(5),
 				required_confirmations: 12,
+				chain_id: None,
 			},
 			authorities: Authorities {
 				accounts: vec![
@@ -400,9 +795,310 @@ required_signatures = 2
 			estimated_gas_cost_of_withdraw: U256::from_dec_str("200000000").unwrap(),
 			max_total_main_contract_balance: U256::from_dec_str("10000000000000000000").unwrap(),
 			max_single_deposit_value: U256::from_dec_str("1000000000000000000").unwrap(),
+			max_main_relay_cost: None,
+			gas_estimate_multiplier: 1.2,
+			max_in_flight_relays: 10,
 		};
 
 		let config = Config::load_from_str(toml).unwrap();
 		assert_eq!(expected, config);
 	}
+
+	#[test]
+	fn validate_reports_all_problems() {
+		let toml = r#"
+address = "0x0000000000000000000000000000000000000004"
+estimated_gas_cost_of_withdraw = "0"
+max_total_main_contract_balance = "10000000000000000000"
+max_single_deposit_value = "1000000000000000000"
+
+[main]
+http = ""
+required_confirmations = 5000
+
+[main.contract]
+bin = "../compiled_contracts/Main.bin"
+
+[side]
+http = ""
+
+[side.contract]
+bin = "../compiled_contracts/Side.bin"
+
+[authorities]
+accounts = [
+	"0x0000000000000000000000000000000000000001",
+	"0x0000000000000000000000000000000000000002",
+	"0x0000000000000000000000000000000000000003"
+]
+required_signatures = 4
+
+[transactions]
+main_deploy = { gas = "20", gas_price = "0" }
+side_deploy = { gas = "20", gas_price = "0" }
+deposit_relay = { gas = "30", gas_price = "0", max_fee_per_gas = "2", max_priority_fee_per_gas = "3" }
+withdraw_confirm = { gas = "0", gas_price = "0" }
+withdraw_relay = { gas = "30", gas_price = "0" }
+"#;
+		let config = Config::load_from_str(toml).unwrap();
+		assert_eq!(
+			config.validate(),
+			vec![
+				"authorities.required_signatures = 4 is greater than the number of authorities.accounts (3)",
+				"address 0x0000000000000000000000000000000000000004 is not one of authorities.accounts",
+				"estimated_gas_cost_of_withdraw must be greater than 0",
+				"transactions.deposit_relay.max_priority_fee_per_gas = 3 is greater than max_fee_per_gas = 2",
+				"transactions.withdraw_confirm.gas must be greater than 0",
+				"main.required_confirmations = 5000 is more than 1000",
+			]
+		);
+	}
+
+	#[test]
+	fn validate_accepts_minimal_setup_with_transactions() {
+		let toml = r#"
+address = "0x0000000000000000000000000000000000000001"
+estimated_gas_cost_of_withdraw = "200000"
+max_total_main_contract_balance = "10000000000000000000"
+max_single_deposit_value = "1000000000000000000"
+
+[main]
+http = ""
+
+[main.contract]
+bin = "../compiled_contracts/Main.bin"
+
+[side]
+http = ""
+
+[side.contract]
+bin = "../compiled_contracts/Side.bin"
+
+[authorities]
+accounts = [
+	"0x0000000000000000000000000000000000000001",
+]
+required_signatures = 1
+
+[transactions]
+main_deploy = { gas = "1500000", gas_price = "0" }
+side_deploy = { gas = "3000000", gas_price = "0" }
+deposit_relay = { gas = "150000", gas_price = "0" }
+withdraw_confirm = { gas = "300000", gas_price = "0" }
+withdraw_relay = { gas = "200000", gas_price = "0" }
+"#;
+		let config = Config::load_from_str(toml).unwrap();
+		assert!(config.validate().is_empty());
+	}
+
+	#[test]
+	fn validate_accepts_config_without_deploy_transactions() {
+		let toml = r#"
+address = "0x0000000000000000000000000000000000000001"
+estimated_gas_cost_of_withdraw = "200000"
+max_total_main_contract_balance = "10000000000000000000"
+max_single_deposit_value = "1000000000000000000"
+
+[main]
+http = ""
+
+[main.contract]
+bin = "../compiled_contracts/Main.bin"
+
+[side]
+http = ""
+
+[side.contract]
+bin = "../compiled_contracts/Side.bin"
+
+[authorities]
+accounts = [
+	"0x0000000000000000000000000000000000000001",
+]
+required_signatures = 1
+
+[transactions]
+deposit_relay = { gas = "150000", gas_price = "0" }
+withdraw_confirm = { gas = "300000", gas_price = "0" }
+"#;
+		let config = Config::load_from_str(toml).unwrap();
+		assert!(config.txs.main_deploy.gas.is_zero());
+		assert!(config.txs.withdraw_relay.gas.is_zero());
+		assert_eq!(config.validate(), Vec::<String>::new());
+	}
+
+	const OVERRIDES_TOML: &str = r#"
+address = "0x0000000000000000000000000000000000000001"
+estimated_gas_cost_of_withdraw = "200000"
+max_total_main_contract_balance = "10000000000000000000"
+max_single_deposit_value = "1000000000000000000"
+
+[main]
+http = "http://localhost:8545"
+poll_interval = 2
+
+[main.contract]
+bin = "../compiled_contracts/Main.bin"
+
+[side]
+http = "http://localhost:8546"
+
+[side.contract]
+bin = "../compiled_contracts/Side.bin"
+
+[authorities]
+accounts = [
+	"0x0000000000000000000000000000000000000001",
+]
+required_signatures = 1
+
+[transactions]
+withdraw_relay = { gas = "200000", gas_price = "0" }
+"#;
+
+	#[test]
+	fn overrides_from_env_and_flags() {
+		let mut overrides = ConfigOverride::from_env(vec![
+			("RUST_LOG".to_string(), "info".to_string()),
+			(
+				"PARITY_BRIDGE_MAIN__HTTP".to_string(),
+				"http://main-from-env:8545".to_string(),
+			),
+			(
+				"PARITY_BRIDGE_ADDRESS".to_string(),
+				"0x0000000000000000000000000000000000000002".to_string(),
+			),
+			(
+				"PARITY_BRIDGE_TRANSACTIONS__WITHDRAW_RELAY__GAS_PRICE".to_string(),
+				"1000".to_string(),
+			),
+			(
+				"PARITY_BRIDGE_MAX_MAIN_RELAY_COST".to_string(),
+				"5000".to_string(),
+			),
+		]);
+		assert_eq!(
+			overrides.iter().map(|x| x.key.as_str()).collect::<Vec<_>>(),
+			vec![
+				"address",
+				"main.http",
+				"max_main_relay_cost",
+				"transactions.withdraw_relay.gas_price",
+			]
+		);
+
+		overrides.push(ConfigOverride::from_flag("main.http=http://main-from-flag:8545").unwrap());
+		overrides.push(ConfigOverride::from_flag("side.poll_interval=3").unwrap());
+		overrides.push(
+			ConfigOverride::from_flag(
+				r#"authorities.accounts=["0x0000000000000000000000000000000000000002"]"#,
+			)
+			.unwrap(),
+		);
+
+		let config = Config::load_from_str_with_overrides(OVERRIDES_TOML, &overrides).unwrap();
+		assert_eq!(
+			config.address,
+			"0000000000000000000000000000000000000002".parse().unwrap()
+		);
+		assert_eq!(config.main.http, "http://main-from-flag:8545");
+		assert_eq!(config.main.poll_interval, Duration::from_secs(2));
+		assert_eq!(config.side.poll_interval, Duration::from_secs(3));
+		assert_eq!(config.txs.withdraw_relay.gas_price, 1000.into());
+		assert_eq!(config.max_main_relay_cost, Some(5000.into()));
+		assert_eq!(
+			config.authorities.accounts,
+			vec!["0000000000000000000000000000000000000002".parse().unwrap()]
+		);
+	}
+
+	#[test]
+	fn override_errors_name_the_source() {
+		assert!(ConfigOverride::from_flag("main.http").is_err());
+
+		let overrides = ConfigOverride::from_env(vec![(
+			"PARITY_BRIDGE_MAIN__POLL_INTERVAL".to_string(),
+			"soon".to_string(),
+		)]);
+		let err = Config::load_from_str_with_overrides(OVERRIDES_TOML, &overrides).unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"environment variable PARITY_BRIDGE_MAIN__POLL_INTERVAL: expected integer for `main.poll_interval` but got `soon`"
+		);
+
+		let overrides = vec![ConfigOverride::from_flag("address=0x1234").unwrap()];
+		let err = Config::load_from_str_with_overrides(OVERRIDES_TOML, &overrides).unwrap_err();
+		assert!(err.to_string().starts_with(
+			"Invalid value for `address` from command line flag --set address=0x1234"
+		));
+	}
+
+	#[test]
+	fn immutable_changes_ignore_tunables() {
+		let config = Config::load_from_str(OVERRIDES_TOML).unwrap();
+
+		let tunables = vec![
+			ConfigOverride::from_flag("main.poll_interval=10").unwrap(),
+			ConfigOverride::from_flag("side.request_timeout=30").unwrap(),
+			ConfigOverride::from_flag("transactions.withdraw_relay.gas_price=1000").unwrap(),
+			ConfigOverride::from_flag("transactions.withdraw_relay.max_fee_per_gas=2000").unwrap(),
+		];
+		let new = Config::load_from_str_with_overrides(OVERRIDES_TOML, &tunables).unwrap();
+		assert_ne!(config, new);
+		assert!(config.immutable_changes(&new).is_empty());
+
+		let immutables = vec![
+			ConfigOverride::from_flag("main.http=http://other:8545").unwrap(),
+			ConfigOverride::from_flag("transactions.withdraw_relay.gas=1").unwrap(),
+			ConfigOverride::from_flag("max_in_flight_relays=3").unwrap(),
+		];
+		let new = Config::load_from_str_with_overrides(OVERRIDES_TOML, &immutables).unwrap();
+		assert_eq!(
+			config.immutable_changes(&new),
+			vec![
+				"main.http",
+				"transactions.withdraw_relay.gas",
+				"max_in_flight_relays"
+			]
+		);
+	}
+
+	#[test]
+	fn contract_bytecode_defaults_to_compiled_contracts() {
+		let toml = r#"
+address = "0x0000000000000000000000000000000000000001"
+estimated_gas_cost_of_withdraw = "200000"
+max_total_main_contract_balance = "10000000000000000000"
+max_single_deposit_value = "1000000000000000000"
+
+[main]
+http = ""
+
+[side]
+http = ""
+
+[side.contract]
+
+[authorities]
+accounts = [
+	"0x0000000000000000000000000000000000000001",
+]
+required_signatures = 1
+"#;
+		let config = Config::load_from_str(toml).unwrap();
+		assert_eq!(
+			config.main.contract.bin,
+			include_str!("../../compiled_contracts/Main.bin")
+				.from_hex::<Vec<u8>>()
+				.unwrap()
+				.into()
+		);
+		assert_eq!(
+			config.side.contract.bin,
+			include_str!("../../compiled_contracts/Side.bin")
+				.from_hex::<Vec<u8>>()
+				.unwrap()
+				.into()
+		);
+	}
 }
@@ -0,0 +1,228 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity-Bridge.
+
+// Parity-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! reloading the config while the bridge is running
+
+use config::{Config, ConfigOverride};
+use error::{self, ResultExt};
+use futures::{Async, Poll, Stream};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio_timer::{Interval, Timer};
+
+/// `Stream` that checks the config file every `poll_interval` and
+/// yields the reloaded config whenever the file changed.
+/// reloads that change options which can't change while the bridge is
+/// running (see `Config::immutable_changes`) are rejected with a logged error.
+pub struct ConfigWatcher {
+	path: PathBuf,
+	overrides: Vec<ConfigOverride>,
+	/// the config that is currently in use
+	config: Config,
+	/// contents of the config file when it was last checked
+	contents: Option<String>,
+	interval: Interval,
+}
+
+impl ConfigWatcher {
+	/// `config` must have been loaded from `path` with `overrides`
+	pub fn new<P: Into<PathBuf>>(
+		path: P,
+		overrides: Vec<ConfigOverride>,
+		config: Config,
+		poll_interval: Duration,
+	) -> Self {
+		let path = path.into();
+		Self {
+			contents: fs::read_to_string(&path).ok(),
+			path,
+			overrides,
+			config,
+			interval: Timer::default().interval(poll_interval),
+		}
+	}
+
+	/// the reloaded config if it can be applied
+	fn reload(&mut self) -> Option<Config> {
+		let config = match Config::load_with_overrides(&self.path, &self.overrides) {
+			Ok(config) => config,
+			Err(err) => {
+				let message = err
+					.iter()
+					.map(|e| e.to_string())
+					.collect::<Vec<_>>()
+					.join(": ");
+				error!("not reloading config {:?}: {}", self.path, message);
+				return None;
+			}
+		};
+
+		let problems = config.validate();
+		if !problems.is_empty() {
+			error!(
+				"not reloading invalid config {:?}: {}",
+				self.path,
+				problems.join(", ")
+			);
+			return None;
+		}
+
+		let changes = self.config.immutable_changes(&config);
+		if !changes.is_empty() {
+			error!(
+				"not reloading config {:?}: {} can't be changed while the bridge is running. restart it instead",
+				self.path,
+				changes.join(", ")
+			);
+			return None;
+		}
+
+		if config == self.config {
+			return None;
+		}
+
+		info!("reloaded config {:?}", self.path);
+		self.config = config.clone();
+		Some(config)
+	}
+}
+
+impl Stream for ConfigWatcher {
+	type Item = Config;
+	type Error = error::Error;
+
+	fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+		loop {
+			try_stream!(self
+				.interval
+				.poll()
+				.chain_err(|| "ConfigWatcher: polling interval failed"));
+
+			let contents = fs::read_to_string(&self.path).ok();
+			if contents == self.contents {
+				continue;
+			}
+			self.contents = contents;
+
+			if let Some(config) = self.reload() {
+				return Ok(Async::Ready(Some(config)));
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Write;
+	use tempfile;
+	use tokio_core::reactor::Core;
+
+	const CONFIG: &str = r#"
+address = "0x0000000000000000000000000000000000000001"
+estimated_gas_cost_of_withdraw = "200000"
+max_total_main_contract_balance = "10000000000000000000"
+max_single_deposit_value = "1000000000000000000"
+
+[main]
+http = "http://localhost:8545"
+poll_interval = 2
+
+[main.contract]
+bin = "../compiled_contracts/Main.bin"
+
+[side]
+http = "http://localhost:8546"
+
+[side.contract]
+bin = "../compiled_contracts/Side.bin"
+
+[authorities]
+accounts = [
+	"0x0000000000000000000000000000000000000001",
+]
+required_signatures = 1
+
+[transactions]
+main_deploy = { gas = "1500000", gas_price = "0" }
+side_deploy = { gas = "3000000", gas_price = "0" }
+deposit_relay = { gas = "150000", gas_price = "0" }
+withdraw_confirm = { gas = "300000", gas_price = "0" }
+withdraw_relay = { gas = "200000", gas_price = "0" }
+"#;
+
+	fn write(file: &tempfile::NamedTempFile, contents: &str) {
+		let mut file = file.reopen().unwrap();
+		file.set_len(0).unwrap();
+		file.write_all(contents.as_bytes()).unwrap();
+	}
+
+	#[test]
+	fn test_config_watcher_rejects_immutable_changes() {
+		let file = tempfile::NamedTempFile::new().unwrap();
+		write(&file, CONFIG);
+		let config = Config::load(file.path()).unwrap();
+		let mut watcher =
+			ConfigWatcher::new(file.path(), vec![], config.clone(), Duration::from_secs(0));
+
+		assert_eq!(watcher.reload(), None);
+
+		write(
+			&file,
+			&CONFIG.replace("http://localhost:8545", "http://other:8545"),
+		);
+		assert_eq!(watcher.reload(), None);
+
+		write(&file, "not toml");
+		assert_eq!(watcher.reload(), None);
+
+		write(
+			&file,
+			&CONFIG.replace("poll_interval = 2", "poll_interval = 5"),
+		);
+		let reloaded = watcher.reload().unwrap();
+		assert_eq!(reloaded.main.poll_interval, Duration::from_secs(5));
+		assert_eq!(watcher.config, reloaded);
+	}
+
+	#[test]
+	fn test_config_watcher_yields_changed_config() {
+		let file = tempfile::NamedTempFile::new().unwrap();
+		write(&file, CONFIG);
+		let overrides = vec![ConfigOverride::from_flag("side.request_timeout=7").unwrap()];
+		let config = Config::load_with_overrides(file.path(), &overrides).unwrap();
+		let watcher = ConfigWatcher::new(file.path(), overrides, config, Duration::from_millis(1));
+
+		write(
+			&file,
+			&CONFIG.replace(
+				"withdraw_relay = { gas = \"200000\", gas_price = \"0\" }",
+				"withdraw_relay = { gas = \"200000\", gas_price = \"1000\" }",
+			),
+		);
+
+		let mut event_loop = Core::new().unwrap();
+		let (reloaded, _) = event_loop
+			.run(watcher.into_future())
+			.map_err(|(err, _)| err)
+			.unwrap();
+		let reloaded = reloaded.unwrap();
+		assert_eq!(reloaded.txs.withdraw_relay.gas_price, 1000.into());
+		// overrides still apply after reloads
+		assert_eq!(reloaded.side.request_timeout, Duration::from_secs(7));
+	}
+}
@@ -17,12 +17,14 @@
 //! concerning reading/writing `State` from/to toml file
 
 use error::{Error, ErrorKind, ResultExt};
+use helpers::{deserialize_u256, serialize_u256};
+use std::collections::BTreeMap;
 use std::io::{Read, Write};
 /// the state of a bridge node process and ways to persist it
 use std::path::{Path, PathBuf};
 use std::{fmt, fs, io, str};
 use toml;
-use web3::types::{Address, TransactionReceipt};
+use web3::types::{Address, Bytes, Log, TransactionReceipt, H256, U256};
 
 /// bridge process state
 #[derive(Debug, PartialEq, Deserialize, Serialize, Default, Clone)]
@@ -37,10 +39,209 @@ pub struct State {
 	pub side_deployed_at_block: u64,
 	/// Number of last block which has been checked for deposit relays.
 	pub last_main_to_side_sign_at_block: u64,
+	/// Index of the last log in `last_main_to_side_sign_at_block` that has been relayed.
+	/// `None` if the whole block has been checked.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub last_main_to_side_sign_at_log_index: Option<u64>,
 	/// Number of last block which has been checked for withdraw relays.
 	pub last_side_to_main_signatures_at_block: u64,
+	/// Index of the last log in `last_side_to_main_signatures_at_block` that has been relayed.
+	/// `None` if the whole block has been checked.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub last_side_to_main_signatures_at_log_index: Option<u64>,
 	/// Number of last block which has been checked for withdraw confirms.
 	pub last_side_to_main_sign_at_block: u64,
+	/// Index of the last log in `last_side_to_main_sign_at_block` that has been relayed.
+	/// `None` if the whole block has been checked.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub last_side_to_main_sign_at_log_index: Option<u64>,
+	/// Gas paid for the relay transactions sent by this authority.
+	/// One running total per relay direction and message sender.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub relay_cost_totals: Vec<RelayCostTotal>,
+	/// Side to main relays that were not executed because they were too expensive.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub held_messages: Vec<HeldMessage>,
+	/// Logs that could not be relayed because they could not be decoded.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub quarantined_logs: Vec<QuarantinedLog>,
+}
+
+/// the relays run by the bridge
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Relay {
+	MainToSideSign,
+	SideToMainSign,
+	SideToMainSignatures,
+}
+
+impl Relay {
+	pub fn name(&self) -> &'static str {
+		match *self {
+			Relay::MainToSideSign => "main_to_side_sign",
+			Relay::SideToMainSign => "side_to_main_sign",
+			Relay::SideToMainSignatures => "side_to_main_signatures",
+		}
+	}
+}
+
+impl str::FromStr for Relay {
+	type Err = Error;
+
+	fn from_str(name: &str) -> Result<Self, Self::Err> {
+		[
+			Relay::MainToSideSign,
+			Relay::SideToMainSign,
+			Relay::SideToMainSignatures,
+		]
+		.iter()
+		.cloned()
+		.find(|relay| relay.name() == name)
+		.ok_or_else(|| {
+			format!(
+				"unknown relay {}. expected one of main_to_side_sign, side_to_main_sign, side_to_main_signatures",
+				name
+			)
+			.into()
+		})
+	}
+}
+
+/// how far a rescan of the logs of `relay` in blocks `from_block` to `to_block` has progressed
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub struct RescanCheckpoint {
+	pub relay: Relay,
+	pub from_block: u64,
+	pub to_block: u64,
+	/// Number of the block of the last log that has been relayed by the rescan.
+	pub last_block: u64,
+	/// Index of the last log in `last_block` that has been relayed by the rescan.
+	pub last_log_index: u64,
+}
+
+/// direction in which a message is relayed
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum RelayDirection {
+	MainToSide,
+	SideToMain,
+}
+
+impl RelayDirection {
+	pub fn name(&self) -> &'static str {
+		match *self {
+			RelayDirection::MainToSide => "main_to_side",
+			RelayDirection::SideToMain => "side_to_main",
+		}
+	}
+}
+
+/// gas paid by this authority for a single mined relay transaction
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub struct RelayCost {
+	pub direction: RelayDirection,
+	/// sender of the relayed message
+	pub sender: Address,
+	pub transaction_hash: H256,
+	#[serde(
+		serialize_with = "serialize_u256",
+		deserialize_with = "deserialize_u256"
+	)]
+	pub gas_used: U256,
+	#[serde(
+		serialize_with = "serialize_u256",
+		deserialize_with = "deserialize_u256"
+	)]
+	pub gas_price: U256,
+}
+
+impl RelayCost {
+	/// `gas_price` is the price the transaction was sent with
+	/// since receipts don't contain it
+	pub fn from_receipt(
+		direction: RelayDirection,
+		sender: Address,
+		receipt: &TransactionReceipt,
+		gas_price: U256,
+	) -> Self {
+		Self {
+			direction,
+			sender,
+			transaction_hash: receipt.transaction_hash,
+			gas_used: receipt.gas_used.unwrap_or_else(U256::zero),
+			gas_price,
+		}
+	}
+
+	/// cost of the transaction in wei
+	pub fn cost(&self) -> U256 {
+		self.gas_used.saturating_mul(self.gas_price)
+	}
+}
+
+/// gas paid by this authority for all relay transactions of messages
+/// from `sender` in `direction`
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub struct RelayCostTotal {
+	pub direction: RelayDirection,
+	pub sender: Address,
+	/// number of relay transactions
+	pub relays: u64,
+	/// cost of the relay transactions in wei
+	#[serde(
+		serialize_with = "serialize_u256",
+		deserialize_with = "deserialize_u256"
+	)]
+	pub cost: U256,
+}
+
+/// a side to main relay that was skipped because its estimated cost
+/// exceeded `max_main_relay_cost`. it stays here until released manually.
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub struct HeldMessage {
+	pub side_tx_hash: H256,
+	pub message_hash: H256,
+	#[serde(
+		serialize_with = "serialize_u256",
+		deserialize_with = "deserialize_u256"
+	)]
+	pub estimated_cost: U256,
+}
+
+/// a log that could not be turned into a relay, for example because it
+/// could not be decoded. kept with its raw topics and data for inspection.
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub struct QuarantinedLog {
+	pub address: Address,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub transaction_hash: Option<H256>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub block_number: Option<u64>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub log_index: Option<u64>,
+	pub topics: Vec<H256>,
+	pub data: Bytes,
+	/// why the log could not be relayed
+	pub reason: String,
+}
+
+impl QuarantinedLog {
+	pub fn new(log: &Log, error: &Error) -> Self {
+		Self {
+			address: log.address,
+			transaction_hash: log.transaction_hash,
+			block_number: log.block_number.map(|number| number.as_u64()),
+			log_index: log.log_index.map(|index| index.low_u64()),
+			topics: log.topics.clone(),
+			data: log.data.clone(),
+			reason: error
+				.iter()
+				.map(|err| err.to_string())
+				.collect::<Vec<_>>()
+				.join(": "),
+		}
+	}
 }
 
 impl State {
@@ -49,6 +250,28 @@ impl State {
 	pub fn from_transaction_receipts(
 		main_contract_deployment_receipt: &TransactionReceipt,
 		side_contract_deployment_receipt: &TransactionReceipt,
+	) -> Self {
+		Self::from_deployments(
+			main_contract_deployment_receipt
+				.contract_address
+				.expect("main contract creation receipt must have an address; qed"),
+			main_contract_deployment_receipt,
+			side_contract_deployment_receipt
+				.contract_address
+				.expect("side contract creation receipt must have an address; qed"),
+			side_contract_deployment_receipt,
+		)
+	}
+
+	/// creates initial state for the bridge processes from the contract addresses
+	/// and the receipts of the transactions that deployed them.
+	/// for contracts created by another contract (like the `CREATE2` factory)
+	/// whose receipts don't have a contract address
+	pub fn from_deployments(
+		main_contract_address: Address,
+		main_contract_deployment_receipt: &TransactionReceipt,
+		side_contract_address: Address,
+		side_contract_deployment_receipt: &TransactionReceipt,
 	) -> Self {
 		let main_block_number = main_contract_deployment_receipt
 			.block_number
@@ -61,19 +284,67 @@ impl State {
 			.as_u64();
 
 		Self {
-			main_contract_address: main_contract_deployment_receipt
-				.contract_address
-				.expect("main contract creation receipt must have an address; qed"),
-			side_contract_address: side_contract_deployment_receipt
-				.contract_address
-				.expect("side contract creation receipt must have an address; qed"),
+			main_contract_address,
+			side_contract_address,
 			main_deployed_at_block: main_block_number,
 			side_deployed_at_block: side_block_number,
 			last_main_to_side_sign_at_block: main_block_number,
+			last_main_to_side_sign_at_log_index: None,
 			last_side_to_main_sign_at_block: side_block_number,
+			last_side_to_main_sign_at_log_index: None,
 			last_side_to_main_signatures_at_block: side_block_number,
+			last_side_to_main_signatures_at_log_index: None,
+			relay_cost_totals: Vec::new(),
+			held_messages: Vec::new(),
+			quarantined_logs: Vec::new(),
 		}
 	}
+
+	/// adds the cost of a mined relay transaction to the running totals
+	pub fn add_relay_cost(&mut self, relay_cost: &RelayCost) {
+		let position = self.relay_cost_totals.iter().position(|total| {
+			total.direction == relay_cost.direction && total.sender == relay_cost.sender
+		});
+		let index = match position {
+			Some(index) => index,
+			None => {
+				self.relay_cost_totals.push(RelayCostTotal {
+					direction: relay_cost.direction,
+					sender: relay_cost.sender,
+					relays: 0,
+					cost: U256::zero(),
+				});
+				self.relay_cost_totals.len() - 1
+			}
+		};
+		let total = &mut self.relay_cost_totals[index];
+		total.relays += 1;
+		total.cost = total.cost.saturating_add(relay_cost.cost());
+	}
+
+	/// total cost of relay transactions in wei per relay direction
+	pub fn relay_cost_by_direction(&self) -> BTreeMap<RelayDirection, U256> {
+		let mut totals = BTreeMap::new();
+		for relay_cost_total in &self.relay_cost_totals {
+			let total = totals
+				.entry(relay_cost_total.direction)
+				.or_insert_with(U256::zero);
+			*total = total.saturating_add(relay_cost_total.cost);
+		}
+		totals
+	}
+
+	/// total cost of relay transactions in wei per message sender
+	pub fn relay_cost_by_sender(&self) -> BTreeMap<Address, U256> {
+		let mut totals = BTreeMap::new();
+		for relay_cost_total in &self.relay_cost_totals {
+			let total = totals
+				.entry(relay_cost_total.sender)
+				.or_insert_with(U256::zero);
+			*total = total.saturating_add(relay_cost_total.cost);
+		}
+		totals
+	}
 }
 
 impl State {
@@ -145,3 +416,176 @@ impl Database for TomlFileDatabase {
 		Ok(())
 	}
 }
+
+/// `RescanCheckpoint` of an unfinished rescan stored in a TOML file of its own
+/// so a rescan never writes the database of a running bridge
+pub struct RescanCheckpointFile {
+	filepath: PathBuf,
+}
+
+impl RescanCheckpointFile {
+	/// the file next to the database at `database_filepath`
+	pub fn next_to_database<P: AsRef<Path>>(database_filepath: P) -> Self {
+		let mut filepath = database_filepath.as_ref().as_os_str().to_owned();
+		filepath.push(".rescan");
+		Self {
+			filepath: filepath.into(),
+		}
+	}
+
+	pub fn filepath(&self) -> &Path {
+		&self.filepath
+	}
+
+	/// `None` if there is no unfinished rescan
+	pub fn read(&self) -> Result<Option<RescanCheckpoint>, Error> {
+		let mut file = match fs::File::open(&self.filepath) {
+			Ok(file) => file,
+			Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+			Err(err) => return Err(err).chain_err(|| "Cannot open rescan checkpoint"),
+		};
+
+		let mut buffer = String::new();
+		file.read_to_string(&mut buffer)?;
+		let checkpoint = toml::from_str(&buffer).chain_err(|| "Cannot parse rescan checkpoint")?;
+		Ok(Some(checkpoint))
+	}
+
+	pub fn write(&self, checkpoint: &RescanCheckpoint) -> Result<(), Error> {
+		let serialized = toml::to_string(checkpoint).expect("serialization can't fail; qed");
+		fs::write(&self.filepath, serialized)?;
+		Ok(())
+	}
+
+	/// called once the rescan is complete
+	pub fn remove(&self) -> Result<(), Error> {
+		match fs::remove_file(&self.filepath) {
+			Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+			result => Ok(result?),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tempfile;
+
+	#[test]
+	fn test_state_with_relay_costs_roundtrips_through_toml() {
+		let sender: Address = "aff3454fce5edbc8cca8697c15331677e6ebcccc".parse().unwrap();
+		let other_sender: Address = "aff3454fce5edbc8cca8697c15331677e6ebdddd".parse().unwrap();
+
+		let mut state = State::default();
+		state.last_side_to_main_signatures_at_block = 10;
+		state.last_side_to_main_signatures_at_log_index = Some(3);
+		for relay_cost in &[
+			RelayCost {
+				direction: RelayDirection::MainToSide,
+				sender,
+				transaction_hash: H256::from_low_u64_be(1),
+				gas_used: 21000.into(),
+				gas_price: 2.into(),
+			},
+			RelayCost {
+				direction: RelayDirection::SideToMain,
+				sender,
+				transaction_hash: H256::from_low_u64_be(2),
+				gas_used: 50000.into(),
+				gas_price: 3.into(),
+			},
+			RelayCost {
+				direction: RelayDirection::SideToMain,
+				sender: other_sender,
+				transaction_hash: H256::from_low_u64_be(3),
+				gas_used: 10000.into(),
+				gas_price: 1.into(),
+			},
+			RelayCost {
+				direction: RelayDirection::SideToMain,
+				sender,
+				transaction_hash: H256::from_low_u64_be(8),
+				gas_used: 5000.into(),
+				gas_price: 2.into(),
+			},
+		] {
+			state.add_relay_cost(relay_cost);
+		}
+		assert_eq!(state.relay_cost_totals.len(), 3);
+		assert_eq!(state.relay_cost_totals[1].relays, 2);
+		state.held_messages = vec![HeldMessage {
+			side_tx_hash: H256::from_low_u64_be(4),
+			message_hash: H256::from_low_u64_be(5),
+			estimated_cost: U256::from_dec_str("100000000000000000000").unwrap(),
+		}];
+		state.quarantined_logs = vec![QuarantinedLog {
+			address: other_sender,
+			transaction_hash: Some(H256::from_low_u64_be(6)),
+			block_number: Some(10),
+			log_index: Some(4),
+			topics: vec![H256::from_low_u64_be(7)],
+			data: vec![1, 2, 3].into(),
+			reason: "`log` must contain valid message".into(),
+		}];
+		let deserialized: State = toml::from_str(&state.to_string()).unwrap();
+		assert_eq!(deserialized, state);
+
+		let by_direction = state.relay_cost_by_direction();
+		assert_eq!(by_direction[&RelayDirection::MainToSide], 42000.into());
+		assert_eq!(by_direction[&RelayDirection::SideToMain], 170000.into());
+
+		let by_sender = state.relay_cost_by_sender();
+		assert_eq!(by_sender[&sender], 202000.into());
+		assert_eq!(by_sender[&other_sender], 10000.into());
+	}
+
+	#[test]
+	fn test_rescan_checkpoint_file() {
+		let dir = tempfile::tempdir().unwrap();
+		let file = RescanCheckpointFile::next_to_database(dir.path().join("bridge.db"));
+		assert_eq!(
+			file.filepath(),
+			dir.path().join("bridge.db.rescan").as_path()
+		);
+		assert_eq!(file.read().unwrap(), None);
+
+		let checkpoint = RescanCheckpoint {
+			relay: Relay::SideToMainSignatures,
+			from_block: 5,
+			to_block: 20,
+			last_block: 8,
+			last_log_index: 1,
+		};
+		file.write(&checkpoint).unwrap();
+		assert_eq!(file.read().unwrap(), Some(checkpoint));
+
+		file.remove().unwrap();
+		assert_eq!(file.read().unwrap(), None);
+		file.remove().unwrap();
+	}
+
+	#[test]
+	fn test_relay_from_name() {
+		for relay in &[
+			Relay::MainToSideSign,
+			Relay::SideToMainSign,
+			Relay::SideToMainSignatures,
+		] {
+			assert_eq!(relay.name().parse::<Relay>().unwrap(), *relay);
+		}
+		assert!("main_to_side".parse::<Relay>().is_err());
+	}
+
+	#[test]
+	fn test_state_without_relay_costs_omits_them() {
+		let serialized = State::default().to_string();
+		assert!(!serialized.contains("relay_cost_totals"));
+		assert!(!serialized.contains("held_messages"));
+		assert!(!serialized.contains("quarantined_logs"));
+		assert!(!serialized.contains("log_index"));
+		assert_eq!(
+			toml::from_str::<State>(&serialized).unwrap(),
+			State::default()
+		);
+	}
+}
@@ -16,107 +16,469 @@
 
 //! concerning deployment of the bridge contracts
 
-use config::Config;
+use config::{Config, TransactionConfig};
 use contracts;
 use error::{self, ResultExt};
-use futures::{Future, Poll};
-use rustc_hex::ToHex;
-use send_tx_with_receipt::{SendTransactionWithReceipt, SendTransactionWithReceiptOptions};
+use futures::future::{FromErr, Join3, Join5};
+use futures::{Async, Future, Poll};
+use helpers::{self, keccak256};
+use rustc_hex::{FromHex, ToHex};
+use send_tx_with_receipt::{
+	PendingTransaction, SendTransactionWithReceipt, SendTransactionWithReceiptOptions,
+};
+use serde_json;
+use std::fmt;
 use std::fs;
 use std::fs::File;
-use std::io::Write;
-use std::path::Path;
-use web3::types::{TransactionReceipt, TransactionRequest};
-use web3::Transport;
+use std::io::{self, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio_timer::{Timeout, Timer};
+use transaction::{AsyncFees, Eip1559Support, Fees, Transaction};
+use web3::api::Namespace;
+use web3::helpers::{self as web3_helpers, CallFuture};
+use web3::types::{Address, BlockNumber, Bytes, TransactionReceipt, H256, U256, U64};
+use web3::{self, Transport};
 
-pub enum DeployState<T: Transport + Clone> {
-	NotDeployed,
-	Deploying {
-		data: Vec<u8>,
-		future: SendTransactionWithReceipt<T>,
-	},
-	Deployed {
-		contract: DeployedContract,
-	},
+/// progress of the deployment of a contract.
+/// persisted after every step so that a rerun of an interrupted deployment
+/// waits for the pending transaction or skips the deployed contract
+/// instead of deploying it again
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct DeployProgress {
+	/// hash of the contract creation code the deployment was started with
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub creation_code_hash: Option<H256>,
+	/// salt if the contract is deployed through the CREATE3 proxy
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub create2_salt: Option<H256>,
+	/// hash of the sent deployment transaction
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub transaction_hash: Option<H256>,
+	/// receipt of the deployment transaction once it has enough confirmations
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub receipt: Option<TransactionReceipt>,
 }
 
-pub struct DeployMain<T: Transport + Clone> {
-	config: Config,
-	main_transport: T,
-	state: DeployState<T>,
+/// `DeployProgress` stored in a JSON file
+pub struct DeployProgressFile {
+	path: PathBuf,
+	progress: DeployProgress,
+}
+
+impl DeployProgressFile {
+	/// starts without progress if there's no file at `path`
+	pub fn open<P: Into<PathBuf>>(path: P) -> error::Result<Self> {
+		let path = path.into();
+		let progress = match fs::read_to_string(&path) {
+			Ok(contents) => serde_json::from_str(&contents)
+				.chain_err(|| format!("Cannot parse deployment progress {:?}", path))?,
+			Err(ref err) if err.kind() == io::ErrorKind::NotFound => DeployProgress::default(),
+			Err(err) => {
+				return Err(err).chain_err(|| format!("Cannot read deployment progress {:?}", path))
+			}
+		};
+		Ok(Self { path, progress })
+	}
+
+	pub fn progress(&self) -> &DeployProgress {
+		&self.progress
+	}
+
+	/// fails if the progress was recorded for a deployment with different
+	/// contract creation code. for example because the authorities changed
+	fn check_creation_code(&self, data: &[u8]) -> error::Result<()> {
+		match self.progress.creation_code_hash {
+			Some(hash) if hash != keccak256(data) => bail!(
+				"deployment progress {:?} was recorded for different contract creation code. remove it to deploy a new contract",
+				self.path
+			),
+			_ => Ok(()),
+		}
+	}
+
+	/// fails if the progress was recorded for a deployment with a different
+	/// CREATE3 salt or one that didn't use the proxy
+	fn check_create2_salt(&self, create2_salt: Option<H256>) -> error::Result<()> {
+		if self.progress.transaction_hash.is_some() && self.progress.create2_salt != create2_salt {
+			bail!(
+				"deployment progress {:?} was recorded with CREATE2 salt {:?} instead of {:?}. remove it to deploy a new contract",
+				self.path,
+				self.progress.create2_salt,
+				create2_salt
+			);
+		}
+		Ok(())
+	}
+
+	fn record_transaction_hash(
+		&mut self,
+		data: &[u8],
+		create2_salt: Option<H256>,
+		transaction_hash: H256,
+	) -> error::Result<()> {
+		if self.progress.transaction_hash == Some(transaction_hash) {
+			return Ok(());
+		}
+		let mut progress = self.progress.clone();
+		progress.creation_code_hash = Some(keccak256(data));
+		progress.create2_salt = create2_salt;
+		progress.transaction_hash = Some(transaction_hash);
+		self.write(progress)
+	}
+
+	fn record_receipt(&mut self, receipt: &TransactionReceipt) -> error::Result<()> {
+		let mut progress = self.progress.clone();
+		progress.transaction_hash = Some(receipt.transaction_hash);
+		progress.receipt = Some(receipt.clone());
+		self.write(progress)
+	}
+
+	/// writes to a temporary file first so an interruption
+	/// can't leave a partially written file behind
+	fn write(&mut self, progress: DeployProgress) -> error::Result<()> {
+		let serialized =
+			serde_json::to_string_pretty(&progress).expect("serialization can't fail; qed");
+		let tmp_path = self.path.with_extension("tmp");
+		fs::write(&tmp_path, serialized)
+			.and_then(|_| fs::rename(&tmp_path, &self.path))
+			.chain_err(|| format!("Cannot write deployment progress {:?}", self.path))?;
+		self.progress = progress;
+		Ok(())
+	}
+}
+
+/// bytecode of the main contract followed by the constructor arguments
+/// for the configured authorities
+pub fn main_creation_code(config: &Config) -> Vec<u8> {
+	contracts::main::constructor(
+		config.main.contract.bin.clone().0,
+		config.authorities.required_signatures,
+		config.authorities.accounts.clone(),
+	)
+}
+
+/// bytecode of the side contract followed by the constructor arguments
+/// for the configured authorities
+pub fn side_creation_code(config: &Config) -> Vec<u8> {
+	contracts::side::constructor(
+		config.side.contract.bin.clone().0,
+		config.authorities.required_signatures,
+		config.authorities.accounts.clone(),
+	)
+}
+
+/// address of the deterministic deployment proxy
+/// (https://github.com/Arachnid/deterministic-deployment-proxy).
+/// called with a 32 byte salt followed by contract creation code
+/// it creates the contract with `CREATE2` and returns its address.
+/// it reverts if it can't create the contract.
+/// it's deployed by a presigned transaction without chain id
+/// so it has this address on every chain
+pub const CREATE2_FACTORY: &str = "4e59b44847b379578588920ca78fbf26c0b4956c";
+
+/// the account that signed `CREATE2_FACTORY_DEPLOY_TRANSACTION`.
+/// nobody knows its private key
+const CREATE2_FACTORY_DEPLOYER: &str = "3fab184622dc19b6109349b94811493bf2a45362";
+
+/// the first transaction of `CREATE2_FACTORY_DEPLOYER`. deploys the `CREATE2_FACTORY`.
+/// 100000 gas at a gas price of 100 gwei
+const CREATE2_FACTORY_DEPLOY_TRANSACTION: &str = "f8a58085174876e800830186a08080b853604580600e600039806000f350fe7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe03601600081602082378035828234f58015156039578182fd5b8082525050506014600cf31ba02222222222222222222222222222222222222222222222222222222222222222a02222222222222222222222222222222222222222222222222222222222222222";
+
+/// wei `CREATE2_FACTORY_DEPLOYER` needs to pay for `CREATE2_FACTORY_DEPLOY_TRANSACTION`
+const CREATE2_FACTORY_DEPLOY_COST: u64 = 10_000_000_000_000_000;
+
+/// creation code of the CREATE3 proxy. the `CREATE2_FACTORY` creates a proxy for a salt.
+/// called with contract creation code the proxy creates the contract with `CREATE`.
+/// so the first contract a proxy creates has an address that only depends on the salt.
+/// the proxy doesn't revert if it can't create the contract
+const CREATE3_PROXY_CREATION_CODE: &str = "67363d3d37363d34f03d5260086018f3";
+
+/// gas of the transaction that makes the `CREATE2_FACTORY` create a CREATE3 proxy
+const CREATE3_PROXY_DEPLOY_GAS: u64 = 100_000;
+
+pub fn create2_factory_address() -> Address {
+	CREATE2_FACTORY.parse().expect("valid address; qed")
+}
+
+/// input of the transaction that makes the `CREATE2_FACTORY` create
+/// a contract from `creation_code` with `salt`
+pub fn create2_factory_input(salt: H256, creation_code: &[u8]) -> Vec<u8> {
+	let mut input = salt.as_bytes().to_vec();
+	input.extend_from_slice(creation_code);
+	input
+}
+
+fn create3_proxy_creation_code() -> Vec<u8> {
+	CREATE3_PROXY_CREATION_CODE
+		.from_hex()
+		.expect("valid hex; qed")
+}
+
+/// address the `CREATE2_FACTORY` creates the CREATE3 proxy for `salt` at
+pub fn create3_proxy_address(salt: H256) -> Address {
+	helpers::create2_address(
+		create2_factory_address(),
+		salt,
+		&create3_proxy_creation_code(),
+	)
+}
+
+/// address of the first contract the CREATE3 proxy for `salt` creates.
+/// contracts start with nonce 1 (EIP-161)
+pub fn create3_address(salt: H256) -> Address {
+	helpers::contract_address(create3_proxy_address(salt), 1.into())
+}
+
+/// the transaction that deploys a contract from `creation_code`.
+/// sent to the CREATE3 proxy for `create2_salt` if there is one
+fn deploy_transaction(
+	from: Address,
+	gas: U256,
+	fees: Fees,
+	creation_code: &[u8],
+	create2_salt: Option<H256>,
+) -> Transaction {
+	Transaction {
+		from,
+		to: create2_salt.map(create3_proxy_address),
+		gas,
+		fees,
+		data: creation_code.to_vec().into(),
+	}
+}
+
+pub struct PrepareCreate3DeployOptions<T> {
+	pub transport: T,
+	pub request_timeout: Duration,
+	pub poll_interval: Duration,
+	pub confirmations: u32,
+	/// sends the transaction that creates the CREATE3 proxy
+	pub from: Address,
+	/// fees of the transaction that creates the CREATE3 proxy
+	pub transaction_config: TransactionConfig,
+	pub contract_name: String,
+	pub create2_salt: H256,
+}
+
+enum PrepareCreate3DeployState<T: Transport> {
+	/// code of the factory, nonce of the proxy and code of the contract
+	AwaitAccounts(Join3<Request<T, Bytes>, Request<T, U256>, Request<T, Bytes>>),
+	AwaitDeployerBalance(Request<T, U256>),
+	AwaitFactoryDeployTransaction(Request<T, H256>),
+	AwaitFactoryDeployReceipt(SendTransactionWithReceipt<T>),
+	AwaitProxyFees(AsyncFees<T>),
+	AwaitProxyDeployReceipt(SendTransactionWithReceipt<T>),
 }
 
-impl<T: Transport + Clone> DeployMain<T> {
-	pub fn new(config: Config, main_transport: T) -> Self {
+/// `Future` that prepares the deployment of a contract through the CREATE3 proxy
+/// for `create2_salt`. fails if there already is a contract at `create3_address`
+/// or the proxy already created a contract. deploys the `CREATE2_FACTORY`
+/// and creates the proxy if they are missing.
+/// `CREATE2_FACTORY_DEPLOYER` must have been funded to deploy the factory
+/// and the node must accept transactions without chain id (EIP-155)
+pub struct PrepareCreate3Deploy<T: Transport> {
+	options: PrepareCreate3DeployOptions<T>,
+	timer: Timer,
+	state: PrepareCreate3DeployState<T>,
+}
+
+impl<T: Transport> PrepareCreate3Deploy<T> {
+	pub fn new(options: PrepareCreate3DeployOptions<T>) -> Self {
+		let timer = Timer::default();
+		let eth = web3::api::Eth::new(&options.transport);
+		let salt = options.create2_salt;
+		let future = timer
+			.timeout(
+				eth.code(create2_factory_address(), None).from_err(),
+				options.request_timeout,
+			)
+			.join3(
+				timer.timeout(
+					eth.transaction_count(create3_proxy_address(salt), None)
+						.from_err(),
+					options.request_timeout,
+				),
+				timer.timeout(
+					eth.code(create3_address(salt), None).from_err(),
+					options.request_timeout,
+				),
+			);
 		Self {
-			config,
-			main_transport,
-			state: DeployState::NotDeployed,
+			options,
+			timer,
+			state: PrepareCreate3DeployState::AwaitAccounts(future),
 		}
 	}
+
+	fn await_proxy_fees(&self) -> PrepareCreate3DeployState<T> {
+		info!(
+			"creating CREATE3 proxy {:?} for salt {:?}",
+			create3_proxy_address(self.options.create2_salt),
+			self.options.create2_salt
+		);
+		PrepareCreate3DeployState::AwaitProxyFees(AsyncFees::new(
+			&self.options.transport,
+			self.options.request_timeout,
+			&self.options.transaction_config,
+			&Eip1559Support::default(),
+		))
+	}
 }
 
-impl<T: Transport + Clone> Future for DeployMain<T> {
-	type Item = DeployedContract;
+impl<T: Transport + Clone> Future for PrepareCreate3Deploy<T> {
+	type Item = ();
 	type Error = error::Error;
 
 	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
 		loop {
 			let next_state = match self.state {
-				DeployState::Deployed { ref contract } => return Ok(contract.clone().into()),
-				DeployState::NotDeployed => {
-					let data = contracts::main::constructor(
-						self.config.main.contract.bin.clone().0,
-						self.config.authorities.required_signatures,
-						self.config.authorities.accounts.clone(),
+				PrepareCreate3DeployState::AwaitAccounts(ref mut future) => {
+					let (factory_code, proxy_nonce, contract_code) = try_ready!(future
+						.poll()
+						.chain_err(|| "PrepareCreate3Deploy: fetching code and nonce failed"));
+					let salt = self.options.create2_salt;
+					if !contract_code.0.is_empty() {
+						bail!(
+							"there already is a contract at {:?} where the {} contract would be deployed to. use a different salt or `parity-bridge-deploy attach`",
+							create3_address(salt),
+							self.options.contract_name
+						);
+					}
+					// the proxy's nonce is 1 until it creates a contract
+					if proxy_nonce > 1.into() {
+						bail!(
+							"the CREATE3 proxy {:?} for salt {:?} already created a contract and can't create the {} contract at {:?} anymore. use a different salt",
+							create3_proxy_address(salt),
+							salt,
+							self.options.contract_name,
+							create3_address(salt)
+						);
+					}
+					if proxy_nonce == 1.into() {
+						return Ok(Async::Ready(()));
+					}
+					if !factory_code.0.is_empty() {
+						self.await_proxy_fees()
+					} else {
+						info!(
+							"CREATE2 factory {:?} is missing. deploying it",
+							create2_factory_address()
+						);
+						let deployer = CREATE2_FACTORY_DEPLOYER
+							.parse()
+							.expect("valid address; qed");
+						let future =
+							web3::api::Eth::new(&self.options.transport).balance(deployer, None);
+						PrepareCreate3DeployState::AwaitDeployerBalance(
+							self.timer
+								.timeout(future.from_err(), self.options.request_timeout),
+						)
+					}
+				}
+				PrepareCreate3DeployState::AwaitDeployerBalance(ref mut future) => {
+					let balance = try_ready!(future.poll().chain_err(|| {
+						"PrepareCreate3Deploy: fetching balance of CREATE2 factory deployer failed"
+					}));
+					let cost = U256::from(CREATE2_FACTORY_DEPLOY_COST);
+					if balance < cost {
+						bail!(
+							"the CREATE2 factory {:?} is missing. it's deployed by a presigned transaction of 0x{} which costs {} wei. send 0x{} at least {} wei and rerun",
+							create2_factory_address(),
+							CREATE2_FACTORY_DEPLOYER,
+							cost,
+							CREATE2_FACTORY_DEPLOYER,
+							cost - balance
+						);
+					}
+					let raw_transaction: Vec<u8> = CREATE2_FACTORY_DEPLOY_TRANSACTION
+						.from_hex()
+						.expect("valid hex; qed");
+					let future = CallFuture::new(self.options.transport.execute(
+						"eth_sendRawTransaction",
+						vec![web3_helpers::serialize(&Bytes(raw_transaction))],
+					));
+					PrepareCreate3DeployState::AwaitFactoryDeployTransaction(
+						self.timer
+							.timeout(future.from_err(), self.options.request_timeout),
+					)
+				}
+				PrepareCreate3DeployState::AwaitFactoryDeployTransaction(ref mut future) => {
+					let transaction_hash = try_ready!(future.poll().chain_err(|| {
+						"PrepareCreate3Deploy: sending CREATE2 factory deployment transaction failed. the node must accept transactions without chain id (EIP-155)"
+					}));
+					info!(
+						"waiting for {} confirmations of CREATE2 factory deployment transaction {:?}...",
+						self.options.confirmations, transaction_hash
 					);
-
-					let tx_request = TransactionRequest {
-						from: self.config.address,
-						to: None,
-						gas: Some(self.config.txs.main_deploy.gas.into()),
-						gas_price: Some(self.config.txs.main_deploy.gas_price.into()),
-						value: None,
-						data: Some(data.clone().into()),
-						nonce: None,
-						condition: None,
-					};
-
-					let future =
+					PrepareCreate3DeployState::AwaitFactoryDeployReceipt(
 						SendTransactionWithReceipt::new(SendTransactionWithReceiptOptions {
-							transport: self.main_transport.clone(),
-							request_timeout: self.config.main.request_timeout,
-							poll_interval: self.config.main.poll_interval,
-							confirmations: self.config.main.required_confirmations,
-							transaction: tx_request,
-						});
-
-					info!("sending MainBridge contract deployment transaction and waiting for {} confirmations...", self.config.main.required_confirmations);
-
-					DeployState::Deploying { data, future }
+							transport: self.options.transport.clone(),
+							request_timeout: self.options.request_timeout,
+							poll_interval: self.options.poll_interval,
+							confirmations: self.options.confirmations,
+							transaction: PendingTransaction::Sent(transaction_hash),
+						}),
+					)
 				}
-				DeployState::Deploying {
-					ref mut future,
-					ref data,
-				} => {
-					let receipt = try_ready!(future
+				PrepareCreate3DeployState::AwaitFactoryDeployReceipt(ref mut future) => {
+					let receipt = try_ready!(future.poll().chain_err(|| {
+						"PrepareCreate3Deploy: CREATE2 factory deployment transaction failed"
+					}));
+					if receipt.status == Some(0.into()) {
+						bail!(
+							"CREATE2 factory deployment transaction {:?} failed",
+							receipt.transaction_hash
+						);
+					}
+					info!(
+						"CREATE2 factory deployed to {:?}",
+						create2_factory_address()
+					);
+					self.await_proxy_fees()
+				}
+				PrepareCreate3DeployState::AwaitProxyFees(ref mut future) => {
+					let fees = try_ready!(future
 						.poll()
-						.chain_err(|| "DeployMain: deployment transaction failed"));
-					let address = receipt
-						.contract_address
-						.expect("contract creation receipt must have an address; qed");
-					info!("MainBridge deployment completed to {:?}", address);
-
-					DeployState::Deployed {
-						contract: DeployedContract::new(
-							"Main".into(),
-							include_str!("../../arbitrary/contracts/bridge.sol").into(),
-							include_str!("../../compiled_contracts/Main.abi").into(),
-							include_str!("../../compiled_contracts/Main.bin").into(),
-							data.to_hex(),
-							receipt,
-						),
+						.chain_err(|| "PrepareCreate3Deploy: fetching fees failed"));
+					let transaction = Transaction {
+						from: self.options.from,
+						to: Some(create2_factory_address()),
+						gas: CREATE3_PROXY_DEPLOY_GAS.into(),
+						fees,
+						data: create2_factory_input(
+							self.options.create2_salt,
+							&create3_proxy_creation_code(),
+						)
+						.into(),
+					};
+					PrepareCreate3DeployState::AwaitProxyDeployReceipt(
+						SendTransactionWithReceipt::new(SendTransactionWithReceiptOptions {
+							transport: self.options.transport.clone(),
+							request_timeout: self.options.request_timeout,
+							poll_interval: self.options.poll_interval,
+							confirmations: self.options.confirmations,
+							transaction: transaction.into(),
+						}),
+					)
+				}
+				PrepareCreate3DeployState::AwaitProxyDeployReceipt(ref mut future) => {
+					let receipt = try_ready!(future.poll().chain_err(|| {
+						"PrepareCreate3Deploy: CREATE3 proxy creation transaction failed"
+					}));
+					// the factory reverts if it can't create the proxy
+					if receipt.status == Some(0.into()) {
+						bail!(
+							"CREATE3 proxy creation transaction {:?} failed",
+							receipt.transaction_hash
+						);
 					}
+					info!(
+						"CREATE3 proxy created at {:?}",
+						create3_proxy_address(self.options.create2_salt)
+					);
+					return Ok(Async::Ready(()));
 				}
 			};
 
@@ -125,83 +487,325 @@ impl<T: Transport + Clone> Future for DeployMain<T> {
 	}
 }
 
-pub struct DeploySide<T: Transport + Clone> {
-	config: Config,
-	side_transport: T,
+pub enum DeployState<T: Transport + Clone> {
+	NotDeployed,
+	AwaitProxy(PrepareCreate3Deploy<T>),
+	AwaitFees(AsyncFees<T>),
+	Deploying(SendTransactionWithReceipt<T>),
+	/// the CREATE3 proxy doesn't revert if it can't create the contract.
+	/// so the code at the contract address is checked
+	AwaitCode {
+		future: Request<T, Bytes>,
+		contract_address: Address,
+		receipt: TransactionReceipt,
+	},
+	Deployed(DeployedContract),
+}
+
+pub struct DeployContractOptions<T> {
+	pub transport: T,
+	pub request_timeout: Duration,
+	pub poll_interval: Duration,
+	pub confirmations: u32,
+	/// sends the deployment transactions
+	pub from: Address,
+	pub transaction_config: TransactionConfig,
+	pub contract_name: String,
+	pub abi: String,
+	pub bytecode: Vec<u8>,
+	/// `bytecode` followed by the constructor arguments
+	pub creation_code: Vec<u8>,
+}
+
+/// `Future` that deploys a contract and resolves with the `DeployedContract`.
+/// resumes the deployment recorded in `progress`
+pub struct DeployContract<T: Transport + Clone> {
+	options: DeployContractOptions<T>,
+	progress: DeployProgressFile,
+	create2_salt: Option<H256>,
+	timer: Timer,
 	state: DeployState<T>,
 }
 
-impl<T: Transport + Clone> DeploySide<T> {
-	pub fn new(config: Config, side_transport: T) -> Self {
+impl<T: Transport + Clone> DeployContract<T> {
+	pub fn new(options: DeployContractOptions<T>, progress: DeployProgressFile) -> Self {
 		Self {
-			config,
-			side_transport,
+			options,
+			progress,
+			create2_salt: None,
+			timer: Timer::default(),
 			state: DeployState::NotDeployed,
 		}
 	}
+
+	/// deploys the main contract for the configured authorities
+	pub fn main(config: &Config, main_transport: T, progress: DeployProgressFile) -> Self {
+		Self::new(
+			DeployContractOptions {
+				transport: main_transport,
+				request_timeout: config.main.request_timeout,
+				poll_interval: config.main.poll_interval,
+				confirmations: config.main.required_confirmations,
+				from: config.address,
+				transaction_config: config.txs.main_deploy.clone(),
+				contract_name: "Main".into(),
+				abi: include_str!("../../compiled_contracts/Main.abi").into(),
+				bytecode: config.main.contract.bin.0.clone(),
+				creation_code: main_creation_code(config),
+			},
+			progress,
+		)
+	}
+
+	/// deploys the side contract for the configured authorities
+	pub fn side(config: &Config, side_transport: T, progress: DeployProgressFile) -> Self {
+		Self::new(
+			DeployContractOptions {
+				transport: side_transport,
+				request_timeout: config.side.request_timeout,
+				poll_interval: config.side.poll_interval,
+				confirmations: config.side.required_confirmations,
+				from: config.address,
+				transaction_config: config.txs.side_deploy.clone(),
+				contract_name: "SideBridge".into(),
+				abi: include_str!("../../compiled_contracts/Side.abi").into(),
+				bytecode: config.side.contract.bin.0.clone(),
+				creation_code: side_creation_code(config),
+			},
+			progress,
+		)
+	}
+
+	/// deploy through the CREATE3 proxy for `salt`
+	/// instead of sending a contract creation transaction
+	pub fn with_create2_salt(mut self, salt: H256) -> Self {
+		self.create2_salt = Some(salt);
+		self
+	}
+
+	/// the address the contract gets deployed to if it's deployed through the CREATE3 proxy
+	pub fn create3_address(&self) -> Option<Address> {
+		self.create2_salt.map(create3_address)
+	}
+
+	fn send_transaction_with_receipt(
+		&self,
+		transaction: PendingTransaction,
+	) -> SendTransactionWithReceipt<T> {
+		SendTransactionWithReceipt::new(SendTransactionWithReceiptOptions {
+			transport: self.options.transport.clone(),
+			request_timeout: self.options.request_timeout,
+			poll_interval: self.options.poll_interval,
+			confirmations: self.options.confirmations,
+			transaction,
+		})
+	}
+
+	fn await_fees(&self) -> DeployState<T> {
+		DeployState::AwaitFees(AsyncFees::new(
+			&self.options.transport,
+			self.options.request_timeout,
+			&self.options.transaction_config,
+			&Eip1559Support::default(),
+		))
+	}
+
+	fn deploy_transaction(&self, fees: Fees) -> Transaction {
+		deploy_transaction(
+			self.options.from,
+			self.options.transaction_config.gas,
+			fees,
+			&self.options.creation_code,
+			self.create2_salt,
+		)
+	}
+
+	fn deployed_contract(&self, receipt: TransactionReceipt) -> DeployedContract {
+		let contract_name = self.options.contract_name.clone();
+		let contract_source = include_str!("../../arbitrary/contracts/bridge.sol").to_string();
+		let abi = self.options.abi.clone();
+		let bytecode_hex = self.options.bytecode.to_hex();
+		let creation_code_hex = self.options.creation_code.to_hex();
+		match self.create2_salt {
+			Some(salt) => DeployedContract::created_by_proxy(
+				contract_name,
+				contract_source,
+				abi,
+				bytecode_hex,
+				creation_code_hex,
+				salt,
+				Some(receipt),
+			),
+			None => DeployedContract::new(
+				contract_name,
+				contract_source,
+				abi,
+				bytecode_hex,
+				creation_code_hex,
+				receipt,
+			),
+		}
+	}
+
+	/// records the receipt of the deployment transaction
+	fn deployed(&mut self, receipt: TransactionReceipt) -> error::Result<DeployState<T>> {
+		self.progress.record_receipt(&receipt)?;
+		let contract = self.deployed_contract(receipt);
+		info!(
+			"{} deployment completed to 0x{}",
+			self.options.contract_name, contract.contract_address
+		);
+		Ok(DeployState::Deployed(contract))
+	}
+
+	/// checks the deployment without sending anything.
+	/// ignores the recorded progress
+	pub fn dry_run(&self) -> DryRunDeploy<T> {
+		DryRunDeploy::new(DryRunDeployOptions {
+			transport: self.options.transport.clone(),
+			request_timeout: self.options.request_timeout,
+			contract_name: self.options.contract_name.clone(),
+			abi: self.options.abi.clone(),
+			bytecode_hex: self.options.bytecode.to_hex(),
+			transaction: self.deploy_transaction(Fees::Legacy {
+				gas_price: self.options.transaction_config.gas_price,
+			}),
+			transaction_config: self.options.transaction_config.clone(),
+			create2_salt: self.create2_salt,
+		})
+	}
 }
 
-impl<T: Transport + Clone> Future for DeploySide<T> {
+impl<T: Transport + Clone> Future for DeployContract<T> {
 	type Item = DeployedContract;
 	type Error = error::Error;
 
 	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
 		loop {
 			let next_state = match self.state {
-				DeployState::Deployed { ref contract } => return Ok(contract.clone().into()),
+				DeployState::Deployed(ref contract) => return Ok(Async::Ready(contract.clone())),
 				DeployState::NotDeployed => {
-					let data = contracts::side::constructor(
-						self.config.side.contract.bin.clone().0,
-						self.config.authorities.required_signatures,
-						self.config.authorities.accounts.clone(),
-					);
+					self.progress
+						.check_creation_code(&self.options.creation_code)?;
+					self.progress.check_create2_salt(self.create2_salt)?;
 
-					let tx_request = TransactionRequest {
-						from: self.config.address,
-						to: None,
-						gas: Some(self.config.txs.side_deploy.gas.into()),
-						gas_price: Some(self.config.txs.side_deploy.gas_price.into()),
-						value: None,
-						data: Some(data.clone().into()),
-						nonce: None,
-						condition: None,
-					};
-
-					let future =
-						SendTransactionWithReceipt::new(SendTransactionWithReceiptOptions {
-							transport: self.side_transport.clone(),
-							request_timeout: self.config.side.request_timeout,
-							poll_interval: self.config.side.poll_interval,
-							confirmations: self.config.side.required_confirmations,
-							transaction: tx_request,
-						});
-
-					info!("sending SideBridge contract deployment transaction and waiting for {} confirmations...", self.config.side.required_confirmations);
-
-					DeployState::Deploying { data, future }
+					let progress = self.progress.progress().clone();
+					if let Some(receipt) = progress.receipt {
+						info!(
+							"{} contract already deployed in transaction {:?}. skipping",
+							self.options.contract_name, receipt.transaction_hash
+						);
+						DeployState::Deployed(self.deployed_contract(receipt))
+					} else if let Some(transaction_hash) = progress.transaction_hash {
+						info!(
+							"waiting for {} confirmations of pending {} contract deployment transaction {:?}...",
+							self.options.confirmations, self.options.contract_name, transaction_hash
+						);
+						DeployState::Deploying(self.send_transaction_with_receipt(
+							PendingTransaction::Sent(transaction_hash),
+						))
+					} else if let Some(salt) = self.create2_salt {
+						info!(
+							"{} contract will be deployed to {:?} through the CREATE3 proxy {:?} for salt {:?}",
+							self.options.contract_name,
+							create3_address(salt),
+							create3_proxy_address(salt),
+							salt
+						);
+						DeployState::AwaitProxy(PrepareCreate3Deploy::new(
+							PrepareCreate3DeployOptions {
+								transport: self.options.transport.clone(),
+								request_timeout: self.options.request_timeout,
+								poll_interval: self.options.poll_interval,
+								confirmations: self.options.confirmations,
+								from: self.options.from,
+								transaction_config: self.options.transaction_config.clone(),
+								contract_name: self.options.contract_name.clone(),
+								create2_salt: salt,
+							},
+						))
+					} else {
+						self.await_fees()
+					}
 				}
-				DeployState::Deploying {
+				DeployState::AwaitProxy(ref mut future) => {
+					try_ready!(future
+						.poll()
+						.chain_err(|| "DeployContract: preparing CREATE3 deployment failed"));
+					self.await_fees()
+				}
+				DeployState::AwaitFees(ref mut future) => {
+					let fees = try_ready!(future
+						.poll()
+						.chain_err(|| "DeployContract: fetching fees failed"));
+					let transaction = self.deploy_transaction(fees);
+					info!(
+						"sending {} contract deployment transaction and waiting for {} confirmations...",
+						self.options.contract_name, self.options.confirmations
+					);
+					DeployState::Deploying(self.send_transaction_with_receipt(transaction.into()))
+				}
+				DeployState::Deploying(ref mut future) => {
+					let poll = future
+						.poll()
+						.chain_err(|| "DeployContract: deployment transaction failed");
+					// persist the hash as soon as the transaction is sent
+					if let Some(transaction_hash) = future.transaction_hash() {
+						self.progress.record_transaction_hash(
+							&self.options.creation_code,
+							self.create2_salt,
+							transaction_hash,
+						)?;
+					}
+					let receipt = try_ready!(poll);
+					match self.create2_salt {
+						Some(salt) => {
+							if receipt.status == Some(0.into()) {
+								bail!(
+									"{} contract deployment transaction {:?} to the CREATE3 proxy failed",
+									self.options.contract_name,
+									receipt.transaction_hash
+								);
+							}
+							let contract_address = create3_address(salt);
+							let future = web3::api::Eth::new(&self.options.transport)
+								.code(contract_address, None);
+							DeployState::AwaitCode {
+								future: self
+									.timer
+									.timeout(future.from_err(), self.options.request_timeout),
+								contract_address,
+								receipt,
+							}
+						}
+						None => self.deployed(receipt)?,
+					}
+				}
+				DeployState::AwaitCode {
 					ref mut future,
-					ref data,
+					contract_address,
+					ref receipt,
 				} => {
-					let receipt = try_ready!(future
-						.poll()
-						.chain_err(|| "DeploySide: deployment transaction failed"));
-					let address = receipt
-						.contract_address
-						.expect("contract creation receipt must have an address; qed");
-					info!("SideBridge deployment completed to {:?}", address);
-
-					DeployState::Deployed {
-						contract: DeployedContract::new(
-							"SideBridge".into(),
-							include_str!("../../arbitrary/contracts/bridge.sol").into(),
-							include_str!("../../compiled_contracts/Side.abi").into(),
-							include_str!("../../compiled_contracts/Side.bin").into(),
-							data.to_hex(),
-							receipt,
-						),
+					let code = try_ready!(future.poll().chain_err(|| {
+						"DeployContract: fetching code of the deployed contract failed"
+					}));
+					if code.0.is_empty() {
+						bail!(
+							"the CREATE3 proxy didn't create the {} contract in transaction {:?} and can't create it anymore. remove the deployment progress and use a different salt",
+							self.options.contract_name,
+							receipt.transaction_hash
+						);
 					}
+					// someone else might have called the proxy first
+					if !helpers::is_runtime_code_of(&code.0, &self.options.creation_code) {
+						bail!(
+							"the contract at {:?} was not created from the {} contract creation code. someone else used the CREATE3 proxy first. remove the deployment progress and use a different salt",
+							contract_address,
+							self.options.contract_name
+						);
+					}
+					let receipt = receipt.clone();
+					self.deployed(receipt)?
 				}
 			};
 
@@ -218,7 +822,10 @@ pub struct DeployedContract {
 	pub abi: String,
 	pub bytecode_hex: String,
 	pub contract_creation_code_hex: String,
-	pub receipt: TransactionReceipt,
+	/// `None` for dry runs
+	pub receipt: Option<TransactionReceipt>,
+	/// salt if the contract is deployed through the CREATE3 proxy
+	pub create2_salt: Option<H256>,
 }
 
 impl DeployedContract {
@@ -229,6 +836,73 @@ impl DeployedContract {
 		bytecode_hex: String,
 		contract_creation_code_hex: String,
 		receipt: TransactionReceipt,
+	) -> Self {
+		let contract_address = receipt
+			.contract_address
+			.expect("contract creation receipt must have an address; qed");
+		Self::with_address(
+			contract_name,
+			contract_source,
+			abi,
+			bytecode_hex,
+			contract_creation_code_hex,
+			contract_address,
+			Some(receipt),
+		)
+	}
+
+	/// a contract that would be deployed to the predicted `contract_address`
+	pub fn predicted(
+		contract_name: String,
+		contract_source: String,
+		abi: String,
+		bytecode_hex: String,
+		contract_creation_code_hex: String,
+		contract_address: Address,
+	) -> Self {
+		Self::with_address(
+			contract_name,
+			contract_source,
+			abi,
+			bytecode_hex,
+			contract_creation_code_hex,
+			contract_address,
+			None,
+		)
+	}
+
+	/// a contract that the CREATE3 proxy for `salt` creates.
+	/// `receipt` is `None` for dry runs
+	pub fn created_by_proxy(
+		contract_name: String,
+		contract_source: String,
+		abi: String,
+		bytecode_hex: String,
+		contract_creation_code_hex: String,
+		salt: H256,
+		receipt: Option<TransactionReceipt>,
+	) -> Self {
+		let mut contract = Self::with_address(
+			contract_name,
+			contract_source,
+			abi,
+			bytecode_hex,
+			contract_creation_code_hex,
+			create3_address(salt),
+			receipt,
+		);
+		contract.create2_salt = Some(salt);
+		contract
+	}
+
+	fn with_address(
+		contract_name: String,
+		contract_source: String,
+		abi: String,
+		bytecode_hex: String,
+		contract_creation_code_hex: String,
+		contract_address: Address,
+		receipt: Option<TransactionReceipt>,
 	) -> Self {
 		assert_eq!(
 			bytecode_hex,
@@ -238,17 +912,13 @@ impl DeployedContract {
 
 		Self {
 			contract_name,
-			contract_address: format!(
-				"{:x}",
-				receipt
-					.contract_address
-					.expect("contract creation receipt must have an address; qed")
-			),
+			contract_address: format!("{:x}", contract_address),
 			contract_source,
 			abi,
 			bytecode_hex,
 			contract_creation_code_hex,
 			receipt,
+			create2_salt: None,
 		}
 	}
 
@@ -261,7 +931,8 @@ impl DeployedContract {
 	/// - git commit
 	/// - contract source code
 	/// - contract address
-	/// - hash of transaction the contract got deployed in
+	/// - hash of transaction the contract got deployed in (not for dry runs)
+	/// - `CREATE2` factory, salt and CREATE3 proxy (if deployed through the proxy)
 	/// - contract byte code
 	/// - input data for contract creation transaction
 	/// - ...
@@ -296,8 +967,22 @@ impl DeployedContract {
 		let mut file = File::create(dir.join("contract_source.sol"))?;
 		file.write_all(self.contract_source.as_bytes())?;
 
-		let mut file = File::create(dir.join("transaction_hash"))?;
-		file.write_all(format!("{:x}", self.receipt.transaction_hash).as_bytes())?;
+		// dry runs don't send a transaction
+		if let Some(ref receipt) = self.receipt {
+			let mut file = File::create(dir.join("transaction_hash"))?;
+			file.write_all(format!("{:x}", receipt.transaction_hash).as_bytes())?;
+		}
+
+		if let Some(salt) = self.create2_salt {
+			let mut file = File::create(dir.join("create2_factory"))?;
+			file.write_all(CREATE2_FACTORY.as_bytes())?;
+
+			let mut file = File::create(dir.join("create2_salt"))?;
+			file.write_all(format!("{:x}", salt).as_bytes())?;
+
+			let mut file = File::create(dir.join("create3_proxy"))?;
+			file.write_all(format!("{:x}", create3_proxy_address(salt)).as_bytes())?;
+		}
 
 		let mut file = File::create(dir.join("deployed_bytecode"))?;
 		file.write_all(self.bytecode_hex.as_bytes())?;
@@ -313,3 +998,1285 @@ impl DeployedContract {
 		Ok(())
 	}
 }
+
+/// result of a deployment dry run: the transaction that would be sent
+/// and the state of the account that would send it
+pub struct DryRun {
+	pub transaction: Transaction,
+	pub estimated_gas: U256,
+	pub balance: U256,
+	/// nonce the transaction would be sent with. includes pending transactions
+	pub nonce: U256,
+	/// nonce of the next transaction in the latest block
+	pub mined_nonce: U256,
+	/// deployed to the address predicted from sender and `nonce`
+	/// or from the CREATE3 salt
+	pub contract: DeployedContract,
+}
+
+impl DryRun {
+	/// reasons the deployment would fail or not end up at the predicted address
+	pub fn problems(&self) -> Vec<String> {
+		let mut problems = Vec::new();
+		if self.estimated_gas > self.transaction.gas {
+			problems.push(format!(
+				"{} deployment is estimated to use {} gas but only {} gas is configured",
+				self.contract.contract_name, self.estimated_gas, self.transaction.gas
+			));
+		}
+		let max_cost = self.transaction.fees.max_cost(self.transaction.gas);
+		if self.balance < max_cost {
+			problems.push(format!(
+				"{} deployment can cost up to {} wei but {:?} only has {} wei",
+				self.contract.contract_name, max_cost, self.transaction.from, self.balance
+			));
+		}
+		// the address of contracts created by the proxy doesn't depend on the nonce
+		if self.contract.create2_salt.is_none() && self.nonce != self.mined_nonce {
+			problems.push(format!(
+				"{:?} has {} pending transactions. the {} contract only gets deployed to the predicted address if they all get mined first",
+				self.transaction.from,
+				self.nonce.saturating_sub(self.mined_nonce),
+				self.contract.contract_name
+			));
+		}
+		problems
+	}
+}
+
+impl fmt::Display for DryRun {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "{} deployment (dry run)", self.contract.contract_name)?;
+		writeln!(f, "from: {:?}", self.transaction.from)?;
+		writeln!(f, "nonce: {}", self.nonce)?;
+		writeln!(
+			f,
+			"gas: {} (estimated {})",
+			self.transaction.gas, self.estimated_gas
+		)?;
+		writeln!(f, "fees: {:?}", self.transaction.fees)?;
+		writeln!(
+			f,
+			"max cost: {} wei",
+			self.transaction.fees.max_cost(self.transaction.gas)
+		)?;
+		writeln!(f, "balance: {} wei", self.balance)?;
+		writeln!(
+			f,
+			"predicted contract address: 0x{}",
+			self.contract.contract_address
+		)?;
+		if let Some(salt) = self.contract.create2_salt {
+			writeln!(
+				f,
+				"deployed through CREATE3 proxy {:?} created by CREATE2 factory 0x{} with salt {:?}",
+				create3_proxy_address(salt),
+				CREATE2_FACTORY,
+				salt
+			)?;
+		}
+		write!(
+			f,
+			"eth_sendTransaction params: {}",
+			serde_json::to_string_pretty(&self.transaction.to_json())
+				.expect("serialization can't fail; qed")
+		)
+	}
+}
+
+type Request<T, R> = Timeout<FromErr<CallFuture<R, <T as Transport>::Out>, error::Error>>;
+
+pub struct DryRunDeployOptions<T> {
+	pub transport: T,
+	pub request_timeout: Duration,
+	pub contract_name: String,
+	pub abi: String,
+	pub bytecode_hex: String,
+	/// fees are replaced by the ones from `transaction_config`
+	pub transaction: Transaction,
+	pub transaction_config: TransactionConfig,
+	/// salt if `transaction` is sent to the CREATE3 proxy
+	pub create2_salt: Option<H256>,
+}
+
+/// `Future` that checks a contract deployment without sending anything.
+/// fetches the fees, gas estimate, balance and nonces in parallel.
+/// resolves with a `DryRun`
+pub struct DryRunDeploy<T: Transport> {
+	contract_name: String,
+	abi: String,
+	bytecode_hex: String,
+	transaction: Transaction,
+	create2_salt: Option<H256>,
+	future:
+		Join5<AsyncFees<T>, Request<T, U256>, Request<T, U256>, Request<T, U256>, Request<T, U256>>,
+}
+
+impl<T: Transport> DryRunDeploy<T> {
+	pub fn new(options: DryRunDeployOptions<T>) -> Self {
+		let timer = Timer::default();
+		let from = options.transaction.from;
+		// estimated without fees so the balance doesn't affect the estimate.
+		// the CREATE3 proxy might not exist yet. a deployment through it
+		// is estimated like a contract creation transaction which costs about the same
+		let estimate_transaction = Transaction {
+			to: None,
+			fees: Fees::Legacy {
+				gas_price: 0.into(),
+			},
+			..options.transaction.clone()
+		};
+		let eth = web3::api::Eth::new(&options.transport);
+
+		let future = AsyncFees::new(
+			&options.transport,
+			options.request_timeout,
+			&options.transaction_config,
+			&Eip1559Support::default(),
+		)
+		.join5(
+			timer.timeout(
+				estimate_transaction
+					.estimate_gas(&options.transport)
+					.from_err(),
+				options.request_timeout,
+			),
+			timer.timeout(eth.balance(from, None).from_err(), options.request_timeout),
+			timer.timeout(
+				eth.transaction_count(from, Some(BlockNumber::Pending))
+					.from_err(),
+				options.request_timeout,
+			),
+			timer.timeout(
+				eth.transaction_count(from, Some(BlockNumber::Latest))
+					.from_err(),
+				options.request_timeout,
+			),
+		);
+
+		Self {
+			contract_name: options.contract_name,
+			abi: options.abi,
+			bytecode_hex: options.bytecode_hex,
+			transaction: options.transaction,
+			create2_salt: options.create2_salt,
+			future,
+		}
+	}
+}
+
+impl<T: Transport> Future for DryRunDeploy<T> {
+	type Item = DryRun;
+	type Error = error::Error;
+
+	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+		let (fees, estimated_gas, balance, nonce, mined_nonce) =
+			try_ready!(self.future.poll().chain_err(|| format!(
+				"DryRunDeploy: checking {} deployment failed",
+				self.contract_name
+			)));
+
+		let mut transaction = self.transaction.clone();
+		transaction.fees = fees;
+
+		let contract = match self.create2_salt {
+			Some(salt) => DeployedContract::created_by_proxy(
+				self.contract_name.clone(),
+				include_str!("../../arbitrary/contracts/bridge.sol").into(),
+				self.abi.clone(),
+				self.bytecode_hex.clone(),
+				transaction.data.0.to_hex(),
+				salt,
+				None,
+			),
+			None => DeployedContract::predicted(
+				self.contract_name.clone(),
+				include_str!("../../arbitrary/contracts/bridge.sol").into(),
+				self.abi.clone(),
+				self.bytecode_hex.clone(),
+				transaction.data.0.to_hex(),
+				helpers::contract_address(transaction.from, nonce),
+			),
+		};
+
+		Ok(Async::Ready(DryRun {
+			transaction,
+			estimated_gas,
+			balance,
+			nonce,
+			mined_nonce,
+			contract,
+		}))
+	}
+}
+
+/// transaction in the response of `eth_getBlockByNumber`.
+/// only the fields needed to find contract creations
+#[derive(Debug, Deserialize)]
+struct BlockTransaction {
+	hash: H256,
+	to: Option<Address>,
+	input: Bytes,
+}
+
+/// response of `eth_getBlockByNumber` with full transactions
+#[derive(Debug, Deserialize)]
+struct BlockTransactions {
+	transactions: Vec<BlockTransaction>,
+}
+
+/// the transaction that created a contract
+#[derive(Debug, PartialEq, Clone)]
+pub struct ContractCreation {
+	/// contract bytecode followed by the constructor arguments
+	pub creation_code: Bytes,
+	/// the CREATE3 proxy if it created the contract
+	pub proxy: Option<Address>,
+	pub receipt: TransactionReceipt,
+}
+
+impl ContractCreation {
+	/// the creation of the contract at `contract_address` if `transaction` created it
+	fn from_receipt(
+		contract_address: Address,
+		transaction: &BlockTransaction,
+		receipt: TransactionReceipt,
+	) -> Option<Self> {
+		if receipt.contract_address == Some(contract_address) {
+			return Some(Self {
+				creation_code: transaction.input.clone(),
+				proxy: None,
+				receipt,
+			});
+		}
+
+		// a CREATE3 proxy is called with the creation code.
+		// its first contract is at the address derived from its nonce 1
+		let proxy = match transaction.to {
+			Some(to) if helpers::contract_address(to, 1.into()) == contract_address => to,
+			_ => return None,
+		};
+		if receipt.status == Some(0.into()) {
+			return None;
+		}
+		Some(Self {
+			creation_code: transaction.input.clone(),
+			proxy: Some(proxy),
+			receipt,
+		})
+	}
+
+	/// fails unless the contract was created with `expected_creation_code`
+	/// which consists of `bytecode` followed by the constructor arguments
+	pub fn check_creation_code(
+		&self,
+		contract_name: &str,
+		bytecode: &[u8],
+		expected_creation_code: &[u8],
+	) -> error::Result<()> {
+		if self.creation_code.0 == expected_creation_code {
+			return Ok(());
+		}
+		if !self.creation_code.0.starts_with(bytecode) {
+			bail!(
+				"{} contract was created in transaction {:?} from different bytecode than expected",
+				contract_name,
+				self.receipt.transaction_hash
+			);
+		}
+		bail!(
+			"{} contract was created in transaction {:?} with different constructor arguments. check `authorities.accounts` and `authorities.required_signatures`",
+			contract_name,
+			self.receipt.transaction_hash
+		);
+	}
+}
+
+enum FindContractCreationState<T: Transport> {
+	AwaitLatestBlock(Request<T, U64>),
+	/// binary search for the first block the contract has code in.
+	/// the contract has no code before block `from`.
+	/// `to` is the earliest block the contract is known to have code in
+	AwaitCode {
+		future: Request<T, Bytes>,
+		block: u64,
+		from: u64,
+		to: Option<u64>,
+	},
+	AwaitBlock {
+		future: Request<T, Option<BlockTransactions>>,
+		block: u64,
+	},
+	/// checks the contract creation transactions of `block` one by one.
+	/// `block` is `None` if the creation transaction is known
+	AwaitReceipt {
+		future: Request<T, Option<TransactionReceipt>>,
+		transaction: BlockTransaction,
+		remaining: Vec<BlockTransaction>,
+		block: Option<u64>,
+	},
+	AwaitTransaction {
+		future: Request<T, Option<BlockTransaction>>,
+		transaction_hash: H256,
+	},
+}
+
+/// `Future` that finds the transaction that created the contract at `contract_address`.
+/// binary searches for the first block the contract has code in
+/// which requires the node to have the state of that block
+/// (an archive node for older deployments).
+/// fails for contracts that were created by other contracts than a CREATE3 proxy.
+/// use `FindContractCreation::from_transaction` if the transaction is known.
+pub struct FindContractCreation<T: Transport> {
+	transport: T,
+	request_timeout: Duration,
+	contract_address: Address,
+	timer: Timer,
+	state: FindContractCreationState<T>,
+}
+
+impl<T: Transport> FindContractCreation<T> {
+	pub fn new(transport: T, request_timeout: Duration, contract_address: Address) -> Self {
+		let timer = Timer::default();
+		let future = web3::api::Eth::new(&transport).block_number();
+		let state = FindContractCreationState::AwaitLatestBlock(
+			timer.timeout(future.from_err(), request_timeout),
+		);
+		Self {
+			transport,
+			request_timeout,
+			contract_address,
+			timer,
+			state,
+		}
+	}
+
+	/// checks that `transaction_hash` created the contract at `contract_address`
+	pub fn from_transaction(
+		transport: T,
+		request_timeout: Duration,
+		contract_address: Address,
+		transaction_hash: H256,
+	) -> Self {
+		let timer = Timer::default();
+		let future = CallFuture::new(transport.execute(
+			"eth_getTransactionByHash",
+			vec![web3_helpers::serialize(&transaction_hash)],
+		));
+		let state = FindContractCreationState::AwaitTransaction {
+			future: timer.timeout(future.from_err(), request_timeout),
+			transaction_hash,
+		};
+		Self {
+			transport,
+			request_timeout,
+			contract_address,
+			timer,
+			state,
+		}
+	}
+
+	fn code_at(&self, block: u64) -> Request<T, Bytes> {
+		let future = web3::api::Eth::new(&self.transport)
+			.code(self.contract_address, Some(BlockNumber::from(block)));
+		self.timer.timeout(future.from_err(), self.request_timeout)
+	}
+
+	fn await_code(&self, block: u64, from: u64, to: Option<u64>) -> FindContractCreationState<T> {
+		FindContractCreationState::AwaitCode {
+			future: self.code_at(block),
+			block,
+			from,
+			to,
+		}
+	}
+
+	fn block_transactions(&self, block: u64) -> Request<T, Option<BlockTransactions>> {
+		let future = CallFuture::new(self.transport.execute(
+			"eth_getBlockByNumber",
+			vec![
+				web3_helpers::serialize(&BlockNumber::from(block)),
+				web3_helpers::serialize(&true),
+			],
+		));
+		self.timer.timeout(future.from_err(), self.request_timeout)
+	}
+
+	/// checks the receipt of the next remaining contract creation transaction
+	fn next_receipt(
+		&self,
+		mut remaining: Vec<BlockTransaction>,
+		block: Option<u64>,
+	) -> error::Result<FindContractCreationState<T>> {
+		if remaining.is_empty() {
+			match block {
+				Some(block) => bail!(
+					"contract {:?} was not created by a transaction in block {}. contracts created by other contracts than a CREATE3 proxy are not supported",
+					self.contract_address,
+					block
+				),
+				None => bail!(
+					"contract {:?} was not created by the given transaction",
+					self.contract_address
+				),
+			}
+		}
+		let transaction = remaining.remove(0);
+		let future = web3::api::Eth::new(&self.transport).transaction_receipt(transaction.hash);
+		Ok(FindContractCreationState::AwaitReceipt {
+			future: self.timer.timeout(future.from_err(), self.request_timeout),
+			transaction,
+			remaining,
+			block,
+		})
+	}
+}
+
+impl<T: Transport> Future for FindContractCreation<T> {
+	type Item = ContractCreation;
+	type Error = error::Error;
+
+	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+		loop {
+			let next_state = match self.state {
+				FindContractCreationState::AwaitLatestBlock(ref mut future) => {
+					let latest = try_ready!(future.poll().chain_err(|| {
+						"FindContractCreation: fetching last block number failed"
+					}))
+					.as_u64();
+					self.await_code(latest, 0, None)
+				}
+				FindContractCreationState::AwaitCode {
+					ref mut future,
+					block,
+					from,
+					to,
+				} => {
+					let code = try_ready!(future.poll().chain_err(|| format!(
+						"FindContractCreation: fetching code at block {} failed",
+						block
+					)));
+					let (from, to) = match (code.0.is_empty(), to) {
+						// the first check is at the latest block
+						(true, None) => {
+							bail!("there is no contract at {:?}", self.contract_address)
+						}
+						(true, Some(to)) => (block + 1, to),
+						(false, _) => (from, block),
+					};
+					if from < to {
+						self.await_code(from + (to - from) / 2, from, Some(to))
+					} else {
+						info!(
+							"contract {:?} was created in block {}",
+							self.contract_address, to
+						);
+						FindContractCreationState::AwaitBlock {
+							future: self.block_transactions(to),
+							block: to,
+						}
+					}
+				}
+				FindContractCreationState::AwaitBlock {
+					ref mut future,
+					block,
+				} => {
+					let block_transactions = try_ready!(future.poll().chain_err(|| format!(
+						"FindContractCreation: fetching block {} failed",
+						block
+					)))
+					.ok_or_else(|| format!("FindContractCreation: block {} not found", block))?;
+					let contract_address = self.contract_address;
+					// contract creation transactions and calls of a CREATE3 proxy
+					// whose first contract is at `contract_address`
+					let creations = block_transactions
+						.transactions
+						.into_iter()
+						.filter(|transaction| {
+							transaction.to.map_or(true, |to| {
+								helpers::contract_address(to, 1.into()) == contract_address
+							})
+						})
+						.collect();
+					self.next_receipt(creations, Some(block))?
+				}
+				FindContractCreationState::AwaitTransaction {
+					ref mut future,
+					transaction_hash,
+				} => {
+					let transaction = try_ready!(future.poll().chain_err(|| format!(
+						"FindContractCreation: fetching transaction {:?} failed",
+						transaction_hash
+					)))
+					.ok_or_else(|| {
+						format!(
+							"FindContractCreation: transaction {:?} not found",
+							transaction_hash
+						)
+					})?;
+					self.next_receipt(vec![transaction], None)?
+				}
+				FindContractCreationState::AwaitReceipt {
+					ref mut future,
+					ref transaction,
+					ref mut remaining,
+					block,
+				} => {
+					let maybe_receipt = try_ready!(future.poll().chain_err(|| format!(
+						"FindContractCreation: fetching receipt of transaction {:?} failed",
+						transaction.hash
+					)));
+					let contract_address = self.contract_address;
+					if let Some(creation) = maybe_receipt.and_then(|receipt| {
+						ContractCreation::from_receipt(contract_address, transaction, receipt)
+					}) {
+						return Ok(Async::Ready(creation));
+					}
+					let remaining = mem::replace(remaining, Vec::new());
+					self.next_receipt(remaining, block)?
+				}
+			};
+
+			self.state = next_state;
+		}
+	}
+}
+
+/// the information `DeployedContract::dump_info` wrote about a deployment.
+/// used to independently verify a deployment
+#[derive(Debug, PartialEq, Clone)]
+pub struct DeploymentInfo {
+	pub contract_name: String,
+	pub contract_address: Address,
+	/// `None` for dry runs
+	pub transaction_hash: Option<H256>,
+	/// salt if the contract was deployed through the CREATE3 proxy
+	pub create2_salt: Option<H256>,
+	pub bytecode: Vec<u8>,
+	pub constructor_arguments: Vec<u8>,
+}
+
+fn read_info_file(dir: &Path, name: &str) -> error::Result<String> {
+	let path = dir.join(name);
+	let contents = fs::read_to_string(&path).chain_err(|| format!("Cannot read {:?}", path))?;
+	Ok(contents.trim().to_string())
+}
+
+impl DeploymentInfo {
+	/// reads a directory written by `DeployedContract::dump_info`
+	pub fn read<P: AsRef<Path>>(dir: P) -> error::Result<Self> {
+		let dir = dir.as_ref();
+		let contract_address = read_info_file(dir, "contract_address")?;
+		let transaction_hash = if dir.join("transaction_hash").exists() {
+			let transaction_hash = read_info_file(dir, "transaction_hash")?;
+			Some(
+				transaction_hash
+					.trim_start_matches("0x")
+					.parse()
+					.map_err(|_| format!("invalid transaction hash {}", transaction_hash))?,
+			)
+		} else {
+			None
+		};
+		let create2_salt = if dir.join("create2_salt").exists() {
+			let salt = read_info_file(dir, "create2_salt")?;
+			Some(
+				salt.trim_start_matches("0x")
+					.parse()
+					.map_err(|_| format!("invalid CREATE2 salt {}", salt))?,
+			)
+		} else {
+			None
+		};
+
+		Ok(Self {
+			contract_name: read_info_file(dir, "contract_name")?,
+			contract_address: contract_address
+				.trim_start_matches("0x")
+				.parse()
+				.map_err(|_| format!("invalid contract address {}", contract_address))?,
+			transaction_hash,
+			create2_salt,
+			bytecode: read_info_file(dir, "deployed_bytecode")?
+				.from_hex()
+				.chain_err(|| "Cannot parse deployed_bytecode")?,
+			constructor_arguments: read_info_file(dir, "constructor_arguments_bytecode")?
+				.from_hex()
+				.chain_err(|| "Cannot parse constructor_arguments_bytecode")?,
+		})
+	}
+
+	/// bytecode followed by the constructor arguments
+	pub fn creation_code(&self) -> Vec<u8> {
+		let mut creation_code = self.bytecode.clone();
+		creation_code.extend_from_slice(&self.constructor_arguments);
+		creation_code
+	}
+
+	/// differences between this information and the creation transaction
+	/// and runtime code fetched from a node
+	pub fn verify(&self, creation: &ContractCreation, runtime_code: &[u8]) -> Vec<String> {
+		let creation_code = self.creation_code();
+		let mut problems = Vec::new();
+		if let Err(err) =
+			creation.check_creation_code(&self.contract_name, &self.bytecode, &creation_code)
+		{
+			problems.push(err.to_string());
+		}
+		let expected_proxy = self.create2_salt.map(create3_proxy_address);
+		if creation.proxy != expected_proxy {
+			problems.push(format!(
+				"{} contract was created through proxy {:?} instead of {:?} (the CREATE3 proxy for salt {:?})",
+				self.contract_name, creation.proxy, expected_proxy, self.create2_salt
+			));
+		}
+		if runtime_code.is_empty() {
+			problems.push(format!("there is no code at {:?}", self.contract_address));
+		} else if !helpers::is_runtime_code_of(runtime_code, &creation_code) {
+			problems.push(format!(
+				"runtime code at {:?} (keccak256 {:?}) was not deployed from the published bytecode",
+				self.contract_address,
+				keccak256(runtime_code)
+			));
+		}
+		problems
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tempfile;
+	use tokio_core::reactor::Core;
+
+	#[test]
+	fn test_deploy_progress_file_roundtrips() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("bridge.deploy-main.json");
+
+		let mut progress_file = DeployProgressFile::open(&path).unwrap();
+		assert_eq!(progress_file.progress(), &DeployProgress::default());
+
+		let data = vec![0x60, 0x80];
+		let transaction_hash = H256::from_low_u64_be(1);
+		progress_file
+			.record_transaction_hash(&data, None, transaction_hash)
+			.unwrap();
+
+		let mut progress_file = DeployProgressFile::open(&path).unwrap();
+		assert_eq!(
+			progress_file.progress(),
+			&DeployProgress {
+				creation_code_hash: Some(keccak256(&data)),
+				create2_salt: None,
+				transaction_hash: Some(transaction_hash),
+				receipt: None,
+			}
+		);
+		progress_file.check_creation_code(&data).unwrap();
+		assert!(progress_file.check_creation_code(&[0x60]).is_err());
+		progress_file.check_create2_salt(None).unwrap();
+		assert!(progress_file
+			.check_create2_salt(Some(H256::from_low_u64_be(1)))
+			.is_err());
+
+		let receipt = TransactionReceipt {
+			transaction_hash,
+			transaction_index: 0.into(),
+			block_hash: Some(H256::from_low_u64_be(2)),
+			block_number: Some(10.into()),
+			cumulative_gas_used: 21000.into(),
+			gas_used: Some(21000.into()),
+			contract_address: Some("b1ac3a5584519119419a8e56422d912c782d8e5b".parse().unwrap()),
+			logs: vec![],
+			status: Some(1.into()),
+			logs_bloom: Default::default(),
+		};
+		progress_file.record_receipt(&receipt).unwrap();
+
+		let progress_file = DeployProgressFile::open(&path).unwrap();
+		assert_eq!(progress_file.progress().receipt, Some(receipt));
+		assert!(!path.with_extension("tmp").exists());
+	}
+
+	#[test]
+	fn test_dry_run_deploy() {
+		let transport = mock_transport!(
+			"eth_feeHistory" =>
+				req => json!(["0x5", "latest", [50.0]]),
+				res => json!({
+					"oldestBlock": "0x1000",
+					"baseFeePerGas": ["0x0", "0x0", "0x0", "0x0", "0x0", "0x0"],
+				});
+			"eth_estimateGas" =>
+				req => json!([{
+					"data": "0x6001",
+					"from": "0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0",
+					"gas": "0x186a0",
+					"gasPrice": "0x0"
+				}]),
+				res => json!("0x1d4c0");
+			"eth_getBalance" =>
+				req => json!(["0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0", "latest"]),
+				res => json!("0x100000");
+			"eth_getTransactionCount" =>
+				req => json!(["0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0", "pending"]),
+				res => json!("0x2");
+			"eth_getTransactionCount" =>
+				req => json!(["0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0", "latest"]),
+				res => json!("0x1");
+		);
+
+		let dry_run_deploy = DryRunDeploy::new(DryRunDeployOptions {
+			transport: transport.clone(),
+			request_timeout: Duration::from_secs(1),
+			contract_name: "Main".into(),
+			abi: "[]".into(),
+			bytecode_hex: "60".into(),
+			transaction: Transaction {
+				from: "6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0".parse().unwrap(),
+				to: None,
+				gas: 100000.into(),
+				fees: Fees::Legacy {
+					gas_price: 0.into(),
+				},
+				data: vec![0x60, 0x01].into(),
+			},
+			transaction_config: TransactionConfig {
+				gas: 100000.into(),
+				gas_price: 20.into(),
+				max_fee_per_gas: None,
+				max_priority_fee_per_gas: None,
+			},
+			create2_salt: None,
+		});
+
+		let mut event_loop = Core::new().unwrap();
+		let dry_run = event_loop.run(dry_run_deploy).unwrap();
+		assert_eq!(transport.actual_requests(), transport.expected_requests());
+
+		assert_eq!(
+			dry_run.transaction.fees,
+			Fees::Legacy {
+				gas_price: 20.into()
+			}
+		);
+		assert_eq!(dry_run.estimated_gas, 120000.into());
+		assert_eq!(dry_run.nonce, 2.into());
+		assert_eq!(
+			dry_run.contract.contract_address,
+			"f778b86fa74e846c4f0a1fbd1335fe81c00a0c91"
+		);
+		assert_eq!(dry_run.contract.contract_creation_code_hex, "6001");
+		assert_eq!(dry_run.contract.receipt, None);
+		assert_eq!(
+			dry_run.problems(),
+			vec![
+				"Main deployment is estimated to use 120000 gas but only 100000 gas is configured".to_string(),
+				"Main deployment can cost up to 2000000 wei but 0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0 only has 1048576 wei".to_string(),
+				"0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0 has 1 pending transactions. the Main contract only gets deployed to the predicted address if they all get mined first".to_string(),
+			]
+		);
+
+		let dir = tempfile::tempdir().unwrap();
+		let dump_dir = dir.path().join("deployment-main");
+		dry_run.contract.dump_info(&dump_dir).unwrap();
+		assert_eq!(
+			fs::read_to_string(dump_dir.join("contract_address")).unwrap(),
+			"f778b86fa74e846c4f0a1fbd1335fe81c00a0c91"
+		);
+		assert!(!dump_dir.join("transaction_hash").exists());
+	}
+
+	#[test]
+	fn test_find_contract_creation() {
+		let transport = mock_transport!(
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x4");
+			"eth_getCode" =>
+				req => json!(["0xb1ac3a5584519119419a8e56422d912c782d8e5b", "0x4"]),
+				res => json!("0x60");
+			"eth_getCode" =>
+				req => json!(["0xb1ac3a5584519119419a8e56422d912c782d8e5b", "0x2"]),
+				res => json!("0x");
+			"eth_getCode" =>
+				req => json!(["0xb1ac3a5584519119419a8e56422d912c782d8e5b", "0x3"]),
+				res => json!("0x60");
+			"eth_getBlockByNumber" =>
+				req => json!(["0x3", true]),
+				res => json!({
+					"number": "0x3",
+					"transactions": [
+						{"hash": "0x1111111111111111111111111111111111111111111111111111111111111111", "to": "0x0000000000000000000000000000000000000001", "input": "0x"},
+						{"hash": "0x2222222222222222222222222222222222222222222222222222222222222222", "to": null, "input": "0x6002"},
+						{"hash": "0x3333333333333333333333333333333333333333333333333333333333333333", "to": null, "input": "0x6001"},
+					],
+				});
+			"eth_getTransactionReceipt" =>
+				req => json!(["0x2222222222222222222222222222222222222222222222222222222222222222"]),
+				res => json!({
+					"blockHash": "0xe0bdcf35b14a292d2998308d9b3fdea93a8c3d9c0b6c824c633fb9b15f9c3919",
+					"blockNumber": "0x3",
+					"contractAddress": "0x0000000000000000000000000000000000000002",
+					"cumulativeGasUsed": "0x1c1999",
+					"gasUsed": "0xcdb5d",
+					"logs": [],
+					"logsBloom": "0x00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000",
+					"root": null,
+					"status": "0x1",
+					"transactionHash": "0x2222222222222222222222222222222222222222222222222222222222222222",
+					"transactionIndex": "0x1"
+				});
+			"eth_getTransactionReceipt" =>
+				req => json!(["0x3333333333333333333333333333333333333333333333333333333333333333"]),
+				res => json!({
+					"blockHash": "0xe0bdcf35b14a292d2998308d9b3fdea93a8c3d9c0b6c824c633fb9b15f9c3919",
+					"blockNumber": "0x3",
+					"contractAddress": "0xb1ac3a5584519119419a8e56422d912c782d8e5b",
+					"cumulativeGasUsed": "0x1c1999",
+					"gasUsed": "0xcdb5d",
+					"logs": [],
+					"logsBloom": "0x00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000",
+					"root": null,
+					"status": "0x1",
+					"transactionHash": "0x3333333333333333333333333333333333333333333333333333333333333333",
+					"transactionIndex": "0x1"
+				});
+		);
+
+		let find_contract_creation = FindContractCreation::new(
+			transport.clone(),
+			Duration::from_secs(1),
+			"b1ac3a5584519119419a8e56422d912c782d8e5b".parse().unwrap(),
+		);
+
+		let mut event_loop = Core::new().unwrap();
+		let creation = event_loop.run(find_contract_creation).unwrap();
+		assert_eq!(transport.actual_requests(), transport.expected_requests());
+
+		assert_eq!(creation.creation_code, vec![0x60, 0x01].into());
+		assert_eq!(creation.receipt.transaction_hash, H256::repeat_byte(0x33));
+		assert_eq!(creation.receipt.block_number, Some(3.into()));
+
+		creation
+			.check_creation_code("main", &[0x60], &[0x60, 0x01])
+			.unwrap();
+		assert!(creation
+			.check_creation_code("main", &[0x60], &[0x60, 0x02])
+			.unwrap_err()
+			.to_string()
+			.contains("different constructor arguments"));
+		assert!(creation
+			.check_creation_code("main", &[0x61], &[0x61, 0x01])
+			.unwrap_err()
+			.to_string()
+			.contains("different bytecode"));
+	}
+
+	#[test]
+	fn test_find_contract_creation_without_contract() {
+		let transport = mock_transport!(
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x4");
+			"eth_getCode" =>
+				req => json!(["0xb1ac3a5584519119419a8e56422d912c782d8e5b", "0x4"]),
+				res => json!("0x");
+		);
+
+		let find_contract_creation = FindContractCreation::new(
+			transport.clone(),
+			Duration::from_secs(1),
+			"b1ac3a5584519119419a8e56422d912c782d8e5b".parse().unwrap(),
+		);
+
+		let mut event_loop = Core::new().unwrap();
+		assert!(event_loop.run(find_contract_creation).is_err());
+		assert_eq!(transport.actual_requests(), transport.expected_requests());
+	}
+
+	#[test]
+	fn test_find_contract_creation_from_transaction() {
+		let transport = mock_transport!(
+			"eth_getTransactionByHash" =>
+				req => json!(["0x3333333333333333333333333333333333333333333333333333333333333333"]),
+				res => json!({"hash": "0x3333333333333333333333333333333333333333333333333333333333333333", "to": null, "input": "0x6001"});
+			"eth_getTransactionReceipt" =>
+				req => json!(["0x3333333333333333333333333333333333333333333333333333333333333333"]),
+				res => json!({
+					"blockHash": "0xe0bdcf35b14a292d2998308d9b3fdea93a8c3d9c0b6c824c633fb9b15f9c3919",
+					"blockNumber": "0x3",
+					"contractAddress": "0xb1ac3a5584519119419a8e56422d912c782d8e5b",
+					"cumulativeGasUsed": "0x1c1999",
+					"gasUsed": "0xcdb5d",
+					"logs": [],
+					"logsBloom": "0x00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000",
+					"root": null,
+					"status": "0x1",
+					"transactionHash": "0x3333333333333333333333333333333333333333333333333333333333333333",
+					"transactionIndex": "0x1"
+				});
+		);
+
+		let find_contract_creation = FindContractCreation::from_transaction(
+			transport.clone(),
+			Duration::from_secs(1),
+			"b1ac3a5584519119419a8e56422d912c782d8e5b".parse().unwrap(),
+			H256::repeat_byte(0x33),
+		);
+
+		let mut event_loop = Core::new().unwrap();
+		let creation = event_loop.run(find_contract_creation).unwrap();
+		assert_eq!(transport.actual_requests(), transport.expected_requests());
+		assert_eq!(creation.creation_code, vec![0x60, 0x01].into());
+		assert_eq!(creation.receipt.block_number, Some(3.into()));
+	}
+
+	#[test]
+	fn test_deployment_info_roundtrips_and_verifies() {
+		let receipt = TransactionReceipt {
+			transaction_hash: H256::repeat_byte(0x33),
+			transaction_index: 0.into(),
+			block_hash: Some(H256::repeat_byte(0x44)),
+			block_number: Some(3.into()),
+			cumulative_gas_used: 21000.into(),
+			gas_used: Some(21000.into()),
+			contract_address: Some("b1ac3a5584519119419a8e56422d912c782d8e5b".parse().unwrap()),
+			logs: vec![],
+			status: Some(1.into()),
+			logs_bloom: Default::default(),
+		};
+		// runtime code `0x6001` is part of the bytecode
+		let contract = DeployedContract::new(
+			"Main".into(),
+			"contract Main {}".into(),
+			"[]".into(),
+			"6080600160ff".into(),
+			"6080600160ff0a0b".into(),
+			receipt.clone(),
+		);
+		let dir = tempfile::tempdir().unwrap();
+		let dump_dir = dir.path().join("deployment-main");
+		contract.dump_info(&dump_dir).unwrap();
+
+		let info = DeploymentInfo::read(&dump_dir).unwrap();
+		assert_eq!(
+			info,
+			DeploymentInfo {
+				contract_name: "Main".into(),
+				contract_address: "b1ac3a5584519119419a8e56422d912c782d8e5b".parse().unwrap(),
+				transaction_hash: Some(H256::repeat_byte(0x33)),
+				create2_salt: None,
+				bytecode: vec![0x60, 0x80, 0x60, 0x01, 0x60, 0xff],
+				constructor_arguments: vec![0x0a, 0x0b],
+			}
+		);
+
+		let creation = ContractCreation {
+			creation_code: info.creation_code().into(),
+			proxy: None,
+			receipt: receipt.clone(),
+		};
+		assert!(info.verify(&creation, &[0x60, 0x01]).is_empty());
+		assert_eq!(info.verify(&creation, &[0x60, 0x02]).len(), 1);
+		assert_eq!(info.verify(&creation, &[]).len(), 1);
+
+		let other_authorities = ContractCreation {
+			creation_code: vec![0x60, 0x80, 0x60, 0x01, 0x60, 0xff, 0x0c].into(),
+			proxy: None,
+			receipt,
+		};
+		let problems = info.verify(&other_authorities, &[0x60, 0x01]);
+		assert_eq!(problems.len(), 1);
+		assert!(problems[0].contains("different constructor arguments"));
+	}
+
+	fn receipt_json(transaction_hash: &str, block_number: &str) -> serde_json::Value {
+		json!({
+			"blockHash": "0xe0bdcf35b14a292d2998308d9b3fdea93a8c3d9c0b6c824c633fb9b15f9c3919",
+			"blockNumber": block_number,
+			"contractAddress": null,
+			"cumulativeGasUsed": "0x1c1999",
+			"gasUsed": "0xcdb5d",
+			"logs": [],
+			"logsBloom": format!("0x{}", "0".repeat(512)),
+			"root": null,
+			"status": "0x1",
+			"transactionHash": transaction_hash,
+			"transactionIndex": "0x0"
+		})
+	}
+
+	fn transaction_config() -> TransactionConfig {
+		TransactionConfig {
+			gas: 100000.into(),
+			gas_price: 20.into(),
+			max_fee_per_gas: None,
+			max_priority_fee_per_gas: None,
+		}
+	}
+
+	#[test]
+	fn test_create3_address_only_depends_on_salt() {
+		let salt = H256::from_low_u64_be(1);
+		assert_eq!(
+			create3_proxy_address(salt),
+			"da6aaece7c33947f0cbb0539cc39d4f3417c1fa0".parse().unwrap()
+		);
+		assert_eq!(
+			create3_address(salt),
+			"c806b39d2f50be40f61da6b5e71bdf8c735c369f".parse().unwrap()
+		);
+		assert_ne!(
+			create3_address(H256::from_low_u64_be(2)),
+			create3_address(salt)
+		);
+	}
+
+	#[test]
+	fn test_prepare_create3_deploy_deploys_missing_factory_and_proxy() {
+		let transport = mock_transport!(
+			"eth_getCode" =>
+				req => json!(["0x4e59b44847b379578588920ca78fbf26c0b4956c", "latest"]),
+				res => json!("0x");
+			"eth_getTransactionCount" =>
+				req => json!(["0xda6aaece7c33947f0cbb0539cc39d4f3417c1fa0", "latest"]),
+				res => json!("0x0");
+			"eth_getCode" =>
+				req => json!(["0xc806b39d2f50be40f61da6b5e71bdf8c735c369f", "latest"]),
+				res => json!("0x");
+			"eth_getBalance" =>
+				req => json!(["0x3fab184622dc19b6109349b94811493bf2a45362", "latest"]),
+				res => json!("0x2386f26fc10000");
+			"eth_sendRawTransaction" =>
+				req => json!([format!("0x{}", CREATE2_FACTORY_DEPLOY_TRANSACTION)]),
+				res => json!("0x1111111111111111111111111111111111111111111111111111111111111111");
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x1017");
+			"eth_getTransactionReceipt" =>
+				req => json!(["0x1111111111111111111111111111111111111111111111111111111111111111"]),
+				res => receipt_json("0x1111111111111111111111111111111111111111111111111111111111111111", "0x1015");
+			"eth_feeHistory" =>
+				req => json!(["0x5", "latest", [50.0]]),
+				res => json!({
+					"oldestBlock": "0x1000",
+					"baseFeePerGas": ["0x0", "0x0", "0x0", "0x0", "0x0", "0x0"],
+				});
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x1017");
+			"eth_sendTransaction" =>
+				req => json!([{
+					"data": "0x000000000000000000000000000000000000000000000000000000000000000167363d3d37363d34f03d5260086018f3",
+					"from": "0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0",
+					"gas": "0x186a0",
+					"gasPrice": "0x14",
+					"to": "0x4e59b44847b379578588920ca78fbf26c0b4956c"
+				}]),
+				res => json!("0x2222222222222222222222222222222222222222222222222222222222222222");
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x101a");
+			"eth_getTransactionReceipt" =>
+				req => json!(["0x2222222222222222222222222222222222222222222222222222222222222222"]),
+				res => receipt_json("0x2222222222222222222222222222222222222222222222222222222222222222", "0x1018");
+		);
+
+		let prepare = PrepareCreate3Deploy::new(PrepareCreate3DeployOptions {
+			transport: transport.clone(),
+			request_timeout: Duration::from_secs(1),
+			poll_interval: Duration::from_secs(0),
+			confirmations: 2,
+			from: "6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0".parse().unwrap(),
+			transaction_config: transaction_config(),
+			contract_name: "Main".into(),
+			create2_salt: H256::from_low_u64_be(1),
+		});
+
+		let mut event_loop = Core::new().unwrap();
+		event_loop.run(prepare).unwrap();
+		assert_eq!(transport.actual_requests(), transport.expected_requests());
+	}
+
+	fn prepare_create3_deploy_error(proxy_nonce: &str, contract_code: &str) -> String {
+		let transport = mock_transport!(
+			"eth_getCode" =>
+				req => json!(["0x4e59b44847b379578588920ca78fbf26c0b4956c", "latest"]),
+				res => json!("0x60");
+			"eth_getTransactionCount" =>
+				req => json!(["0xda6aaece7c33947f0cbb0539cc39d4f3417c1fa0", "latest"]),
+				res => json!(proxy_nonce);
+			"eth_getCode" =>
+				req => json!(["0xc806b39d2f50be40f61da6b5e71bdf8c735c369f", "latest"]),
+				res => json!(contract_code);
+		);
+
+		let prepare = PrepareCreate3Deploy::new(PrepareCreate3DeployOptions {
+			transport: transport.clone(),
+			request_timeout: Duration::from_secs(1),
+			poll_interval: Duration::from_secs(0),
+			confirmations: 2,
+			from: "6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0".parse().unwrap(),
+			transaction_config: transaction_config(),
+			contract_name: "Main".into(),
+			create2_salt: H256::from_low_u64_be(1),
+		});
+
+		let mut event_loop = Core::new().unwrap();
+		let err = event_loop.run(prepare).unwrap_err();
+		assert_eq!(transport.actual_requests(), transport.expected_requests());
+		err.to_string()
+	}
+
+	#[test]
+	fn test_prepare_create3_deploy_fails_if_contract_exists() {
+		assert!(
+			prepare_create3_deploy_error("0x2", "0x6001").contains("there already is a contract")
+		);
+	}
+
+	#[test]
+	fn test_prepare_create3_deploy_fails_if_proxy_was_used() {
+		assert!(prepare_create3_deploy_error("0x2", "0x").contains("already created a contract"));
+	}
+
+	#[test]
+	fn test_deploy_contract_checks_code_created_by_proxy() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("bridge.deploy-main.json");
+		let salt = H256::from_low_u64_be(1);
+		let transaction_hash = H256::repeat_byte(0x33);
+		DeployProgressFile::open(&path)
+			.unwrap()
+			.record_transaction_hash(&[0x60, 0x01], Some(salt), transaction_hash)
+			.unwrap();
+
+		let deploy_contract = |code: &str| {
+			let transport = mock_transport!(
+				"eth_blockNumber" =>
+					req => json!([]),
+					res => json!("0x1017");
+				"eth_getTransactionReceipt" =>
+					req => json!(["0x3333333333333333333333333333333333333333333333333333333333333333"]),
+					res => receipt_json("0x3333333333333333333333333333333333333333333333333333333333333333", "0x1015");
+				"eth_getCode" =>
+					req => json!(["0xc806b39d2f50be40f61da6b5e71bdf8c735c369f", "latest"]),
+					res => json!(code);
+			);
+			let deploy = DeployContract::new(
+				DeployContractOptions {
+					transport: transport.clone(),
+					request_timeout: Duration::from_secs(1),
+					poll_interval: Duration::from_secs(0),
+					confirmations: 2,
+					from: "6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0".parse().unwrap(),
+					transaction_config: transaction_config(),
+					contract_name: "Main".into(),
+					abi: "[]".into(),
+					bytecode: vec![0x60],
+					creation_code: vec![0x60, 0x01],
+				},
+				DeployProgressFile::open(&path).unwrap(),
+			)
+			.with_create2_salt(salt);
+			assert_eq!(
+				deploy.create3_address(),
+				Some("c806b39d2f50be40f61da6b5e71bdf8c735c369f".parse().unwrap())
+			);
+			let result = Core::new().unwrap().run(deploy);
+			assert_eq!(transport.actual_requests(), transport.expected_requests());
+			result
+		};
+
+		// the proxy doesn't revert if it can't create the contract
+		let err = deploy_contract("0x").err().unwrap();
+		assert!(err.to_string().contains("didn't create the Main contract"));
+		assert_eq!(
+			DeployProgressFile::open(&path).unwrap().progress().receipt,
+			None
+		);
+
+		// someone else called the proxy first
+		let err = deploy_contract("0x6002").err().unwrap();
+		assert!(err
+			.to_string()
+			.contains("someone else used the CREATE3 proxy"));
+
+		let contract = deploy_contract("0x6001").unwrap();
+		assert_eq!(
+			contract.contract_address,
+			"c806b39d2f50be40f61da6b5e71bdf8c735c369f"
+		);
+		assert_eq!(contract.create2_salt, Some(salt));
+		assert!(DeployProgressFile::open(&path)
+			.unwrap()
+			.progress()
+			.receipt
+			.is_some());
+	}
+
+	#[test]
+	fn test_find_contract_creation_by_create3_proxy() {
+		let transport = mock_transport!(
+			"eth_getTransactionByHash" =>
+				req => json!(["0x3333333333333333333333333333333333333333333333333333333333333333"]),
+				res => json!({
+					"hash": "0x3333333333333333333333333333333333333333333333333333333333333333",
+					"to": "0xda6aaece7c33947f0cbb0539cc39d4f3417c1fa0",
+					"input": "0x6001"
+				});
+			"eth_getTransactionReceipt" =>
+				req => json!(["0x3333333333333333333333333333333333333333333333333333333333333333"]),
+				res => receipt_json("0x3333333333333333333333333333333333333333333333333333333333333333", "0x3");
+		);
+
+		let find_contract_creation = FindContractCreation::from_transaction(
+			transport.clone(),
+			Duration::from_secs(1),
+			"c806b39d2f50be40f61da6b5e71bdf8c735c369f".parse().unwrap(),
+			H256::repeat_byte(0x33),
+		);
+
+		let mut event_loop = Core::new().unwrap();
+		let creation = event_loop.run(find_contract_creation).unwrap();
+		assert_eq!(transport.actual_requests(), transport.expected_requests());
+		assert_eq!(creation.creation_code, vec![0x60, 0x01].into());
+		assert_eq!(
+			creation.proxy,
+			Some(create3_proxy_address(H256::from_low_u64_be(1)))
+		);
+
+		let contract = DeployedContract::created_by_proxy(
+			"Main".into(),
+			"contract Main {}".into(),
+			"[]".into(),
+			"60".into(),
+			"6001".into(),
+			H256::from_low_u64_be(1),
+			Some(creation.receipt.clone()),
+		);
+		assert_eq!(
+			contract.contract_address,
+			"c806b39d2f50be40f61da6b5e71bdf8c735c369f"
+		);
+		let dir = tempfile::tempdir().unwrap();
+		let dump_dir = dir.path().join("deployment-main");
+		contract.dump_info(&dump_dir).unwrap();
+		assert_eq!(
+			fs::read_to_string(dump_dir.join("create3_proxy")).unwrap(),
+			"da6aaece7c33947f0cbb0539cc39d4f3417c1fa0"
+		);
+
+		let info = DeploymentInfo::read(&dump_dir).unwrap();
+		assert_eq!(info.create2_salt, Some(H256::from_low_u64_be(1)));
+		assert!(info.verify(&creation, &[0x60, 0x01]).is_empty());
+
+		let without_proxy = ContractCreation {
+			proxy: None,
+			..creation
+		};
+		assert_eq!(info.verify(&without_proxy, &[0x60, 0x01]).len(), 1);
+	}
+}
@@ -18,6 +18,7 @@
 
 use std::io;
 use tokio_timer::{TimeoutError, TimerError};
+use web3::types::U256;
 use {ethabi, rustc_hex, toml, web3};
 
 error_chain! {
@@ -49,6 +50,14 @@ error_chain! {
 			description("web3 error"),
 			display("{:?}", err),
 		}
+		TransactionWouldRevert(reason: String) {
+			description("Transaction would revert"),
+			display("Gas estimation says the transaction would revert: {}", reason),
+		}
+		GasEstimateExceedsCap(estimate: U256, cap: U256) {
+			description("Gas estimate exceeds configured gas"),
+			display("Gas estimate {} exceeds configured gas {}. increase the configured gas if this is expected", estimate, cap),
+		}
 	}
 }
 
@@ -0,0 +1,608 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity-Bridge.
+
+// Parity-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! injection of failures into JSON-RPC exchanges.
+//!
+//! `FaultInjectingTransport` wraps a transport and randomly
+//! lets requests time out, fails them with JSON-RPC errors,
+//! drops transactions, answers requests out of order,
+//! answers with block numbers lower than ones seen before
+//! and moves logs of recent blocks into later blocks like a reorg would.
+//! faults are drawn from a PRNG seeded by `FaultInjectionOptions.seed`
+//! so a run can be repeated.
+
+use futures::task::{self, Task};
+use futures::{Async, Future, Poll};
+use helpers::keccak256;
+use jsonrpc_core::{self, Call, Params, Value};
+use serde_json;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use web3::{self, Transport};
+
+/// probabilities (between 0 and 1) of the faults per request
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultInjectionOptions {
+	pub seed: u64,
+	/// request is forwarded but never answered
+	pub timeout: f64,
+	/// request isn't forwarded and fails with a JSON-RPC error
+	pub rpc_error: f64,
+	/// `eth_sendTransaction` isn't forwarded but answered with a transaction hash
+	pub dropped_transaction: f64,
+	/// response is held back until `reorder_distance` later requests have been sent
+	pub reordered_response: f64,
+	pub reorder_distance: u64,
+	/// `eth_blockNumber` is answered with a block up to `max_block_regression`
+	/// below the highest block returned so far
+	pub block_number_regression: f64,
+	pub max_block_regression: u64,
+	/// `eth_getLogs` moves the logs of the last `reorg_depth` blocks
+	/// into blocks that aren't mined yet. like a reorg to a fork
+	/// that includes their transactions later
+	pub reorg: f64,
+	pub reorg_depth: u64,
+}
+
+impl Default for FaultInjectionOptions {
+	fn default() -> Self {
+		Self {
+			seed: 1,
+			timeout: 0.0,
+			rpc_error: 0.0,
+			dropped_transaction: 0.0,
+			reordered_response: 0.0,
+			reorder_distance: 2,
+			block_number_regression: 0.0,
+			max_block_regression: 3,
+			reorg: 0.0,
+			reorg_depth: 2,
+		}
+	}
+}
+
+/// number of faults injected so far
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InjectedFaults {
+	pub timeouts: usize,
+	pub rpc_errors: usize,
+	pub dropped_transactions: usize,
+	pub reordered_responses: usize,
+	pub block_number_regressions: usize,
+	pub reorgs: usize,
+}
+
+impl InjectedFaults {
+	pub fn total(&self) -> usize {
+		self.timeouts
+			+ self.rpc_errors
+			+ self.dropped_transactions
+			+ self.reordered_responses
+			+ self.block_number_regressions
+			+ self.reorgs
+	}
+}
+
+#[derive(Debug)]
+struct Faults {
+	options: FaultInjectionOptions,
+	rng: u64,
+	/// number of requests sent so far
+	sent: u64,
+	highest_block: u64,
+	injected: InjectedFaults,
+	/// tasks of held back responses
+	held: Vec<Task>,
+	/// blocks that logs were moved to by reorgs, by `log_key`
+	reorged_logs: HashMap<String, u64>,
+}
+
+/// identifies a log across reorgs
+fn log_key(log: &Value) -> String {
+	format!("{}:{}", log["transactionHash"], log["logIndex"])
+}
+
+fn parse_block_number(value: &Value) -> Option<u64> {
+	serde_json::from_value::<web3::types::U64>(value.clone())
+		.ok()
+		.map(|block| block.as_u64())
+}
+
+fn block_number_value(block: u64) -> Value {
+	Value::String(format!("0x{:x}", block))
+}
+
+/// `eth_getLogs` requests are sent for all blocks when reorgs are injected
+/// so that logs moved into the requested blocks are found.
+/// returns the requested range of blocks
+fn widen_logs_request(request: &mut Call) -> Option<(u64, u64)> {
+	let filter = match *request {
+		Call::MethodCall(ref mut call) => match call.params {
+			Params::Array(ref mut params) if !params.is_empty() => &mut params[0],
+			_ => return None,
+		},
+		_ => return None,
+	};
+	let from = parse_block_number(&filter["fromBlock"])?;
+	let to = parse_block_number(&filter["toBlock"])?;
+	filter["fromBlock"] = block_number_value(0);
+	filter["toBlock"] = Value::String("latest".into());
+	Some((from, to))
+}
+
+impl Faults {
+	/// xorshift64
+	fn next_random(&mut self) -> u64 {
+		self.rng ^= self.rng << 13;
+		self.rng ^= self.rng >> 7;
+		self.rng ^= self.rng << 17;
+		self.rng
+	}
+
+	fn chance(&mut self, probability: f64) -> bool {
+		probability > 0.0 && (self.next_random() as f64 / u64::max_value() as f64) < probability
+	}
+
+	fn on_send(&mut self) {
+		self.sent += 1;
+		for task in self.held.drain(..) {
+			task.notify();
+		}
+	}
+
+	/// whether a response held back until request `release_at` can be returned
+	fn is_released(&mut self, release_at: u64) -> bool {
+		if self.sent >= release_at {
+			return true;
+		}
+		self.held.push(task::current());
+		false
+	}
+
+	fn alter_response(
+		&mut self,
+		method: &str,
+		logs_range: Option<(u64, u64)>,
+		mut value: Value,
+	) -> Value {
+		match method {
+			"eth_blockNumber" => {
+				let block = match parse_block_number(&value) {
+					Some(block) => block,
+					None => return value,
+				};
+				if block > self.highest_block {
+					self.highest_block = block;
+				}
+				let probability = self.options.block_number_regression;
+				if self.highest_block > 0 && self.chance(probability) {
+					let regression =
+						1 + self.next_random() % self.options.max_block_regression.max(1);
+					self.injected.block_number_regressions += 1;
+					return block_number_value(self.highest_block.saturating_sub(regression));
+				}
+				value
+			}
+			"eth_getLogs" => {
+				let (from, to) = match logs_range {
+					Some(range) => range,
+					None => return value,
+				};
+				let logs = match value {
+					Value::Array(logs) => logs,
+					value => return value,
+				};
+				let probability = self.options.reorg;
+				let reorg = self.chance(probability);
+				let last_final_block = self.highest_block.saturating_sub(self.options.reorg_depth);
+				let mut moved = false;
+				let mut logs: Vec<(u64, Value)> = logs
+					.into_iter()
+					.filter_map(|mut log| {
+						let key = log_key(&log);
+						let block = parse_block_number(&log["blockNumber"])?;
+						if reorg
+							&& block > last_final_block
+							&& !self.reorged_logs.contains_key(&key)
+						{
+							let new_block = self.highest_block
+								+ 1 + self.next_random()
+								% self.options.reorg_depth.max(1);
+							self.reorged_logs.insert(key.clone(), new_block);
+							moved = true;
+						}
+						let block = match self.reorged_logs.get(&key) {
+							None => block,
+							Some(&new_block) => {
+								log["blockNumber"] = block_number_value(new_block);
+								log["blockHash"] = serde_json::to_value(keccak256(
+									&format!("reorged block {}", new_block).into_bytes(),
+								))
+								.expect("H256 always serializes; qed");
+								new_block
+							}
+						};
+						Some((block, log))
+					})
+					.filter(|&(block, _)| from <= block && block <= to)
+					.collect();
+				if moved {
+					self.injected.reorgs += 1;
+				}
+				// stable, so logs of a block keep their order
+				logs.sort_by_key(|&(block, _)| block);
+				Value::Array(logs.into_iter().map(|(_, log)| log).collect())
+			}
+			_ => value,
+		}
+	}
+}
+
+/// a `Transport` that injects faults into the exchanges with the wrapped transport.
+/// `clone`d versions share the PRNG and the counts of injected faults
+#[derive(Debug, Clone)]
+pub struct FaultInjectingTransport<T> {
+	inner: T,
+	faults: Rc<RefCell<Faults>>,
+}
+
+impl<T: Transport> FaultInjectingTransport<T> {
+	pub fn new(inner: T, options: FaultInjectionOptions) -> Self {
+		Self {
+			inner,
+			faults: Rc::new(RefCell::new(Faults {
+				// xorshift never leaves 0
+				rng: options.seed.max(1),
+				options,
+				sent: 0,
+				highest_block: 0,
+				injected: InjectedFaults::default(),
+				held: Vec::new(),
+				reorged_logs: HashMap::new(),
+			})),
+		}
+	}
+
+	pub fn injected(&self) -> InjectedFaults {
+		self.faults.borrow().injected.clone()
+	}
+}
+
+fn injected_error() -> web3::Error {
+	web3::Error::Rpc(jsonrpc_core::Error {
+		code: jsonrpc_core::ErrorCode::ServerError(-32000),
+		message: "injected fault".into(),
+		data: None,
+	})
+}
+
+impl<T: Transport> Transport for FaultInjectingTransport<T> {
+	type Out = FaultInjectingFuture<T::Out>;
+
+	fn prepare(&self, method: &str, params: Vec<Value>) -> (usize, Call) {
+		self.inner.prepare(method, params)
+	}
+
+	fn send(&self, id: usize, mut request: Call) -> Self::Out {
+		let method = match request {
+			Call::MethodCall(ref call) => call.method.clone(),
+			_ => String::new(),
+		};
+
+		let mut faults = self.faults.borrow_mut();
+		faults.on_send();
+		let options = faults.options.clone();
+
+		let logs_range = if method == "eth_getLogs" && options.reorg > 0.0 {
+			widen_logs_request(&mut request)
+		} else {
+			None
+		};
+
+		let state = if method == "eth_sendTransaction" && faults.chance(options.dropped_transaction)
+		{
+			faults.injected.dropped_transactions += 1;
+			let random = faults.next_random();
+			let hash = keccak256(&format!("dropped transaction {}", random).into_bytes());
+			FutureState::Done(Some(Ok(
+				serde_json::to_value(hash).expect("H256 always serializes; qed")
+			)))
+		} else if faults.chance(options.rpc_error) {
+			faults.injected.rpc_errors += 1;
+			FutureState::Done(Some(Err(injected_error())))
+		} else if faults.chance(options.timeout) {
+			faults.injected.timeouts += 1;
+			// the request still reaches the node
+			let _ = self.inner.send(id, request);
+			FutureState::Unanswered
+		} else if faults.chance(options.reordered_response) {
+			faults.injected.reordered_responses += 1;
+			FutureState::Held {
+				future: self.inner.send(id, request),
+				release_at: faults.sent + options.reorder_distance,
+			}
+		} else {
+			FutureState::Forwarded(self.inner.send(id, request))
+		};
+
+		FaultInjectingFuture {
+			method,
+			logs_range,
+			state,
+			faults: self.faults.clone(),
+		}
+	}
+}
+
+enum FutureState<F> {
+	Forwarded(F),
+	Held { future: F, release_at: u64 },
+	Unanswered,
+	Done(Option<Result<Value, web3::Error>>),
+}
+
+/// `Future` returned by `FaultInjectingTransport`
+pub struct FaultInjectingFuture<F> {
+	method: String,
+	/// blocks requested by `eth_getLogs` if the request was widened
+	logs_range: Option<(u64, u64)>,
+	state: FutureState<F>,
+	faults: Rc<RefCell<Faults>>,
+}
+
+impl<F: Future<Item = Value, Error = web3::Error>> Future for FaultInjectingFuture<F> {
+	type Item = Value;
+	type Error = web3::Error;
+
+	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+		let value = match self.state {
+			FutureState::Forwarded(ref mut future) => try_ready!(future.poll()),
+			FutureState::Held {
+				ref mut future,
+				release_at,
+			} => {
+				if !self.faults.borrow_mut().is_released(release_at) {
+					return Ok(Async::NotReady);
+				}
+				try_ready!(future.poll())
+			}
+			FutureState::Unanswered => return Ok(Async::NotReady),
+			FutureState::Done(ref mut result) => {
+				let result = result.take().expect("polled after completion");
+				return result.map(Async::Ready);
+			}
+		};
+		Ok(Async::Ready(self.faults.borrow_mut().alter_response(
+			&self.method,
+			self.logs_range,
+			value,
+		)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use database::State;
+	use message_to_main::MessageToMain;
+	use relay_log::Chain;
+	use simulated_chains::{bridge_contracts, run_bridge, SimulatedChains};
+	use web3::types::H256;
+
+	const MAX_RUNS: usize = 20;
+	/// requests that time out are never answered.
+	/// a bridge that waits for one is restarted after this many steps
+	const STEPS_PER_RUN: usize = 30;
+
+	/// runs and restarts a single authority bridge against simulated chains
+	/// with faults until every message is relayed.
+	/// asserts that every message is relayed exactly once.
+	/// returns the faults injected on main and side
+	fn assert_relays_every_message_once(options: FaultInjectionOptions) -> InjectedFaults {
+		let chains = SimulatedChains::new(1, 1);
+		let authority = chains.authority(0);
+
+		let to_side: Vec<H256> = (0..3u8)
+			.map(|i| chains.send_message_to_side(vec![i]))
+			.collect();
+		let to_main: Vec<MessageToMain> = (0..3u8)
+			.map(|i| chains.send_message_to_main(vec![i]))
+			.collect();
+
+		let main = FaultInjectingTransport::new(chains.main(), options.clone());
+		let side = FaultInjectingTransport::new(
+			chains.side(),
+			FaultInjectionOptions {
+				seed: options.seed + 1,
+				..options.clone()
+			},
+		);
+
+		let is_relayed = || {
+			to_side
+				.iter()
+				.all(|hash| !chains.side_acceptances(*hash).is_empty())
+				&& to_main
+					.iter()
+					.all(|message| !chains.main_acceptances(message).is_empty())
+		};
+
+		let mut state = State::default();
+		let mut runs = 0;
+		while !is_relayed() {
+			assert!(runs < MAX_RUNS, "messages not relayed after {} runs", runs);
+			runs += 1;
+			// the confirmations keep reorged logs from being relayed
+			let (main_contract, side_contract) = bridge_contracts(
+				authority,
+				1,
+				options.reorg_depth as u32,
+				main.clone(),
+				side.clone(),
+			);
+			// errors stop the bridge. it is then restarted
+			run_bridge(
+				&mut state,
+				main_contract,
+				side_contract,
+				STEPS_PER_RUN,
+				|| chains.advance(),
+			);
+		}
+
+		for hash in &to_side {
+			assert_eq!(chains.side_acceptances(*hash), vec![authority]);
+		}
+		for message in &to_main {
+			assert_eq!(chains.side_signatures(message), vec![authority]);
+			assert_eq!(chains.main_acceptances(message), vec![authority]);
+		}
+		// relays sent again after a fault would have been rejected by the contracts
+		assert!(chains.reverted_transactions(Chain::Main).is_empty());
+		assert!(chains.reverted_transactions(Chain::Side).is_empty());
+
+		let main = main.injected();
+		let side = side.injected();
+		InjectedFaults {
+			timeouts: main.timeouts + side.timeouts,
+			rpc_errors: main.rpc_errors + side.rpc_errors,
+			dropped_transactions: main.dropped_transactions + side.dropped_transactions,
+			reordered_responses: main.reordered_responses + side.reordered_responses,
+			block_number_regressions: main.block_number_regressions + side.block_number_regressions,
+			reorgs: main.reorgs + side.reorgs,
+		}
+	}
+
+	#[test]
+	fn test_bridge_with_timeouts() {
+		let injected = assert_relays_every_message_once(FaultInjectionOptions {
+			timeout: 0.02,
+			..FaultInjectionOptions::default()
+		});
+		assert!(injected.timeouts > 0);
+	}
+
+	#[test]
+	fn test_bridge_with_rpc_errors() {
+		let injected = assert_relays_every_message_once(FaultInjectionOptions {
+			rpc_error: 0.02,
+			..FaultInjectionOptions::default()
+		});
+		assert!(injected.rpc_errors > 0);
+	}
+
+	#[test]
+	fn test_bridge_with_dropped_transactions() {
+		let injected = assert_relays_every_message_once(FaultInjectionOptions {
+			dropped_transaction: 0.3,
+			..FaultInjectionOptions::default()
+		});
+		assert!(injected.dropped_transactions > 0);
+	}
+
+	#[test]
+	fn test_bridge_with_reordered_responses() {
+		let injected = assert_relays_every_message_once(FaultInjectionOptions {
+			reordered_response: 0.3,
+			..FaultInjectionOptions::default()
+		});
+		assert!(injected.reordered_responses > 0);
+	}
+
+	#[test]
+	fn test_bridge_with_block_number_regressions() {
+		let injected = assert_relays_every_message_once(FaultInjectionOptions {
+			block_number_regression: 0.3,
+			..FaultInjectionOptions::default()
+		});
+		assert!(injected.block_number_regressions > 0);
+	}
+
+	#[test]
+	fn test_bridge_with_reorgs() {
+		let injected = assert_relays_every_message_once(FaultInjectionOptions {
+			reorg: 0.5,
+			..FaultInjectionOptions::default()
+		});
+		assert!(injected.reorgs > 0);
+	}
+
+	#[test]
+	fn test_bridge_with_all_faults() {
+		let injected = assert_relays_every_message_once(FaultInjectionOptions {
+			seed: 7,
+			timeout: 0.01,
+			rpc_error: 0.01,
+			dropped_transaction: 0.1,
+			reordered_response: 0.1,
+			block_number_regression: 0.1,
+			reorg: 0.3,
+			..FaultInjectionOptions::default()
+		});
+		assert!(injected.total() > 0);
+	}
+
+	#[test]
+	fn test_reorg_moves_logs_of_recent_blocks() {
+		let chains = SimulatedChains::new(1, 1);
+		chains.send_message_to_side(vec![]);
+
+		let transport = FaultInjectingTransport::new(
+			chains.main(),
+			FaultInjectionOptions {
+				reorg: 1.0,
+				reorg_depth: 2,
+				..FaultInjectionOptions::default()
+			},
+		);
+		let block_number = || {
+			let block_number = transport.execute("eth_blockNumber", vec![]);
+			chains.advance();
+			block_number.wait().unwrap()
+		};
+		let logs_of_block = |block: u64| {
+			transport
+				.execute(
+					"eth_getLogs",
+					vec![
+						json!({"fromBlock": format!("0x{:x}", block), "toBlock": format!("0x{:x}", block)}),
+					],
+				)
+				.wait()
+				.unwrap()
+				.as_array()
+				.unwrap()
+				.len()
+		};
+
+		// the message was sent in block 1 which is the second to last block
+		assert_eq!(block_number(), json!("0x2"));
+		assert_eq!(logs_of_block(1), 0);
+		assert_eq!(transport.injected().reorgs, 1);
+
+		// it was moved into block 3 or 4
+		let new_block = (3..5).find(|block| logs_of_block(*block) == 1).unwrap();
+		// and stays there once that block is mined
+		for _ in 0..new_block {
+			block_number();
+		}
+		assert_eq!(logs_of_block(new_block), 1);
+		assert_eq!(logs_of_block(1), 0);
+		// a log is moved by one reorg at most
+		assert_eq!(transport.injected().reorgs, 1);
+	}
+}
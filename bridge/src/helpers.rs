@@ -16,17 +16,20 @@
 
 //! various helper functions
 
-use error::{self, ResultExt};
+use error::{self, ErrorKind, ResultExt};
 use ethabi::{self, FunctionOutputDecoder, RawLog};
 use futures::future::FromErr;
 use futures::{Async, Future, Poll, Stream};
-use serde::de::Error;
-use serde::{Deserialize, Deserializer, Serializer};
+use serde::de::{Error, Visitor};
+use serde::{Deserializer, Serializer};
+use std::fmt;
 use std::time::Duration;
+use tiny_keccak::{Hasher, Keccak};
 use tokio_timer::{Timeout, Timer};
+use transaction::{Fees, Transaction};
 use web3::api::Namespace;
 use web3::helpers::CallFuture;
-use web3::types::{Address, Bytes, CallRequest, TransactionRequest, H256, U256};
+use web3::types::{Address, Bytes, CallRequest, H256, U256};
 use web3::{self, Transport};
 
 /// attempts to convert a raw `web3_log` into the ethabi log type of a specific `event`
@@ -95,6 +98,8 @@ impl<T: Transport, F: FunctionOutputDecoder> Future for AsyncCall<T, F> {
 	}
 }
 
+/// use `AsyncTransaction::new(transport, contract_address, authority_address, ...)`
+/// to get a `Future` that resolves with the hash of the sent transaction.
 pub struct AsyncTransaction<T: Transport> {
 	future: Timeout<FromErr<CallFuture<H256, T::Out>, error::Error>>,
 }
@@ -105,23 +110,18 @@ impl<T: Transport> AsyncTransaction<T> {
 		contract_address: Address,
 		authority_address: Address,
 		gas: U256,
-		gas_price: U256,
+		fees: Fees,
 		timeout: Duration,
 		payload: Vec<u8>,
 	) -> Self {
-		let request = TransactionRequest {
+		let transaction = Transaction {
 			from: authority_address,
 			to: Some(contract_address),
-			gas: Some(gas),
-			gas_price: Some(gas_price),
-			value: None,
-			data: Some(Bytes(payload)),
-			nonce: None,
-			condition: None,
+			gas,
+			fees,
+			data: Bytes(payload),
 		};
-		let inner_future = web3::api::Eth::new(transport)
-			.send_transaction(request)
-			.from_err();
+		let inner_future = transaction.send(transport).from_err();
 		let future = Timer::default().timeout(inner_future, timeout);
 		Self { future }
 	}
@@ -135,17 +135,213 @@ impl<T: Transport> Future for AsyncTransaction<T> {
 	}
 }
 
+/// use `AsyncGasEstimate::new(transport, contract_address, authority_address, ...)`
+/// to get a `Future` that resolves with the gas limit to use for a transaction:
+/// the result of `eth_estimateGas` times `multiplier`, capped at `max_gas`.
+pub struct AsyncGasEstimate<T: Transport> {
+	future: Timeout<FromErr<CallFuture<U256, T::Out>, error::Error>>,
+	multiplier: f64,
+	max_gas: U256,
+}
+
+impl<T: Transport> AsyncGasEstimate<T> {
+	pub fn new(
+		transport: &T,
+		contract_address: Address,
+		authority_address: Address,
+		multiplier: f64,
+		max_gas: U256,
+		timeout: Duration,
+		payload: Vec<u8>,
+	) -> Self {
+		let request = CallRequest {
+			from: Some(authority_address),
+			to: contract_address,
+			gas: None,
+			gas_price: None,
+			value: None,
+			data: Some(Bytes(payload)),
+		};
+		let inner_future = web3::api::Eth::new(transport)
+			.estimate_gas(request, None)
+			.from_err();
+		let future = Timer::default().timeout(inner_future, timeout);
+		Self {
+			future,
+			multiplier,
+			max_gas,
+		}
+	}
+}
+
+impl<T: Transport> Future for AsyncGasEstimate<T> {
+	type Item = U256;
+	type Error = error::Error;
+
+	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+		let estimate = match self.future.poll() {
+			Ok(Async::Ready(estimate)) => estimate,
+			Ok(Async::NotReady) => return Ok(Async::NotReady),
+			Err(err) => {
+				if let ErrorKind::Web3(ref web3_error) = *err.kind() {
+					if let Some(reason) = execution_revert_reason(web3_error) {
+						bail!(ErrorKind::TransactionWouldRevert(reason));
+					}
+				}
+				return Err(err).chain_err(|| "failed to estimate gas");
+			}
+		};
+		if estimate > self.max_gas {
+			bail!(ErrorKind::GasEstimateExceedsCap(estimate, self.max_gas));
+		}
+		Ok(Async::Ready(
+			apply_gas_multiplier(estimate, self.multiplier).min(self.max_gas),
+		))
+	}
+}
+
+/// the reason if `err` says that executing the transaction reverts:
+/// error code 3, -32000 with "execution reverted" or revert data attached.
+/// other rpc errors (for example from an overloaded node) say nothing about the transaction
+fn execution_revert_reason(err: &web3::Error) -> Option<String> {
+	let rpc_error = match *err {
+		web3::Error::Rpc(ref rpc_error) => rpc_error,
+		_ => return None,
+	};
+	let code = rpc_error.code.code();
+	let has_revert_data = rpc_error
+		.data
+		.as_ref()
+		.and_then(|data| data.as_str())
+		.map_or(false, |data| {
+			data.starts_with("0x") || data.starts_with("Reverted")
+		});
+	if code == 3
+		|| (code == -32000 && rpc_error.message.contains("execution reverted"))
+		|| has_revert_data
+	{
+		Some(rpc_error.message.clone())
+	} else {
+		None
+	}
+}
+
+/// like `chain_err` but keeps `ErrorKind::TransactionWouldRevert` as it is
+/// so `RelayStream` can quarantine the log of a relay that would revert
+pub fn chain_estimate_err<T, F: FnOnce() -> String>(
+	result: Poll<T, error::Error>,
+	context: F,
+) -> Poll<T, error::Error> {
+	result.map_err(|err| match *err.kind() {
+		ErrorKind::TransactionWouldRevert(_) => err,
+		_ => error::Error::with_chain(err, context()),
+	})
+}
+
+/// `estimate * multiplier` with a precision of two decimal places
+fn apply_gas_multiplier(estimate: U256, multiplier: f64) -> U256 {
+	let percent = U256::from((multiplier * 100.0).round() as u64);
+	estimate.saturating_mul(percent) / U256::from(100)
+}
+
+pub fn keccak256(bytes: &[u8]) -> H256 {
+	let mut output = [0u8; 32];
+	let mut keccak = Keccak::v256();
+	keccak.update(bytes);
+	keccak.finalize(&mut output);
+	H256::from_slice(&output)
+}
+
+/// whether `runtime_code` (as returned by `eth_getCode`) was deployed from `creation_code`.
+/// solc embeds the runtime code unchanged in the creation code.
+pub fn is_runtime_code_of(runtime_code: &[u8], creation_code: &[u8]) -> bool {
+	!runtime_code.is_empty()
+		&& creation_code
+			.windows(runtime_code.len())
+			.any(|window| window == runtime_code)
+}
+
+/// address of the contract created by the transaction with `nonce` sent by `sender`:
+/// the last 20 bytes of `keccak256(rlp([sender, nonce]))`
+pub fn contract_address(sender: Address, nonce: U256) -> Address {
+	let mut nonce_bytes = [0u8; 32];
+	nonce.to_big_endian(&mut nonce_bytes);
+	let first_non_zero = nonce_bytes.iter().position(|byte| *byte != 0).unwrap_or(32);
+	let nonce_bytes = &nonce_bytes[first_non_zero..];
+
+	let mut payload = vec![0x80 + 20];
+	payload.extend_from_slice(sender.as_bytes());
+	// single bytes below 0x80 are their own rlp encoding
+	if nonce_bytes.len() == 1 && nonce_bytes[0] < 0x80 {
+		payload.push(nonce_bytes[0]);
+	} else {
+		payload.push(0x80 + nonce_bytes.len() as u8);
+		payload.extend_from_slice(nonce_bytes);
+	}
+
+	// the payload is always shorter than 56 bytes
+	let mut rlp = vec![0xc0 + payload.len() as u8];
+	rlp.extend_from_slice(&payload);
+	Address::from_slice(&keccak256(&rlp)[12..])
+}
+
+/// address of the contract created by `factory` through `CREATE2` with `salt`
+/// and `init_code` (EIP-1014):
+/// the last 20 bytes of `keccak256(0xff ++ factory ++ salt ++ keccak256(init_code))`
+pub fn create2_address(factory: Address, salt: H256, init_code: &[u8]) -> Address {
+	let mut payload = vec![0xff];
+	payload.extend_from_slice(factory.as_bytes());
+	payload.extend_from_slice(salt.as_bytes());
+	payload.extend_from_slice(keccak256(init_code).as_bytes());
+	Address::from_slice(&keccak256(&payload)[12..])
+}
+
 /// the toml crate parses integer literals as `i64`.
 /// certain config options (example: `max_total_home_contract_balance`)
 /// frequently don't fit into `i64`.
 /// workaround: put them in string literals, use this custom
 /// deserializer and parse them as U256.
+/// integer literals are accepted too since config overrides
+/// (see `config::ConfigOverride`) can't know the type of an omitted option.
 pub fn deserialize_u256<'de, D>(deserializer: D) -> Result<U256, D::Error>
 where
 	D: Deserializer<'de>,
 {
-	let s: &str = Deserialize::deserialize(deserializer)?;
-	U256::from_dec_str(s).map_err(|_| D::Error::custom("failed to parse U256 from dec str"))
+	struct U256Visitor;
+
+	impl<'de> Visitor<'de> for U256Visitor {
+		type Value = U256;
+
+		fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+			formatter.write_str("a decimal number in a string")
+		}
+
+		fn visit_str<E: Error>(self, value: &str) -> Result<U256, E> {
+			U256::from_dec_str(value).map_err(|_| E::custom("failed to parse U256 from dec str"))
+		}
+
+		fn visit_u64<E: Error>(self, value: u64) -> Result<U256, E> {
+			Ok(value.into())
+		}
+
+		fn visit_i64<E: Error>(self, value: i64) -> Result<U256, E> {
+			if value < 0 {
+				return Err(E::custom("U256 can't be negative"));
+			}
+			Ok((value as u64).into())
+		}
+	}
+
+	deserializer.deserialize_any(U256Visitor)
+}
+
+/// like `deserialize_u256` but for config options that may be omitted.
+/// use together with `#[serde(default)]`.
+pub fn deserialize_optional_u256<'de, D>(deserializer: D) -> Result<Option<U256>, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	deserialize_u256(deserializer).map(Some)
 }
 
 pub fn serialize_u256<S>(value: &U256, serializer: S) -> Result<S::Ok, S::Error>
@@ -210,8 +406,188 @@ where
 mod tests {
 	use super::*;
 	use futures;
+	use jsonrpc_core;
 	use tokio_core::reactor::Core;
 
+	#[test]
+	fn test_is_runtime_code_of() {
+		let creation_code = [0x60, 0x80, 0x01, 0x02, 0x03, 0x00];
+		assert!(is_runtime_code_of(&[0x01, 0x02, 0x03], &creation_code));
+		assert!(!is_runtime_code_of(&[0x01, 0x03], &creation_code));
+		assert!(!is_runtime_code_of(&[], &creation_code));
+	}
+
+	#[test]
+	fn test_contract_address() {
+		let sender: Address = "6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0".parse().unwrap();
+		let expected = [
+			(0, "cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d"),
+			(1, "343c43a37d37dff08ae8c4a11544c718abb4fcf8"),
+			(2, "f778b86fa74e846c4f0a1fbd1335fe81c00a0c91"),
+			(3, "fffd933a0bc612844eaf0c6fe3e5b8e9b6c1d19c"),
+			(0x80, "08e190dcb7b73f5fcdabb43e102215c83659a76d"),
+		];
+		for (nonce, address) in expected.iter() {
+			assert_eq!(
+				contract_address(sender, (*nonce).into()),
+				address.parse().unwrap()
+			);
+		}
+	}
+
+	#[test]
+	fn test_create2_address() {
+		// examples from EIP-1014
+		assert_eq!(
+			create2_address(Address::zero(), H256::zero(), &[0x00]),
+			"4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38".parse().unwrap()
+		);
+		assert_eq!(
+			create2_address(
+				"deadbeef00000000000000000000000000000000".parse().unwrap(),
+				H256::zero(),
+				&[0x00]
+			),
+			"b928f69bb1d91cd65274e3c79d8986362984fda3".parse().unwrap()
+		);
+		assert_eq!(
+			create2_address(
+				"00000000000000000000000000000000deadbeef".parse().unwrap(),
+				H256::from_low_u64_be(0xcafebabe),
+				&[0xde, 0xad, 0xbe, 0xef]
+			),
+			"60f3f640a8508fc6a86d45df051962668e1e8ac7".parse().unwrap()
+		);
+	}
+
+	#[test]
+	fn test_apply_gas_multiplier() {
+		assert_eq!(apply_gas_multiplier(100.into(), 1.0), 100.into());
+		assert_eq!(apply_gas_multiplier(100.into(), 1.2), 120.into());
+		assert_eq!(apply_gas_multiplier(21000.into(), 1.5), 31500.into());
+	}
+
+	#[test]
+	fn test_async_gas_estimate_is_multiplied_and_capped() {
+		let transport = mock_transport!(
+			"eth_estimateGas" =>
+				req => json!([{
+					"data": "0x60",
+					"from": "0x0000000000000000000000000000000000000001",
+					"to": "0x0000000000000000000000000000000000000dd1",
+				}]),
+				res => json!("0x64");
+			"eth_estimateGas" =>
+				req => json!([{
+					"data": "0x60",
+					"from": "0x0000000000000000000000000000000000000001",
+					"to": "0x0000000000000000000000000000000000000dd1",
+				}]),
+				res => json!("0x64");
+			"eth_estimateGas" =>
+				req => json!([{
+					"data": "0x60",
+					"from": "0x0000000000000000000000000000000000000001",
+					"to": "0x0000000000000000000000000000000000000dd1",
+				}]),
+				res => json!("0x64");
+		);
+
+		let estimate = |max_gas: u64| {
+			AsyncGasEstimate::new(
+				&transport,
+				"0000000000000000000000000000000000000dd1".parse().unwrap(),
+				"0000000000000000000000000000000000000001".parse().unwrap(),
+				1.2,
+				max_gas.into(),
+				Duration::from_secs(1),
+				vec![0x60],
+			)
+		};
+
+		let mut event_loop = Core::new().unwrap();
+		assert_eq!(event_loop.run(estimate(200)).unwrap(), 120.into());
+		assert_eq!(event_loop.run(estimate(110)).unwrap(), 110.into());
+		match *event_loop.run(estimate(99)).unwrap_err().kind() {
+			ErrorKind::GasEstimateExceedsCap(estimate, cap) => {
+				assert_eq!(estimate, 100.into());
+				assert_eq!(cap, 99.into());
+			}
+			ref kind => panic!("unexpected error {:?}", kind),
+		}
+		assert_eq!(transport.actual_requests(), transport.expected_requests());
+	}
+
+	fn rpc_error(code: i64, message: &str, data: Option<&str>) -> web3::Error {
+		web3::Error::Rpc(jsonrpc_core::Error {
+			code: jsonrpc_core::ErrorCode::ServerError(code),
+			message: message.into(),
+			data: data.map(|data| json!(data)),
+		})
+	}
+
+	#[test]
+	fn test_execution_revert_reason() {
+		assert_eq!(
+			execution_revert_reason(&rpc_error(
+				3,
+				"execution reverted: already accepted",
+				Some("0x08c379a0")
+			)),
+			Some("execution reverted: already accepted".into())
+		);
+		assert_eq!(
+			execution_revert_reason(&rpc_error(-32000, "execution reverted", None)),
+			Some("execution reverted".into())
+		);
+		assert_eq!(
+			execution_revert_reason(&rpc_error(
+				-32015,
+				"VM execution error.",
+				Some("Reverted 0x")
+			)),
+			Some("VM execution error.".into())
+		);
+	}
+
+	#[test]
+	fn test_execution_revert_reason_of_other_errors() {
+		assert_eq!(
+			execution_revert_reason(&rpc_error(-32000, "header not found", None)),
+			None
+		);
+		assert_eq!(
+			execution_revert_reason(&rpc_error(-32005, "too many requests", None)),
+			None
+		);
+		assert_eq!(
+			execution_revert_reason(&web3::Error::Transport("connection refused".into())),
+			None
+		);
+	}
+
+	#[test]
+	fn test_chain_estimate_err_keeps_reverts() {
+		let revert: Poll<(), error::Error> =
+			Err(ErrorKind::TransactionWouldRevert("execution reverted".into()).into());
+		match *chain_estimate_err(revert, || "estimating gas failed".into())
+			.unwrap_err()
+			.kind()
+		{
+			ErrorKind::TransactionWouldRevert(_) => {}
+			ref kind => panic!("unexpected error {:?}", kind),
+		}
+
+		let other: Poll<(), error::Error> = Err(ErrorKind::TimedOut.into());
+		match *chain_estimate_err(other, || "estimating gas failed".into())
+			.unwrap_err()
+			.kind()
+		{
+			ErrorKind::Msg(ref message) => assert_eq!(message, "estimating gas failed"),
+			ref kind => panic!("unexpected error {:?}", kind),
+		}
+	}
+
 	#[test]
 	fn test_stream_ext_last_empty() {
 		let stream = futures::stream::empty::<(), ()>();
@@ -30,9 +30,11 @@
 //! those futures are supposed to each do one single complete relay
 //! (1. `MainToSideSign`, 2. `SideToMainSign`, 3. `SideToMainSignatures`).
 //! `RelayStream` polls the log stream, calls `LogToFuture.log_to_future` for each log,
-//! and yields the numbers of those blocks for which all such created futures
-//! have completed. these block numbers are then persisted
+//! and yields the positions (block number and log index) of the logs up to which
+//! all such created futures have completed. these positions are then persisted
 //! so the bridge doesn't have to check logs up to them again next time it's started.
+//! logs for which `log_to_future` fails (for example because they can't be decoded)
+//! are quarantined in the database instead of stopping the bridge.
 //!
 //! a `Bridge` instance is constructed as follows (how the parts fit together):
 //!
@@ -56,6 +58,9 @@
 //! - the relay future is responsible for the entire relay operation
 //! - currently relay futures check whether the specific relay has already happened,
 //!   ignore if it has and execute the corresponding transaction otherwise
+//! - relay futures wait for the receipt of their transaction and resolve with
+//!   a `RelayOutcome` which contains the gas the relay cost
+//! - the `Bridge` records relay costs and held relays in its `State`
 //! - relay futures should (currently don't) and easily could observe whether
 //!   the transaction succeeds, log it to help with troubleshooting and
 //!   retry if the condition can be recovered from
@@ -66,6 +71,7 @@ extern crate ethabi;
 extern crate ethereum_types;
 #[macro_use]
 extern crate futures;
+extern crate jsonrpc_core;
 #[macro_use]
 extern crate log;
 #[macro_use]
@@ -78,8 +84,14 @@ extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate bridge_contracts as contracts;
-#[cfg_attr(test, macro_use)]
+#[cfg(any(test, feature = "test-util"))]
+extern crate revm;
+#[cfg(any(test, feature = "test-util"))]
+extern crate secp256k1;
+#[cfg_attr(any(test, feature = "test-util"), macro_use)]
 extern crate serde_json;
+#[cfg(test)]
+extern crate tempfile;
 extern crate tiny_keccak;
 extern crate tokio_core;
 extern crate tokio_timer;
@@ -93,10 +105,21 @@ mod macros;
 #[macro_use]
 mod test;
 
+#[cfg(any(test, feature = "test-util"))]
+pub mod simulated_chains;
+
+#[cfg(any(test, feature = "test-util"))]
+pub mod fault_injection;
+
+#[cfg(test)]
+mod authority_simulation;
+
 mod block_number_stream;
 mod bridge;
-pub use bridge::Bridge;
+pub use bridge::{record_outcome, Bridge};
 pub mod config;
+mod config_watcher;
+pub use config_watcher::ConfigWatcher;
 pub mod database;
 pub mod deploy;
 pub mod error;
@@ -107,8 +130,18 @@ mod main_contract;
 pub use main_contract::MainContract;
 mod accept_message_from_main;
 pub use accept_message_from_main::AcceptMessageFromMain;
+mod reconciliation;
+pub use reconciliation::{
+	ReconciledMessage, ReconciledStatus, Reconciliation, ReconciliationOptions,
+	ReconciliationReport,
+};
+pub mod relay_log;
 mod relay_stream;
-pub use relay_stream::RelayStream;
+pub use relay_stream::{RelayOutcome, RelayStream};
+mod rescan;
+pub use rescan::{Rescan, RescanProgress};
+mod send_message;
+pub use send_message::{SendMessage, SendMessageOptions, SendMessageProgress};
 mod send_tx_with_receipt;
 mod side_contract;
 pub use side_contract::SideContract;
@@ -116,9 +149,12 @@ mod side_to_main_sign;
 pub use side_to_main_sign::SideToMainSign;
 mod side_to_main_signatures;
 pub use side_to_main_signatures::SideToMainSignatures;
+mod transaction;
+pub use transaction::{AsyncFees, Fees, Transaction};
+pub mod transport_recording;
 
 mod log_stream;
-pub use log_stream::{LogStream, LogStreamOptions};
+pub use log_stream::{LogPosition, LogStream, LogStreamOptions};
 
 mod signature;
 pub use signature::Signature;
@@ -126,8 +162,8 @@ pub use signature::Signature;
 mod message_to_main;
 pub use message_to_main::{MessageToMain, MESSAGE_LENGTH};
 
-#[cfg(test)]
-extern crate jsonrpc_core;
+mod message_status;
+pub use message_status::{MainToSideStatus, MessageStatus, MessageStatusLookup, SideToMainStatus};
 
 #[cfg(test)]
 pub use test::MockTransport;
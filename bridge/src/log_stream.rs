@@ -35,7 +35,7 @@ fn ethabi_topic_to_web3(topic: &ethabi::Topic<ethabi::Hash>) -> Option<Vec<H256>
 	}
 }
 
-fn filter_to_builder(filter: &ethabi::TopicFilter, address: Address) -> FilterBuilder {
+pub fn filter_to_builder(filter: &ethabi::TopicFilter, address: Address) -> FilterBuilder {
 	let t0 = ethabi_topic_to_web3(&filter.topic0);
 	let t1 = ethabi_topic_to_web3(&filter.topic1);
 	let t2 = ethabi_topic_to_web3(&filter.topic2);
@@ -64,6 +64,23 @@ pub struct LogsInBlockRange {
 	pub logs: Vec<Log>,
 }
 
+/// position of a log on its chain
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct LogPosition {
+	pub block_number: u64,
+	pub log_index: u64,
+}
+
+impl LogPosition {
+	/// `None` if `log` is not mined
+	pub fn from_log(log: &Log) -> Option<Self> {
+		Some(Self {
+			block_number: log.block_number?.as_u64(),
+			log_index: log.log_index?.low_u64(),
+		})
+	}
+}
+
 /// Log Stream state.
 enum State<T: Transport> {
 	/// Fetching best block number.
@@ -79,15 +96,21 @@ enum State<T: Transport> {
 /// `Stream` that repeatedly polls logs matching `filter_builder` from `contract_address`
 /// with adjustable `poll_interval` and `request_timeout`.
 /// yields new logs that are `confirmations` blocks deep.
+/// ends once block `until` has been checked (if set).
+/// fetches at most `block_window` blocks per request (if set).
 pub struct LogStream<T: Transport> {
 	block_number_stream: BlockNumberStream<T>,
 	request_timeout: Duration,
 	transport: T,
 	last_checked_block: u64,
+	/// last block known to be confirmed
+	last_confirmed_block: u64,
 	timer: Timer,
 	state: State<T>,
 	filter_builder: FilterBuilder,
 	topic: Vec<H256>,
+	until: Option<u64>,
+	block_window: Option<u64>,
 }
 
 impl<T: Transport> LogStream<T> {
@@ -111,12 +134,36 @@ impl<T: Transport> LogStream<T> {
 			request_timeout: options.request_timeout,
 			transport: options.transport,
 			last_checked_block: options.after,
+			last_confirmed_block: options.after,
 			timer,
 			state: State::AwaitBlockNumber,
 			filter_builder,
 			topic,
+			until: None,
+			block_window: None,
 		}
 	}
+
+	/// end the stream after the logs up to and including block `until` have been yielded
+	pub fn with_until(mut self, until: u64) -> Self {
+		self.until = Some(until);
+		self
+	}
+
+	/// fetch the logs of at most `blocks` blocks per `eth_getLogs` request.
+	/// nodes limit the block range or the number of logs of a request
+	pub fn with_block_window(mut self, blocks: u64) -> Self {
+		assert!(blocks > 0, "block window must not be empty");
+		self.block_window = Some(blocks);
+		self
+	}
+
+	/// used for requests and intervals started after this call
+	pub fn set_timings(&mut self, request_timeout: Duration, poll_interval: Duration) {
+		self.request_timeout = request_timeout;
+		self.block_number_stream
+			.set_timings(request_timeout, poll_interval);
+	}
 }
 
 impl<T: Transport> Stream for LogStream<T> {
@@ -127,13 +174,31 @@ impl<T: Transport> Stream for LogStream<T> {
 		loop {
 			let (next_state, value_to_yield) = match self.state {
 				State::AwaitBlockNumber => {
-					let last_block = try_stream!(self
-						.block_number_stream
-						.poll()
-						.chain_err(|| "LogStream: fetching of last confirmed block number failed"));
-					info!("LogStream: fetched confirmed block number {}", last_block);
+					if self
+						.until
+						.map_or(false, |until| self.last_checked_block >= until)
+					{
+						return Ok(Async::Ready(None));
+					}
+
+					// blocks that are known to be confirmed are fetched
+					// before the block number is polled again
+					if self.last_confirmed_block <= self.last_checked_block {
+						let last_block =
+							try_stream!(self.block_number_stream.poll().chain_err(|| {
+								"LogStream: fetching of last confirmed block number failed"
+							}));
+						info!("LogStream: fetched confirmed block number {}", last_block);
+						self.last_confirmed_block =
+							self.until.map_or(last_block, |until| last_block.min(until));
+					}
 
 					let from = self.last_checked_block + 1;
+					let last_block = self
+						.block_window
+						.map_or(self.last_confirmed_block, |blocks| {
+							self.last_confirmed_block.min(from + blocks - 1)
+						});
 					let filter = self
 						.filter_builder
 						.clone()
@@ -254,6 +319,108 @@ mod tests {
 		assert_eq!(transport.actual_requests(), transport.expected_requests());
 	}
 
+	#[test]
+	fn test_log_stream_with_until_ends() {
+		let deposit_topic = contracts::main::events::relay_message::filter().topic0;
+
+		let transport = mock_transport!(
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x1011");
+			"eth_getLogs" =>
+				req => json!([{
+					"address": "0x0000000000000000000000000000000000000001",
+					"fromBlock": "0x4",
+					"toBlock": "0xa",
+					"topics": [deposit_topic]
+				}]),
+				res => json!([]);
+		);
+
+		let log_stream = LogStream::new(LogStreamOptions {
+			request_timeout: Duration::from_secs(1),
+			poll_interval: Duration::from_secs(1),
+			confirmations: 12,
+			transport: transport.clone(),
+			contract_address: "0000000000000000000000000000000000000001".parse().unwrap(),
+			after: 3,
+			filter: contracts::main::events::relay_message::filter(),
+		})
+		.with_until(10);
+
+		let mut event_loop = Core::new().unwrap();
+		let log_ranges = event_loop.run(log_stream.collect()).unwrap();
+
+		assert_eq!(
+			log_ranges,
+			vec![LogsInBlockRange {
+				from: 4,
+				to: 10,
+				logs: vec![],
+			}]
+		);
+		assert_eq!(transport.actual_requests(), transport.expected_requests());
+	}
+
+	#[test]
+	fn test_log_stream_with_block_window() {
+		let deposit_topic = contracts::main::events::relay_message::filter().topic0;
+
+		let transport = mock_transport!(
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x1011");
+			"eth_getLogs" =>
+				req => json!([{
+					"address": "0x0000000000000000000000000000000000000001",
+					"fromBlock": "0x4",
+					"toBlock": "0x7",
+					"topics": [deposit_topic]
+				}]),
+				res => json!([]);
+			"eth_getLogs" =>
+				req => json!([{
+					"address": "0x0000000000000000000000000000000000000001",
+					"fromBlock": "0x8",
+					"toBlock": "0xa",
+					"topics": [deposit_topic]
+				}]),
+				res => json!([]);
+		);
+
+		let log_stream = LogStream::new(LogStreamOptions {
+			request_timeout: Duration::from_secs(1),
+			poll_interval: Duration::from_secs(1),
+			confirmations: 12,
+			transport: transport.clone(),
+			contract_address: "0000000000000000000000000000000000000001".parse().unwrap(),
+			after: 3,
+			filter: contracts::main::events::relay_message::filter(),
+		})
+		.with_until(10)
+		.with_block_window(4);
+
+		let mut event_loop = Core::new().unwrap();
+		let log_ranges = event_loop.run(log_stream.collect()).unwrap();
+
+		assert_eq!(
+			log_ranges,
+			vec![
+				LogsInBlockRange {
+					from: 4,
+					to: 7,
+					logs: vec![],
+				},
+				LogsInBlockRange {
+					from: 8,
+					to: 10,
+					logs: vec![],
+				},
+			]
+		);
+		assert_eq!(transport.actual_requests(), transport.expected_requests());
+	}
+
 	#[test]
 	fn test_log_stream_once_one_log() {
 		let deposit_topic = contracts::main::events::relay_message::filter().topic0;
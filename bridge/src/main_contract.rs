@@ -13,16 +13,19 @@
 
 // You should have received a copy of the GNU General Public License
 // along with Parity-Bridge.  If not, see <http://www.gnu.org/licenses/>.
-use config::Config;
+use config::{Config, TransactionConfig};
 use contracts;
 use database::State;
 use ethabi::FunctionOutputDecoder;
 use ethereum_types::{Address, H256, U256};
-use helpers::{AsyncCall, AsyncTransaction};
+use helpers::{AsyncCall, AsyncGasEstimate};
 use log_stream::{LogStream, LogStreamOptions};
 use message_to_main::MessageToMain;
+use send_tx_with_receipt::{SendTransactionWithReceipt, SendTransactionWithReceiptOptions};
 use signature::Signature;
 use std::time::Duration;
+use transaction::{AsyncFees, Eip1559Support, Fees, Transaction};
+use web3::types::Bytes;
 use web3::Transport;
 
 /// highlevel wrapper around the auto generated ethabi contract `bridge_contracts::main`
@@ -31,10 +34,17 @@ pub struct MainContract<T> {
 	pub transport: T,
 	pub contract_address: Address,
 	pub authority_address: Address,
-	pub submit_collected_signatures_gas: U256,
+	/// `gas` is the upper cap for the gas of `relay_side_to_main` transactions
+	pub submit_collected_signatures_tx: TransactionConfig,
+	/// gas estimates are multiplied by this to leave a safety margin
+	pub gas_estimate_multiplier: f64,
+	/// relays whose estimated cost exceeds this are held instead of sent
+	pub max_relay_cost: Option<U256>,
 	pub request_timeout: Duration,
 	pub logs_poll_interval: Duration,
 	pub required_log_confirmations: u32,
+	/// shared by the relays so `eth_feeHistory` is only requested while it can succeed
+	pub eip1559_support: Eip1559Support,
 }
 
 impl<T: Transport> MainContract<T> {
@@ -43,13 +53,27 @@ impl<T: Transport> MainContract<T> {
 			transport,
 			contract_address: state.main_contract_address,
 			authority_address: config.address,
-			submit_collected_signatures_gas: config.estimated_gas_cost_of_withdraw,
+			submit_collected_signatures_tx: TransactionConfig {
+				gas: config.estimated_gas_cost_of_withdraw,
+				..config.txs.withdraw_relay.clone()
+			},
+			gas_estimate_multiplier: config.gas_estimate_multiplier,
+			max_relay_cost: config.max_main_relay_cost,
 			request_timeout: config.main.request_timeout,
 			logs_poll_interval: config.main.poll_interval,
 			required_log_confirmations: config.main.required_confirmations,
+			eip1559_support: Eip1559Support::default(),
 		}
 	}
 
+	/// applies the options of `config` that can change while the bridge is running
+	pub fn apply_config(&mut self, config: &Config) {
+		self.request_timeout = config.main.request_timeout;
+		self.logs_poll_interval = config.main.poll_interval;
+		self.submit_collected_signatures_tx
+			.set_fees_from(&config.txs.withdraw_relay);
+	}
+
 	pub fn call<F: FunctionOutputDecoder>(
 		&self,
 		payload: Vec<u8>,
@@ -71,14 +95,68 @@ impl<T: Transport> MainContract<T> {
 		self.call(payload, decoder)
 	}
 
-	/// relay a tx from side to main by submitting message and collected signatures
+	/// the fees to use for `relay_side_to_main`
+	pub fn relay_side_to_main_fees(&self) -> AsyncFees<T> {
+		AsyncFees::new(
+			&self.transport,
+			self.request_timeout,
+			&self.submit_collected_signatures_tx,
+			&self.eip1559_support,
+		)
+	}
+
+	/// estimate the gas for `relay_side_to_main`
+	pub fn estimate_relay_side_to_main_gas(
+		&self,
+		message: &MessageToMain,
+		signatures: &Vec<Signature>,
+		data: Vec<u8>,
+	) -> AsyncGasEstimate<T> {
+		AsyncGasEstimate::new(
+			&self.transport,
+			self.contract_address,
+			self.authority_address,
+			self.gas_estimate_multiplier,
+			self.submit_collected_signatures_tx.gas,
+			self.request_timeout,
+			Self::relay_side_to_main_payload(message, signatures, data),
+		)
+	}
+
+	/// relay a tx from side to main by submitting message and collected signatures.
+	/// resolves with the receipt once the transaction is mined.
 	pub fn relay_side_to_main(
 		&self,
 		message: &MessageToMain,
 		signatures: &Vec<Signature>,
 		data: Vec<u8>,
-	) -> AsyncTransaction<T> {
-		let payload = contracts::main::functions::accept_message::encode_input(
+		gas: U256,
+		fees: Fees,
+	) -> SendTransactionWithReceipt<T> {
+		let payload = Self::relay_side_to_main_payload(message, signatures, data);
+
+		SendTransactionWithReceipt::new(SendTransactionWithReceiptOptions {
+			transport: self.transport.clone(),
+			request_timeout: self.request_timeout,
+			poll_interval: self.logs_poll_interval,
+			confirmations: self.required_log_confirmations,
+			transaction: Transaction {
+				from: self.authority_address,
+				to: Some(self.contract_address),
+				gas,
+				fees,
+				data: Bytes(payload),
+			}
+			.into(),
+		})
+	}
+
+	fn relay_side_to_main_payload(
+		message: &MessageToMain,
+		signatures: &Vec<Signature>,
+		data: Vec<u8>,
+	) -> Vec<u8> {
+		contracts::main::functions::accept_message::encode_input(
 			signatures.iter().map(|x| x.v),
 			signatures.iter().map(|x| x.r),
 			signatures.iter().map(|x| x.s),
@@ -86,18 +164,6 @@ impl<T: Transport> MainContract<T> {
 			data,
 			message.sender,
 			message.recipient,
-		);
-
-		AsyncTransaction::new(
-			&self.transport,
-			self.contract_address,
-			self.authority_address,
-			self.submit_collected_signatures_gas,
-			// TODO:
-			//message.main_gas_price,
-			1000.into(),
-			self.request_timeout,
-			payload,
 		)
 	}
 
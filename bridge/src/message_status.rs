@@ -0,0 +1,699 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity-Bridge.
+
+// Parity-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! looking up how far the relay of a message has progressed
+
+use contracts;
+use error::{self, ResultExt};
+use ethabi;
+use futures::future::{join_all, FromErr, Join, JoinAll};
+use futures::{Async, Future, Poll};
+use helpers::{self, AsyncCall};
+use log_stream::filter_to_builder;
+use main_contract::MainContract;
+use message_to_main::MessageToMain;
+use side_contract::SideContract;
+use std::fmt;
+use tokio_timer::{Timeout, Timer};
+use web3::api::Namespace;
+use web3::helpers::CallFuture;
+use web3::types::{Address, BlockNumber, Log, TransactionReceipt, H256};
+use web3::{self, Transport};
+
+/// how far the relay of a message from main to side has progressed
+#[derive(Debug, PartialEq, Clone)]
+pub struct MainToSideStatus {
+	pub main_tx_hash: H256,
+	pub message_id: H256,
+	pub sender: Address,
+	pub recipient: Address,
+	pub data: Vec<u8>,
+	/// whether each authority has accepted the message on side
+	pub accepted_by: Vec<(Address, bool)>,
+	pub required_signatures: u32,
+}
+
+/// how far the relay of a message from side to main has progressed
+#[derive(Debug, PartialEq, Clone)]
+pub struct SideToMainStatus {
+	pub message: MessageToMain,
+	pub message_hash: H256,
+	/// signatures collected on side so far
+	pub signatures: u32,
+	pub required_signatures: u32,
+	/// transaction that logged `SignedMessage` for the message (if any)
+	pub signed_message_tx_hash: Option<H256>,
+	/// `main.accepted_messages(message_hash)`
+	pub accepted_on_main: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum MessageStatus {
+	MainToSide(MainToSideStatus),
+	SideToMain(SideToMainStatus),
+}
+
+impl MessageStatus {
+	/// whether the message has been accepted on the other chain
+	pub fn is_accepted(&self) -> bool {
+		match *self {
+			MessageStatus::MainToSide(ref status) => {
+				status
+					.accepted_by
+					.iter()
+					.filter(|&&(_, accepted)| accepted)
+					.count() >= status.required_signatures as usize
+			}
+			MessageStatus::SideToMain(ref status) => status.accepted_on_main,
+		}
+	}
+}
+
+fn yes_no(value: bool) -> &'static str {
+	if value {
+		"yes"
+	} else {
+		"no"
+	}
+}
+
+impl fmt::Display for MessageStatus {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			MessageStatus::MainToSide(ref status) => {
+				writeln!(
+					f,
+					"main to side message {:?} sent in main transaction {:?}",
+					status.message_id, status.main_tx_hash
+				)?;
+				writeln!(f, "sender: {:?}", status.sender)?;
+				writeln!(f, "recipient: {:?}", status.recipient)?;
+				writeln!(f, "data: {} bytes", status.data.len())?;
+				let accepted = status
+					.accepted_by
+					.iter()
+					.filter(|&&(_, accepted)| accepted)
+					.count();
+				write!(
+					f,
+					"accepted on side by {} of {} authorities ({} required):",
+					accepted,
+					status.accepted_by.len(),
+					status.required_signatures
+				)?;
+				for &(authority, accepted) in &status.accepted_by {
+					write!(f, "\n  {:?}: {}", authority, yes_no(accepted))?;
+				}
+				Ok(())
+			}
+			MessageStatus::SideToMain(ref status) => {
+				writeln!(
+					f,
+					"side to main message {:?} sent in side transaction {:?}",
+					status.message_hash, status.message.side_tx_hash
+				)?;
+				writeln!(f, "message id: {:?}", status.message.message_id)?;
+				writeln!(f, "sender: {:?}", status.message.sender)?;
+				writeln!(f, "recipient: {:?}", status.message.recipient)?;
+				writeln!(
+					f,
+					"signatures collected on side: {} of {}",
+					status.signatures, status.required_signatures
+				)?;
+				match status.signed_message_tx_hash {
+					Some(tx_hash) => {
+						writeln!(f, "SignedMessage: logged in side transaction {:?}", tx_hash)?
+					}
+					None => writeln!(f, "SignedMessage: not logged yet")?,
+				}
+				write!(f, "accepted on main: {}", yes_no(status.accepted_on_main))
+			}
+		}
+	}
+}
+
+type Request<T, R> = Timeout<FromErr<CallFuture<R, <T as Transport>::Out>, error::Error>>;
+
+enum State<T: Transport> {
+	AwaitReceipts(
+		Join<Request<T, Option<TransactionReceipt>>, Request<T, Option<TransactionReceipt>>>,
+	),
+	AwaitMainToSideMessage {
+		future: AsyncCall<T, contracts::main::functions::relayed_messages::Decoder>,
+		log: contracts::main::logs::RelayMessage,
+	},
+	AwaitAcceptedFromMain {
+		future: JoinAll<
+			Vec<
+				AsyncCall<
+					T,
+					contracts::side::functions::has_authority_accepted_message_from_main::Decoder,
+				>,
+			>,
+		>,
+		log: contracts::main::logs::RelayMessage,
+		data: Vec<u8>,
+	},
+	/// signatures are fetched one by one until one is missing
+	AwaitSignature {
+		future: AsyncCall<T, contracts::side::functions::signature::Decoder>,
+		status: SideToMainStatus,
+		block: u64,
+	},
+	AwaitSignedMessageLogs {
+		future: Request<T, Vec<Log>>,
+		status: SideToMainStatus,
+	},
+	AwaitAcceptedOnMain {
+		future: AsyncCall<T, contracts::main::functions::accepted_messages::Decoder>,
+		status: SideToMainStatus,
+	},
+}
+
+/// `Future` that finds the message sent in the transaction with `transaction_hash`
+/// on main or side and resolves with how far its relay has progressed
+pub struct MessageStatusLookup<T: Transport> {
+	main: MainContract<T>,
+	side: SideContract<T>,
+	/// the authorities whose acceptance of main to side messages is checked
+	authorities: Vec<Address>,
+	transaction_hash: H256,
+	timer: Timer,
+	state: State<T>,
+}
+
+impl<T: Transport> MessageStatusLookup<T> {
+	pub fn new(
+		main: MainContract<T>,
+		side: SideContract<T>,
+		authorities: Vec<Address>,
+		transaction_hash: H256,
+	) -> Self {
+		let timer = Timer::default();
+		let main_receipt = web3::api::Eth::new(&main.transport)
+			.transaction_receipt(transaction_hash)
+			.from_err();
+		let side_receipt = web3::api::Eth::new(&side.transport)
+			.transaction_receipt(transaction_hash)
+			.from_err();
+		let future = timer
+			.timeout(main_receipt, main.request_timeout)
+			.join(timer.timeout(side_receipt, side.request_timeout));
+		Self {
+			main,
+			side,
+			authorities,
+			transaction_hash,
+			timer,
+			state: State::AwaitReceipts(future),
+		}
+	}
+
+	fn await_signature(&self, status: SideToMainStatus, block: u64) -> State<T> {
+		let (payload, decoder) =
+			contracts::side::functions::signature::call(status.message_hash, status.signatures);
+		State::AwaitSignature {
+			future: self.side.call(payload, decoder),
+			status,
+			block,
+		}
+	}
+
+	fn await_signed_message_logs(&self, status: SideToMainStatus, block: u64) -> State<T> {
+		let filter = filter_to_builder(
+			&contracts::side::events::signed_message::filter(ethabi::Topic::Any),
+			self.side.contract_address,
+		)
+		.from_block(BlockNumber::from(block))
+		.build();
+		let future = web3::api::Eth::new(&self.side.transport).logs(filter);
+		State::AwaitSignedMessageLogs {
+			future: self
+				.timer
+				.timeout(future.from_err(), self.side.request_timeout),
+			status,
+		}
+	}
+}
+
+impl<T: Transport> Future for MessageStatusLookup<T> {
+	type Item = MessageStatus;
+	type Error = error::Error;
+
+	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+		loop {
+			let next_state = match self.state {
+				State::AwaitReceipts(ref mut future) => {
+					let (main_receipt, side_receipt) = try_ready!(future
+						.poll()
+						.chain_err(|| "MessageStatusLookup: fetching receipts failed"));
+
+					let main_log = main_receipt
+						.iter()
+						.flat_map(|receipt| receipt.logs.iter())
+						.filter(|log| log.address == self.main.contract_address)
+						.filter_map(|log| {
+							helpers::parse_log(
+								contracts::main::events::relay_message::parse_log,
+								log,
+							)
+							.ok()
+						})
+						.next();
+					let side_message = side_receipt
+						.iter()
+						.flat_map(|receipt| receipt.logs.iter())
+						.filter(|log| log.address == self.side.contract_address)
+						.filter_map(|log| MessageToMain::from_log(log).ok())
+						.next();
+
+					match (main_log, side_message) {
+						(Some(log), _) => {
+							info!(
+								"{:?} - main to side message {:?}",
+								self.transaction_hash, log.message_id
+							);
+							State::AwaitMainToSideMessage {
+								future: self.main.relayed_message_by_id(log.message_id),
+								log,
+							}
+						}
+						(None, Some(message)) => {
+							let message_hash = message.keccak256();
+							info!(
+								"{:?} - side to main message {:?}",
+								self.transaction_hash, message_hash
+							);
+							let block = side_receipt
+								.and_then(|receipt| receipt.block_number)
+								.ok_or_else(|| {
+									format!(
+										"receipt of side transaction {:?} has no block number",
+										self.transaction_hash
+									)
+								})?
+								.as_u64();
+							let status = SideToMainStatus {
+								message,
+								message_hash,
+								signatures: 0,
+								required_signatures: self.side.required_signatures,
+								signed_message_tx_hash: None,
+								accepted_on_main: false,
+							};
+							self.await_signature(status, block)
+						}
+						(None, None) => {
+							if main_receipt.is_none() && side_receipt.is_none() {
+								bail!(
+									"transaction {:?} was found neither on main nor on side",
+									self.transaction_hash
+								);
+							}
+							bail!(
+								"transaction {:?} didn't log a `RelayMessage` event of the bridge contracts",
+								self.transaction_hash
+							);
+						}
+					}
+				}
+				State::AwaitMainToSideMessage {
+					ref mut future,
+					ref log,
+				} => {
+					let data = try_ready!(future
+						.poll()
+						.chain_err(|| "MessageStatusLookup: fetching message failed"));
+					let futures = self
+						.authorities
+						.iter()
+						.map(|authority| {
+							let (payload, decoder) = contracts::side::functions::has_authority_accepted_message_from_main::call(
+								self.transaction_hash,
+								data.clone(),
+								log.sender,
+								log.recipient,
+								*authority,
+							);
+							self.side.call(payload, decoder)
+						})
+						.collect::<Vec<_>>();
+					State::AwaitAcceptedFromMain {
+						future: join_all(futures),
+						log: log.clone(),
+						data,
+					}
+				}
+				State::AwaitAcceptedFromMain {
+					ref mut future,
+					ref log,
+					ref data,
+				} => {
+					let accepted = try_ready!(future.poll().chain_err(|| {
+						"MessageStatusLookup: checking acceptance by authorities failed"
+					}));
+					return Ok(Async::Ready(MessageStatus::MainToSide(MainToSideStatus {
+						main_tx_hash: self.transaction_hash,
+						message_id: log.message_id,
+						sender: log.sender,
+						recipient: log.recipient,
+						data: data.clone(),
+						accepted_by: self.authorities.iter().cloned().zip(accepted).collect(),
+						required_signatures: self.side.required_signatures,
+					})));
+				}
+				State::AwaitSignature {
+					ref mut future,
+					ref status,
+					block,
+				} => {
+					// the contract reverts for signatures that haven't been submitted yet
+					let signature = match future.poll() {
+						Ok(Async::NotReady) => return Ok(Async::NotReady),
+						Ok(Async::Ready(signature)) => signature,
+						Err(_) => Vec::new(),
+					};
+					let mut status = status.clone();
+					if signature.is_empty() {
+						self.await_signed_message_logs(status, block)
+					} else {
+						status.signatures += 1;
+						if status.signatures < status.required_signatures {
+							self.await_signature(status, block)
+						} else {
+							self.await_signed_message_logs(status, block)
+						}
+					}
+				}
+				State::AwaitSignedMessageLogs {
+					ref mut future,
+					ref status,
+				} => {
+					let logs = try_ready!(future
+						.poll()
+						.chain_err(|| "MessageStatusLookup: fetching SignedMessage logs failed"));
+					let mut status = status.clone();
+					status.signed_message_tx_hash = logs
+						.iter()
+						.filter(|log| {
+							helpers::parse_log(
+								contracts::side::events::signed_message::parse_log,
+								log,
+							)
+							.map(|log| log.message_hash == status.message_hash)
+							.unwrap_or(false)
+						})
+						.filter_map(|log| log.transaction_hash)
+						.next();
+					let (payload, decoder) =
+						contracts::main::functions::accepted_messages::call(status.message_hash);
+					State::AwaitAcceptedOnMain {
+						future: self.main.call(payload, decoder),
+						status,
+					}
+				}
+				State::AwaitAcceptedOnMain {
+					ref mut future,
+					ref status,
+				} => {
+					let accepted_on_main = try_ready!(future.poll().chain_err(|| {
+						"MessageStatusLookup: checking whether main accepted the message failed"
+					}));
+					let mut status = status.clone();
+					status.accepted_on_main = accepted_on_main;
+					return Ok(Async::Ready(MessageStatus::SideToMain(status)));
+				}
+			};
+
+			self.state = next_state;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use config::TransactionConfig;
+	use rustc_hex::ToHex;
+	use std::time::Duration;
+	use tokio_core::reactor::Core;
+
+	fn main_contract<T: Transport>(transport: T, contract_address: Address) -> MainContract<T> {
+		MainContract {
+			transport,
+			contract_address,
+			authority_address: "0000000000000000000000000000000000000001".parse().unwrap(),
+			request_timeout: Duration::from_millis(0),
+			logs_poll_interval: Duration::from_millis(0),
+			required_log_confirmations: 0,
+			submit_collected_signatures_tx: TransactionConfig::default(),
+			gas_estimate_multiplier: 1.2,
+			max_relay_cost: None,
+			eip1559_support: Default::default(),
+		}
+	}
+
+	fn side_contract<T: Transport>(transport: T, contract_address: Address) -> SideContract<T> {
+		SideContract {
+			transport,
+			contract_address,
+			authority_address: "0000000000000000000000000000000000000001".parse().unwrap(),
+			required_signatures: 2,
+			request_timeout: Duration::from_millis(0),
+			logs_poll_interval: Duration::from_millis(0),
+			required_log_confirmations: 0,
+			gas_estimate_multiplier: 1.2,
+			sign_main_to_side_tx: TransactionConfig::default(),
+			sign_side_to_main_tx: TransactionConfig::default(),
+			eip1559_support: Default::default(),
+		}
+	}
+
+	fn receipt(
+		tx_hash: H256,
+		log_address: Address,
+		topic: H256,
+		data: Vec<u8>,
+	) -> ::serde_json::Value {
+		json!({
+			"blockHash": "0xe0bdcf35b14a292d2998308d9b3fdea93a8c3d9c0b6c824c633fb9b15f9c3919",
+			"blockNumber": "0x10",
+			"contractAddress": null,
+			"cumulativeGasUsed": "0x1c1999",
+			"gasUsed": "0x5208",
+			"logs": [{
+				"address": format!("0x{:x}", log_address),
+				"topics": [format!("0x{:x}", topic)],
+				"data": format!("0x{}", data.to_hex::<String>()),
+				"transactionHash": format!("0x{:x}", tx_hash),
+			}],
+			"logsBloom": format!("0x{}", "0".repeat(512)),
+			"root": null,
+			"status": "0x1",
+			"transactionHash": format!("0x{:x}", tx_hash),
+			"transactionIndex": "0x4"
+		})
+	}
+
+	#[test]
+	fn test_message_status_main_to_side() {
+		let main_contract_address: Address =
+			"0000000000000000000000000000000000000dd2".parse().unwrap();
+		let side_contract_address: Address =
+			"0000000000000000000000000000000000000dd1".parse().unwrap();
+		let authorities: Vec<Address> = vec![
+			"0000000000000000000000000000000000000001".parse().unwrap(),
+			"0000000000000000000000000000000000000002".parse().unwrap(),
+		];
+		let tx_hash: H256 = "884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364"
+			.parse()
+			.unwrap();
+		let log = contracts::main::logs::RelayMessage {
+			message_id: "1db8f385535c0d178b8f40016048f3a3cffee8f94e68978ea4b277f57b638f0b"
+				.parse()
+				.unwrap(),
+			sender: "aff3454fce5edbc8cca8697c15331677e6ebdddd".parse().unwrap(),
+			recipient: "aff3454fce5edbc8cca8697c15331677e6ebcccc".parse().unwrap(),
+		};
+		let log_data = ethabi::encode(&[
+			ethabi::Token::FixedBytes(log.message_id.as_bytes().to_vec()),
+			ethabi::Token::Address(log.sender),
+			ethabi::Token::Address(log.recipient),
+		]);
+		let topic = contracts::main::events::relay_message::filter().topic0;
+		let data: Vec<u8> = vec![0x12, 0x34];
+
+		let main_transport = mock_transport!(
+			"eth_getTransactionReceipt" =>
+				req => json!([format!("0x{:x}", tx_hash)]),
+				res => receipt(tx_hash, main_contract_address, topic[0], log_data);
+			"eth_call" =>
+				req => json!([{
+					"data": format!("0x{}", contracts::main::functions::relayed_messages::encode_input(log.message_id).to_hex::<String>()),
+					"to": format!("0x{:x}", main_contract_address),
+				}, "latest"]),
+				res => json!(format!("0x{}", ethabi::encode(&[ethabi::Token::Bytes(data.clone())]).to_hex::<String>()));
+		);
+
+		let side_transport = mock_transport!(
+			"eth_getTransactionReceipt" =>
+				req => json!([format!("0x{:x}", tx_hash)]),
+				res => json!(null);
+			"eth_call" =>
+				req => json!([{
+					"data": format!("0x{}", contracts::side::functions::has_authority_accepted_message_from_main::encode_input(
+						tx_hash, data.clone(), log.sender, log.recipient, authorities[0]).to_hex::<String>()),
+					"to": format!("0x{:x}", side_contract_address),
+				}, "latest"]),
+				res => json!(format!("0x{}", ethabi::encode(&[ethabi::Token::Bool(true)]).to_hex::<String>()));
+			"eth_call" =>
+				req => json!([{
+					"data": format!("0x{}", contracts::side::functions::has_authority_accepted_message_from_main::encode_input(
+						tx_hash, data.clone(), log.sender, log.recipient, authorities[1]).to_hex::<String>()),
+					"to": format!("0x{:x}", side_contract_address),
+				}, "latest"]),
+				res => json!(format!("0x{}", ethabi::encode(&[ethabi::Token::Bool(false)]).to_hex::<String>()));
+		);
+
+		let future = MessageStatusLookup::new(
+			main_contract(main_transport.clone(), main_contract_address),
+			side_contract(side_transport.clone(), side_contract_address),
+			authorities.clone(),
+			tx_hash,
+		);
+
+		let mut event_loop = Core::new().unwrap();
+		let status = event_loop.run(future).unwrap();
+		assert_eq!(
+			status,
+			MessageStatus::MainToSide(MainToSideStatus {
+				main_tx_hash: tx_hash,
+				message_id: log.message_id,
+				sender: log.sender,
+				recipient: log.recipient,
+				data,
+				accepted_by: vec![(authorities[0], true), (authorities[1], false)],
+				required_signatures: 2,
+			})
+		);
+
+		assert_eq!(
+			main_transport.expected_requests(),
+			main_transport.actual_requests()
+		);
+		assert_eq!(
+			side_transport.expected_requests(),
+			side_transport.actual_requests()
+		);
+	}
+
+	#[test]
+	fn test_message_status_side_to_main() {
+		let main_contract_address: Address =
+			"0000000000000000000000000000000000000dd2".parse().unwrap();
+		let side_contract_address: Address =
+			"0000000000000000000000000000000000000dd1".parse().unwrap();
+		let tx_hash: H256 = "884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364"
+			.parse()
+			.unwrap();
+		let message = MessageToMain {
+			side_tx_hash: tx_hash,
+			message_id: "1db8f385535c0d178b8f40016048f3a3cffee8f94e68978ea4b277f57b638f0b"
+				.parse()
+				.unwrap(),
+			sender: "aff3454fce5edbc8cca8697c15331677e6ebdddd".parse().unwrap(),
+			recipient: "aff3454fce5edbc8cca8697c15331677e6ebcccc".parse().unwrap(),
+		};
+		let message_hash = message.keccak256();
+		let log_data = ethabi::encode(&[
+			ethabi::Token::FixedBytes(message.message_id.as_bytes().to_vec()),
+			ethabi::Token::Address(message.sender),
+			ethabi::Token::Address(message.recipient),
+		]);
+		let topic = contracts::side::events::relay_message::filter().topic0;
+		let signed_message_topic =
+			contracts::side::events::signed_message::filter(ethabi::Topic::Any).topic0;
+		let signature = vec![0x11; 65];
+
+		let main_transport = mock_transport!(
+			"eth_getTransactionReceipt" =>
+				req => json!([format!("0x{:x}", tx_hash)]),
+				res => json!(null);
+			"eth_call" =>
+				req => json!([{
+					"data": format!("0x{}", contracts::main::functions::accepted_messages::encode_input(message_hash).to_hex::<String>()),
+					"to": format!("0x{:x}", main_contract_address),
+				}, "latest"]),
+				res => json!(format!("0x{}", ethabi::encode(&[ethabi::Token::Bool(false)]).to_hex::<String>()));
+		);
+
+		let side_transport = mock_transport!(
+			"eth_getTransactionReceipt" =>
+				req => json!([format!("0x{:x}", tx_hash)]),
+				res => receipt(tx_hash, side_contract_address, topic[0], log_data);
+			"eth_call" =>
+				req => json!([{
+					"data": format!("0x{}", contracts::side::functions::signature::encode_input(message_hash, 0).to_hex::<String>()),
+					"to": format!("0x{:x}", side_contract_address),
+				}, "latest"]),
+				res => json!(format!("0x{}", ethabi::encode(&[ethabi::Token::Bytes(signature)]).to_hex::<String>()));
+			// the second signature hasn't been submitted yet so the call reverts
+			"eth_call" =>
+				req => json!([{
+					"data": format!("0x{}", contracts::side::functions::signature::encode_input(message_hash, 1).to_hex::<String>()),
+					"to": format!("0x{:x}", side_contract_address),
+				}, "latest"]),
+				res => json!("0x");
+			"eth_getLogs" =>
+				req => json!([{
+					"address": format!("0x{:x}", side_contract_address),
+					"fromBlock": "0x10",
+					"topics": [signed_message_topic]
+				}]),
+				res => json!([]);
+		);
+
+		let future = MessageStatusLookup::new(
+			main_contract(main_transport.clone(), main_contract_address),
+			side_contract(side_transport.clone(), side_contract_address),
+			vec![],
+			tx_hash,
+		);
+
+		let mut event_loop = Core::new().unwrap();
+		let status = event_loop.run(future).unwrap();
+		assert_eq!(
+			status,
+			MessageStatus::SideToMain(SideToMainStatus {
+				message,
+				message_hash,
+				signatures: 1,
+				required_signatures: 2,
+				signed_message_tx_hash: None,
+				accepted_on_main: false,
+			})
+		);
+
+		assert_eq!(
+			main_transport.expected_requests(),
+			main_transport.actual_requests()
+		);
+		assert_eq!(
+			side_transport.expected_requests(),
+			side_transport.actual_requests()
+		);
+	}
+}
@@ -51,6 +51,12 @@ use futures::{Async, Future, Poll, Stream};
 ///
 /// items with the same `order` are yielded in the order they were `insert`ed.
 ///
+/// `OrderedStream::with_max_in_flight(n)` bounds the number of futures that are
+/// polled concurrently: only the first `n` (in insertion order) futures that have not yet
+/// completed are polled. the others are queued and not polled before
+/// a slot frees up. use it with futures that only start their work
+/// once polled for the first time.
+///
 /// example in the context of the bridge:
 /// a `RelayStream` polls a Stream of logs
 /// calls a ... for every log and yields the block number for
@@ -62,13 +68,25 @@ use futures::{Async, Future, Poll, Stream};
 
 pub struct OrderedStream<O, F: Future> {
 	entries: Vec<Entry<O, F>>,
+	max_in_flight: Option<usize>,
 }
 
 impl<O: Ord, F: Future> OrderedStream<O, F> {
-	/// returns a new empty `OrderedStream`
+	/// returns a new empty `OrderedStream` that polls all inserted futures
 	pub fn new() -> Self {
 		Self {
 			entries: Vec::new(),
+			max_in_flight: None,
+		}
+	}
+
+	/// returns a new empty `OrderedStream` that polls
+	/// at most `max_in_flight` not yet completed futures at a time
+	pub fn with_max_in_flight(max_in_flight: usize) -> Self {
+		assert!(max_in_flight > 0, "max_in_flight must be greater than 0");
+		Self {
+			entries: Vec::new(),
+			max_in_flight: Some(max_in_flight),
 		}
 	}
 
@@ -92,7 +110,8 @@ impl<O: Ord, F: Future> OrderedStream<O, F> {
 			.count()
 	}
 
-	/// returns the count of futures that have not yet completed
+	/// returns the count of futures that have not yet completed.
+	/// includes futures that are queued because of `max_in_flight`
 	pub fn not_ready_count(&self) -> usize {
 		self.entries
 			.iter()
@@ -112,13 +131,21 @@ impl<O: Ord + Clone, F: Future> Stream for OrderedStream<O, F> {
 		let mut maybe_min_not_ready: Option<O> = None;
 		// the index (in entries) of the completed order with the lowest order
 		let mut maybe_min_ready: Option<(O, usize)> = None;
+		// count of polled futures that are still not ready
+		let mut in_flight = 0;
 
 		for (index, entry) in self.entries.iter_mut().enumerate() {
 			// poll futures which are not ready without every polling any future twice.
 			if !entry.item_if_ready.is_some() {
-				if let Async::Ready(item) = entry.future.poll()? {
-					entry.item_if_ready = Some(item);
-				} else {
+				let has_free_slot = self.max_in_flight.map_or(true, |max| in_flight < max);
+				if has_free_slot {
+					if let Async::Ready(item) = entry.future.poll()? {
+						entry.item_if_ready = Some(item);
+					} else {
+						in_flight += 1;
+					}
+				}
+				if entry.item_if_ready.is_none() {
 					maybe_min_not_ready = maybe_min_not_ready
 						.map(|x| x.min(entry.order.clone()))
 						.or(Some(entry.order.clone()));
@@ -153,8 +180,9 @@ impl<O: Ord + Clone, F: Future> Stream for OrderedStream<O, F> {
 			}
 		}
 
-		// this is O(1)
-		let entry_to_yield = self.entries.swap_remove(min_ready_index);
+		// `remove` rather than `swap_remove` keeps the insertion order
+		// which items with the same order and `max_in_flight` rely on
+		let entry_to_yield = self.entries.remove(min_ready_index);
 
 		Ok(Async::Ready(Some((
 			entry_to_yield.order,
@@ -180,11 +208,239 @@ mod tests {
 	extern crate tokio_timer;
 	use futures::stream::Stream;
 	use futures::Future;
+	use quickcheck::TestResult;
+	use std::cell::Cell;
+	use std::rc::Rc;
 	use std::time::Duration;
 
-	// TODO test multiple ready at same time
-	//
-	// TODO all are ready. none are not ready
+	/// a future that resolves with `id` (fails with `id`)
+	/// once `state` is set to `Some(true)` (`Some(false)`)
+	struct Controlled {
+		id: usize,
+		state: Rc<Cell<Option<bool>>>,
+	}
+
+	impl Future for Controlled {
+		type Item = usize;
+		type Error = usize;
+
+		fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+			match self.state.get() {
+				None => Ok(Async::NotReady),
+				Some(true) => Ok(Async::Ready(self.id)),
+				Some(false) => Err(self.id),
+			}
+		}
+	}
+
+	/// model of an entry of an `OrderedStream`
+	struct ModelEntry {
+		order: u8,
+		state: Rc<Cell<Option<bool>>>,
+		yielded: bool,
+	}
+
+	/// polls `ordered_stream` until it's not ready and checks every
+	/// result against the model `entries`.
+	/// returns whether the stream failed or a description of the first violation
+	fn poll_and_check(
+		ordered_stream: &mut OrderedStream<u8, Controlled>,
+		entries: &mut [ModelEntry],
+	) -> Result<bool, String> {
+		loop {
+			match ordered_stream.poll() {
+				Ok(Async::Ready(Some((order, id)))) => {
+					let entry = &entries[id];
+					if entry.yielded || entry.state.get() != Some(true) || entry.order != order {
+						return Err(format!(
+							"yielded {} with order {} which is not completed",
+							id, order
+						));
+					}
+					if let Some(lower) = entries.iter().position(|x| !x.yielded && x.order < order)
+					{
+						return Err(format!(
+							"yielded {} with order {} before {} with order {}",
+							id, order, lower, entries[lower].order
+						));
+					}
+					entries[id].yielded = true;
+				}
+				Ok(Async::Ready(None)) => return Err("stream ended".into()),
+				Ok(Async::NotReady) => {
+					let not_yielded = || entries.iter().filter(|x| !x.yielded);
+					let min_pending = not_yielded()
+						.filter(|x| x.state.get().is_none())
+						.map(|x| x.order)
+						.min();
+					let min_ready = not_yielded()
+						.filter(|x| x.state.get() == Some(true))
+						.map(|x| x.order)
+						.min();
+					if let Some(ready) = min_ready {
+						if min_pending.map_or(true, |pending| ready <= pending) {
+							return Err(format!("not ready although order {} is ready", ready));
+						}
+					}
+					return Ok(false);
+				}
+				Err(id) => {
+					if entries[id].state.get() != Some(false) {
+						return Err(format!("failed with {} which didn't fail", id));
+					}
+					return Ok(true);
+				}
+			}
+		}
+	}
+
+	quickcheck! {
+		fn quickcheck_ordered_stream_yields_completed_futures_in_order(
+			operations: Vec<(bool, u8, u8)>
+		) -> TestResult {
+			let mut ordered_stream = OrderedStream::new();
+			let mut entries: Vec<ModelEntry> = Vec::new();
+
+			for (insert, value, failure) in operations {
+				// roughly one in 16 completions fails
+				let fails = failure % 16 == 0;
+				if insert {
+					let state = Rc::new(Cell::new(None));
+					let id = entries.len();
+					ordered_stream.insert(value % 8, Controlled { id, state: state.clone() });
+					entries.push(ModelEntry { order: value % 8, state, yielded: false });
+				} else {
+					let pending: Vec<usize> = (0..entries.len())
+						.filter(|&index| entries[index].state.get().is_none())
+						.collect();
+					if pending.is_empty() {
+						continue;
+					}
+					entries[pending[value as usize % pending.len()]].state.set(Some(!fails));
+				}
+
+				match poll_and_check(&mut ordered_stream, &mut entries) {
+					Err(violation) => return TestResult::error(violation),
+					// the bridge stops once a relay fails
+					Ok(true) => return TestResult::passed(),
+					Ok(false) if fails && !insert => return TestResult::error("failure was not yielded"),
+					Ok(false) => {}
+				}
+			}
+
+			for entry in &entries {
+				if entry.state.get().is_none() {
+					entry.state.set(Some(true));
+				}
+			}
+			match poll_and_check(&mut ordered_stream, &mut entries) {
+				Err(violation) => return TestResult::error(violation),
+				Ok(true) => return TestResult::error("failed although no future failed"),
+				Ok(false) => {}
+			}
+
+			if entries.iter().any(|entry| !entry.yielded) {
+				return TestResult::error("not all completed futures were yielded");
+			}
+			if ordered_stream.ready_count() + ordered_stream.not_ready_count() != 0 {
+				return TestResult::error("yielded futures were not removed");
+			}
+			TestResult::passed()
+		}
+
+		fn quickcheck_ordered_stream_yields_monotonic_orders(
+			orders: Vec<u8>,
+			completion_keys: Vec<u8>,
+			max_in_flight: u8
+		) -> TestResult {
+			let mut ordered_stream = match max_in_flight % 4 {
+				0 => OrderedStream::new(),
+				max_in_flight => OrderedStream::with_max_in_flight(max_in_flight as usize),
+			};
+			let states: Vec<Rc<Cell<Option<bool>>>> =
+				orders.iter().map(|_| Rc::new(Cell::new(None))).collect();
+			for (id, &order) in orders.iter().enumerate() {
+				ordered_stream.insert(order, Controlled { id, state: states[id].clone() });
+			}
+
+			let mut completion_order: Vec<usize> = (0..orders.len()).collect();
+			completion_order.sort_by_key(|&id| completion_keys.get(id).cloned().unwrap_or(0));
+
+			let mut yielded: Vec<u8> = Vec::new();
+			for id in completion_order {
+				states[id].set(Some(true));
+				loop {
+					match ordered_stream.poll() {
+						Ok(Async::Ready(Some((order, id)))) => {
+							if yielded.last().map_or(false, |&last| order < last) {
+								return TestResult::error(format!("yielded order {} after {:?}", order, yielded));
+							}
+							let pending_lower = orders
+								.iter()
+								.zip(&states)
+								.any(|(&other, state)| other < order && state.get().is_none());
+							if pending_lower || states[id].get() != Some(true) {
+								return TestResult::error(format!("yielded order {} too early", order));
+							}
+							yielded.push(order);
+						}
+						Ok(Async::NotReady) => break,
+						other => return TestResult::error(format!("unexpected {:?}", other)),
+					}
+				}
+			}
+
+			let mut sorted = orders.clone();
+			sorted.sort();
+			if yielded != sorted {
+				return TestResult::error(format!("yielded {:?} instead of {:?}", yielded, sorted));
+			}
+			TestResult::passed()
+		}
+	}
+
+	#[test]
+	fn test_ordered_stream_all_ready() {
+		let mut ordered_stream: OrderedStream<u32, futures::future::FutureResult<&str, ()>> =
+			OrderedStream::new();
+		ordered_stream.insert(3, futures::future::ok("c"));
+		ordered_stream.insert(1, futures::future::ok("a"));
+		ordered_stream.insert(2, futures::future::ok("b"));
+
+		let results = ordered_stream.take(3).collect().wait().unwrap();
+		assert_eq!(results, vec![(1, "a"), (2, "b"), (3, "c")]);
+	}
+
+	#[test]
+	fn test_ordered_stream_multiple_ready_at_same_time() {
+		let mut ordered_stream = OrderedStream::new();
+		let states: Vec<Rc<Cell<Option<bool>>>> =
+			(0..4).map(|_| Rc::new(Cell::new(None))).collect();
+		for (id, &order) in [4u32, 1, 3, 2].iter().enumerate() {
+			ordered_stream.insert(
+				order,
+				Controlled {
+					id,
+					state: states[id].clone(),
+				},
+			);
+		}
+
+		// all but the lowest order are ready
+		states[0].set(Some(true));
+		states[2].set(Some(true));
+		states[3].set(Some(true));
+		assert_eq!(ordered_stream.poll(), Ok(Async::NotReady));
+		assert_eq!(ordered_stream.ready_count(), 3);
+		assert_eq!(ordered_stream.not_ready_count(), 1);
+
+		states[1].set(Some(true));
+		assert_eq!(ordered_stream.poll(), Ok(Async::Ready(Some((1, 1)))));
+		assert_eq!(ordered_stream.poll(), Ok(Async::Ready(Some((2, 3)))));
+		assert_eq!(ordered_stream.poll(), Ok(Async::Ready(Some((3, 2)))));
+		assert_eq!(ordered_stream.poll(), Ok(Async::Ready(Some((4, 0)))));
+		assert_eq!(ordered_stream.poll(), Ok(Async::NotReady));
+	}
 
 	#[test]
 	fn test_empty_ordered_stream_is_not_ready() {
@@ -315,4 +571,52 @@ mod tests {
 			]
 		);
 	}
+
+	#[test]
+	fn test_ordered_stream_with_max_in_flight() {
+		let mut ordered_stream: OrderedStream<
+			u32,
+			Box<dyn Future<Item = &str, Error = tokio_timer::TimerError>>,
+		> = OrderedStream::with_max_in_flight(2);
+
+		let timer = tokio_timer::Timer::default();
+		let in_flight = Rc::new(Cell::new(0));
+		let max_seen_in_flight = Rc::new(Cell::new(0));
+
+		for &(order, millis, name) in &[
+			(1, 30, "a"),
+			(1, 0, "b"),
+			(2, 10, "c"),
+			(3, 0, "d"),
+			(3, 20, "e"),
+		] {
+			let started = in_flight.clone();
+			let max_seen = max_seen_in_flight.clone();
+			let finished = in_flight.clone();
+			let sleep = timer.sleep(Duration::from_millis(millis));
+			let future = futures::future::lazy(move || {
+				// only runs once the future is polled for the first time
+				started.set(started.get() + 1);
+				max_seen.set(max_seen.get().max(started.get()));
+				sleep
+			})
+			.map(move |_| {
+				finished.set(finished.get() - 1);
+				name
+			});
+			ordered_stream.insert(order, Box::new(future));
+		}
+
+		assert_eq!(ordered_stream.not_ready_count(), 5);
+
+		let mut event_loop = tokio_core::reactor::Core::new().unwrap();
+
+		let results = event_loop.run(ordered_stream.take(5).collect()).unwrap();
+		assert_eq!(
+			results,
+			vec![(1, "a"), (1, "b"), (2, "c"), (3, "d"), (3, "e")]
+		);
+		assert_eq!(max_seen_in_flight.get(), 2);
+		assert_eq!(in_flight.get(), 0);
+	}
 }
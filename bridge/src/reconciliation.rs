@@ -0,0 +1,759 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity-Bridge.
+
+// Parity-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! reconciling the messages sent on both chains with their acceptance on the other chain
+
+use contracts;
+use database::RelayDirection;
+use error::{self, ResultExt};
+use futures::future::{join_all, FromErr, Join, JoinAll};
+use futures::stream::Collect;
+use futures::{Async, Future, Poll, Stream};
+use helpers::{self, AsyncCall};
+use log_stream::{LogStream, LogsInBlockRange};
+use main_contract::MainContract;
+use message_to_main::MessageToMain;
+use serde_json;
+use side_contract::SideContract;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio_timer::{Timeout, Timer};
+use web3::api::Namespace;
+use web3::helpers::{self as web3_helpers, CallFuture};
+use web3::types::{Address, BlockNumber, Log, H256, U256, U64};
+use web3::{self, Transport};
+
+/// where a message stands in a reconciliation report
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReconciledStatus {
+	/// accepted on the other chain
+	Completed,
+	/// not yet accepted on the other chain
+	Pending,
+	/// not accepted on the other chain for longer than `stuck_after`
+	Stuck,
+}
+
+impl ReconciledStatus {
+	fn name(&self) -> &'static str {
+		match *self {
+			ReconciledStatus::Completed => "completed",
+			ReconciledStatus::Pending => "pending",
+			ReconciledStatus::Stuck => "stuck",
+		}
+	}
+}
+
+/// a message sent on one chain joined with its acceptance state on the other chain
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct ReconciledMessage {
+	pub direction: RelayDirection,
+	/// transaction that sent the message
+	pub transaction_hash: H256,
+	pub block_number: u64,
+	pub message_id: H256,
+	pub sender: Address,
+	pub recipient: Address,
+	pub status: ReconciledStatus,
+	/// seconds since the block of `transaction_hash` was mined
+	pub age: u64,
+}
+
+/// all messages sent on both chains in the scanned block ranges
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct ReconciliationReport {
+	pub messages: Vec<ReconciledMessage>,
+}
+
+impl ReconciliationReport {
+	pub fn count(&self, status: ReconciledStatus) -> usize {
+		self.messages
+			.iter()
+			.filter(|message| message.status == status)
+			.count()
+	}
+
+	pub fn to_json(&self) -> String {
+		serde_json::to_string_pretty(self).expect("serialization can't fail; qed")
+	}
+
+	/// one line per message preceded by a header line
+	pub fn to_csv(&self) -> String {
+		let mut csv = String::from(
+			"direction,transaction_hash,block_number,message_id,sender,recipient,status,age\n",
+		);
+		for message in &self.messages {
+			csv.push_str(&format!(
+				"{},{:?},{},{:?},{:?},{:?},{},{}\n",
+				message.direction.name(),
+				message.transaction_hash,
+				message.block_number,
+				message.message_id,
+				message.sender,
+				message.recipient,
+				message.status.name(),
+				message.age
+			));
+		}
+		csv
+	}
+}
+
+/// options for creating a `Reconciliation`. passed to `Reconciliation::new`
+pub struct ReconciliationOptions<T> {
+	pub main: MainContract<T>,
+	pub side: SideContract<T>,
+	/// the authorities whose acceptance of main to side messages is checked
+	pub authorities: Vec<Address>,
+	/// usually `State::main_deployed_at_block`
+	pub main_from_block: u64,
+	/// usually `State::side_deployed_at_block`
+	pub side_from_block: u64,
+	/// number of blocks whose logs are fetched per `eth_getLogs` request
+	pub block_window: u64,
+	/// messages that have not been accepted for longer are `Stuck`
+	pub stuck_after: Duration,
+	/// unix timestamp the ages of the messages are computed at
+	pub now: u64,
+}
+
+/// response of `eth_getBlockByNumber`.
+/// only the fields needed to compute the age of messages
+#[derive(Debug, Deserialize)]
+struct BlockTimestamp {
+	timestamp: U256,
+}
+
+/// a message found in a `RelayMessage` log
+#[derive(Debug, Clone)]
+struct SentMessage {
+	direction: RelayDirection,
+	transaction_hash: H256,
+	block_number: u64,
+	message_id: H256,
+	sender: Address,
+	recipient: Address,
+}
+
+type Request<T, R> = Timeout<FromErr<CallFuture<R, <T as Transport>::Out>, error::Error>>;
+
+type AcceptedFromMain<T> = JoinAll<
+	Vec<
+		AsyncCall<T, contracts::side::functions::has_authority_accepted_message_from_main::Decoder>,
+	>,
+>;
+
+enum MainToSideAcceptanceState<T: Transport> {
+	AwaitData(AsyncCall<T, contracts::main::functions::relayed_messages::Decoder>),
+	AwaitAccepted(AcceptedFromMain<T>),
+}
+
+/// `Future` that resolves with how many of the `authorities` have
+/// accepted a main to side message on side
+struct MainToSideAcceptance<T: Transport> {
+	side: SideContract<T>,
+	authorities: Vec<Address>,
+	message: SentMessage,
+	state: MainToSideAcceptanceState<T>,
+}
+
+impl<T: Transport> MainToSideAcceptance<T> {
+	fn new(
+		main: &MainContract<T>,
+		side: SideContract<T>,
+		authorities: Vec<Address>,
+		message: SentMessage,
+	) -> Self {
+		let future = main.relayed_message_by_id(message.message_id);
+		Self {
+			side,
+			authorities,
+			message,
+			state: MainToSideAcceptanceState::AwaitData(future),
+		}
+	}
+}
+
+impl<T: Transport> Future for MainToSideAcceptance<T> {
+	type Item = usize;
+	type Error = error::Error;
+
+	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+		loop {
+			let next_state = match self.state {
+				MainToSideAcceptanceState::AwaitData(ref mut future) => {
+					let data = try_ready!(future
+						.poll()
+						.chain_err(|| "MainToSideAcceptance: fetching message data failed"));
+					let futures = self
+						.authorities
+						.iter()
+						.map(|authority| {
+							let (payload, decoder) = contracts::side::functions::has_authority_accepted_message_from_main::call(
+								self.message.transaction_hash,
+								data.clone(),
+								self.message.sender,
+								self.message.recipient,
+								*authority,
+							);
+							self.side.call(payload, decoder)
+						})
+						.collect::<Vec<_>>();
+					MainToSideAcceptanceState::AwaitAccepted(join_all(futures))
+				}
+				MainToSideAcceptanceState::AwaitAccepted(ref mut future) => {
+					let accepted = try_ready!(future.poll().chain_err(|| {
+						"MainToSideAcceptance: checking acceptance by authorities failed"
+					}));
+					return Ok(Async::Ready(
+						accepted.into_iter().filter(|accepted| *accepted).count(),
+					));
+				}
+			};
+			self.state = next_state;
+		}
+	}
+}
+
+enum State<T: Transport> {
+	AwaitBlockNumbers(Join<Request<T, U64>, Request<T, U64>>),
+	AwaitLogs(Join<Collect<LogStream<T>>, Collect<LogStream<T>>>),
+	AwaitTimestamps {
+		future: Join<
+			JoinAll<Vec<Request<T, Option<BlockTimestamp>>>>,
+			JoinAll<Vec<Request<T, Option<BlockTimestamp>>>>,
+		>,
+		main_blocks: Vec<u64>,
+		side_blocks: Vec<u64>,
+	},
+	AwaitAcceptance {
+		future: Join<
+			JoinAll<Vec<MainToSideAcceptance<T>>>,
+			JoinAll<Vec<AsyncCall<T, contracts::main::functions::accepted_messages::Decoder>>>,
+		>,
+		timestamps: BTreeMap<(RelayDirection, u64), u64>,
+	},
+}
+
+/// `Future` that fetches all `RelayMessage` logs of both contracts
+/// up to the blocks that have the required confirmations,
+/// checks whether the messages have been accepted on the other chain
+/// and resolves with a `ReconciliationReport` of them
+pub struct Reconciliation<T: Transport> {
+	options: ReconciliationOptions<T>,
+	timer: Timer,
+	main_to_side: Vec<SentMessage>,
+	side_to_main: Vec<SentMessage>,
+	state: State<T>,
+}
+
+impl<T: Transport> Reconciliation<T> {
+	pub fn new(options: ReconciliationOptions<T>) -> Self {
+		let timer = Timer::default();
+		let main_block = web3::api::Eth::new(&options.main.transport).block_number();
+		let side_block = web3::api::Eth::new(&options.side.transport).block_number();
+		let future = timer
+			.timeout(main_block.from_err(), options.main.request_timeout)
+			.join(timer.timeout(side_block.from_err(), options.side.request_timeout));
+		Self {
+			options,
+			timer,
+			main_to_side: Vec::new(),
+			side_to_main: Vec::new(),
+			state: State::AwaitBlockNumbers(future),
+		}
+	}
+
+	/// the logs of `log_stream` up to the last block with `confirmations`
+	/// when the reconciliation started
+	fn confirmed_logs(
+		&self,
+		log_stream: LogStream<T>,
+		last_block: U64,
+		confirmations: u32,
+	) -> Collect<LogStream<T>> {
+		log_stream
+			.with_until(last_block.as_u64().saturating_sub(confirmations as u64))
+			.with_block_window(self.options.block_window)
+			.collect()
+	}
+
+	fn block_timestamps(
+		&self,
+		transport: &T,
+		request_timeout: Duration,
+		blocks: &[u64],
+	) -> JoinAll<Vec<Request<T, Option<BlockTimestamp>>>> {
+		let futures = blocks
+			.iter()
+			.map(|block| {
+				let future = CallFuture::new(transport.execute(
+					"eth_getBlockByNumber",
+					vec![
+						web3_helpers::serialize(&BlockNumber::from(*block)),
+						web3_helpers::serialize(&false),
+					],
+				));
+				self.timer.timeout(future.from_err(), request_timeout)
+			})
+			.collect::<Vec<_>>();
+		join_all(futures)
+	}
+
+	fn status(&self, accepted: bool, timestamp: u64) -> (ReconciledStatus, u64) {
+		let age = self.options.now.saturating_sub(timestamp);
+		let status = if accepted {
+			ReconciledStatus::Completed
+		} else if age > self.options.stuck_after.as_secs() {
+			ReconciledStatus::Stuck
+		} else {
+			ReconciledStatus::Pending
+		};
+		(status, age)
+	}
+}
+
+/// the distinct block numbers of `messages` in ascending order
+fn distinct_blocks(messages: &[SentMessage]) -> Vec<u64> {
+	let mut blocks = messages
+		.iter()
+		.map(|message| message.block_number)
+		.collect::<Vec<_>>();
+	blocks.sort();
+	blocks.dedup();
+	blocks
+}
+
+fn logs_of(ranges: Vec<LogsInBlockRange>) -> Vec<Log> {
+	ranges.into_iter().flat_map(|range| range.logs).collect()
+}
+
+fn log_block_number(log: &Log) -> error::Result<u64> {
+	log.block_number
+		.map(|number| number.as_u64())
+		.ok_or_else(|| {
+			format!(
+				"log of transaction {:?} has no block number",
+				log.transaction_hash
+			)
+			.into()
+		})
+}
+
+impl<T: Transport> Future for Reconciliation<T> {
+	type Item = ReconciliationReport;
+	type Error = error::Error;
+
+	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+		loop {
+			let next_state = match self.state {
+				State::AwaitBlockNumbers(ref mut future) => {
+					let (main_block, side_block) = try_ready!(future
+						.poll()
+						.chain_err(|| "Reconciliation: fetching last block numbers failed"));
+					// the streams yield the logs of the blocks after `after`
+					let main_logs = self.confirmed_logs(
+						self.options.main.main_to_side_log_stream(
+							self.options.main_from_block.saturating_sub(1),
+						),
+						main_block,
+						self.options.main.required_log_confirmations,
+					);
+					let side_logs = self.confirmed_logs(
+						self.options.side.side_to_main_sign_log_stream(
+							self.options.side_from_block.saturating_sub(1),
+						),
+						side_block,
+						self.options.side.required_log_confirmations,
+					);
+					State::AwaitLogs(main_logs.join(side_logs))
+				}
+				State::AwaitLogs(ref mut future) => {
+					let (main_ranges, side_ranges) = try_ready!(future
+						.poll()
+						.chain_err(|| "Reconciliation: fetching logs failed"));
+					let main_logs = logs_of(main_ranges);
+					let side_logs = logs_of(side_ranges);
+					info!(
+						"Reconciliation: found {} messages on main and {} on side",
+						main_logs.len(),
+						side_logs.len()
+					);
+					for log in &main_logs {
+						let relay_message = helpers::parse_log(
+							contracts::main::events::relay_message::parse_log,
+							log,
+						)
+						.chain_err(|| "Reconciliation: decoding main RelayMessage log failed")?;
+						self.main_to_side.push(SentMessage {
+							direction: RelayDirection::MainToSide,
+							transaction_hash: log.transaction_hash.ok_or_else(|| {
+								"Reconciliation: main log has no transaction hash"
+							})?,
+							block_number: log_block_number(log)?,
+							message_id: relay_message.message_id,
+							sender: relay_message.sender,
+							recipient: relay_message.recipient,
+						});
+					}
+					for log in &side_logs {
+						let message = MessageToMain::from_log(log).chain_err(|| {
+							"Reconciliation: decoding side RelayMessage log failed"
+						})?;
+						self.side_to_main.push(SentMessage {
+							direction: RelayDirection::SideToMain,
+							transaction_hash: message.side_tx_hash,
+							block_number: log_block_number(log)?,
+							message_id: message.message_id,
+							sender: message.sender,
+							recipient: message.recipient,
+						});
+					}
+
+					let main_blocks = distinct_blocks(&self.main_to_side);
+					let side_blocks = distinct_blocks(&self.side_to_main);
+					let future = self
+						.block_timestamps(
+							&self.options.main.transport,
+							self.options.main.request_timeout,
+							&main_blocks,
+						)
+						.join(self.block_timestamps(
+							&self.options.side.transport,
+							self.options.side.request_timeout,
+							&side_blocks,
+						));
+					State::AwaitTimestamps {
+						future,
+						main_blocks,
+						side_blocks,
+					}
+				}
+				State::AwaitTimestamps {
+					ref mut future,
+					ref main_blocks,
+					ref side_blocks,
+				} => {
+					let (main_timestamps, side_timestamps) = try_ready!(future
+						.poll()
+						.chain_err(|| "Reconciliation: fetching block timestamps failed"));
+					let mut timestamps = BTreeMap::new();
+					for (direction, blocks, block_timestamps) in vec![
+						(RelayDirection::MainToSide, main_blocks, main_timestamps),
+						(RelayDirection::SideToMain, side_blocks, side_timestamps),
+					] {
+						for (block, timestamp) in blocks.iter().zip(block_timestamps) {
+							let timestamp = timestamp.ok_or_else(|| {
+								format!("Reconciliation: block {} was not found", block)
+							})?;
+							timestamps.insert((direction, *block), timestamp.timestamp.low_u64());
+						}
+					}
+
+					let main_to_side = self
+						.main_to_side
+						.iter()
+						.map(|message| {
+							MainToSideAcceptance::new(
+								&self.options.main,
+								self.options.side.clone(),
+								self.options.authorities.clone(),
+								message.clone(),
+							)
+						})
+						.collect::<Vec<_>>();
+					let side_to_main = self
+						.side_to_main
+						.iter()
+						.map(|message| {
+							let message_hash = MessageToMain {
+								side_tx_hash: message.transaction_hash,
+								message_id: message.message_id,
+								sender: message.sender,
+								recipient: message.recipient,
+							}
+							.keccak256();
+							let (payload, decoder) =
+								contracts::main::functions::accepted_messages::call(message_hash);
+							self.options.main.call(payload, decoder)
+						})
+						.collect::<Vec<_>>();
+					State::AwaitAcceptance {
+						future: join_all(main_to_side).join(join_all(side_to_main)),
+						timestamps,
+					}
+				}
+				State::AwaitAcceptance {
+					ref mut future,
+					ref timestamps,
+				} => {
+					let (accepted_by, accepted_on_main) = try_ready!(future
+						.poll()
+						.chain_err(|| "Reconciliation: checking acceptance failed"));
+					let required_signatures = self.options.side.required_signatures as usize;
+					let accepted = accepted_by
+						.into_iter()
+						.map(|count| count >= required_signatures)
+						.chain(accepted_on_main);
+					let messages = self
+						.main_to_side
+						.iter()
+						.chain(self.side_to_main.iter())
+						.zip(accepted)
+						.map(|(message, accepted)| {
+							let timestamp = timestamps[&(message.direction, message.block_number)];
+							let (status, age) = self.status(accepted, timestamp);
+							ReconciledMessage {
+								direction: message.direction,
+								transaction_hash: message.transaction_hash,
+								block_number: message.block_number,
+								message_id: message.message_id,
+								sender: message.sender,
+								recipient: message.recipient,
+								status,
+								age,
+							}
+						})
+						.collect();
+					return Ok(Async::Ready(ReconciliationReport { messages }));
+				}
+			};
+
+			self.state = next_state;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use config::TransactionConfig;
+	use ethabi;
+	use rustc_hex::ToHex;
+	use tokio_core::reactor::Core;
+
+	fn log_json(
+		address: Address,
+		topic: H256,
+		data: Vec<u8>,
+		tx_hash: H256,
+		block: u64,
+	) -> ::serde_json::Value {
+		json!({
+			"address": format!("0x{:x}", address),
+			"topics": [format!("0x{:x}", topic)],
+			"data": format!("0x{}", data.to_hex::<String>()),
+			"blockNumber": format!("0x{:x}", block),
+			"logIndex": "0x0",
+			"transactionHash": format!("0x{:x}", tx_hash),
+		})
+	}
+
+	#[test]
+	fn test_reconciliation_report() {
+		let main_contract_address: Address =
+			"0000000000000000000000000000000000000dd2".parse().unwrap();
+		let side_contract_address: Address =
+			"0000000000000000000000000000000000000dd1".parse().unwrap();
+		let authority: Address = "0000000000000000000000000000000000000001".parse().unwrap();
+		let sender: Address = "aff3454fce5edbc8cca8697c15331677e6ebdddd".parse().unwrap();
+		let recipient: Address = "aff3454fce5edbc8cca8697c15331677e6ebcccc".parse().unwrap();
+		let main_tx_hash = H256::from_low_u64_be(1);
+		let side_tx_hash = H256::from_low_u64_be(2);
+		let main_message_id = H256::from_low_u64_be(3);
+		let side_message_id = H256::from_low_u64_be(4);
+		let data: Vec<u8> = vec![0x12, 0x34];
+
+		let relay_message_data = |message_id: H256| {
+			ethabi::encode(&[
+				ethabi::Token::FixedBytes(message_id.as_bytes().to_vec()),
+				ethabi::Token::Address(sender),
+				ethabi::Token::Address(recipient),
+			])
+		};
+		let main_topic = contracts::main::events::relay_message::filter().topic0;
+		let side_topic = contracts::side::events::relay_message::filter().topic0;
+		let side_message_hash = MessageToMain {
+			side_tx_hash,
+			message_id: side_message_id,
+			sender,
+			recipient,
+		}
+		.keccak256();
+
+		let main_transport = mock_transport!(
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x32");
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x32");
+			"eth_getLogs" =>
+				req => json!([{
+					"address": format!("0x{:x}", main_contract_address),
+					"fromBlock": "0x5",
+					"toBlock": "0x24",
+					"topics": [main_topic]
+				}]),
+				res => json!([log_json(main_contract_address, main_topic[0], relay_message_data(main_message_id), main_tx_hash, 0x10)]);
+			"eth_getLogs" =>
+				req => json!([{
+					"address": format!("0x{:x}", main_contract_address),
+					"fromBlock": "0x25",
+					"toBlock": "0x30",
+					"topics": [main_topic]
+				}]),
+				res => json!([]);
+			"eth_getBlockByNumber" =>
+				req => json!(["0x10", false]),
+				res => json!({"timestamp": "0x3e8"});
+			"eth_call" =>
+				req => json!([{
+					"data": format!("0x{}", contracts::main::functions::relayed_messages::encode_input(main_message_id).to_hex::<String>()),
+					"to": format!("0x{:x}", main_contract_address),
+				}, "latest"]),
+				res => json!(format!("0x{}", ethabi::encode(&[ethabi::Token::Bytes(data.clone())]).to_hex::<String>()));
+			"eth_call" =>
+				req => json!([{
+					"data": format!("0x{}", contracts::main::functions::accepted_messages::encode_input(side_message_hash).to_hex::<String>()),
+					"to": format!("0x{:x}", main_contract_address),
+				}, "latest"]),
+				res => json!(format!("0x{}", ethabi::encode(&[ethabi::Token::Bool(false)]).to_hex::<String>()));
+		);
+
+		let side_transport = mock_transport!(
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x30");
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x30");
+			"eth_getLogs" =>
+				req => json!([{
+					"address": format!("0x{:x}", side_contract_address),
+					"fromBlock": "0x6",
+					"toBlock": "0x25",
+					"topics": [side_topic]
+				}]),
+				res => json!([log_json(side_contract_address, side_topic[0], relay_message_data(side_message_id), side_tx_hash, 0x20)]);
+			"eth_getLogs" =>
+				req => json!([{
+					"address": format!("0x{:x}", side_contract_address),
+					"fromBlock": "0x26",
+					"toBlock": "0x30",
+					"topics": [side_topic]
+				}]),
+				res => json!([]);
+			"eth_getBlockByNumber" =>
+				req => json!(["0x20", false]),
+				res => json!({"timestamp": "0x64"});
+			"eth_call" =>
+				req => json!([{
+					"data": format!("0x{}", contracts::side::functions::has_authority_accepted_message_from_main::encode_input(
+						main_tx_hash, data.clone(), sender, recipient, authority).to_hex::<String>()),
+					"to": format!("0x{:x}", side_contract_address),
+				}, "latest"]),
+				res => json!(format!("0x{}", ethabi::encode(&[ethabi::Token::Bool(true)]).to_hex::<String>()));
+		);
+
+		let main = MainContract {
+			transport: main_transport.clone(),
+			contract_address: main_contract_address,
+			authority_address: authority,
+			request_timeout: Duration::from_millis(0),
+			logs_poll_interval: Duration::from_millis(0),
+			required_log_confirmations: 2,
+			submit_collected_signatures_tx: TransactionConfig::default(),
+			gas_estimate_multiplier: 1.2,
+			max_relay_cost: None,
+			eip1559_support: Default::default(),
+		};
+		let side = SideContract {
+			transport: side_transport.clone(),
+			contract_address: side_contract_address,
+			authority_address: authority,
+			required_signatures: 1,
+			request_timeout: Duration::from_millis(0),
+			logs_poll_interval: Duration::from_millis(0),
+			required_log_confirmations: 0,
+			gas_estimate_multiplier: 1.2,
+			sign_main_to_side_tx: TransactionConfig::default(),
+			sign_side_to_main_tx: TransactionConfig::default(),
+			eip1559_support: Default::default(),
+		};
+
+		let future = Reconciliation::new(ReconciliationOptions {
+			main,
+			side,
+			authorities: vec![authority],
+			main_from_block: 5,
+			side_from_block: 6,
+			block_window: 0x20,
+			stuck_after: Duration::from_secs(500),
+			now: 1100,
+		});
+
+		let mut event_loop = Core::new().unwrap();
+		let report = event_loop.run(future).unwrap();
+		assert_eq!(
+			report,
+			ReconciliationReport {
+				messages: vec![
+					ReconciledMessage {
+						direction: RelayDirection::MainToSide,
+						transaction_hash: main_tx_hash,
+						block_number: 0x10,
+						message_id: main_message_id,
+						sender,
+						recipient,
+						status: ReconciledStatus::Completed,
+						age: 100,
+					},
+					ReconciledMessage {
+						direction: RelayDirection::SideToMain,
+						transaction_hash: side_tx_hash,
+						block_number: 0x20,
+						message_id: side_message_id,
+						sender,
+						recipient,
+						status: ReconciledStatus::Stuck,
+						age: 1000,
+					},
+				],
+			}
+		);
+		assert_eq!(report.count(ReconciledStatus::Stuck), 1);
+		assert_eq!(report.count(ReconciledStatus::Pending), 0);
+
+		let csv = report.to_csv();
+		assert_eq!(csv.lines().count(), 3);
+		assert!(csv.starts_with("direction,transaction_hash,"));
+		assert!(csv.lines().nth(2).unwrap().starts_with(
+			"side_to_main,0x0000000000000000000000000000000000000000000000000000000000000002,32,"
+		));
+		assert!(csv.lines().nth(2).unwrap().ends_with(",stuck,1000"));
+
+		assert_eq!(
+			main_transport.expected_requests(),
+			main_transport.actual_requests()
+		);
+		assert_eq!(
+			side_transport.expected_requests(),
+			side_transport.actual_requests()
+		);
+	}
+}
@@ -0,0 +1,265 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity-Bridge.
+
+// Parity-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! logging of the steps of the relay of a single message.
+//!
+//! every step is logged with target `RELAY_LOG_TARGET` and carries the
+//! relay direction, the hash of the transaction that sent the message,
+//! the message id, the step, the chain the step acts on and
+//! the hash of the transaction the step resulted in.
+//! steps are logged as text by default.
+//! after `set_json(true)` they are logged as JSON objects
+//! that `json_line` merges into its JSON log lines.
+
+use database::RelayDirection;
+use log::Level;
+use serde_json;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use web3::types::H256;
+
+/// log target of the steps of relays
+pub const RELAY_LOG_TARGET: &str = "bridge::relay";
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// log the steps of relays as JSON objects instead of text
+pub fn set_json(json: bool) {
+	JSON.store(json, Ordering::Relaxed);
+}
+
+/// chain a step of a relay acts on
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Chain {
+	Main,
+	Side,
+}
+
+/// a single logged step of a relay
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub struct RelayStep {
+	pub direction: RelayDirection,
+	/// hash of the transaction that sent the message on its origin chain
+	pub origin_tx_hash: Option<H256>,
+	pub message_id: Option<H256>,
+	pub step: String,
+	pub chain: Chain,
+	/// hash of the transaction sent by this step
+	pub result_tx_hash: Option<H256>,
+	pub message: String,
+}
+
+impl fmt::Display for RelayStep {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.origin_tx_hash {
+			Some(hash) => write!(f, "{:?}", hash)?,
+			None => write!(f, "unknown")?,
+		}
+		write!(f, " - {} - {}", self.step, self.message)?;
+		if let Some(hash) = self.result_tx_hash {
+			write!(f, " {:?}", hash)?;
+		}
+		Ok(())
+	}
+}
+
+/// logs the steps of the relay of a single message
+#[derive(Debug, Clone)]
+pub struct RelayLog {
+	pub direction: RelayDirection,
+	pub origin_tx_hash: Option<H256>,
+	pub message_id: Option<H256>,
+}
+
+impl RelayLog {
+	pub fn new(
+		direction: RelayDirection,
+		origin_tx_hash: Option<H256>,
+		message_id: Option<H256>,
+	) -> Self {
+		Self {
+			direction,
+			origin_tx_hash,
+			message_id,
+		}
+	}
+
+	pub fn to_step<S: Into<String>>(
+		&self,
+		step: &str,
+		chain: Chain,
+		result_tx_hash: Option<H256>,
+		message: S,
+	) -> RelayStep {
+		RelayStep {
+			direction: self.direction,
+			origin_tx_hash: self.origin_tx_hash,
+			message_id: self.message_id,
+			step: step.into(),
+			chain,
+			result_tx_hash,
+			message: message.into(),
+		}
+	}
+
+	pub fn log<S: Into<String>>(
+		&self,
+		level: Level,
+		step: &str,
+		chain: Chain,
+		result_tx_hash: Option<H256>,
+		message: S,
+	) {
+		if !log_enabled!(target: RELAY_LOG_TARGET, level) {
+			return;
+		}
+		let step = self.to_step(step, chain, result_tx_hash, message);
+		if JSON.load(Ordering::Relaxed) {
+			let json = serde_json::to_string(&step).expect("RelayStep always serializes; qed");
+			log!(target: RELAY_LOG_TARGET, level, "{}", json);
+		} else {
+			log!(target: RELAY_LOG_TARGET, level, "{}", step);
+		}
+	}
+
+	/// log a step that didn't send a transaction
+	pub fn step<S: Into<String>>(&self, step: &str, chain: Chain, message: S) {
+		self.log(Level::Info, step, chain, None, message);
+	}
+
+	/// log the step that sent transaction `result_tx_hash`
+	pub fn sent<S: Into<String>>(
+		&self,
+		step: &str,
+		chain: Chain,
+		result_tx_hash: H256,
+		message: S,
+	) {
+		self.log(Level::Info, step, chain, Some(result_tx_hash), message);
+	}
+}
+
+/// a log line as a JSON object with `timestamp`, `level`, `target` and `message`.
+/// if `message` is a `RelayStep` its fields are added to the object instead.
+pub fn json_line(timestamp: &str, level: Level, target: &str, message: &str) -> String {
+	let mut object = serde_json::Map::new();
+	object.insert("timestamp".into(), timestamp.into());
+	object.insert("level".into(), level.to_string().into());
+	object.insert("target".into(), target.into());
+
+	let step = if target == RELAY_LOG_TARGET {
+		serde_json::from_str::<RelayStep>(message).ok()
+	} else {
+		None
+	};
+	match step.and_then(|step| serde_json::to_value(step).ok()) {
+		Some(serde_json::Value::Object(fields)) => object.extend(fields),
+		_ => {
+			object.insert("message".into(), message.into());
+		}
+	}
+
+	serde_json::Value::Object(object).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn relay_log() -> RelayLog {
+		RelayLog::new(
+			RelayDirection::MainToSide,
+			Some(
+				"884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364"
+					.parse()
+					.unwrap(),
+			),
+			Some(
+				"1db8f385535c0d178b8f40016048f3a3cffee8f94e68978ea4b277f57b638f0b"
+					.parse()
+					.unwrap(),
+			),
+		)
+	}
+
+	#[test]
+	fn test_relay_step_to_string() {
+		let step = relay_log().to_step(
+			"done",
+			Chain::Side,
+			Some(
+				"0000000000000000000000000000000000000000000000000000000000000001"
+					.parse()
+					.unwrap(),
+			),
+			"accepted in",
+		);
+		assert_eq!(
+			step.to_string(),
+			"0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364 - done - accepted in 0x0000000000000000000000000000000000000000000000000000000000000001"
+		);
+	}
+
+	#[test]
+	fn test_json_line_with_relay_step() {
+		let step = relay_log().to_step("2/4", Chain::Side, None, "checking if accepted");
+		let message = serde_json::to_string(&step).unwrap();
+
+		let line = json_line(
+			"2019-11-01T10:00:00Z",
+			Level::Info,
+			RELAY_LOG_TARGET,
+			&message,
+		);
+		let expected = json!({
+			"timestamp": "2019-11-01T10:00:00Z",
+			"level": "INFO",
+			"target": "bridge::relay",
+			"direction": "main_to_side",
+			"origin_tx_hash": "0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364",
+			"message_id": "0x1db8f385535c0d178b8f40016048f3a3cffee8f94e68978ea4b277f57b638f0b",
+			"step": "2/4",
+			"chain": "side",
+			"result_tx_hash": null,
+			"message": "checking if accepted"
+		});
+		assert_eq!(
+			serde_json::from_str::<serde_json::Value>(&line).unwrap(),
+			expected
+		);
+	}
+
+	#[test]
+	fn test_json_line_with_text() {
+		let line = json_line(
+			"2019-11-01T10:00:00Z",
+			Level::Warn,
+			"parity_bridge",
+			"Started polling logs",
+		);
+		let expected = json!({
+			"timestamp": "2019-11-01T10:00:00Z",
+			"level": "WARN",
+			"target": "parity_bridge",
+			"message": "Started polling logs"
+		});
+		assert_eq!(
+			serde_json::from_str::<serde_json::Value>(&line).unwrap(),
+			expected
+		);
+	}
+}
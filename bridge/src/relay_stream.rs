@@ -17,89 +17,576 @@
 ///
 /// where a "relay" is the detection of an event on chain A
 /// followed by a transaction on chain B
+use database::{HeldMessage, QuarantinedLog, RelayCost};
 use error::{self, ResultExt};
 use futures::{Async, Future, Poll, Stream};
-use log_stream::LogsInBlockRange;
+use log_stream::{LogPosition, LogsInBlockRange};
+use std::cell::{Cell, RefCell, RefMut};
+use std::rc::Rc;
 use web3::types::Log;
 use OrderedStream;
 
+/// what a relay future resolves with
+#[derive(Debug, PartialEq, Clone)]
+pub enum RelayOutcome {
+	/// the relay has already happened. nothing was sent
+	AlreadyRelayed,
+	/// the relay transaction was sent by this authority and got mined
+	Relayed(RelayCost),
+	/// the relay was not sent because it is estimated to cost more than
+	/// `max_main_relay_cost`
+	Held(HeldMessage),
+	/// the log could not be turned into a relay (for example because it
+	/// couldn't be decoded or the relay transaction kept reverting). nothing was sent
+	Quarantined(QuarantinedLog),
+}
+
 /// something that can create relay futures from logs.
 /// to be called by `RelayStream` for every log.
 pub trait LogToFuture {
-	type Future: Future<Error = error::Error>;
+	type Future: Future<Item = RelayOutcome, Error = error::Error>;
+
+	/// fails if `log` can't be relayed (for example because it can't be decoded)
+	fn log_to_future(&self, log: &Log) -> error::Result<Self::Future>;
+}
+
+/// how often the relay transaction of a log may be estimated to revert,
+/// each time in a later block, before the log is quarantined.
+/// a relay can revert for a while without being broken, for example until
+/// the other authorities' signatures for it have been mined
+const MAX_RELAY_REVERTS: u32 = 3;
+
+/// how far a `RelayStream` has fetched logs. shared with its relays
+/// so reverted relays know when to retry
+#[derive(Debug, Clone, Copy, Default)]
+struct LogsProgress {
+	/// the last block whose logs have been fetched
+	checked_until: u64,
+	/// whether no more logs will be fetched
+	ended: bool,
+}
+
+/// relay future that is only created from its log when polled for the first time.
+/// relays that are queued in the `OrderedStream` of a `RelayStream`
+/// therefore don't send any requests.
+/// resolves with `RelayOutcome::Quarantined` if no relay future
+/// can be created from the log or if its relay transaction would revert
+/// `MAX_RELAY_REVERTS` times.
+struct LazyRelay<F: LogToFuture> {
+	log_to_future: Rc<RefCell<F>>,
+	logs_progress: Rc<Cell<LogsProgress>>,
+	state: LazyRelayState<F::Future>,
+}
+
+enum LazyRelayState<R> {
+	/// the log and how often its relay reverted so far
+	Queued(Log, u32),
+	Running(Log, u32, R),
+	/// the relay reverted while logs were fetched up to the block.
+	/// it's retried once logs of a later block have been fetched
+	Reverted(Log, u32, u64, error::Error),
+}
+
+impl<F: LogToFuture> LazyRelay<F> {
+	fn quarantine(log: &Log, err: &error::Error) -> Poll<RelayOutcome, error::Error> {
+		let quarantined = QuarantinedLog::new(log, err);
+		warn!("quarantining log that can't be relayed: {:?}", quarantined);
+		Ok(Async::Ready(RelayOutcome::Quarantined(quarantined)))
+	}
+}
+
+impl<F: LogToFuture> Future for LazyRelay<F> {
+	type Item = RelayOutcome;
+	type Error = error::Error;
+
+	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+		loop {
+			let next_state = match self.state {
+				LazyRelayState::Queued(ref log, reverts) => {
+					match self.log_to_future.borrow().log_to_future(log) {
+						Ok(future) => LazyRelayState::Running(log.clone(), reverts, future),
+						Err(err) => return Self::quarantine(log, &err),
+					}
+				}
+				LazyRelayState::Running(ref log, reverts, ref mut future) => match future.poll() {
+					Err(err) => {
+						if !is_revert(&err) {
+							return Err(err);
+						}
+						let reverts = reverts + 1;
+						let progress = self.logs_progress.get();
+						if reverts >= MAX_RELAY_REVERTS || progress.ended {
+							return Self::quarantine(log, &err);
+						}
+						warn!(
+							"relay of log {:?} would revert ({} of {} times). retrying after block {}: {}",
+							log.transaction_hash,
+							reverts,
+							MAX_RELAY_REVERTS,
+							progress.checked_until,
+							err
+						);
+						LazyRelayState::Reverted(log.clone(), reverts, progress.checked_until, err)
+					}
+					result => return result,
+				},
+				LazyRelayState::Reverted(ref log, reverts, checked_until, ref err) => {
+					let progress = self.logs_progress.get();
+					if progress.checked_until > checked_until {
+						LazyRelayState::Queued(log.clone(), reverts)
+					} else if progress.ended {
+						return Self::quarantine(log, err);
+					} else {
+						// `RelayStream` polls again once it fetched logs of a later block
+						return Ok(Async::NotReady);
+					}
+				}
+			};
+			self.state = next_state;
+		}
+	}
+}
 
-	fn log_to_future(&self, log: &Log) -> Self::Future;
+fn is_revert(err: &error::Error) -> bool {
+	match *err.kind() {
+		error::ErrorKind::TransactionWouldRevert(_) => true,
+		_ => false,
+	}
 }
 
 /// a tokio `Stream` that when polled fetches all new logs from `stream_of_logs`
 /// calls `log_to_future` for each to obtain relay futures, waits for those
-/// futures to complete and yields the block numbers for which all relay
-/// futures have completed.
-/// those block numbers can then be persisted since they'll never need to be
+/// futures to complete and yields the log positions up to which all relay
+/// futures have completed together with the outcome of the relay that just completed.
+/// those positions can then be persisted since they'll never need to be
 /// checked again.
+/// at most `max_in_flight` relays are running at the same time.
+/// the relays for the remaining logs are queued.
+/// ends once `stream_of_logs` has ended and all relays have completed.
 pub struct RelayStream<S: Stream<Item = LogsInBlockRange, Error = error::Error>, F: LogToFuture> {
 	stream_of_logs: S,
-	log_to_future: F,
-	/// reorders relay futures so they are yielded in block order
+	/// shared with the queued relays so changes to it also apply to them
+	log_to_future: Rc<RefCell<F>>,
+	/// reorders relay futures so they are yielded in log order
 	/// rather than the order they complete.
 	/// this is required because relay futures are not guaranteed to
-	/// complete in block order.
-	ordered_stream: OrderedStream<u64, F::Future>,
+	/// complete in log order.
+	/// also limits the number of relays in flight.
+	ordered_stream: OrderedStream<LogPosition, LazyRelay<F>>,
+	/// logs up to and including this position have already been relayed
+	/// and are skipped
+	relayed_until: Option<LogPosition>,
+	/// shared with the relays so reverted ones can be retried in later blocks
+	logs_progress: Rc<Cell<LogsProgress>>,
 }
 
 impl<S: Stream<Item = LogsInBlockRange, Error = error::Error>, F: LogToFuture> RelayStream<S, F> {
-	pub fn new(stream_of_logs: S, log_to_future: F) -> Self {
+	/// `relayed_until` is the position of the last log that has been relayed
+	/// in the block `stream_of_logs` starts at (if any)
+	pub fn new(
+		stream_of_logs: S,
+		log_to_future: F,
+		max_in_flight: usize,
+		relayed_until: Option<LogPosition>,
+	) -> Self {
 		Self {
 			stream_of_logs,
-			log_to_future,
-			ordered_stream: OrderedStream::new(),
+			log_to_future: Rc::new(RefCell::new(log_to_future)),
+			ordered_stream: OrderedStream::with_max_in_flight(max_in_flight),
+			relayed_until,
+			logs_progress: Rc::new(Cell::new(LogsProgress::default())),
 		}
 	}
+
+	/// changes apply to relays that haven't started yet
+	pub fn log_to_future_mut(&self) -> RefMut<F> {
+		self.log_to_future.borrow_mut()
+	}
+
+	pub fn stream_of_logs_mut(&mut self) -> &mut S {
+		&mut self.stream_of_logs
+	}
 }
 
 impl<S: Stream<Item = LogsInBlockRange, Error = error::Error>, F: LogToFuture> Stream
 	for RelayStream<S, F>
 {
-	type Item = u64;
+	type Item = (LogPosition, RelayOutcome);
 	type Error = error::Error;
 
 	fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
 		// on each poll we loop until there are neither new logs
 		// nor newly completed relays
 		loop {
-			let maybe_logs_in_block_range = try_maybe_stream!(self
-				.stream_of_logs
-				.poll()
-				.chain_err(|| "RelayStream: fetching logs failed"));
+			let mut progress = self.logs_progress.get();
+			let maybe_logs_in_block_range = if progress.ended {
+				None
+			} else {
+				match self
+					.stream_of_logs
+					.poll()
+					.chain_err(|| "RelayStream: fetching logs failed")?
+				{
+					Async::Ready(None) => {
+						progress.ended = true;
+						None
+					}
+					Async::Ready(Some(logs_in_block_range)) => {
+						progress.checked_until = logs_in_block_range.to;
+						Some(logs_in_block_range)
+					}
+					Async::NotReady => None,
+				}
+			};
+			self.logs_progress.set(progress);
 
 			if let Some(ref logs_in_block_range) = maybe_logs_in_block_range {
-				// if there are new logs, create futures from them
-				// which are responsible for the relay and add them to the
-				// ordered stream
+				// if there are new logs, add relays for them to the ordered stream.
+				// the relay futures are only created once there's room in the
+				// in-flight window of the ordered stream
 				for log in &logs_in_block_range.logs {
-					let relay_future = self.log_to_future.log_to_future(log);
-					self.ordered_stream
-						.insert(logs_in_block_range.to, relay_future);
+					let position = match LogPosition::from_log(log) {
+						Some(position) => position,
+						None => bail!(
+							"RelayStream: log of transaction {:?} has no block number or log index",
+							log.transaction_hash
+						),
+					};
+					if self.relayed_until.map_or(false, |until| position <= until) {
+						continue;
+					}
+					let relay_future = LazyRelay {
+						log_to_future: self.log_to_future.clone(),
+						logs_progress: self.logs_progress.clone(),
+						state: LazyRelayState::Queued(log.clone(), 0),
+					};
+					self.ordered_stream.insert(position, relay_future);
 				}
 			}
 
-			let maybe_fully_relayed_until_block = try_maybe_stream!(self
+			let maybe_fully_relayed_until = try_maybe_stream!(self
 				.ordered_stream
 				.poll()
 				.chain_err(|| "RelayStream: relaying logs failed"));
 
-			if let Some((fully_relayed_until_block, _)) = maybe_fully_relayed_until_block {
-				// all relay futures for this block or before have completed
-				// we can yield the block number which can be safely
+			if let Some((fully_relayed_until, outcome)) = maybe_fully_relayed_until {
+				// all relay futures for this log or before have completed
+				// we can yield the position which can be safely
 				// persisted since it doesn't need to get checked again
-				return Ok(Async::Ready(Some(fully_relayed_until_block)));
+				self.relayed_until = Some(fully_relayed_until);
+				return Ok(Async::Ready(Some((fully_relayed_until, outcome))));
 			}
 
-			if maybe_logs_in_block_range.is_none() && maybe_fully_relayed_until_block.is_none() {
-				// there are neither new logs nor is there a new block number
+			if maybe_logs_in_block_range.is_none() && maybe_fully_relayed_until.is_none() {
+				if progress.ended
+					&& self.ordered_stream.ready_count() + self.ordered_stream.not_ready_count()
+						== 0
+				{
+					// there will be neither new logs nor new relays
+					return Ok(Async::Ready(None));
+				}
+				// there are neither new logs nor is there a new position
 				// until which all relays have completed
 				return Ok(Async::NotReady);
 			}
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures::{future, stream};
+	use quickcheck::TestResult;
+	use std::cell::Cell;
+	use std::collections::BTreeMap;
+	use web3::types::{Address, Bytes};
+
+	type RelayStates = BTreeMap<LogPosition, Rc<Cell<Option<bool>>>>;
+
+	/// a relay that completes (fails) once its state is set to `Some(true)` (`Some(false)`)
+	struct ControlledRelay(Rc<Cell<Option<bool>>>);
+
+	impl Future for ControlledRelay {
+		type Item = RelayOutcome;
+		type Error = error::Error;
+
+		fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+			match self.0.get() {
+				None => Ok(Async::NotReady),
+				Some(true) => Ok(Async::Ready(RelayOutcome::AlreadyRelayed)),
+				Some(false) => Err("relay failed".into()),
+			}
+		}
+	}
+
+	struct ControlledRelays(Rc<RelayStates>);
+
+	impl LogToFuture for ControlledRelays {
+		type Future = ControlledRelay;
+
+		fn log_to_future(&self, log: &Log) -> error::Result<Self::Future> {
+			let position = LogPosition::from_log(log).expect("test logs are mined; qed");
+			Ok(ControlledRelay(self.0[&position].clone()))
+		}
+	}
+
+	fn log(block_number: u64, log_index: u64) -> Log {
+		Log {
+			address: Address::zero(),
+			topics: vec![],
+			data: Bytes(vec![]),
+			transaction_hash: None,
+			block_hash: None,
+			block_number: Some(block_number.into()),
+			transaction_index: None,
+			log_index: Some(log_index.into()),
+			transaction_log_index: None,
+			log_type: None,
+			removed: None,
+		}
+	}
+
+	/// relays that fail with the error made by `self.0`
+	struct FailingRelays(fn() -> error::Error);
+
+	impl LogToFuture for FailingRelays {
+		type Future = future::FutureResult<RelayOutcome, error::Error>;
+
+		fn log_to_future(&self, _log: &Log) -> error::Result<Self::Future> {
+			Ok(future::err((self.0)()))
+		}
+	}
+
+	fn one_log() -> stream::IterOk<::std::vec::IntoIter<LogsInBlockRange>, error::Error> {
+		stream::iter_ok(vec![LogsInBlockRange {
+			from: 1,
+			to: 1,
+			logs: vec![log(1, 0)],
+		}])
+	}
+
+	#[test]
+	fn test_relay_stream_quarantines_log_of_relay_that_would_revert() {
+		let mut relay_stream = RelayStream::new(
+			one_log(),
+			FailingRelays(|| {
+				error::ErrorKind::TransactionWouldRevert("execution reverted".into()).into()
+			}),
+			1,
+			None,
+		);
+		match relay_stream.poll() {
+			Ok(Async::Ready(Some((position, RelayOutcome::Quarantined(quarantined))))) => {
+				assert_eq!(
+					position,
+					LogPosition {
+						block_number: 1,
+						log_index: 0
+					}
+				);
+				assert!(quarantined.reason.contains("execution reverted"));
+			}
+			other => panic!("unexpected {:?}", other),
+		}
+		assert_eq!(relay_stream.poll().unwrap(), Async::Ready(None));
+	}
+
+	/// relays whose transaction is estimated to revert the first `reverts` times
+	struct RevertingRelays {
+		reverts: Cell<u32>,
+	}
+
+	impl LogToFuture for RevertingRelays {
+		type Future = future::FutureResult<RelayOutcome, error::Error>;
+
+		fn log_to_future(&self, _log: &Log) -> error::Result<Self::Future> {
+			if self.reverts.get() == 0 {
+				return Ok(future::ok(RelayOutcome::AlreadyRelayed));
+			}
+			self.reverts.set(self.reverts.get() - 1);
+			Ok(future::err(
+				error::ErrorKind::TransactionWouldRevert("execution reverted".into()).into(),
+			))
+		}
+	}
+
+	/// a log in block 1 followed by empty ranges up to block `blocks`
+	fn one_log_then_blocks(
+		blocks: u64,
+	) -> stream::IterOk<::std::vec::IntoIter<LogsInBlockRange>, error::Error> {
+		let mut ranges = one_log().collect().wait().unwrap();
+		ranges.extend((2..=blocks).map(|block_number| LogsInBlockRange {
+			from: block_number,
+			to: block_number,
+			logs: vec![],
+		}));
+		stream::iter_ok(ranges)
+	}
+
+	#[test]
+	fn test_relay_stream_retries_relay_whose_estimate_reverted_once_in_later_block() {
+		let mut relay_stream = RelayStream::new(
+			one_log_then_blocks(2),
+			RevertingRelays {
+				reverts: Cell::new(1),
+			},
+			1,
+			None,
+		);
+		assert_eq!(
+			relay_stream.poll().unwrap(),
+			Async::Ready(Some((
+				LogPosition {
+					block_number: 1,
+					log_index: 0
+				},
+				RelayOutcome::AlreadyRelayed
+			)))
+		);
+		assert_eq!(relay_stream.log_to_future_mut().reverts.get(), 0);
+		assert_eq!(relay_stream.poll().unwrap(), Async::Ready(None));
+	}
+
+	#[test]
+	fn test_relay_stream_quarantines_log_after_max_relay_reverts() {
+		let mut relay_stream = RelayStream::new(
+			one_log_then_blocks(10),
+			RevertingRelays {
+				reverts: Cell::new(10),
+			},
+			1,
+			None,
+		);
+		match relay_stream.poll() {
+			Ok(Async::Ready(Some((_, RelayOutcome::Quarantined(quarantined))))) => {
+				assert!(quarantined.reason.contains("execution reverted"));
+			}
+			other => panic!("unexpected {:?}", other),
+		}
+		assert_eq!(
+			relay_stream.log_to_future_mut().reverts.get(),
+			10 - MAX_RELAY_REVERTS
+		);
+	}
+
+	#[test]
+	fn test_relay_stream_fails_if_relay_fails_otherwise() {
+		let mut relay_stream = RelayStream::new(
+			one_log(),
+			FailingRelays(|| "node unavailable".into()),
+			1,
+			None,
+		);
+		assert!(relay_stream.poll().is_err());
+	}
+
+	/// polls `relay_stream` until it's not ready and checks that every yielded
+	/// position is beyond `relayed_until` and that all relays up to it completed.
+	/// returns whether the stream ended or failed or a description of the first violation
+	fn poll_and_check<S: Stream<Item = LogsInBlockRange, Error = error::Error>>(
+		relay_stream: &mut RelayStream<S, ControlledRelays>,
+		relays: &RelayStates,
+		relayed_until: &mut Option<LogPosition>,
+	) -> Result<bool, String> {
+		loop {
+			match relay_stream.poll() {
+				Ok(Async::Ready(Some((position, _)))) => {
+					if relayed_until.map_or(false, |until| position <= until) {
+						return Err(format!("yielded {:?} after {:?}", position, relayed_until));
+					}
+					let incomplete = relays
+						.range(..=position)
+						.find(|&(_, state)| state.get() != Some(true));
+					if let Some((incomplete, _)) = incomplete {
+						return Err(format!(
+							"yielded {:?} although the relay of {:?} is incomplete",
+							position, incomplete
+						));
+					}
+					*relayed_until = Some(position);
+				}
+				Ok(Async::Ready(None)) => return Ok(true),
+				Ok(Async::NotReady) => return Ok(false),
+				Err(_) => {
+					if relays.values().all(|state| state.get() != Some(false)) {
+						return Err("failed although no relay failed".into());
+					}
+					return Ok(true);
+				}
+			}
+		}
+	}
+
+	quickcheck! {
+		fn quickcheck_relay_stream_never_yields_beyond_incomplete_relay(
+			logs_per_block: Vec<u8>,
+			completion_keys: Vec<u8>,
+			failing_relay: Option<u8>,
+			max_in_flight: u8
+		) -> TestResult {
+			let mut relays = RelayStates::new();
+			let ranges: Vec<LogsInBlockRange> = logs_per_block
+				.iter()
+				.enumerate()
+				.map(|(index, &count)| {
+					let block_number = index as u64 + 1;
+					let logs = (0..u64::from(count % 4))
+						.map(|log_index| {
+							let position = LogPosition { block_number, log_index };
+							relays.insert(position, Rc::new(Cell::new(None)));
+							log(block_number, log_index)
+						})
+						.collect();
+					LogsInBlockRange { from: block_number, to: block_number, logs }
+				})
+				.collect();
+			let positions: Vec<LogPosition> = relays.keys().cloned().collect();
+			let failing_relay = failing_relay
+				.and_then(|index| positions.get(index as usize % positions.len().max(1)).cloned());
+			let relays = Rc::new(relays);
+
+			let mut relay_stream = RelayStream::new(
+				stream::iter_ok(ranges),
+				ControlledRelays(relays.clone()),
+				max_in_flight as usize % 4 + 1,
+				None,
+			);
+			let mut relayed_until = None;
+
+			let mut completion_order: Vec<usize> = (0..positions.len()).collect();
+			completion_order.sort_by_key(|&index| completion_keys.get(index).cloned().unwrap_or(0));
+
+			let mut ended = false;
+			for index in completion_order {
+				match poll_and_check(&mut relay_stream, &relays, &mut relayed_until) {
+					Err(violation) => return TestResult::error(violation),
+					Ok(true) => {
+						ended = true;
+						break;
+					}
+					Ok(false) => {}
+				}
+				let position = positions[index];
+				relays[&position].set(Some(Some(position) != failing_relay));
+			}
+			if !ended {
+				match poll_and_check(&mut relay_stream, &relays, &mut relayed_until) {
+					Err(violation) => return TestResult::error(violation),
+					Ok(true) => {}
+					Ok(false) if failing_relay.is_some() => {}
+					Ok(false) => return TestResult::error("not ended although all relays completed"),
+				}
+			}
+
+			if failing_relay.is_none() && relayed_until != positions.last().cloned() {
+				return TestResult::error(format!(
+					"relayed until {:?} instead of {:?}",
+					relayed_until,
+					positions.last()
+				));
+			}
+			TestResult::passed()
+		}
+	}
+}
@@ -0,0 +1,296 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity-Bridge.
+
+// Parity-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! running a single relay over a fixed range of historical blocks
+
+use accept_message_from_main::LogToAcceptMessageFromMain;
+use bridge::resume_from;
+use database::{Relay, RescanCheckpoint};
+use error::{self, ResultExt};
+use futures::{Async, Poll, Stream};
+use log_stream::{LogPosition, LogStream};
+use main_contract::MainContract;
+use relay_stream::{RelayOutcome, RelayStream};
+use side_contract::SideContract;
+use side_to_main_sign::LogToSideToMainSign;
+use side_to_main_signatures::LogToSideToMainSignatures;
+use web3::Transport;
+
+enum RescanRelayStream<T: Transport> {
+	MainToSideSign(RelayStream<LogStream<T>, LogToAcceptMessageFromMain<T>>),
+	SideToMainSign(RelayStream<LogStream<T>, LogToSideToMainSign<T>>),
+	SideToMainSignatures(RelayStream<LogStream<T>, LogToSideToMainSignatures<T>>),
+}
+
+impl<T: Transport> Stream for RescanRelayStream<T> {
+	type Item = (LogPosition, RelayOutcome);
+	type Error = error::Error;
+
+	fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+		match *self {
+			RescanRelayStream::MainToSideSign(ref mut stream) => stream.poll(),
+			RescanRelayStream::SideToMainSign(ref mut stream) => stream.poll(),
+			RescanRelayStream::SideToMainSignatures(ref mut stream) => stream.poll(),
+		}
+	}
+}
+
+/// yielded by `Rescan`
+#[derive(Debug, PartialEq, Clone)]
+pub enum RescanProgress {
+	/// a log has been relayed. the checkpoint should be persisted
+	/// and the outcome recorded
+	Relayed(RescanCheckpoint, RelayOutcome),
+	/// all logs have been relayed. the checkpoint can be removed
+	Complete,
+}
+
+/// `Stream` that runs the `RelayStream` of `relay` over the logs in blocks
+/// `from_block` to `to_block` (inclusive) and ends once all of them have been relayed.
+/// the relays check whether a log has already been relayed so rescanning is idempotent.
+/// progress is tracked in a `RescanCheckpoint` rather than in the `last_*_at_block`
+/// checkpoints of the running bridge. a rescan of the same range resumes from there.
+pub struct Rescan<T: Transport> {
+	relay: Relay,
+	from_block: u64,
+	to_block: u64,
+	stream: RescanRelayStream<T>,
+	done: bool,
+}
+
+impl<T: Transport> Rescan<T> {
+	/// `checkpoint` is the one last yielded by an interrupted rescan, if any
+	pub fn new(
+		checkpoint: Option<RescanCheckpoint>,
+		relay: Relay,
+		from_block: u64,
+		to_block: u64,
+		main_contract: MainContract<T>,
+		side_contract: SideContract<T>,
+		max_in_flight_relays: usize,
+	) -> Self {
+		let resumed = checkpoint.as_ref().and_then(|checkpoint| {
+			if checkpoint.relay == relay
+				&& checkpoint.from_block == from_block
+				&& checkpoint.to_block == to_block
+			{
+				info!(
+					"resuming rescan of {} after block {} log {}",
+					relay.name(),
+					checkpoint.last_block,
+					checkpoint.last_log_index
+				);
+				Some(resume_from(
+					checkpoint.last_block,
+					Some(checkpoint.last_log_index),
+				))
+			} else {
+				None
+			}
+		});
+		let (after, relayed_until) =
+			resumed.unwrap_or_else(|| (from_block.saturating_sub(1), None));
+
+		let stream = match relay {
+			Relay::MainToSideSign => RescanRelayStream::MainToSideSign(RelayStream::new(
+				main_contract
+					.main_to_side_log_stream(after)
+					.with_until(to_block),
+				LogToAcceptMessageFromMain {
+					main: main_contract.clone(),
+					side: side_contract.clone(),
+				},
+				max_in_flight_relays,
+				relayed_until,
+			)),
+			Relay::SideToMainSign => RescanRelayStream::SideToMainSign(RelayStream::new(
+				side_contract
+					.side_to_main_sign_log_stream(after)
+					.with_until(to_block),
+				LogToSideToMainSign {
+					side: side_contract.clone(),
+				},
+				max_in_flight_relays,
+				relayed_until,
+			)),
+			Relay::SideToMainSignatures => {
+				RescanRelayStream::SideToMainSignatures(RelayStream::new(
+					side_contract
+						.side_to_main_signatures_log_stream(after, main_contract.authority_address)
+						.with_until(to_block),
+					LogToSideToMainSignatures {
+						main: main_contract.clone(),
+						side: side_contract.clone(),
+					},
+					max_in_flight_relays,
+					relayed_until,
+				))
+			}
+		};
+
+		Self {
+			relay,
+			from_block,
+			to_block,
+			stream,
+			done: false,
+		}
+	}
+}
+
+impl<T: Transport> Stream for Rescan<T> {
+	type Item = RescanProgress;
+	type Error = error::Error;
+
+	fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+		if self.done {
+			return Ok(Async::Ready(None));
+		}
+
+		let maybe_relayed = try_ready!(self
+			.stream
+			.poll()
+			.chain_err(|| format!("Rescan: relaying {} failed", self.relay.name())));
+
+		match maybe_relayed {
+			Some((position, outcome)) => {
+				info!(
+					"last log relayed by rescan of {} is now {:?}",
+					self.relay.name(),
+					position
+				);
+				let checkpoint = RescanCheckpoint {
+					relay: self.relay,
+					from_block: self.from_block,
+					to_block: self.to_block,
+					last_block: position.block_number,
+					last_log_index: position.log_index,
+				};
+				Ok(Async::Ready(Some(RescanProgress::Relayed(
+					checkpoint, outcome,
+				))))
+			}
+			None => {
+				info!(
+					"rescan of {} in blocks {} to {} is complete",
+					self.relay.name(),
+					self.from_block,
+					self.to_block
+				);
+				self.done = true;
+				Ok(Async::Ready(Some(RescanProgress::Complete)))
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use config::TransactionConfig;
+	use contracts;
+	use std::time::Duration;
+	use tokio_core::reactor::Core;
+	use web3::types::Address;
+
+	#[test]
+	fn test_rescan_resumes_from_its_checkpoint() {
+		let main_contract_address: Address =
+			"0000000000000000000000000000000000000dd2".parse().unwrap();
+		let side_contract_address: Address =
+			"0000000000000000000000000000000000000dd1".parse().unwrap();
+		let authority_address: Address =
+			"0000000000000000000000000000000000000001".parse().unwrap();
+		let topic = contracts::main::events::relay_message::filter().topic0;
+
+		let main_transport = mock_transport!(
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x20");
+			"eth_getLogs" =>
+				req => json!([{
+					"address": format!("0x{:x}", main_contract_address),
+					"fromBlock": "0x8",
+					"toBlock": "0xa",
+					"topics": [topic]
+				}]),
+				res => json!([]);
+		);
+		// nothing is sent to side since there are no logs in the range
+		let side_transport = ::MockTransport {
+			expected_requests: vec![],
+			actual_requests: Default::default(),
+			mock_responses: vec![],
+		};
+
+		let main_contract = MainContract {
+			transport: main_transport.clone(),
+			contract_address: main_contract_address,
+			authority_address,
+			request_timeout: Duration::from_millis(0),
+			logs_poll_interval: Duration::from_millis(0),
+			required_log_confirmations: 0,
+			submit_collected_signatures_tx: TransactionConfig::default(),
+			gas_estimate_multiplier: 1.2,
+			max_relay_cost: None,
+			eip1559_support: Default::default(),
+		};
+		let side_contract = SideContract {
+			transport: side_transport.clone(),
+			contract_address: side_contract_address,
+			authority_address,
+			required_signatures: 1,
+			request_timeout: Duration::from_millis(0),
+			logs_poll_interval: Duration::from_millis(0),
+			required_log_confirmations: 0,
+			gas_estimate_multiplier: 1.2,
+			sign_main_to_side_tx: TransactionConfig::default(),
+			sign_side_to_main_tx: TransactionConfig::default(),
+			eip1559_support: Default::default(),
+		};
+
+		let checkpoint = RescanCheckpoint {
+			relay: Relay::MainToSideSign,
+			from_block: 5,
+			to_block: 10,
+			last_block: 8,
+			last_log_index: 1,
+		};
+
+		let rescan = Rescan::new(
+			Some(checkpoint),
+			Relay::MainToSideSign,
+			5,
+			10,
+			main_contract,
+			side_contract,
+			1,
+		);
+
+		let mut event_loop = Core::new().unwrap();
+		let progress = event_loop.run(rescan.collect()).unwrap();
+		assert_eq!(progress, vec![RescanProgress::Complete]);
+
+		assert_eq!(
+			main_transport.expected_requests(),
+			main_transport.actual_requests()
+		);
+		assert_eq!(
+			side_transport.expected_requests(),
+			side_transport.actual_requests()
+		);
+	}
+}
@@ -0,0 +1,464 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity-Bridge.
+
+// Parity-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! sending a message through the bridge and following its relay
+
+use config::TransactionConfig;
+use contracts;
+use database::RelayDirection;
+use error::{self, ResultExt};
+use futures::{Async, Future, Poll, Stream};
+use main_contract::MainContract;
+use message_status::{MessageStatus, MessageStatusLookup};
+use send_tx_with_receipt::{SendTransactionWithReceipt, SendTransactionWithReceiptOptions};
+use side_contract::SideContract;
+use std::time::Duration;
+use tokio_timer::{Sleep, Timer};
+use transaction::{AsyncFees, Transaction};
+use web3::types::{Address, Bytes, TransactionReceipt, H256};
+use web3::Transport;
+
+/// options for creating a `SendMessage`. passed to `SendMessage::new`
+pub struct SendMessageOptions<T> {
+	pub main: MainContract<T>,
+	pub side: SideContract<T>,
+	/// `MainToSide` sends the message on main
+	pub direction: RelayDirection,
+	/// unlocked account that sends the message transaction
+	pub sender: Address,
+	pub recipient: Address,
+	pub data: Vec<u8>,
+	pub transaction_config: TransactionConfig,
+	/// the authorities whose acceptance of main to side messages is followed
+	pub authorities: Vec<Address>,
+	/// how long to wait between checks of the relay progress
+	pub poll_interval: Duration,
+	/// how long to wait for the message to be accepted on the other chain
+	/// (including the wait for the message transaction)
+	pub timeout: Duration,
+}
+
+/// what `SendMessage` yields as the message makes progress
+#[derive(Debug, PartialEq, Clone)]
+pub enum SendMessageProgress {
+	/// the message transaction was sent
+	Sent(H256),
+	/// the message transaction got mined and confirmed
+	Mined(TransactionReceipt),
+	/// the relay progressed. the last one yielded is accepted on the other chain
+	Relaying(MessageStatus),
+}
+
+enum State<T: Transport> {
+	AwaitFees(AsyncFees<T>),
+	AwaitReceipt {
+		future: SendTransactionWithReceipt<T>,
+		sent: bool,
+	},
+	AwaitStatus {
+		future: MessageStatusLookup<T>,
+		transaction_hash: H256,
+		last_status: Option<MessageStatus>,
+	},
+	AwaitNextCheck {
+		future: Sleep,
+		transaction_hash: H256,
+		last_status: Option<MessageStatus>,
+	},
+	Done,
+}
+
+/// `Stream` that sends a message on the chain `direction` starts at,
+/// waits for the receipt of the message transaction and then follows the relay
+/// until the message is accepted on the other chain.
+/// ends after yielding the status in which the message is accepted.
+/// fails if that doesn't happen within `options.timeout`.
+pub struct SendMessage<T: Transport> {
+	options: SendMessageOptions<T>,
+	timer: Timer,
+	deadline: Sleep,
+	state: State<T>,
+}
+
+impl<T: Transport> SendMessage<T> {
+	pub fn new(options: SendMessageOptions<T>) -> Self {
+		let future = match options.direction {
+			RelayDirection::MainToSide => AsyncFees::new(
+				&options.main.transport,
+				options.main.request_timeout,
+				&options.transaction_config,
+				&options.main.eip1559_support,
+			),
+			RelayDirection::SideToMain => AsyncFees::new(
+				&options.side.transport,
+				options.side.request_timeout,
+				&options.transaction_config,
+				&options.side.eip1559_support,
+			),
+		};
+		let timer = Timer::default();
+		Self {
+			deadline: timer.sleep(options.timeout),
+			options,
+			timer,
+			state: State::AwaitFees(future),
+		}
+	}
+
+	/// `SendTransactionWithReceiptOptions` for the chain the message is sent on
+	fn send_options(&self, transaction: Transaction) -> SendTransactionWithReceiptOptions<T> {
+		match self.options.direction {
+			RelayDirection::MainToSide => SendTransactionWithReceiptOptions {
+				transport: self.options.main.transport.clone(),
+				request_timeout: self.options.main.request_timeout,
+				poll_interval: self.options.main.logs_poll_interval,
+				confirmations: self.options.main.required_log_confirmations,
+				transaction: transaction.into(),
+			},
+			RelayDirection::SideToMain => SendTransactionWithReceiptOptions {
+				transport: self.options.side.transport.clone(),
+				request_timeout: self.options.side.request_timeout,
+				poll_interval: self.options.side.logs_poll_interval,
+				confirmations: self.options.side.required_log_confirmations,
+				transaction: transaction.into(),
+			},
+		}
+	}
+
+	fn await_status(&self, transaction_hash: H256, last_status: Option<MessageStatus>) -> State<T> {
+		State::AwaitStatus {
+			future: MessageStatusLookup::new(
+				self.options.main.clone(),
+				self.options.side.clone(),
+				self.options.authorities.clone(),
+				transaction_hash,
+			),
+			transaction_hash,
+			last_status,
+		}
+	}
+}
+
+impl<T: Transport> Stream for SendMessage<T> {
+	type Item = SendMessageProgress;
+	type Error = error::Error;
+
+	fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+		if let State::Done = self.state {
+			return Ok(Async::Ready(None));
+		}
+		if let Async::Ready(()) = self
+			.deadline
+			.poll()
+			.chain_err(|| "SendMessage: waiting for timeout failed")?
+		{
+			bail!(
+				"message was not accepted on the other chain within {} seconds",
+				self.options.timeout.as_secs()
+			);
+		}
+
+		loop {
+			let (next_state, progress) = match self.state {
+				State::AwaitFees(ref mut future) => {
+					let fees = try_ready!(future
+						.poll()
+						.chain_err(|| "SendMessage: fetching fees failed"));
+					let (to, payload) = match self.options.direction {
+						RelayDirection::MainToSide => (
+							self.options.main.contract_address,
+							contracts::main::functions::relay_message::encode_input(
+								self.options.data.clone(),
+								self.options.recipient,
+							),
+						),
+						RelayDirection::SideToMain => (
+							self.options.side.contract_address,
+							contracts::side::functions::relay_message::encode_input(
+								self.options.data.clone(),
+								self.options.recipient,
+							),
+						),
+					};
+					let transaction = Transaction {
+						from: self.options.sender,
+						to: Some(to),
+						gas: self.options.transaction_config.gas,
+						fees,
+						data: Bytes(payload),
+					};
+					let future = SendTransactionWithReceipt::new(self.send_options(transaction));
+					(
+						State::AwaitReceipt {
+							future,
+							sent: false,
+						},
+						None,
+					)
+				}
+				State::AwaitReceipt {
+					ref mut future,
+					ref mut sent,
+				} => {
+					let receipt = match future
+						.poll()
+						.chain_err(|| "SendMessage: sending message transaction failed")?
+					{
+						Async::Ready(receipt) => receipt,
+						Async::NotReady => match future.transaction_hash() {
+							Some(transaction_hash) if !*sent => {
+								*sent = true;
+								return Ok(Async::Ready(Some(SendMessageProgress::Sent(
+									transaction_hash,
+								))));
+							}
+							_ => return Ok(Async::NotReady),
+						},
+					};
+					if receipt.status == Some(0.into()) {
+						bail!("message transaction {:?} failed", receipt.transaction_hash);
+					}
+					(
+						self.await_status(receipt.transaction_hash, None),
+						Some(SendMessageProgress::Mined(receipt)),
+					)
+				}
+				State::AwaitStatus {
+					ref mut future,
+					transaction_hash,
+					ref last_status,
+				} => {
+					let status = try_ready!(future
+						.poll()
+						.chain_err(|| "SendMessage: checking relay progress failed"));
+					let progress = if last_status.as_ref() == Some(&status) {
+						None
+					} else {
+						Some(SendMessageProgress::Relaying(status.clone()))
+					};
+					if status.is_accepted() {
+						(State::Done, progress)
+					} else {
+						(
+							State::AwaitNextCheck {
+								future: self.timer.sleep(self.options.poll_interval),
+								transaction_hash,
+								last_status: Some(status),
+							},
+							progress,
+						)
+					}
+				}
+				State::AwaitNextCheck {
+					ref mut future,
+					transaction_hash,
+					ref mut last_status,
+				} => {
+					try_ready!(future
+						.poll()
+						.chain_err(|| "SendMessage: waiting for next check failed"));
+					(
+						self.await_status(transaction_hash, last_status.take()),
+						None,
+					)
+				}
+				State::Done => return Ok(Async::Ready(None)),
+			};
+
+			self.state = next_state;
+			if let Some(progress) = progress {
+				return Ok(Async::Ready(Some(progress)));
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ethabi;
+	use message_status::MainToSideStatus;
+	use rustc_hex::ToHex;
+	use tokio_core::reactor::Core;
+
+	#[test]
+	fn test_send_message_main_to_side() {
+		let main_contract_address: Address =
+			"0000000000000000000000000000000000000dd2".parse().unwrap();
+		let side_contract_address: Address =
+			"0000000000000000000000000000000000000dd1".parse().unwrap();
+		let authority: Address = "0000000000000000000000000000000000000001".parse().unwrap();
+		let sender: Address = "aff3454fce5edbc8cca8697c15331677e6ebdddd".parse().unwrap();
+		let recipient: Address = "aff3454fce5edbc8cca8697c15331677e6ebcccc".parse().unwrap();
+		let message_id: H256 = "1db8f385535c0d178b8f40016048f3a3cffee8f94e68978ea4b277f57b638f0b"
+			.parse()
+			.unwrap();
+		let tx_hash: H256 = "884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364"
+			.parse()
+			.unwrap();
+		let data: Vec<u8> = vec![0x12, 0x34];
+
+		let log_data = ethabi::encode(&[
+			ethabi::Token::FixedBytes(message_id.as_bytes().to_vec()),
+			ethabi::Token::Address(sender),
+			ethabi::Token::Address(recipient),
+		]);
+		let receipt = json!({
+			"blockHash": "0xe0bdcf35b14a292d2998308d9b3fdea93a8c3d9c0b6c824c633fb9b15f9c3919",
+			"blockNumber": "0x1011",
+			"contractAddress": null,
+			"cumulativeGasUsed": "0x1c1999",
+			"gasUsed": "0x5208",
+			"logs": [{
+				"address": format!("0x{:x}", main_contract_address),
+				"topics": [format!("0x{:x}", contracts::main::events::relay_message::filter().topic0[0])],
+				"data": format!("0x{}", log_data.to_hex::<String>()),
+				"transactionHash": format!("0x{:x}", tx_hash),
+			}],
+			"logsBloom": format!("0x{}", "0".repeat(512)),
+			"root": null,
+			"status": "0x1",
+			"transactionHash": format!("0x{:x}", tx_hash),
+			"transactionIndex": "0x4"
+		});
+
+		let main_transport = mock_transport!(
+			"eth_feeHistory" =>
+				req => json!(["0x5", "latest", [50.0]]),
+				res => json!({
+					"oldestBlock": "0x100b",
+					"baseFeePerGas": ["0x0", "0x0", "0x0", "0x0", "0x0", "0x0"],
+					"gasUsedRatio": [0.0, 0.0, 0.0, 0.0, 0.0],
+				});
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x1010");
+			"eth_sendTransaction" =>
+				req => json!([{
+					"data": format!("0x{}", contracts::main::functions::relay_message::encode_input(data.clone(), recipient).to_hex::<String>()),
+					"from": format!("0x{:x}", sender),
+					"gas": "0xfd",
+					"gasPrice": "0xa0",
+					"to": format!("0x{:x}", main_contract_address),
+				}]),
+				res => json!(format!("0x{:x}", tx_hash));
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x1011");
+			"eth_getTransactionReceipt" =>
+				req => json!([format!("0x{:x}", tx_hash)]),
+				res => receipt.clone();
+			"eth_getTransactionReceipt" =>
+				req => json!([format!("0x{:x}", tx_hash)]),
+				res => receipt;
+			"eth_call" =>
+				req => json!([{
+					"data": format!("0x{}", contracts::main::functions::relayed_messages::encode_input(message_id).to_hex::<String>()),
+					"to": format!("0x{:x}", main_contract_address),
+				}, "latest"]),
+				res => json!(format!("0x{}", ethabi::encode(&[ethabi::Token::Bytes(data.clone())]).to_hex::<String>()));
+		);
+
+		let side_transport = mock_transport!(
+			"eth_getTransactionReceipt" =>
+				req => json!([format!("0x{:x}", tx_hash)]),
+				res => json!(null);
+			"eth_call" =>
+				req => json!([{
+					"data": format!("0x{}", contracts::side::functions::has_authority_accepted_message_from_main::encode_input(
+						tx_hash, data.clone(), sender, recipient, authority).to_hex::<String>()),
+					"to": format!("0x{:x}", side_contract_address),
+				}, "latest"]),
+				res => json!(format!("0x{}", ethabi::encode(&[ethabi::Token::Bool(true)]).to_hex::<String>()));
+		);
+
+		let main = MainContract {
+			transport: main_transport.clone(),
+			contract_address: main_contract_address,
+			authority_address: authority,
+			request_timeout: Duration::from_millis(0),
+			logs_poll_interval: Duration::from_millis(0),
+			required_log_confirmations: 0,
+			submit_collected_signatures_tx: TransactionConfig::default(),
+			gas_estimate_multiplier: 1.2,
+			max_relay_cost: None,
+			eip1559_support: Default::default(),
+		};
+		let side = SideContract {
+			transport: side_transport.clone(),
+			contract_address: side_contract_address,
+			authority_address: authority,
+			required_signatures: 1,
+			request_timeout: Duration::from_millis(0),
+			logs_poll_interval: Duration::from_millis(0),
+			required_log_confirmations: 0,
+			gas_estimate_multiplier: 1.2,
+			sign_main_to_side_tx: TransactionConfig::default(),
+			sign_side_to_main_tx: TransactionConfig::default(),
+			eip1559_support: Default::default(),
+		};
+
+		let stream = SendMessage::new(SendMessageOptions {
+			main,
+			side,
+			direction: RelayDirection::MainToSide,
+			sender,
+			recipient,
+			data: data.clone(),
+			transaction_config: TransactionConfig {
+				gas: 0xfd.into(),
+				gas_price: 0xa0.into(),
+				..Default::default()
+			},
+			authorities: vec![authority],
+			poll_interval: Duration::from_millis(0),
+			timeout: Duration::from_secs(60),
+		});
+
+		let mut event_loop = Core::new().unwrap();
+		let progress = event_loop.run(stream.collect()).unwrap();
+
+		// whether the transaction is reported as sent before it's mined
+		// depends on the timing of the block number polling
+		assert!(progress.iter().any(|progress| match *progress {
+			SendMessageProgress::Mined(ref receipt) => receipt.transaction_hash == tx_hash,
+			_ => false,
+		}));
+		assert_eq!(
+			progress.last(),
+			Some(&SendMessageProgress::Relaying(MessageStatus::MainToSide(
+				MainToSideStatus {
+					main_tx_hash: tx_hash,
+					message_id,
+					sender,
+					recipient,
+					data,
+					accepted_by: vec![(authority, true)],
+					required_signatures: 1,
+				}
+			)))
+		);
+
+		assert_eq!(
+			main_transport.expected_requests(),
+			main_transport.actual_requests()
+		);
+		assert_eq!(
+			side_transport.expected_requests(),
+			side_transport.actual_requests()
+		);
+	}
+}
@@ -19,12 +19,14 @@ use futures::future::FromErr;
 use futures::{Future, Poll};
 use std::time::Duration;
 use tokio_timer::{Timeout, Timer};
+use transaction::Transaction;
 use web3::api::Namespace;
 use web3::helpers::CallFuture;
-use web3::types::{TransactionReceipt, TransactionRequest, U64};
+use web3::types::{TransactionReceipt, H256, U64};
 use web3::{self, Transport};
 
 mod inner {
+	use super::PendingTransaction;
 	use block_number_stream::{BlockNumberStream, BlockNumberStreamOptions};
 	use error::{self, ResultExt};
 	use futures::future::FromErr;
@@ -33,7 +35,7 @@ mod inner {
 	use tokio_timer::{Timeout, Timer};
 	use web3::api::Namespace;
 	use web3::helpers::CallFuture;
-	use web3::types::{TransactionReceipt, TransactionRequest, H256};
+	use web3::types::{TransactionReceipt, H256};
 	use web3::{self, Transport};
 
 	enum State<T: Transport> {
@@ -51,7 +53,7 @@ mod inner {
 		pub request_timeout: Duration,
 		pub poll_interval: Duration,
 		pub confirmations: u32,
-		pub transaction: TransactionRequest,
+		pub transaction: PendingTransaction,
 		pub after: u64,
 	}
 
@@ -75,18 +77,37 @@ mod inner {
 				after: options.after,
 			};
 			let block_number_stream = BlockNumberStream::new(block_number_stream_options);
-			let future =
-				web3::api::Eth::new(&options.transport).send_transaction(options.transaction);
-			let future = timer.timeout(future.from_err(), options.request_timeout);
+			let state = match options.transaction {
+				PendingTransaction::Unsent(transaction) => {
+					let future = transaction.send(&options.transport);
+					State::AwaitSendTransaction(
+						timer.timeout(future.from_err(), options.request_timeout),
+					)
+				}
+				PendingTransaction::Sent(transaction_hash) => {
+					State::AwaitBlockNumber(transaction_hash)
+				}
+			};
 
 			SendTransactionWithReceipt {
 				transport: options.transport,
-				state: State::AwaitSendTransaction(future),
+				state,
 				block_number_stream,
 				request_timeout: options.request_timeout,
 				timer,
 			}
 		}
+
+		/// hash of the transaction once it has been sent
+		pub fn transaction_hash(&self) -> Option<H256> {
+			match self.state {
+				State::AwaitSendTransaction(_) => None,
+				State::AwaitBlockNumber(transaction_hash) => Some(transaction_hash),
+				State::AwaitTransactionReceipt {
+					transaction_hash, ..
+				} => Some(transaction_hash),
+			}
+		}
 	}
 
 	impl<T: Transport> Future for SendTransactionWithReceipt<T> {
@@ -165,10 +186,24 @@ mod inner {
 	}
 }
 
+/// the transaction `SendTransactionWithReceipt` waits for
+pub enum PendingTransaction {
+	/// sent by `SendTransactionWithReceipt`
+	Unsent(Transaction),
+	/// already sent. for example before a restart
+	Sent(H256),
+}
+
+impl From<Transaction> for PendingTransaction {
+	fn from(transaction: Transaction) -> Self {
+		PendingTransaction::Unsent(transaction)
+	}
+}
+
 enum State<T: Transport> {
 	AwaitBlockNumber {
 		future: Timeout<FromErr<CallFuture<U64, T::Out>, error::Error>>,
-		transaction: Option<TransactionRequest>,
+		transaction: Option<Transaction>,
 	},
 	AwaitReceipt(inner::SendTransactionWithReceipt<T>),
 }
@@ -178,7 +213,7 @@ pub struct SendTransactionWithReceiptOptions<T> {
 	pub request_timeout: Duration,
 	pub poll_interval: Duration,
 	pub confirmations: u32,
-	pub transaction: TransactionRequest,
+	pub transaction: PendingTransaction,
 }
 
 pub struct SendTransactionWithReceipt<T: Transport> {
@@ -191,13 +226,27 @@ pub struct SendTransactionWithReceipt<T: Transport> {
 
 impl<T: Transport> SendTransactionWithReceipt<T> {
 	pub fn new(options: SendTransactionWithReceiptOptions<T>) -> Self {
-		let timer = Timer::default();
-
-		let future = web3::api::Eth::new(&options.transport).block_number();
-
-		let state = State::AwaitBlockNumber {
-			future: timer.timeout(future.from_err(), options.request_timeout),
-			transaction: Some(options.transaction),
+		let state = match options.transaction {
+			PendingTransaction::Unsent(transaction) => {
+				let timer = Timer::default();
+				let future = web3::api::Eth::new(&options.transport).block_number();
+				State::AwaitBlockNumber {
+					future: timer.timeout(future.from_err(), options.request_timeout),
+					transaction: Some(transaction),
+				}
+			}
+			// the transaction might have been mined long ago.
+			// so its receipt is checked right away instead of after the next block
+			PendingTransaction::Sent(transaction_hash) => State::AwaitReceipt(
+				inner::SendTransactionWithReceipt::new(inner::SendTransactionWithReceiptOptions {
+					transport: options.transport.clone(),
+					request_timeout: options.request_timeout,
+					poll_interval: options.poll_interval,
+					confirmations: options.confirmations,
+					transaction: PendingTransaction::Sent(transaction_hash),
+					after: 0,
+				}),
+			),
 		};
 
 		SendTransactionWithReceipt {
@@ -208,6 +257,14 @@ impl<T: Transport> SendTransactionWithReceipt<T> {
 			confirmations: options.confirmations,
 		}
 	}
+
+	/// hash of the transaction once it has been sent
+	pub fn transaction_hash(&self) -> Option<H256> {
+		match self.state {
+			State::AwaitBlockNumber { .. } => None,
+			State::AwaitReceipt(ref future) => future.transaction_hash(),
+		}
+	}
 }
 
 impl<T: Transport> Future for SendTransactionWithReceipt<T> {
@@ -238,7 +295,7 @@ impl<T: Transport> Future for SendTransactionWithReceipt<T> {
 						request_timeout: self.request_timeout,
 						poll_interval: self.poll_interval,
 						confirmations: self.confirmations,
-						transaction,
+						transaction: transaction.into(),
 						after: block_number.as_u64(),
 					};
 
@@ -257,6 +314,7 @@ impl<T: Transport> Future for SendTransactionWithReceipt<T> {
 mod tests {
 	use super::*;
 	use tokio_core::reactor::Core;
+	use transaction::Fees;
 
 	#[test]
 	fn test_send_tx_with_receipt() {
@@ -349,16 +407,16 @@ mod tests {
 				request_timeout: Duration::from_secs(1),
 				poll_interval: Duration::from_secs(0),
 				confirmations: 2,
-				transaction: TransactionRequest {
+				transaction: Transaction {
 					from: "006b5dda44dc2606f07ad86c9190fb54fd905f6d".parse().unwrap(),
 					to: None,
-					gas: Some(0xf4240.into()),
-					gas_price: Some(0.into()),
-					value: None,
-					data: Some(vec![0x60].into()),
-					nonce: None,
-					condition: None,
-				},
+					gas: 0xf4240.into(),
+					fees: Fees::Legacy {
+						gas_price: 0.into(),
+					},
+					data: vec![0x60].into(),
+				}
+				.into(),
 			});
 
 		let mut event_loop = Core::new().unwrap();
@@ -387,4 +445,51 @@ mod tests {
 		);
 		assert_eq!(transport.actual_requests(), transport.expected_requests());
 	}
+
+	#[test]
+	fn test_send_tx_with_receipt_of_sent_transaction() {
+		let transport = mock_transport!(
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x1017");
+			"eth_getTransactionReceipt" =>
+				req => json!(["0x36efc16910ea67a2425a1e75f7e39e3c6a94f5763c68a47258f552481e20cd34"]),
+				res => json!({
+					"blockHash": "0xe0bdcf35b14a292d2998308d9b3fdea93a8c3d9c0b6c824c633fb9b15f9c3919",
+					"blockNumber": "0x1015",
+					"contractAddress": "0xb1ac3a5584519119419a8e56422d912c782d8e5b",
+					"cumulativeGasUsed": "0x1c1999",
+					"gasUsed": "0xcdb5d",
+					"logs": [],
+					"logsBloom": "0x00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000",
+					"root": null,
+					"status": "0x1",
+					"transactionHash": "0x36efc16910ea67a2425a1e75f7e39e3c6a94f5763c68a47258f552481e20cd34",
+					"transactionIndex":"0x4"
+				});
+		);
+
+		let transaction_hash: H256 =
+			"36efc16910ea67a2425a1e75f7e39e3c6a94f5763c68a47258f552481e20cd34"
+				.parse()
+				.unwrap();
+		let send_transaction_with_receipt =
+			SendTransactionWithReceipt::new(SendTransactionWithReceiptOptions {
+				transport: transport.clone(),
+				request_timeout: Duration::from_secs(1),
+				poll_interval: Duration::from_secs(0),
+				confirmations: 2,
+				transaction: PendingTransaction::Sent(transaction_hash),
+			});
+		assert_eq!(
+			send_transaction_with_receipt.transaction_hash(),
+			Some(transaction_hash)
+		);
+
+		let mut event_loop = Core::new().unwrap();
+		let receipt = event_loop.run(send_transaction_with_receipt).unwrap();
+		assert_eq!(receipt.transaction_hash, transaction_hash);
+		assert_eq!(receipt.block_number, Some(0x1015.into()));
+		assert_eq!(transport.actual_requests(), transport.expected_requests());
+	}
 }
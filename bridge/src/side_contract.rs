@@ -13,17 +13,19 @@
 
 // You should have received a copy of the GNU General Public License
 // along with Parity-Bridge.  If not, see <http://www.gnu.org/licenses/>.
-use config::Config;
+use config::{Config, TransactionConfig};
 use contracts;
 use database::State;
 use ethabi::FunctionOutputDecoder;
 use futures::future::{join_all, JoinAll};
-use helpers::{AsyncCall, AsyncTransaction};
+use helpers::{AsyncCall, AsyncGasEstimate};
 use log_stream::{LogStream, LogStreamOptions};
 use message_to_main::MessageToMain;
+use send_tx_with_receipt::{SendTransactionWithReceipt, SendTransactionWithReceiptOptions};
 use signature::Signature;
 use std::time::Duration;
-use web3::types::{Address, H256, U256};
+use transaction::{AsyncFees, Eip1559Support, Fees, Transaction};
+use web3::types::{Address, Bytes, H256, U256};
 use web3::Transport;
 
 /// highlevel wrapper around the auto generated ethabi contract `bridge_contracts::side`
@@ -37,10 +39,14 @@ pub struct SideContract<T> {
 	pub request_timeout: Duration,
 	pub logs_poll_interval: Duration,
 	pub required_log_confirmations: u32,
-	pub sign_main_to_side_gas: U256,
-	pub sign_main_to_side_gas_price: U256,
-	pub sign_side_to_main_gas: U256,
-	pub sign_side_to_main_gas_price: U256,
+	/// gas estimates are multiplied by this to leave a safety margin
+	pub gas_estimate_multiplier: f64,
+	/// `gas` is the upper cap for the gas of `accept_message_from_main` transactions
+	pub sign_main_to_side_tx: TransactionConfig,
+	/// `gas` is the upper cap for the gas of `submit_signed_message` transactions
+	pub sign_side_to_main_tx: TransactionConfig,
+	/// shared by the relays so `eth_feeHistory` is only requested while it can succeed
+	pub eip1559_support: Eip1559Support,
 }
 
 impl<T: Transport> SideContract<T> {
@@ -53,13 +59,23 @@ impl<T: Transport> SideContract<T> {
 			request_timeout: config.side.request_timeout,
 			logs_poll_interval: config.side.poll_interval,
 			required_log_confirmations: config.side.required_confirmations,
-			sign_main_to_side_gas: config.txs.deposit_relay.gas,
-			sign_main_to_side_gas_price: config.txs.deposit_relay.gas_price,
-			sign_side_to_main_gas: config.txs.withdraw_confirm.gas,
-			sign_side_to_main_gas_price: config.txs.withdraw_confirm.gas_price,
+			gas_estimate_multiplier: config.gas_estimate_multiplier,
+			sign_main_to_side_tx: config.txs.deposit_relay.clone(),
+			sign_side_to_main_tx: config.txs.withdraw_confirm.clone(),
+			eip1559_support: Eip1559Support::default(),
 		}
 	}
 
+	/// applies the options of `config` that can change while the bridge is running
+	pub fn apply_config(&mut self, config: &Config) {
+		self.request_timeout = config.side.request_timeout;
+		self.logs_poll_interval = config.side.poll_interval;
+		self.sign_main_to_side_tx
+			.set_fees_from(&config.txs.deposit_relay);
+		self.sign_side_to_main_tx
+			.set_fees_from(&config.txs.withdraw_confirm);
+	}
+
 	pub fn call<F: FunctionOutputDecoder>(
 		&self,
 		payload: Vec<u8>,
@@ -115,13 +131,14 @@ impl<T: Transport> SideContract<T> {
 		self.call(payload, decoder)
 	}
 
-	pub fn accept_message_from_main(
+	/// estimate the gas for `accept_message_from_main`
+	pub fn estimate_accept_message_from_main_gas(
 		&self,
 		transaction_hash: H256,
 		data: Vec<u8>,
 		sender: Address,
 		recipient: Address,
-	) -> AsyncTransaction<T> {
+	) -> AsyncGasEstimate<T> {
 		let payload = contracts::side::functions::accept_message::encode_input(
 			transaction_hash,
 			data,
@@ -129,17 +146,39 @@ impl<T: Transport> SideContract<T> {
 			recipient,
 		);
 
-		AsyncTransaction::new(
+		self.estimate_gas(self.sign_main_to_side_tx.gas, payload)
+	}
+
+	/// the fees to use for `accept_message_from_main`
+	pub fn accept_message_from_main_fees(&self) -> AsyncFees<T> {
+		AsyncFees::new(
 			&self.transport,
-			self.contract_address,
-			self.authority_address,
-			self.sign_main_to_side_gas,
-			self.sign_main_to_side_gas_price,
 			self.request_timeout,
-			payload,
+			&self.sign_main_to_side_tx,
+			&self.eip1559_support,
 		)
 	}
 
+	/// resolves with the receipt once the transaction is mined
+	pub fn accept_message_from_main(
+		&self,
+		transaction_hash: H256,
+		data: Vec<u8>,
+		sender: Address,
+		recipient: Address,
+		gas: U256,
+		fees: Fees,
+	) -> SendTransactionWithReceipt<T> {
+		let payload = contracts::side::functions::accept_message::encode_input(
+			transaction_hash,
+			data,
+			sender,
+			recipient,
+		);
+
+		self.send_transaction(gas, fees, payload)
+	}
+
 	pub fn side_to_main_sign_log_stream(&self, after: u64) -> LogStream<T> {
 		LogStream::new(LogStreamOptions {
 			filter: contracts::side::events::relay_message::filter(),
@@ -164,26 +203,78 @@ impl<T: Transport> SideContract<T> {
 		})
 	}
 
+	/// estimate the gas for `submit_signed_message`
+	pub fn estimate_submit_signed_message_gas(
+		&self,
+		message: &MessageToMain,
+		signature: &Signature,
+	) -> AsyncGasEstimate<T> {
+		let payload = contracts::side::functions::submit_signed_message::encode_input(
+			signature.to_bytes(),
+			message.to_bytes(),
+		);
+		self.estimate_gas(self.sign_side_to_main_tx.gas, payload)
+	}
+
+	/// the fees to use for `submit_signed_message`
+	pub fn submit_signed_message_fees(&self) -> AsyncFees<T> {
+		AsyncFees::new(
+			&self.transport,
+			self.request_timeout,
+			&self.sign_side_to_main_tx,
+			&self.eip1559_support,
+		)
+	}
+
+	/// resolves with the receipt once the transaction is mined
 	pub fn submit_signed_message(
 		&self,
 		message: &MessageToMain,
 		signature: &Signature,
-	) -> AsyncTransaction<T> {
+		gas: U256,
+		fees: Fees,
+	) -> SendTransactionWithReceipt<T> {
 		let payload = contracts::side::functions::submit_signed_message::encode_input(
 			signature.to_bytes(),
 			message.to_bytes(),
 		);
-		AsyncTransaction::new(
+		self.send_transaction(gas, fees, payload)
+	}
+
+	fn estimate_gas(&self, max_gas: U256, payload: Vec<u8>) -> AsyncGasEstimate<T> {
+		AsyncGasEstimate::new(
 			&self.transport,
 			self.contract_address,
 			self.authority_address,
-			self.sign_side_to_main_gas,
-			self.sign_side_to_main_gas_price,
+			self.gas_estimate_multiplier,
+			max_gas,
 			self.request_timeout,
 			payload,
 		)
 	}
 
+	fn send_transaction(
+		&self,
+		gas: U256,
+		fees: Fees,
+		payload: Vec<u8>,
+	) -> SendTransactionWithReceipt<T> {
+		SendTransactionWithReceipt::new(SendTransactionWithReceiptOptions {
+			transport: self.transport.clone(),
+			request_timeout: self.request_timeout,
+			poll_interval: self.logs_poll_interval,
+			confirmations: self.required_log_confirmations,
+			transaction: Transaction {
+				from: self.authority_address,
+				to: Some(self.contract_address),
+				gas,
+				fees,
+				data: Bytes(payload),
+			}
+			.into(),
+		})
+	}
+
 	pub fn get_signatures(
 		&self,
 		message_hash: H256,
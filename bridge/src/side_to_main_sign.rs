@@ -14,19 +14,23 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity-Bridge.  If not, see <http://www.gnu.org/licenses/>.
 use contracts;
+use database::{RelayCost, RelayDirection};
 use error::{self, ResultExt};
-use futures::future::FromErr;
+use futures::future::{FromErr, Join};
 use futures::{Async, Future, Poll};
-use helpers::{AsyncCall, AsyncTransaction};
+use helpers::{self, AsyncCall, AsyncGasEstimate};
 use message_to_main::{MessageToMain, MESSAGE_LENGTH};
-use relay_stream::LogToFuture;
+use relay_log::{Chain, RelayLog};
+use relay_stream::{LogToFuture, RelayOutcome};
+use send_tx_with_receipt::SendTransactionWithReceipt;
 use side_contract::SideContract;
 use signature::Signature;
 use tokio_timer::{Timeout, Timer};
+use transaction::AsyncFees;
 use web3;
 use web3::api::Namespace;
 use web3::helpers::CallFuture;
-use web3::types::{Bytes, Log, H256, H520};
+use web3::types::{Bytes, Log, H520, U256};
 use web3::Transport;
 
 enum State<T: Transport> {
@@ -34,7 +38,14 @@ enum State<T: Transport> {
 		AsyncCall<T, contracts::side::functions::has_authority_signed_message::Decoder>,
 	),
 	AwaitSignature(Timeout<FromErr<CallFuture<H520, T::Out>, error::Error>>),
-	AwaitTransaction(AsyncTransaction<T>),
+	AwaitGasEstimate {
+		signature: Signature,
+		future: Join<AsyncGasEstimate<T>, AsyncFees<T>>,
+	},
+	AwaitTransaction {
+		future: SendTransactionWithReceipt<T>,
+		gas_price: U256,
+	},
 }
 
 /// `Future` that is responsible for calling `sideContract.submitSignature`
@@ -42,45 +53,48 @@ enum State<T: Transport> {
 /// these get created by the `side_to_main_sign` `RelayStream` that's part
 /// of the `Bridge`.
 pub struct SideToMainSign<T: Transport> {
-	tx_hash: H256,
 	side: SideContract<T>,
 	message: MessageToMain,
 	state: State<T>,
+	relay_log: RelayLog,
 }
 
 impl<T: Transport> SideToMainSign<T> {
-	pub fn new(log: &Log, side: SideContract<T>) -> Self {
-		let tx_hash = log
-			.transaction_hash
-			.expect("`log` must be mined and contain `transaction_hash`. q.e.d.");
+	pub fn new(log: &Log, side: SideContract<T>) -> error::Result<Self> {
+		log.transaction_hash
+			.ok_or_else(|| "`log` must be mined and contain `transaction_hash`")?;
 
 		let message =
-			MessageToMain::from_log(log).expect("`log` must contain valid message. q.e.d.");
+			MessageToMain::from_log(log).chain_err(|| "`log` must contain valid message")?;
 		let message_bytes = message.to_bytes();
 
-		assert_eq!(
-			message_bytes.len(),
-			MESSAGE_LENGTH,
-			"SideBridge never accepts messages with len != {} bytes; qed",
-			MESSAGE_LENGTH
-		);
+		if message_bytes.len() != MESSAGE_LENGTH {
+			bail!(
+				"SideBridge never accepts messages with len != {} bytes",
+				MESSAGE_LENGTH
+			);
+		}
 
 		let future = side.is_side_to_main_signed_on_side(&message);
 		let state = State::AwaitCheckAlreadySigned(future);
-		info!("{:?} - step 1/3 - about to sign message", tx_hash);
+		let relay_log = RelayLog::new(
+			RelayDirection::SideToMain,
+			Some(message.side_tx_hash),
+			Some(message.message_id),
+		);
+		relay_log.step("1/3", Chain::Side, "about to sign message");
 
-		Self {
+		Ok(Self {
 			side,
-			tx_hash,
 			message,
 			state,
-		}
+			relay_log,
+		})
 	}
 }
 
 impl<T: Transport> Future for SideToMainSign<T> {
-	/// transaction hash
-	type Item = Option<H256>;
+	type Item = RelayOutcome;
 	type Error = error::Error;
 
 	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
@@ -91,7 +105,7 @@ impl<T: Transport> Future for SideToMainSign<T> {
 						.poll()
 						.chain_err(|| "WithdrawConfirm: message signing failed"));
 					if is_already_signed {
-						return Ok(Async::Ready(None));
+						return Ok(Async::Ready(RelayOutcome::AlreadyRelayed));
 					}
 
 					let inner_future = web3::api::Eth::new(self.side.transport.clone())
@@ -105,25 +119,68 @@ impl<T: Transport> Future for SideToMainSign<T> {
 					let signature_bytes = try_ready!(future
 						.poll()
 						.chain_err(|| "WithdrawConfirm: message signing failed"));
-					info!(
-						"{:?} - step 2/3 - message signed. about to send transaction",
-						self.tx_hash
+					self.relay_log.step(
+						"2/3",
+						Chain::Side,
+						"message signed. about to estimate gas and fees",
 					);
 
 					let signature = Signature::from_bytes(&signature_bytes.as_bytes())?;
 
-					let future = self.side.submit_signed_message(&self.message, &signature);
-					State::AwaitTransaction(future)
+					let future = self
+						.side
+						.estimate_submit_signed_message_gas(&self.message, &signature)
+						.join(self.side.submit_signed_message_fees());
+					State::AwaitGasEstimate { signature, future }
 				}
-				State::AwaitTransaction(ref mut future) => {
-					let tx_hash = try_ready!(future
+				State::AwaitGasEstimate {
+					ref signature,
+					ref mut future,
+				} => {
+					let (gas, fees) =
+						try_ready!(helpers::chain_estimate_err(future.poll(), || {
+							"WithdrawConfirm: estimating gas failed".into()
+						}));
+					self.relay_log.step(
+						"2/3",
+						Chain::Side,
+						format!(
+							"about to send transaction with gas {} and fees {:?}",
+							gas, fees
+						),
+					);
+
+					State::AwaitTransaction {
+						gas_price: fees.expected_gas_price(),
+						future: self.side.submit_signed_message(
+							&self.message,
+							signature,
+							gas,
+							fees,
+						),
+					}
+				}
+				State::AwaitTransaction {
+					ref mut future,
+					gas_price,
+				} => {
+					let receipt = try_ready!(future
 						.poll()
 						.chain_err(|| "WithdrawConfirm: sending transaction failed"));
-					info!(
-						"{:?} - step 3/3 - DONE - transaction sent {:?}",
-						self.tx_hash, tx_hash
+					self.relay_log.sent(
+						"done",
+						Chain::Side,
+						receipt.transaction_hash,
+						"transaction mined",
 					);
-					return Ok(Async::Ready(Some(tx_hash)));
+					return Ok(Async::Ready(RelayOutcome::Relayed(
+						RelayCost::from_receipt(
+							RelayDirection::SideToMain,
+							self.message.sender,
+							&receipt,
+							gas_price,
+						),
+					)));
 				}
 			};
 			self.state = next_state;
@@ -139,7 +196,7 @@ pub struct LogToSideToMainSign<T: Transport> {
 impl<T: Transport> LogToFuture for LogToSideToMainSign<T> {
 	type Future = SideToMainSign<T>;
 
-	fn log_to_future(&self, log: &Log) -> Self::Future {
+	fn log_to_future(&self, log: &Log) -> error::Result<Self::Future> {
 		SideToMainSign::new(log, self.side.clone())
 	}
 }
@@ -147,6 +204,7 @@ impl<T: Transport> LogToFuture for LogToSideToMainSign<T> {
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use config::TransactionConfig;
 	use contracts;
 	use ethabi;
 	use rustc_hex::FromHex;
@@ -229,15 +287,50 @@ mod tests {
 					format!("0x{}", message.to_bytes().to_hex::<String>())
 				]),
 				res => json!(format!("0x{}", signature));
+			"eth_estimateGas" =>
+				req => json!([{
+					"data": format!("0x{}", tx_data.to_hex::<String>()),
+					"from": format!("0x{:x}", authority_address),
+					"to": format!("0x{:x}", side_contract_address),
+				}]),
+				res => json!("0x64");
+			"eth_feeHistory" =>
+				req => json!(["0x5", "latest", [50.0]]),
+				res => json!({
+					"oldestBlock": "0x100b",
+					"baseFeePerGas": ["0x0", "0x0", "0x0", "0x0", "0x0", "0x0"],
+					"gasUsedRatio": [0.0, 0.0, 0.0, 0.0, 0.0],
+				});
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x1010");
 			"eth_sendTransaction" =>
 				req => json!([{
 					"data": format!("0x{}", tx_data.to_hex::<String>()),
 					"from": format!("0x{:x}", authority_address),
-					"gas": "0xfd",
+					"gas": "0x78",
 					"gasPrice": "0xa0",
 					"to": format!("0x{:x}", side_contract_address),
 				}]),
 				res => json!(format!("0x{}", tx_hash));
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x1011");
+			"eth_getTransactionReceipt" =>
+				req => json!([format!("0x{}", tx_hash)]),
+				res => json!({
+					"blockHash": "0xe0bdcf35b14a292d2998308d9b3fdea93a8c3d9c0b6c824c633fb9b15f9c3919",
+					"blockNumber": "0x1011",
+					"contractAddress": null,
+					"cumulativeGasUsed": "0x1c1999",
+					"gasUsed": "0x5208",
+					"logs": [],
+					"logsBloom": format!("0x{}", "0".repeat(512)),
+					"root": null,
+					"status": "0x1",
+					"transactionHash": format!("0x{}", tx_hash),
+					"transactionIndex": "0x4"
+				});
 		);
 
 		let side_contract = SideContract {
@@ -248,17 +341,34 @@ mod tests {
 			request_timeout: ::std::time::Duration::from_millis(0),
 			logs_poll_interval: ::std::time::Duration::from_millis(0),
 			required_log_confirmations: 0,
-			sign_main_to_side_gas: 0.into(),
-			sign_main_to_side_gas_price: 0.into(),
-			sign_side_to_main_gas: 0xfd.into(),
-			sign_side_to_main_gas_price: 0xa0.into(),
+			gas_estimate_multiplier: 1.2,
+			sign_main_to_side_tx: TransactionConfig {
+				gas: 0.into(),
+				gas_price: 0.into(),
+				..Default::default()
+			},
+			sign_side_to_main_tx: TransactionConfig {
+				gas: 0xfd.into(),
+				gas_price: 0xa0.into(),
+				..Default::default()
+			},
+			eip1559_support: Default::default(),
 		};
 
-		let future = SideToMainSign::new(&raw_log, side_contract);
+		let future = SideToMainSign::new(&raw_log, side_contract).unwrap();
 
 		let mut event_loop = Core::new().unwrap();
 		let result = event_loop.run(future).unwrap();
-		assert_eq!(result, Some(tx_hash.parse().unwrap()));
+		assert_eq!(
+			result,
+			RelayOutcome::Relayed(RelayCost {
+				direction: RelayDirection::SideToMain,
+				sender: log.sender,
+				transaction_hash: tx_hash.parse().unwrap(),
+				gas_used: 0x5208.into(),
+				gas_price: 0xa0.into(),
+			})
+		);
 
 		assert_eq!(transport.actual_requests(), transport.expected_requests());
 	}
@@ -334,18 +444,73 @@ mod tests {
 			request_timeout: ::std::time::Duration::from_millis(0),
 			logs_poll_interval: ::std::time::Duration::from_millis(0),
 			required_log_confirmations: 0,
-			sign_main_to_side_gas: 0.into(),
-			sign_main_to_side_gas_price: 0.into(),
-			sign_side_to_main_gas: 0xfd.into(),
-			sign_side_to_main_gas_price: 0xa0.into(),
+			gas_estimate_multiplier: 1.2,
+			sign_main_to_side_tx: TransactionConfig {
+				gas: 0.into(),
+				gas_price: 0.into(),
+				..Default::default()
+			},
+			sign_side_to_main_tx: TransactionConfig {
+				gas: 0xfd.into(),
+				gas_price: 0xa0.into(),
+				..Default::default()
+			},
+			eip1559_support: Default::default(),
 		};
 
-		let future = SideToMainSign::new(&raw_log, side_contract);
+		let future = SideToMainSign::new(&raw_log, side_contract).unwrap();
 
 		let mut event_loop = Core::new().unwrap();
 		let result = event_loop.run(future).unwrap();
-		assert_eq!(result, None);
+		assert_eq!(result, RelayOutcome::AlreadyRelayed);
 
 		assert_eq!(transport.actual_requests(), transport.expected_requests());
 	}
+
+	#[test]
+	fn test_side_to_main_sign_relay_future_malformed_log() {
+		let topic = contracts::side::events::relay_message::filter().topic0;
+
+		// truncated: contains only the message id
+		let raw_log = Log {
+			address: "0000000000000000000000000000000000000001".parse().unwrap(),
+			topics: topic.into(),
+			data: Bytes(vec![0x88; 32]),
+			transaction_hash: Some(
+				"884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364"
+					.parse()
+					.unwrap(),
+			),
+			block_hash: None,
+			block_number: None,
+			transaction_index: None,
+			log_index: None,
+			transaction_log_index: None,
+			log_type: None,
+			removed: None,
+		};
+
+		let transport = ::MockTransport {
+			actual_requests: Default::default(),
+			expected_requests: Vec::new(),
+			mock_responses: Vec::new(),
+		};
+
+		let side_contract = SideContract {
+			transport: transport.clone(),
+			contract_address: "0000000000000000000000000000000000000dd1".parse().unwrap(),
+			authority_address: "0000000000000000000000000000000000000001".parse().unwrap(),
+			required_signatures: 1,
+			request_timeout: ::std::time::Duration::from_millis(0),
+			logs_poll_interval: ::std::time::Duration::from_millis(0),
+			required_log_confirmations: 0,
+			gas_estimate_multiplier: 1.2,
+			sign_main_to_side_tx: Default::default(),
+			sign_side_to_main_tx: Default::default(),
+			eip1559_support: Default::default(),
+		};
+
+		assert!(SideToMainSign::new(&raw_log, side_contract).is_err());
+		assert!(transport.actual_requests().is_empty());
+	}
 }
@@ -15,17 +15,22 @@
 // along with Parity-Bridge.  If not, see <http://www.gnu.org/licenses/>.
 
 use contracts;
+use database::{HeldMessage, RelayCost, RelayDirection};
 use error::{self, ResultExt};
-use futures::future::JoinAll;
+use futures::future::{Join, JoinAll};
 use futures::{Async, Future, Poll};
 use helpers;
-use helpers::{AsyncCall, AsyncTransaction};
+use helpers::{AsyncCall, AsyncGasEstimate};
+use log::Level;
 use main_contract::MainContract;
 use message_to_main::MessageToMain;
-use relay_stream::LogToFuture;
+use relay_log::{Chain, RelayLog};
+use relay_stream::{LogToFuture, RelayOutcome};
+use send_tx_with_receipt::SendTransactionWithReceipt;
 use side_contract::SideContract;
 use signature::Signature;
-use web3::types::{Log, H256};
+use transaction::AsyncFees;
+use web3::types::{Address, Log, H256, U256};
 use web3::Transport;
 
 enum State<T: Transport> {
@@ -43,7 +48,17 @@ enum State<T: Transport> {
 		message: MessageToMain,
 		signatures: Vec<Signature>,
 	},
-	AwaitTxSent(AsyncTransaction<T>),
+	AwaitGasEstimate {
+		future: Join<AsyncGasEstimate<T>, AsyncFees<T>>,
+		message: MessageToMain,
+		signatures: Vec<Signature>,
+		message_data: Vec<u8>,
+	},
+	AwaitTxSent {
+		future: SendTransactionWithReceipt<T>,
+		sender: Address,
+		gas_price: U256,
+	},
 }
 
 /// `Future` that completes a transfer from side to main by calling
@@ -52,43 +67,94 @@ enum State<T: Transport> {
 /// of the `Bridge`.
 pub struct SideToMainSignatures<T: Transport> {
 	side_tx_hash: H256,
+	message_hash: H256,
 	main: MainContract<T>,
 	side: SideContract<T>,
+	/// whether to hold the relay if it exceeds `main.max_relay_cost`
+	enforce_max_relay_cost: bool,
 	state: State<T>,
+	relay_log: RelayLog,
 }
 
 impl<T: Transport> SideToMainSignatures<T> {
-	pub fn new(raw_log: &Log, main: MainContract<T>, side: SideContract<T>) -> Self {
+	pub fn new(raw_log: &Log, main: MainContract<T>, side: SideContract<T>) -> error::Result<Self> {
 		let side_tx_hash = raw_log
 			.transaction_hash
-			.expect("`log` must be mined and contain `transaction_hash`. q.e.d.");
+			.ok_or_else(|| "`log` must be mined and contain `transaction_hash`")?;
 
 		let log = helpers::parse_log(contracts::side::events::signed_message::parse_log, raw_log)
-			.expect("`Log` must be a from a `CollectedSignatures` event. q.e.d.");
+			.chain_err(|| "`Log` must be a from a `CollectedSignatures` event")?;
 
 		// authority_responsible_for_relay is an indexed topic and it should be
 		// always set up when creating the filter, so we receive only logs that
 		// we should relay
-		assert_eq!(
-			log.authority_responsible_for_relay, main.authority_address,
-			"incorrectly set up collected_signatures filter, we should only received logs where authority_responsible_for_relay == main.authority_address; qed"
-		);
+		if log.authority_responsible_for_relay != main.authority_address {
+			bail!(
+				"incorrectly set up collected_signatures filter, we should only receive logs where authority_responsible_for_relay ({:?}) == main.authority_address ({:?})",
+				log.authority_responsible_for_relay,
+				main.authority_address
+			);
+		}
+
+		Ok(Self::from_message_hash(
+			side_tx_hash,
+			log.message_hash,
+			main,
+			side,
+			true,
+		))
+	}
 
-		info!("{:?} - step 1/3 - about to fetch message", side_tx_hash,);
-		let (payload, decoder) = contracts::side::functions::message::call(log.message_hash);
+	/// relay a message that was previously held because it was too expensive.
+	/// `main.max_relay_cost` is not enforced.
+	pub fn release(
+		held_message: &HeldMessage,
+		main: MainContract<T>,
+		side: SideContract<T>,
+	) -> Self {
+		Self::from_message_hash(
+			held_message.side_tx_hash,
+			held_message.message_hash,
+			main,
+			side,
+			false,
+		)
+	}
+
+	fn from_message_hash(
+		side_tx_hash: H256,
+		message_hash: H256,
+		main: MainContract<T>,
+		side: SideContract<T>,
+		enforce_max_relay_cost: bool,
+	) -> Self {
+		// the message and with it its origin are only known once fetched
+		let relay_log = RelayLog::new(RelayDirection::SideToMain, None, None);
+		relay_log.step(
+			"1/3",
+			Chain::Side,
+			format!(
+				"about to fetch message {:?} signed in {:?}",
+				message_hash, side_tx_hash
+			),
+		);
+		let (payload, decoder) = contracts::side::functions::message::call(message_hash);
 		let state = State::AwaitMessage(side.call(payload, decoder));
 
 		Self {
 			side_tx_hash,
+			message_hash,
 			main,
 			side,
+			enforce_max_relay_cost,
 			state,
+			relay_log,
 		}
 	}
 }
 
 impl<T: Transport> Future for SideToMainSignatures<T> {
-	type Item = Option<H256>;
+	type Item = RelayOutcome;
 	type Error = error::Error;
 
 	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
@@ -99,6 +165,8 @@ impl<T: Transport> Future for SideToMainSignatures<T> {
 						.poll()
 						.chain_err(|| "SubmitSignature: fetching message failed"));
 					let message = MessageToMain::from_bytes(&message_bytes)?;
+					self.relay_log.origin_tx_hash = Some(message.side_tx_hash);
+					self.relay_log.message_id = Some(message.message_id);
 
 					let (payload, decoder) =
 						contracts::main::functions::accepted_messages::call(message.keccak256());
@@ -116,7 +184,8 @@ impl<T: Transport> Future for SideToMainSignatures<T> {
 						.chain_err(|| "SubmitSignature: fetching message failed"));
 
 					if is_relayed {
-						return Ok(Async::Ready(None));
+						self.relay_log.step("done", Chain::Main, "already relayed");
+						return Ok(Async::Ready(RelayOutcome::AlreadyRelayed));
 					}
 
 					State::AwaitSignatures {
@@ -135,7 +204,14 @@ impl<T: Transport> Future for SideToMainSignatures<T> {
 						.iter()
 						.map(|x| Signature::from_bytes(x))
 						.collect::<Result<_, _>>()?;
-					info!("{:?} - step 2/3 - message and {} signatures received. about to send transaction", self.side_tx_hash, signatures.len());
+					self.relay_log.step(
+						"2/3",
+						Chain::Main,
+						format!(
+							"message and {} signatures received. about to send transaction",
+							signatures.len()
+						),
+					);
 
 					let (payload, decoder) =
 						contracts::side::functions::relayed_messages::call(message.message_id);
@@ -154,21 +230,86 @@ impl<T: Transport> Future for SideToMainSignatures<T> {
 						.poll()
 						.chain_err(|| "SubmitSignature: fetching message failed"));
 
-					State::AwaitTxSent(self.main.relay_side_to_main(
-						&message,
-						&signatures,
+					State::AwaitGasEstimate {
+						future: self
+							.main
+							.estimate_relay_side_to_main_gas(
+								&message,
+								&signatures,
+								message_data.clone(),
+							)
+							.join(self.main.relay_side_to_main_fees()),
+						message: message.clone(),
+						signatures: signatures.clone(),
 						message_data,
-					))
+					}
+				}
+				State::AwaitGasEstimate {
+					ref mut future,
+					ref message,
+					ref signatures,
+					ref message_data,
+				} => {
+					let (gas, fees) =
+						try_ready!(helpers::chain_estimate_err(future.poll(), || {
+							"WithdrawRelay: estimating gas failed".into()
+						}));
+
+					let estimated_cost = fees.max_cost(gas);
+					if let Some(max_relay_cost) = self.main.max_relay_cost {
+						if self.enforce_max_relay_cost && estimated_cost > max_relay_cost {
+							self.relay_log.log(
+								Level::Warn,
+								"held",
+								Chain::Main,
+								None,
+								format!(
+									"estimated cost {} exceeds max_main_relay_cost {}",
+									estimated_cost, max_relay_cost
+								),
+							);
+							return Ok(Async::Ready(RelayOutcome::Held(HeldMessage {
+								side_tx_hash: self.side_tx_hash,
+								message_hash: self.message_hash,
+								estimated_cost,
+							})));
+						}
+					}
+
+					State::AwaitTxSent {
+						gas_price: fees.expected_gas_price(),
+						future: self.main.relay_side_to_main(
+							&message,
+							&signatures,
+							message_data.clone(),
+							gas,
+							fees,
+						),
+						sender: message.sender,
+					}
 				}
-				State::AwaitTxSent(ref mut future) => {
-					let main_tx_hash = try_ready!(future
+				State::AwaitTxSent {
+					ref mut future,
+					sender,
+					gas_price,
+				} => {
+					let receipt = try_ready!(future
 						.poll()
 						.chain_err(|| "WithdrawRelay: sending transaction failed"));
-					info!(
-						"{:?} - step 3/3 - DONE - transaction sent {:?}",
-						self.side_tx_hash, main_tx_hash
+					self.relay_log.sent(
+						"done",
+						Chain::Main,
+						receipt.transaction_hash,
+						"transaction mined",
 					);
-					return Ok(Async::Ready(Some(main_tx_hash)));
+					return Ok(Async::Ready(RelayOutcome::Relayed(
+						RelayCost::from_receipt(
+							RelayDirection::SideToMain,
+							sender,
+							&receipt,
+							gas_price,
+						),
+					)));
 				}
 			};
 			self.state = next_state;
@@ -186,7 +327,7 @@ pub struct LogToSideToMainSignatures<T> {
 impl<T: Transport> LogToFuture for LogToSideToMainSignatures<T> {
 	type Future = SideToMainSignatures<T>;
 
-	fn log_to_future(&self, log: &Log) -> Self::Future {
+	fn log_to_future(&self, log: &Log) -> error::Result<Self::Future> {
 		SideToMainSignatures::new(log, self.main.clone(), self.side.clone())
 	}
 }
@@ -194,6 +335,7 @@ impl<T: Transport> LogToFuture for LogToSideToMainSignatures<T> {
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use config::TransactionConfig;
 	use contracts;
 	use ethabi;
 	use rustc_hex::FromHex;
@@ -268,6 +410,34 @@ mod tests {
 					"to": format!("0x{:x}", main_contract_address),
 				}, "latest"]),
 				res => json!(format!("0x{}", ethabi::encode(&[ethabi::Token::Bool(false)]).to_hex::<String>()));
+			"eth_estimateGas" =>
+				req => json!([{
+					"data": format!(
+						"0x{}",
+						contracts::main::functions::accept_message::encode_input(
+							vec![signature.v],
+							vec![signature.r.clone()],
+							vec![signature.s.clone()],
+							message.side_tx_hash,
+							data.clone(),
+							message.sender,
+							message.recipient,
+						).to_hex::<String>()
+					),
+					"from": format!("0x{:x}", authority_address),
+					"to": format!("0x{:x}", main_contract_address),
+				}]),
+				res => json!("0x64");
+			"eth_feeHistory" =>
+				req => json!(["0x5", "latest", [50.0]]),
+				res => json!({
+					"oldestBlock": "0x100b",
+					"baseFeePerGas": ["0x0", "0x0", "0x0", "0x0", "0x0", "0x0"],
+					"gasUsedRatio": [0.0, 0.0, 0.0, 0.0, 0.0],
+				});
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x1010");
 			"eth_sendTransaction" =>
 				req => json!([{
 					"data": format!(
@@ -283,12 +453,29 @@ mod tests {
 						).to_hex::<String>()
 					),
 					"from": format!("0x{:x}", authority_address),
-					"gas": "0xfd",
-					// TODO: fix gasPrice
-					"gasPrice": format!("0x{:x}", 1000),
+					"gas": "0x78",
+					"gasPrice": "0xa0",
 					"to": format!("0x{:x}", main_contract_address),
 				}]),
 				res => json!(format!("0x{:}", tx_hash));
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x1011");
+			"eth_getTransactionReceipt" =>
+				req => json!([format!("0x{}", tx_hash)]),
+				res => json!({
+					"blockHash": "0xe0bdcf35b14a292d2998308d9b3fdea93a8c3d9c0b6c824c633fb9b15f9c3919",
+					"blockNumber": "0x1011",
+					"contractAddress": null,
+					"cumulativeGasUsed": "0x1c1999",
+					"gasUsed": "0x5208",
+					"logs": [],
+					"logsBloom": format!("0x{}", "0".repeat(512)),
+					"root": null,
+					"status": "0x1",
+					"transactionHash": format!("0x{}", tx_hash),
+					"transactionIndex": "0x4"
+				});
 		);
 
 		let side_transport = mock_transport!(
@@ -322,7 +509,14 @@ mod tests {
 			request_timeout: ::std::time::Duration::from_millis(0),
 			logs_poll_interval: ::std::time::Duration::from_millis(0),
 			required_log_confirmations: 0,
-			submit_collected_signatures_gas: 0xfd.into(),
+			submit_collected_signatures_tx: TransactionConfig {
+				gas: 0xfd.into(),
+				gas_price: 0xa0.into(),
+				..Default::default()
+			},
+			gas_estimate_multiplier: 1.2,
+			max_relay_cost: None,
+			eip1559_support: Default::default(),
 		};
 
 		let side_contract = SideContract {
@@ -333,17 +527,214 @@ mod tests {
 			request_timeout: ::std::time::Duration::from_millis(0),
 			logs_poll_interval: ::std::time::Duration::from_millis(0),
 			required_log_confirmations: 0,
-			sign_main_to_side_gas: 0.into(),
-			sign_main_to_side_gas_price: 0.into(),
-			sign_side_to_main_gas: 0xfd.into(),
-			sign_side_to_main_gas_price: 0xa0.into(),
+			gas_estimate_multiplier: 1.2,
+			sign_main_to_side_tx: TransactionConfig {
+				gas: 0.into(),
+				gas_price: 0.into(),
+				..Default::default()
+			},
+			sign_side_to_main_tx: TransactionConfig {
+				gas: 0xfd.into(),
+				gas_price: 0xa0.into(),
+				..Default::default()
+			},
+			eip1559_support: Default::default(),
 		};
 
-		let future = SideToMainSignatures::new(&raw_log, main_contract, side_contract);
+		let future = SideToMainSignatures::new(&raw_log, main_contract, side_contract).unwrap();
 
 		let mut event_loop = Core::new().unwrap();
 		let result = event_loop.run(future).unwrap();
-		assert_eq!(result, Some(tx_hash.parse().unwrap()));
+		assert_eq!(
+			result,
+			RelayOutcome::Relayed(RelayCost {
+				direction: RelayDirection::SideToMain,
+				sender: message.sender,
+				transaction_hash: tx_hash.parse().unwrap(),
+				gas_used: 0x5208.into(),
+				gas_price: 0xa0.into(),
+			})
+		);
+
+		assert_eq!(
+			main_transport.actual_requests(),
+			main_transport.expected_requests()
+		);
+		assert_eq!(
+			side_transport.actual_requests(),
+			side_transport.expected_requests()
+		);
+	}
+
+	#[test]
+	fn test_side_to_main_sign_relay_future_held_if_too_expensive() {
+		let authority_address: Address =
+			"0000000000000000000000000000000000000001".parse().unwrap();
+		let authority_responsible_for_relay = authority_address;
+		let topic =
+			contracts::side::events::signed_message::filter(authority_responsible_for_relay);
+
+		let message = MessageToMain {
+			side_tx_hash: "884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364"
+				.parse()
+				.unwrap(),
+			message_id: "884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a94243ff"
+				.parse()
+				.unwrap(),
+			sender: "aff3454fce5edbc8cca8697c15331677e6ebccff".parse().unwrap(),
+			recipient: "aff3454fce5edbc8cca8697c15331677e6ebcccc".parse().unwrap(),
+		};
+
+		let log = contracts::side::logs::SignedMessage {
+			authority_responsible_for_relay,
+			message_hash: message.keccak256(),
+		};
+
+		// TODO [snd] would be nice if ethabi derived log structs implemented `encode`
+		let log_data = ethabi::encode(&[ethabi::Token::FixedBytes(
+			log.message_hash.as_bytes().to_vec(),
+		)]);
+
+		let log_tx_hash: H256 = "884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364"
+			.parse()
+			.unwrap();
+
+		let raw_log = Log {
+			address: "0000000000000000000000000000000000000001".parse().unwrap(),
+			topics: vec![topic.topic0[0], topic.topic1[0]],
+			data: Bytes(log_data),
+			transaction_hash: Some(log_tx_hash),
+			block_hash: None,
+			block_number: None,
+			transaction_index: None,
+			log_index: None,
+			transaction_log_index: None,
+			log_type: None,
+			removed: None,
+		};
+
+		let side_contract_address: Address =
+			"0000000000000000000000000000000000000dd1".parse().unwrap();
+		let main_contract_address: Address =
+			"0000000000000000000000000000000000000fff".parse().unwrap();
+
+		let sig: Vec<u8> = "8697c15331677e6ebccccaff3454fce5edbc8cca8697c15331677aff3454fce5edbc8cca8697c15331677e6ebccccaff3454fce5edbc8cca8697c15331677e6ebc"
+			.from_hex()
+			.unwrap();
+		let signature = Signature::from_bytes(&*sig).unwrap();
+
+		let data: Vec<u8> = vec![10, 0];
+
+		let main_transport = mock_transport!(
+			"eth_call" =>
+				req => json!([{
+					"data": format!("0x{}", contracts::main::functions::accepted_messages::encode_input(log.message_hash).to_hex::<String>()),
+					"to": format!("0x{:x}", main_contract_address),
+				}, "latest"]),
+				res => json!(format!("0x{}", ethabi::encode(&[ethabi::Token::Bool(false)]).to_hex::<String>()));
+			"eth_estimateGas" =>
+				req => json!([{
+					"data": format!(
+						"0x{}",
+						contracts::main::functions::accept_message::encode_input(
+							vec![signature.v],
+							vec![signature.r.clone()],
+							vec![signature.s.clone()],
+							message.side_tx_hash,
+							data.clone(),
+							message.sender,
+							message.recipient,
+						).to_hex::<String>()
+					),
+					"from": format!("0x{:x}", authority_address),
+					"to": format!("0x{:x}", main_contract_address),
+				}]),
+				res => json!("0x64");
+			"eth_feeHistory" =>
+				req => json!(["0x5", "latest", [50.0]]),
+				res => json!({
+					"oldestBlock": "0x100b",
+					"baseFeePerGas": ["0x0", "0x0", "0x0", "0x0", "0x0", "0x0"],
+					"gasUsedRatio": [0.0, 0.0, 0.0, 0.0, 0.0],
+				});
+		);
+
+		let side_transport = mock_transport!(
+			"eth_call" =>
+				req => json!([{
+					"data": format!("0x{}",
+									contracts::side::functions::message::encode_input(log.message_hash).to_hex::<String>()),
+					"to": format!("0x{:x}", side_contract_address),
+				}, "latest"]),
+				res => json!(format!("0x{}",
+									 ethabi::encode(&[ethabi::Token::Bytes(message.to_bytes())]).to_hex::<String>()));
+			"eth_call" =>
+				req => json!([{
+					"data": format!("0x{}", contracts::side::functions::signature::encode_input(log.message_hash, 0).to_hex::<String>()),
+					"to": format!("0x{:x}", side_contract_address),
+				}, "latest"]),
+				res => json!(format!("0x{}",
+									 ethabi::encode(&[ethabi::Token::Bytes(signature.to_bytes())]).to_hex::<String>()));
+			"eth_call" =>
+				req => json!([{
+					"data": format!("0x{}", contracts::side::functions::relayed_messages::encode_input(message.message_id).to_hex::<String>()),
+					"to": format!("0x{:x}", side_contract_address),
+				}, "latest"]),
+				res => json!(format!("0x{}", ethabi::encode(&[ethabi::Token::Bytes(data.clone())]).to_hex::<String>()));
+		);
+
+		let main_contract = MainContract {
+			transport: main_transport.clone(),
+			contract_address: main_contract_address,
+			authority_address,
+			request_timeout: ::std::time::Duration::from_millis(0),
+			logs_poll_interval: ::std::time::Duration::from_millis(0),
+			required_log_confirmations: 0,
+			submit_collected_signatures_tx: TransactionConfig {
+				gas: 0xfd.into(),
+				gas_price: 0xa0.into(),
+				..Default::default()
+			},
+			gas_estimate_multiplier: 1.2,
+			// gas estimate 0x64 * 1.2 = 120. 120 * gas price 0xa0 = 19200
+			max_relay_cost: Some(19199.into()),
+			eip1559_support: Default::default(),
+		};
+
+		let side_contract = SideContract {
+			transport: side_transport.clone(),
+			contract_address: side_contract_address,
+			authority_address,
+			required_signatures: 1,
+			request_timeout: ::std::time::Duration::from_millis(0),
+			logs_poll_interval: ::std::time::Duration::from_millis(0),
+			required_log_confirmations: 0,
+			gas_estimate_multiplier: 1.2,
+			sign_main_to_side_tx: TransactionConfig {
+				gas: 0.into(),
+				gas_price: 0.into(),
+				..Default::default()
+			},
+			sign_side_to_main_tx: TransactionConfig {
+				gas: 0xfd.into(),
+				gas_price: 0xa0.into(),
+				..Default::default()
+			},
+			eip1559_support: Default::default(),
+		};
+
+		let future = SideToMainSignatures::new(&raw_log, main_contract, side_contract).unwrap();
+
+		let mut event_loop = Core::new().unwrap();
+		let result = event_loop.run(future).unwrap();
+		assert_eq!(
+			result,
+			RelayOutcome::Held(HeldMessage {
+				side_tx_hash: log_tx_hash,
+				message_hash: log.message_hash,
+				estimated_cost: 19200.into(),
+			})
+		);
 
 		assert_eq!(
 			main_transport.actual_requests(),
@@ -434,7 +825,14 @@ mod tests {
 			request_timeout: ::std::time::Duration::from_millis(0),
 			logs_poll_interval: ::std::time::Duration::from_millis(0),
 			required_log_confirmations: 0,
-			submit_collected_signatures_gas: 0xfd.into(),
+			submit_collected_signatures_tx: TransactionConfig {
+				gas: 0xfd.into(),
+				gas_price: 0xa0.into(),
+				..Default::default()
+			},
+			gas_estimate_multiplier: 1.2,
+			max_relay_cost: None,
+			eip1559_support: Default::default(),
 		};
 
 		let side_contract = SideContract {
@@ -445,17 +843,25 @@ mod tests {
 			request_timeout: ::std::time::Duration::from_millis(0),
 			logs_poll_interval: ::std::time::Duration::from_millis(0),
 			required_log_confirmations: 0,
-			sign_main_to_side_gas: 0.into(),
-			sign_main_to_side_gas_price: 0.into(),
-			sign_side_to_main_gas: 0xfd.into(),
-			sign_side_to_main_gas_price: 0xa0.into(),
+			gas_estimate_multiplier: 1.2,
+			sign_main_to_side_tx: TransactionConfig {
+				gas: 0.into(),
+				gas_price: 0.into(),
+				..Default::default()
+			},
+			sign_side_to_main_tx: TransactionConfig {
+				gas: 0xfd.into(),
+				gas_price: 0xa0.into(),
+				..Default::default()
+			},
+			eip1559_support: Default::default(),
 		};
 
-		let future = SideToMainSignatures::new(&raw_log, main_contract, side_contract);
+		let future = SideToMainSignatures::new(&raw_log, main_contract, side_contract).unwrap();
 
 		let mut event_loop = Core::new().unwrap();
 		let result = event_loop.run(future).unwrap();
-		assert_eq!(result, None);
+		assert_eq!(result, RelayOutcome::AlreadyRelayed);
 
 		assert_eq!(
 			main_transport.actual_requests(),
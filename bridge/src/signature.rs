@@ -24,7 +24,7 @@ use error::Error;
 pub const SIGNATURE_LENGTH: usize = 65;
 
 /// an ECDSA signature consisting of `v`, `r` and `s`
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct Signature {
 	pub v: u8,
 	pub r: H256,
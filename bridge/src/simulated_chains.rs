@@ -0,0 +1,1297 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity-Bridge.
+
+// Parity-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! in memory main and side chains for tests that run the `Bridge`.
+//!
+//! both chains execute the compiled bridge contracts (`contracts::MAIN_BIN`
+//! and `contracts::SIDE_BIN`) in an embedded EVM (`revm`).
+//! their genesis blocks contain the bridge contract and a `RecipientTest`
+//! contract that receives the relayed messages.
+//! calls, gas estimates, receipts and logs are the results of executing the bytecode:
+//! calls and estimates that revert fail with the error a node returns for them
+//! and transactions that revert are mined with status 0.
+//! the accounts of the authorities and the user have secret keys which
+//! `eth_sign` signs with, so the contracts check real signatures.
+//!
+//! every transaction is mined right away into a block of its own.
+//! time is simulated in steps: `SimulatedChains::advance` mines an empty block
+//! on both chains and only then answers the pending `eth_blockNumber` requests.
+//! tests therefore run a `Bridge` for a number of steps instead of a duration.
+
+use bridge::Bridge;
+use config::TransactionConfig;
+use contracts;
+use database::State;
+use error;
+use ethabi::{self, FunctionOutputDecoder, ParamType, Token};
+use futures::executor::{self, Notify, Spawn};
+use futures::task::{self, Task};
+use futures::{Async, Future, Poll};
+use helpers::keccak256;
+use jsonrpc_core::{self, Call, Params, Value};
+use main_contract::MainContract;
+use message_to_main::MessageToMain;
+use relay_log::Chain;
+use revm::primitives::{
+	Address as EvmAddress, ExecutionResult, Output, SpecId, TxKind, U256 as EvmU256,
+};
+use revm::{Evm, InMemoryDB};
+use rustc_hex::{FromHex, ToHex};
+use secp256k1;
+use serde_json;
+use side_contract::SideContract;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+use web3::types::{Address, Log, H256, U256};
+use web3::{self, Transport};
+
+/// gas limit of the blocks and of calls and estimates
+const BLOCK_GAS_LIMIT: u64 = 10_000_000;
+
+#[derive(Debug)]
+struct SimulatedLog {
+	address: Address,
+	block_number: u64,
+	log_index: u64,
+	transaction_hash: H256,
+	topics: Vec<H256>,
+	data: Vec<u8>,
+}
+
+/// a transaction mined on one of the `SimulatedChains`
+#[derive(Debug, Clone)]
+pub struct MinedTransaction {
+	pub hash: H256,
+	pub block_number: u64,
+	pub from: Address,
+	pub to: Option<Address>,
+	pub input: Vec<u8>,
+	pub gas_used: u64,
+	/// `false` if the transaction reverted
+	pub success: bool,
+	pub contract_address: Option<Address>,
+}
+
+/// state and blocks of a chain
+#[derive(Debug)]
+struct ChainState {
+	/// mixed into transaction hashes so they differ between the chains
+	id: u64,
+	db: InMemoryDB,
+	block_number: u64,
+	logs: Vec<SimulatedLog>,
+	transactions: Vec<MinedTransaction>,
+}
+
+/// state of both chains
+#[derive(Debug)]
+pub struct World {
+	main: ChainState,
+	side: ChainState,
+	/// secret keys of the accounts that can send transactions and sign
+	/// like the unlocked accounts of a node
+	secrets: HashMap<Address, H256>,
+	/// number of `SimulatedChains::advance` calls
+	step: u64,
+	/// tasks waiting for the next step
+	waiting: Vec<Task>,
+}
+
+fn rpc_error<S: Into<String>>(message: S) -> web3::Error {
+	web3::Error::Rpc(jsonrpc_core::Error {
+		code: jsonrpc_core::ErrorCode::ServerError(-32000),
+		message: message.into(),
+		data: None,
+	})
+}
+
+/// the error a node responds with to calls and estimates that revert
+fn revert_error(output: &[u8]) -> web3::Error {
+	web3::Error::Rpc(jsonrpc_core::Error {
+		code: jsonrpc_core::ErrorCode::ServerError(3),
+		message: "execution reverted".into(),
+		data: Some(hex_bytes(output)),
+	})
+}
+
+fn parse<T: ::serde::de::DeserializeOwned>(value: &Value) -> Result<T, web3::Error> {
+	serde_json::from_value(value.clone())
+		.map_err(|err| rpc_error(format!("invalid param {}: {}", value, err)))
+}
+
+fn parse_bytes(value: &Value) -> Result<Vec<u8>, web3::Error> {
+	value
+		.as_str()
+		.and_then(|hex| hex.trim_start_matches("0x").from_hex().ok())
+		.ok_or_else(|| rpc_error(format!("invalid bytes {}", value)))
+}
+
+fn parse_number(value: &Value) -> Result<u64, web3::Error> {
+	value
+		.as_str()
+		.and_then(|hex| u64::from_str_radix(hex.trim_start_matches("0x"), 16).ok())
+		.ok_or_else(|| rpc_error(format!("invalid number {}", value)))
+}
+
+/// one or more values of a filter field
+fn parse_one_or_more<T: ::serde::de::DeserializeOwned>(
+	value: &Value,
+) -> Result<Option<Vec<T>>, web3::Error> {
+	match *value {
+		Value::Null => Ok(None),
+		Value::Array(ref values) => Ok(Some(values.iter().map(parse).collect::<Result<_, _>>()?)),
+		ref value => Ok(Some(vec![parse(value)?])),
+	}
+}
+
+/// `from`, `to` and `data` of a call or transaction request
+fn parse_request(request: &Value) -> Result<(Address, Option<Address>, Vec<u8>), web3::Error> {
+	let from = match request["from"] {
+		Value::Null => Address::zero(),
+		ref from => parse(from)?,
+	};
+	let to = match request["to"] {
+		Value::Null => None,
+		ref to => Some(parse(to)?),
+	};
+	let data = match request["data"] {
+		Value::Null => vec![],
+		ref data => parse_bytes(data)?,
+	};
+	Ok((from, to, data))
+}
+
+fn hex_bytes(bytes: &[u8]) -> Value {
+	Value::String(format!("0x{}", bytes.to_hex::<String>()))
+}
+
+fn hex_number(number: u64) -> Value {
+	Value::String(format!("0x{:x}", number))
+}
+
+fn block_hash(block_number: u64) -> H256 {
+	keccak256(&ethabi::encode(&[Token::Uint(block_number.into())]))
+}
+
+fn evm_address(address: Address) -> EvmAddress {
+	EvmAddress::from_slice(address.as_bytes())
+}
+
+fn from_evm_address(address: &EvmAddress) -> Address {
+	Address::from_slice(address.as_slice())
+}
+
+/// the address of the contract that `creator` creates with its transaction with `nonce`
+fn created_address(creator: Address, nonce: u64) -> Address {
+	from_evm_address(&evm_address(creator).create(nonce))
+}
+
+/// the output of a successful execution
+fn output(result: ExecutionResult) -> Result<Vec<u8>, web3::Error> {
+	match result {
+		ExecutionResult::Success { output, .. } => Ok(output.into_data().to_vec()),
+		ExecutionResult::Revert { output, .. } => Err(revert_error(&output)),
+		ExecutionResult::Halt { reason, .. } => {
+			Err(rpc_error(format!("execution halted: {:?}", reason)))
+		}
+	}
+}
+
+/// whether `input` calls the function that `encoded` calls
+fn calls(input: &[u8], encoded: Vec<u8>) -> bool {
+	input.len() >= 4 && input[..4] == encoded[..4]
+}
+
+fn token_h256(token: Token) -> H256 {
+	H256::from_slice(&token.to_fixed_bytes().expect("decoded as fixed bytes; qed"))
+}
+
+/// the hash `eth_sign` signs: keccak256 of `data` prefixed with
+/// "\x19Ethereum Signed Message:\n" and the length of `data`
+fn signed_message_hash(data: &[u8]) -> H256 {
+	let prefix = format!("\x19Ethereum Signed Message:\n{}", data.len());
+	keccak256(&[prefix.as_bytes(), data].concat())
+}
+
+/// signs `data` with `secret` like `eth_sign`
+fn sign(secret: &H256, data: &[u8]) -> Vec<u8> {
+	let secret = secp256k1::SecretKey::parse(&secret.0)
+		.expect("secrets of simulated accounts are valid; qed");
+	let message = secp256k1::Message::parse(&signed_message_hash(data).0);
+	let (signature, recovery_id) = secp256k1::sign(&message, &secret);
+	let mut signature = signature.serialize().to_vec();
+	signature.push(27 + recovery_id.serialize());
+	signature
+}
+
+/// the account that signed `data` with `signature` like `eth_sign`
+fn signer(data: &[u8], signature: &[u8]) -> Option<Address> {
+	if signature.len() != 65 || signature[64] < 27 {
+		return None;
+	}
+	let recovery_id = secp256k1::RecoveryId::parse(signature[64] - 27).ok()?;
+	let signature = secp256k1::Signature::parse_slice(&signature[..64]).ok()?;
+	let message = secp256k1::Message::parse(&signed_message_hash(data).0);
+	let public = secp256k1::recover(&message, &signature, &recovery_id).ok()?;
+	Some(public_address(&public))
+}
+
+fn public_address(public: &secp256k1::PublicKey) -> Address {
+	Address::from_slice(&keccak256(&public.serialize()[1..]).as_bytes()[12..])
+}
+
+/// a simulated account whose secret key is derived from `seed`
+fn account(seed: &str) -> (Address, H256) {
+	let secret = keccak256(seed.as_bytes());
+	let secret_key =
+		secp256k1::SecretKey::parse(&secret.0).expect("hashes of seeds are valid secret keys; qed");
+	let address = public_address(&secp256k1::PublicKey::from_secret_key(&secret_key));
+	(address, secret)
+}
+
+/// creates the bridge and recipient contracts in the genesis blocks
+fn deployer() -> Address {
+	account("deployer").0
+}
+
+/// address of the bridge contract on both chains
+pub fn contract_address() -> Address {
+	created_address(deployer(), 0)
+}
+
+/// address of the `RecipientTest` contract that receives the messages on both chains
+pub fn recipient_address() -> Address {
+	created_address(deployer(), 1)
+}
+
+fn log_json(log: &SimulatedLog) -> Value {
+	json!({
+		"address": log.address,
+		"topics": log.topics,
+		"data": hex_bytes(&log.data),
+		"blockHash": block_hash(log.block_number),
+		"blockNumber": hex_number(log.block_number),
+		"transactionHash": log.transaction_hash,
+		"transactionIndex": "0x0",
+		"logIndex": hex_number(log.log_index),
+		"transactionLogIndex": hex_number(log.log_index),
+		"removed": false,
+	})
+}
+
+impl ChainState {
+	/// a chain whose genesis block contains the contracts created
+	/// by `deployer()` with `creation_codes`
+	fn with_contracts(id: u64, creation_codes: Vec<Vec<u8>>) -> Self {
+		let mut state = ChainState {
+			id,
+			db: InMemoryDB::default(),
+			block_number: 0,
+			logs: Vec::new(),
+			transactions: Vec::new(),
+		};
+		for creation_code in creation_codes {
+			let result = state
+				.execute(deployer(), None, &creation_code, BLOCK_GAS_LIMIT, 0, true)
+				.expect("contract creations in genesis are valid transactions; qed");
+			assert!(
+				result.is_success(),
+				"creating contract in genesis failed: {:?}",
+				result
+			);
+			state.record(deployer(), None, creation_code, result);
+		}
+		state
+	}
+
+	/// executes a transaction or call from `from` to `to` (a contract creation if `None`)
+	/// in block `block_number`. changes the state only if `commit`.
+	/// fails if it's not a valid transaction
+	fn execute(
+		&mut self,
+		from: Address,
+		to: Option<Address>,
+		input: &[u8],
+		gas: u64,
+		block_number: u64,
+		commit: bool,
+	) -> Result<ExecutionResult, web3::Error> {
+		let mut evm = Evm::builder()
+			.with_db(&mut self.db)
+			.with_spec_id(SpecId::ISTANBUL)
+			.modify_block_env(|block| {
+				block.number = EvmU256::from(block_number);
+				block.timestamp = EvmU256::from(block_number);
+				block.gas_limit = EvmU256::from(BLOCK_GAS_LIMIT);
+			})
+			.modify_tx_env(|tx| {
+				tx.caller = evm_address(from);
+				tx.transact_to = to.map_or(TxKind::Create, |to| TxKind::Call(evm_address(to)));
+				tx.data = input.to_vec().into();
+				tx.gas_limit = gas;
+				tx.gas_price = EvmU256::ZERO;
+			})
+			.build();
+		let result = if commit {
+			evm.transact_commit()
+		} else {
+			evm.transact()
+				.map(|result_and_state| result_and_state.result)
+		};
+		result.map_err(|err| rpc_error(format!("invalid transaction: {:?}", err)))
+	}
+
+	/// calls `to` in the latest block
+	fn call(
+		&mut self,
+		from: Address,
+		to: Option<Address>,
+		input: &[u8],
+	) -> Result<Vec<u8>, web3::Error> {
+		let block_number = self.block_number;
+		output(self.execute(from, to, input, BLOCK_GAS_LIMIT, block_number, false)?)
+	}
+
+	/// the lowest gas limit with which the transaction doesn't fail, like nodes estimate gas
+	fn estimate_gas(
+		&mut self,
+		from: Address,
+		to: Option<Address>,
+		input: &[u8],
+	) -> Result<u64, web3::Error> {
+		let block_number = self.block_number;
+		let result = self.execute(from, to, input, BLOCK_GAS_LIMIT, block_number, false)?;
+		let gas_used = result.gas_used();
+		output(result)?;
+		// the required gas limit can be higher than the gas used because of refunds
+		// and the gas a contract has to keep when it calls another
+		let mut failing = gas_used - 1;
+		let mut succeeding = BLOCK_GAS_LIMIT;
+		while succeeding - failing > 1 {
+			let gas = failing + (succeeding - failing) / 2;
+			match self.execute(from, to, input, gas, block_number, false) {
+				Ok(ref result) if result.is_success() => succeeding = gas,
+				_ => failing = gas,
+			}
+		}
+		Ok(succeeding)
+	}
+
+	/// executes the transaction and mines it into a new block.
+	/// it's mined even if it reverts
+	fn transact(
+		&mut self,
+		from: Address,
+		to: Option<Address>,
+		input: Vec<u8>,
+		gas: u64,
+	) -> Result<H256, web3::Error> {
+		let block_number = self.block_number + 1;
+		let result = self.execute(from, to, &input, gas, block_number, true)?;
+		self.block_number = block_number;
+		Ok(self.record(from, to, input, result))
+	}
+
+	/// records the executed transaction and its logs in the latest block
+	fn record(
+		&mut self,
+		from: Address,
+		to: Option<Address>,
+		input: Vec<u8>,
+		result: ExecutionResult,
+	) -> H256 {
+		let hash = keccak256(&ethabi::encode(&[
+			Token::Uint(self.id.into()),
+			Token::Uint(self.transactions.len().into()),
+			Token::Address(from),
+			Token::Bytes(input.clone()),
+		]));
+		let gas_used = result.gas_used();
+		let (success, logs, contract_address) = match result {
+			ExecutionResult::Success { logs, output, .. } => {
+				let contract_address = match output {
+					Output::Create(_, address) => address.as_ref().map(from_evm_address),
+					Output::Call(_) => None,
+				};
+				(true, logs, contract_address)
+			}
+			ExecutionResult::Revert { .. } | ExecutionResult::Halt { .. } => (false, vec![], None),
+		};
+		for (log_index, log) in logs.into_iter().enumerate() {
+			self.logs.push(SimulatedLog {
+				address: from_evm_address(&log.address),
+				block_number: self.block_number,
+				log_index: log_index as u64,
+				transaction_hash: hash,
+				topics: log
+					.data
+					.topics()
+					.iter()
+					.map(|topic| H256::from_slice(topic.as_slice()))
+					.collect(),
+				data: log.data.data.to_vec(),
+			});
+		}
+		self.transactions.push(MinedTransaction {
+			hash,
+			block_number: self.block_number,
+			from,
+			to,
+			input,
+			gas_used,
+			success,
+			contract_address,
+		});
+		hash
+	}
+
+	fn transaction(&self, hash: H256) -> Option<&MinedTransaction> {
+		self.transactions
+			.iter()
+			.find(|transaction| transaction.hash == hash)
+	}
+
+	fn receipt(&self, hash: H256) -> Value {
+		let transaction = match self.transaction(hash) {
+			Some(transaction) => transaction,
+			None => return Value::Null,
+		};
+		let logs: Vec<Value> = self
+			.logs
+			.iter()
+			.filter(|log| log.transaction_hash == hash)
+			.map(log_json)
+			.collect();
+		json!({
+			"blockHash": block_hash(transaction.block_number),
+			"blockNumber": hex_number(transaction.block_number),
+			"contractAddress": transaction.contract_address,
+			"cumulativeGasUsed": hex_number(transaction.gas_used),
+			"from": transaction.from,
+			"gasUsed": hex_number(transaction.gas_used),
+			"logs": logs,
+			"logsBloom": hex_bytes(&[0u8; 256]),
+			"root": null,
+			"status": if transaction.success { "0x1" } else { "0x0" },
+			"to": transaction.to,
+			"transactionHash": hash,
+			"transactionIndex": "0x0",
+		})
+	}
+
+	fn logs(&self, filter: &Value) -> Result<Value, web3::Error> {
+		let from = parse_number(&filter["fromBlock"])?;
+		let to = match filter["toBlock"].as_str() {
+			Some("latest") => self.block_number,
+			_ => parse_number(&filter["toBlock"])?,
+		};
+		let addresses: Option<Vec<Address>> = parse_one_or_more(&filter["address"])?;
+		let topics: Vec<Option<Vec<H256>>> = match filter["topics"] {
+			Value::Array(ref topics) => topics
+				.iter()
+				.map(parse_one_or_more)
+				.collect::<Result<_, _>>()?,
+			_ => vec![],
+		};
+
+		let logs = self
+			.logs
+			.iter()
+			.filter(|log| from <= log.block_number && log.block_number <= to)
+			.filter(|log| {
+				addresses
+					.as_ref()
+					.map_or(true, |addresses| addresses.contains(&log.address))
+			})
+			.filter(|log| {
+				topics
+					.iter()
+					.enumerate()
+					.all(|(index, topic)| match *topic {
+						None => true,
+						Some(ref options) => log
+							.topics
+							.get(index)
+							.map_or(false, |topic| options.contains(topic)),
+					})
+			})
+			.map(log_json)
+			.collect();
+		Ok(Value::Array(logs))
+	}
+}
+
+impl World {
+	fn chain(&self, chain: Chain) -> &ChainState {
+		match chain {
+			Chain::Main => &self.main,
+			Chain::Side => &self.side,
+		}
+	}
+
+	fn chain_mut(&mut self, chain: Chain) -> &mut ChainState {
+		match chain {
+			Chain::Main => &mut self.main,
+			Chain::Side => &mut self.side,
+		}
+	}
+
+	fn secret(&self, account: Address) -> Result<&H256, web3::Error> {
+		self.secrets
+			.get(&account)
+			.ok_or_else(|| rpc_error(format!("unknown account {:?}", account)))
+	}
+
+	fn handle(
+		&mut self,
+		chain: Chain,
+		method: &str,
+		params: &[Value],
+	) -> Result<Value, web3::Error> {
+		let param = |index: usize| params.get(index).cloned().unwrap_or(Value::Null);
+		match method {
+			"eth_blockNumber" => Ok(hex_number(self.chain(chain).block_number)),
+			"eth_getLogs" => self.chain(chain).logs(&param(0)),
+			"eth_call" => {
+				let (from, to, input) = parse_request(&param(0))?;
+				let output = self.chain_mut(chain).call(from, to, &input)?;
+				Ok(hex_bytes(&output))
+			}
+			"eth_estimateGas" => {
+				let (from, to, input) = parse_request(&param(0))?;
+				let gas = self.chain_mut(chain).estimate_gas(from, to, &input)?;
+				Ok(hex_number(gas))
+			}
+			"eth_sign" => {
+				let account: Address = parse(&param(0))?;
+				let data = parse_bytes(&param(1))?;
+				Ok(hex_bytes(&sign(self.secret(account)?, &data)))
+			}
+			"eth_sendTransaction" => {
+				let request = param(0);
+				let (from, to, input) = parse_request(&request)?;
+				self.secret(from)?;
+				let gas = match request["gas"] {
+					Value::Null => BLOCK_GAS_LIMIT,
+					ref gas => parse_number(gas)?,
+				};
+				let hash = self.chain_mut(chain).transact(from, to, input, gas)?;
+				Ok(serde_json::to_value(hash).expect("H256 always serializes; qed"))
+			}
+			"eth_getTransactionReceipt" => {
+				let hash: H256 = parse(&param(0))?;
+				Ok(self.chain(chain).receipt(hash))
+			}
+			_ => Err(rpc_error(format!("method {} is not supported", method))),
+		}
+	}
+}
+
+/// a `Transport` to one of the `SimulatedChains`
+#[derive(Debug, Clone)]
+pub struct SimulatedChain {
+	chain: Chain,
+	world: Rc<RefCell<World>>,
+}
+
+/// response of a `SimulatedChain`
+pub enum SimulatedResponse {
+	Done(Option<Result<Value, web3::Error>>),
+	/// `eth_blockNumber` request sent during `step`
+	BlockNumber {
+		chain: Chain,
+		world: Rc<RefCell<World>>,
+		step: u64,
+	},
+}
+
+impl Future for SimulatedResponse {
+	type Item = Value;
+	type Error = web3::Error;
+
+	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+		match *self {
+			SimulatedResponse::Done(ref mut result) => result
+				.take()
+				.expect("SimulatedResponse polled after completion")
+				.map(Async::Ready),
+			SimulatedResponse::BlockNumber {
+				chain,
+				ref world,
+				step,
+			} => {
+				let mut world = world.borrow_mut();
+				if world.step > step {
+					Ok(Async::Ready(hex_number(world.chain(chain).block_number)))
+				} else {
+					world.waiting.push(task::current());
+					Ok(Async::NotReady)
+				}
+			}
+		}
+	}
+}
+
+impl Transport for SimulatedChain {
+	type Out = SimulatedResponse;
+
+	fn prepare(&self, method: &str, params: Vec<Value>) -> (usize, Call) {
+		(0, web3::helpers::build_request(0, method, params))
+	}
+
+	fn send(&self, _id: usize, request: Call) -> Self::Out {
+		let result = match request {
+			Call::MethodCall(ref call) if call.method == "eth_blockNumber" => {
+				return SimulatedResponse::BlockNumber {
+					chain: self.chain,
+					world: self.world.clone(),
+					step: self.world.borrow().step,
+				};
+			}
+			Call::MethodCall(call) => {
+				let params = match call.params {
+					Params::Array(params) => params,
+					_ => vec![],
+				};
+				self.world
+					.borrow_mut()
+					.handle(self.chain, &call.method, &params)
+			}
+			_ => Err(rpc_error("only method calls are supported")),
+		};
+		SimulatedResponse::Done(Some(result))
+	}
+}
+
+/// a main and a side chain with the bridge contracts deployed
+#[derive(Debug, Clone)]
+pub struct SimulatedChains {
+	world: Rc<RefCell<World>>,
+	authorities: Vec<Address>,
+	user: Address,
+}
+
+impl SimulatedChains {
+	/// chains whose bridge contracts have `authorities` authorities
+	/// of which `required_signatures` must sign a message
+	pub fn new(authorities: usize, required_signatures: u32) -> Self {
+		let mut secrets = HashMap::new();
+		let authorities: Vec<Address> = (0..authorities)
+			.map(|index| {
+				let (address, secret) = account(&format!("authority {}", index));
+				secrets.insert(address, secret);
+				address
+			})
+			.collect();
+		let (user, secret) = account("user");
+		secrets.insert(user, secret);
+
+		let recipient_code: Vec<u8> = contracts::RECIPIENT_TEST_BIN.trim().from_hex().unwrap();
+		let main_code = contracts::main::constructor(
+			contracts::MAIN_BIN.trim().from_hex::<Vec<u8>>().unwrap(),
+			required_signatures,
+			authorities.clone(),
+		);
+		let side_code = contracts::side::constructor(
+			contracts::SIDE_BIN.trim().from_hex::<Vec<u8>>().unwrap(),
+			required_signatures,
+			authorities.clone(),
+		);
+
+		let world = World {
+			main: ChainState::with_contracts(1, vec![main_code, recipient_code.clone()]),
+			side: ChainState::with_contracts(2, vec![side_code, recipient_code]),
+			secrets,
+			step: 0,
+			waiting: Vec::new(),
+		};
+		for state in &[&world.main, &world.side] {
+			let created: Vec<_> = state
+				.transactions
+				.iter()
+				.map(|transaction| transaction.contract_address)
+				.collect();
+			assert_eq!(
+				created,
+				vec![Some(contract_address()), Some(recipient_address())]
+			);
+		}
+
+		Self {
+			world: Rc::new(RefCell::new(world)),
+			authorities,
+			user,
+		}
+	}
+
+	pub fn authority(&self, index: usize) -> Address {
+		self.authorities[index]
+	}
+
+	pub fn authorities(&self) -> &[Address] {
+		&self.authorities
+	}
+
+	/// the account that sends the messages
+	pub fn user(&self) -> Address {
+		self.user
+	}
+
+	pub fn main(&self) -> SimulatedChain {
+		SimulatedChain {
+			chain: Chain::Main,
+			world: self.world.clone(),
+		}
+	}
+
+	pub fn side(&self) -> SimulatedChain {
+		SimulatedChain {
+			chain: Chain::Side,
+			world: self.world.clone(),
+		}
+	}
+
+	/// mines an empty block on both chains and answers the `eth_blockNumber`
+	/// requests sent before
+	pub fn advance(&self) {
+		let waiting = {
+			let mut world = self.world.borrow_mut();
+			world.step += 1;
+			world.main.block_number += 1;
+			world.side.block_number += 1;
+			::std::mem::replace(&mut world.waiting, vec![])
+		};
+		for task in waiting {
+			task.notify();
+		}
+	}
+
+	/// sends `input` from `from` to the bridge contract on `chain`.
+	/// returns the hash of the transaction which may have reverted
+	pub fn send_transaction(&self, chain: Chain, from: Address, input: Vec<u8>) -> H256 {
+		self.world
+			.borrow_mut()
+			.chain_mut(chain)
+			.transact(from, Some(contract_address()), input, BLOCK_GAS_LIMIT)
+			.expect("transactions of simulated accounts are valid; qed")
+	}
+
+	pub fn transaction(&self, chain: Chain, hash: H256) -> Option<MinedTransaction> {
+		self.world.borrow().chain(chain).transaction(hash).cloned()
+	}
+
+	/// the transactions mined on `chain` that reverted
+	pub fn reverted_transactions(&self, chain: Chain) -> Vec<MinedTransaction> {
+		self.world
+			.borrow()
+			.chain(chain)
+			.transactions
+			.iter()
+			.filter(|transaction| !transaction.success)
+			.cloned()
+			.collect()
+	}
+
+	/// calls the bridge contract on `chain` in the latest block
+	pub fn call<D: FunctionOutputDecoder>(
+		&self,
+		chain: Chain,
+		(payload, decoder): (Vec<u8>, D),
+	) -> Result<D::Output, web3::Error> {
+		self.call_contract(chain, contract_address(), payload, decoder)
+	}
+
+	fn call_contract<D: FunctionOutputDecoder>(
+		&self,
+		chain: Chain,
+		contract: Address,
+		payload: Vec<u8>,
+		decoder: D,
+	) -> Result<D::Output, web3::Error> {
+		let output = self.world.borrow_mut().chain_mut(chain).call(
+			Address::zero(),
+			Some(contract),
+			&payload,
+		)?;
+		decoder
+			.decode(&output)
+			.map_err(|err| rpc_error(format!("can't decode output: {:?}", err)))
+	}
+
+	/// sends a message with `data` from the user to the `RecipientTest` contract on side.
+	/// returns the hash of the transaction on main
+	pub fn send_message_to_side(&self, data: Vec<u8>) -> H256 {
+		let hash = self.send_transaction(
+			Chain::Main,
+			self.user,
+			contracts::main::functions::relay_message::encode_input(data, recipient_address()),
+		);
+		assert!(
+			self.transaction(Chain::Main, hash).unwrap().success,
+			"sending message to side reverted"
+		);
+		hash
+	}
+
+	/// sends a message with `data` from the user to the `RecipientTest` contract on main
+	pub fn send_message_to_main(&self, data: Vec<u8>) -> MessageToMain {
+		let hash = self.send_transaction(
+			Chain::Side,
+			self.user,
+			contracts::side::functions::relay_message::encode_input(data, recipient_address()),
+		);
+		let topic = contracts::side::events::relay_message::filter().topic0[0];
+		let world = self.world.borrow();
+		let log = world
+			.side
+			.logs
+			.iter()
+			.find(|log| log.transaction_hash == hash && log.topics.first() == Some(&topic))
+			.expect("sending message to main logs RelayMessage");
+		let log: Log = serde_json::from_value(log_json(log)).expect("log_json returns logs; qed");
+		MessageToMain::from_log(&log).expect("RelayMessage logs are valid messages; qed")
+	}
+
+	/// the data of the last message the `RecipientTest` contract on `chain` received
+	pub fn last_message(&self, chain: Chain) -> Vec<u8> {
+		let (payload, decoder) = contracts::test::functions::last_data::call();
+		self.call_contract(chain, recipient_address(), payload, decoder)
+			.expect("RecipientTest.lastData can't fail; qed")
+	}
+
+	/// the senders of the transactions on `chain` that called the function
+	/// of `encoded` and succeeded, if `matches` the decoded arguments
+	fn successful_calls<F: Fn(&[Token]) -> bool>(
+		&self,
+		chain: Chain,
+		encoded: Vec<u8>,
+		types: &[ParamType],
+		matches: F,
+	) -> Vec<Address> {
+		self.world
+			.borrow()
+			.chain(chain)
+			.transactions
+			.iter()
+			.filter(|transaction| {
+				transaction.success
+					&& transaction.to == Some(contract_address())
+					&& calls(&transaction.input, encoded.clone())
+			})
+			.filter(|transaction| {
+				ethabi::decode(types, &transaction.input[4..])
+					.map_or(false, |tokens| matches(&tokens))
+			})
+			.map(|transaction| transaction.from)
+			.collect()
+	}
+
+	/// authorities whose acceptance of the message sent in `main_tx_hash`
+	/// was mined on side without reverting, in the order they were mined
+	pub fn side_acceptances(&self, main_tx_hash: H256) -> Vec<Address> {
+		self.successful_calls(
+			Chain::Side,
+			contracts::side::functions::accept_message::encode_input(
+				H256::zero(),
+				Vec::<u8>::new(),
+				Address::zero(),
+				Address::zero(),
+			),
+			&[
+				ParamType::FixedBytes(32),
+				ParamType::Bytes,
+				ParamType::Address,
+				ParamType::Address,
+			],
+			|tokens| token_h256(tokens[0].clone()) == main_tx_hash,
+		)
+	}
+
+	/// whether the side contract has the acceptance by `authority` of the message
+	/// with `data` that the user sent to side in `main_tx_hash`
+	pub fn has_authority_accepted_on_side(
+		&self,
+		main_tx_hash: H256,
+		data: Vec<u8>,
+		authority: Address,
+	) -> bool {
+		self.call(
+			Chain::Side,
+			contracts::side::functions::has_authority_accepted_message_from_main::call(
+				main_tx_hash,
+				data,
+				self.user,
+				recipient_address(),
+				authority,
+			),
+		)
+		.expect("Side.hasAuthorityAcceptedMessageFromMain can't fail; qed")
+	}
+
+	/// signers of the signatures of `message` that the side contract stores,
+	/// in the order they were submitted.
+	/// `Address::zero()` for signatures from which no signer can be recovered
+	pub fn side_signatures(&self, message: &MessageToMain) -> Vec<Address> {
+		let data = message.to_bytes();
+		let mut signers = Vec::new();
+		for index in 0u32.. {
+			let signature = match self.call(
+				Chain::Side,
+				contracts::side::functions::signature::call(message.keccak256(), index),
+			) {
+				Ok(ref signature) if !signature.is_empty() => signature.clone(),
+				// there are no more signatures
+				_ => break,
+			};
+			signers.push(signer(&data, &signature).unwrap_or_else(Address::zero));
+		}
+		signers
+	}
+
+	/// authorities whose relay of `message` was mined on main without reverting
+	pub fn main_acceptances(&self, message: &MessageToMain) -> Vec<Address> {
+		self.successful_calls(
+			Chain::Main,
+			contracts::main::functions::accept_message::encode_input(
+				Vec::<u8>::new(),
+				Vec::<H256>::new(),
+				Vec::<H256>::new(),
+				H256::zero(),
+				Vec::<u8>::new(),
+				Address::zero(),
+				Address::zero(),
+			),
+			&[
+				ParamType::Array(Box::new(ParamType::Uint(8))),
+				ParamType::Array(Box::new(ParamType::FixedBytes(32))),
+				ParamType::Array(Box::new(ParamType::FixedBytes(32))),
+				ParamType::FixedBytes(32),
+				ParamType::Bytes,
+				ParamType::Address,
+				ParamType::Address,
+			],
+			|tokens| {
+				token_h256(tokens[3].clone()) == message.side_tx_hash
+					&& tokens[5].clone().to_address() == Some(message.sender)
+					&& tokens[6].clone().to_address() == Some(message.recipient)
+			},
+		)
+	}
+
+	/// whether the main contract accepted `message`
+	pub fn is_accepted_on_main(&self, message: &MessageToMain) -> bool {
+		self.call(
+			Chain::Main,
+			contracts::main::functions::accepted_messages::call(message.keccak256()),
+		)
+		.expect("Main.acceptedMessages can't fail; qed")
+	}
+}
+
+/// requests are answered within a step or not at all.
+/// long enough to never time out during a test but below the
+/// maximum timeout of the default `Timer`
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// `MainContract` and `SideContract` of `authority` with the options used with simulated chains.
+/// the logs are polled whenever a new block is mined
+pub fn bridge_contracts<T: Transport>(
+	authority: Address,
+	required_signatures: u32,
+	confirmations: u32,
+	main: T,
+	side: T,
+) -> (MainContract<T>, SideContract<T>) {
+	let transaction_config = TransactionConfig {
+		gas: U256::from(1_000_000),
+		..TransactionConfig::default()
+	};
+	let main_contract = MainContract {
+		transport: main,
+		contract_address: contract_address(),
+		authority_address: authority,
+		submit_collected_signatures_tx: transaction_config.clone(),
+		gas_estimate_multiplier: 1.2,
+		max_relay_cost: None,
+		request_timeout: REQUEST_TIMEOUT,
+		logs_poll_interval: Duration::from_secs(0),
+		required_log_confirmations: confirmations,
+		eip1559_support: Default::default(),
+	};
+	let side_contract = SideContract {
+		transport: side,
+		contract_address: contract_address(),
+		authority_address: authority,
+		required_signatures,
+		request_timeout: REQUEST_TIMEOUT,
+		logs_poll_interval: Duration::from_secs(0),
+		required_log_confirmations: confirmations,
+		gas_estimate_multiplier: 1.2,
+		sign_main_to_side_tx: transaction_config.clone(),
+		sign_side_to_main_tx: transaction_config,
+		eip1559_support: Default::default(),
+	};
+	(main_contract, side_contract)
+}
+
+/// a `Bridge` that is polled step by step like a `parity-bridge` process
+pub struct SteppedBridge<T: Transport> {
+	bridge: Spawn<Bridge<T>>,
+	/// state after the last relay like it would be in the database
+	pub state: State,
+	/// the error the bridge failed with. it isn't polled anymore afterwards
+	pub error: Option<error::Error>,
+}
+
+impl<T: Transport> SteppedBridge<T> {
+	pub fn new(
+		state: State,
+		main_contract: MainContract<T>,
+		side_contract: SideContract<T>,
+	) -> Self {
+		Self {
+			bridge: executor::spawn(Bridge::new(state.clone(), main_contract, side_contract, 10)),
+			state,
+			error: None,
+		}
+	}
+
+	/// polls the bridge until it can't make progress before the next step
+	pub fn poll_until_stalled(&mut self) {
+		if self.error.is_some() {
+			return;
+		}
+		let notify = Arc::new(NoNotify);
+		loop {
+			match self.bridge.poll_stream_notify(&notify, 0) {
+				Ok(Async::Ready(Some(state))) => self.state = state,
+				Ok(Async::Ready(None)) | Ok(Async::NotReady) => return,
+				Err(err) => {
+					self.error = Some(err);
+					return;
+				}
+			}
+		}
+	}
+}
+
+/// the bridges are polled again every step anyway
+struct NoNotify;
+
+impl Notify for NoNotify {
+	fn notify(&self, _id: usize) {}
+}
+
+/// runs a `Bridge` from `state` for `steps` steps like a `parity-bridge` process
+/// that gets stopped or fails. `advance` is called at the start of every step.
+/// `state` is updated like the database would be.
+/// returns the error the bridge failed with
+pub fn run_bridge<T: Transport, F: FnMut()>(
+	state: &mut State,
+	main_contract: MainContract<T>,
+	side_contract: SideContract<T>,
+	steps: usize,
+	mut advance: F,
+) -> Option<error::Error> {
+	let mut bridge = SteppedBridge::new(state.clone(), main_contract, side_contract);
+	bridge.poll_until_stalled();
+	for _ in 0..steps {
+		if bridge.error.is_some() {
+			break;
+		}
+		advance();
+		bridge.poll_until_stalled();
+	}
+	*state = bridge.state;
+	bridge.error
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use signature::Signature;
+
+	fn relay_between_chains(chains: &SimulatedChains) -> (H256, MessageToMain) {
+		let to_side = chains.send_message_to_side(vec![1, 2]);
+		let to_main = chains.send_message_to_main(vec![3, 4, 5]);
+
+		let mut state = State::default();
+		let (main_contract, side_contract) =
+			bridge_contracts(chains.authority(0), 1, 0, chains.main(), chains.side());
+		let error = run_bridge(&mut state, main_contract, side_contract, 10, || {
+			chains.advance()
+		});
+		assert!(error.is_none(), "bridge failed: {:?}", error);
+		(to_side, to_main)
+	}
+
+	/// estimates the gas of `input` sent from `from` to the bridge contract on `chain`
+	fn estimate(
+		transport: &SimulatedChain,
+		from: Address,
+		input: Vec<u8>,
+	) -> Result<Value, web3::Error> {
+		transport
+			.execute(
+				"eth_estimateGas",
+				vec![json!({"from": from, "to": contract_address(), "data": hex_bytes(&input)})],
+			)
+			.wait()
+	}
+
+	fn eth_sign(transport: &SimulatedChain, account: Address, data: &[u8]) -> Signature {
+		let signature = transport
+			.execute("eth_sign", vec![json!(account), hex_bytes(data)])
+			.wait()
+			.unwrap();
+		Signature::from_bytes(&parse_bytes(&signature).unwrap()).unwrap()
+	}
+
+	fn accept_on_main_input(message: &MessageToMain, signature: &Signature) -> Vec<u8> {
+		contracts::main::functions::accept_message::encode_input(
+			vec![signature.v],
+			vec![signature.r],
+			vec![signature.s],
+			message.side_tx_hash,
+			vec![3u8, 4, 5],
+			message.sender,
+			message.recipient,
+		)
+	}
+
+	#[test]
+	fn test_bridge_relays_messages_between_simulated_chains() {
+		let chains = SimulatedChains::new(1, 1);
+		let (to_side, to_main) = relay_between_chains(&chains);
+		let authority = chains.authority(0);
+
+		assert_eq!(chains.side_acceptances(to_side), vec![authority]);
+		assert!(chains.has_authority_accepted_on_side(to_side, vec![1, 2], authority));
+		assert_eq!(chains.last_message(Chain::Side), vec![1, 2]);
+		assert_eq!(chains.side_signatures(&to_main), vec![authority]);
+		assert_eq!(chains.main_acceptances(&to_main), vec![authority]);
+		assert!(chains.is_accepted_on_main(&to_main));
+		assert_eq!(chains.last_message(Chain::Main), vec![3, 4, 5]);
+		assert!(chains.reverted_transactions(Chain::Main).is_empty());
+		assert!(chains.reverted_transactions(Chain::Side).is_empty());
+	}
+
+	#[test]
+	fn test_eth_sign_signs_with_key_of_account() {
+		let chains = SimulatedChains::new(2, 1);
+		let data = b"message to sign".to_vec();
+		for &authority in chains.authorities() {
+			let signature = eth_sign(&chains.side(), authority, &data);
+			assert_eq!(signer(&data, &signature.to_bytes()), Some(authority));
+		}
+		assert!(chains
+			.side()
+			.execute("eth_sign", vec![json!(Address::zero()), hex_bytes(&data)])
+			.wait()
+			.is_err());
+	}
+
+	#[test]
+	fn test_main_rejects_signature_of_non_authority() {
+		let chains = SimulatedChains::new(1, 1);
+		let message = chains.send_message_to_main(vec![3, 4, 5]);
+		let authority = chains.authority(0);
+
+		let forged = eth_sign(&chains.main(), chains.user(), &message.to_bytes());
+		match estimate(
+			&chains.main(),
+			authority,
+			accept_on_main_input(&message, &forged),
+		) {
+			Err(web3::Error::Rpc(ref err)) => assert_eq!(err.code.code(), 3),
+			other => panic!("unexpected {:?}", other),
+		}
+		let hash = chains.send_transaction(
+			Chain::Main,
+			authority,
+			accept_on_main_input(&message, &forged),
+		);
+		assert!(!chains.transaction(Chain::Main, hash).unwrap().success);
+		assert_eq!(chains.reverted_transactions(Chain::Main).len(), 1);
+		assert!(!chains.is_accepted_on_main(&message));
+
+		let signature = eth_sign(&chains.main(), authority, &message.to_bytes());
+		let input = accept_on_main_input(&message, &signature);
+		let gas =
+			parse_number(&estimate(&chains.main(), authority, input.clone()).unwrap()).unwrap();
+		assert!(gas > 21000);
+		let hash = chains.send_transaction(Chain::Main, authority, input);
+		let transaction = chains.transaction(Chain::Main, hash).unwrap();
+		assert!(transaction.success);
+		assert!(transaction.gas_used <= gas);
+		assert!(chains.is_accepted_on_main(&message));
+		assert_eq!(chains.last_message(Chain::Main), vec![3, 4, 5]);
+	}
+
+	#[test]
+	fn test_contracts_reject_relaying_twice() {
+		let chains = SimulatedChains::new(1, 1);
+		let (to_side, to_main) = relay_between_chains(&chains);
+		let authority = chains.authority(0);
+
+		let accept_again = contracts::side::functions::accept_message::encode_input(
+			to_side,
+			vec![1u8, 2],
+			chains.user(),
+			recipient_address(),
+		);
+		assert!(estimate(&chains.side(), authority, accept_again).is_err());
+
+		let signature = eth_sign(&chains.main(), authority, &to_main.to_bytes());
+		let relay_again = accept_on_main_input(&to_main, &signature);
+		assert!(estimate(&chains.main(), authority, relay_again).is_err());
+	}
+
+	#[test]
+	fn test_receipts_and_logs_are_results_of_execution() {
+		let chains = SimulatedChains::new(1, 1);
+		let hash = chains.send_message_to_side(vec![7]);
+
+		let receipt = chains
+			.main()
+			.execute("eth_getTransactionReceipt", vec![json!(hash)])
+			.wait()
+			.unwrap();
+		assert_eq!(receipt["status"], json!("0x1"));
+		assert_eq!(receipt["blockNumber"], json!("0x1"));
+		let logs = receipt["logs"].as_array().unwrap();
+		assert_eq!(logs.len(), 1);
+		assert_eq!(logs[0]["address"], json!(contract_address()));
+
+		let fetched = chains
+			.main()
+			.execute(
+				"eth_getLogs",
+				vec![json!({
+					"fromBlock": "0x1",
+					"toBlock": "0x1",
+					"address": contract_address(),
+					"topics": [contracts::main::events::relay_message::filter().topic0[0]],
+				})],
+			)
+			.wait()
+			.unwrap();
+		assert_eq!(fetched, json!([logs[0].clone()]));
+	}
+}
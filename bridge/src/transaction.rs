@@ -0,0 +1,521 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity-Bridge.
+
+// Parity-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! concerning building transactions and choosing their fees.
+//!
+//! `web3::types::TransactionRequest` only supports legacy transactions
+//! with a `gas_price`. `Transaction` also supports EIP-1559 type-2 transactions.
+
+use config::TransactionConfig;
+use error::{self, ErrorKind, ResultExt};
+use futures::future::FromErr;
+use futures::{Async, Future, Poll};
+use jsonrpc_core;
+use serde_json;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+use tokio_timer::{Timeout, Timer};
+use web3::helpers::{self, CallFuture};
+use web3::types::{Address, Bytes, H256, U256, U64};
+use web3::{self, Transport};
+
+/// number of blocks `eth_feeHistory` is asked for
+const FEE_HISTORY_BLOCKS: u64 = 5;
+
+/// the priority fee is the median of the rewards paid at this percentile
+/// in the last `FEE_HISTORY_BLOCKS` blocks
+const FEE_HISTORY_REWARD_PERCENTILE: f64 = 50.0;
+
+/// how a transaction pays for its gas
+#[derive(Debug, PartialEq, Clone)]
+pub enum Fees {
+	/// legacy transaction for chains without EIP-1559
+	Legacy { gas_price: U256 },
+	/// EIP-1559 type-2 transaction
+	Eip1559 {
+		/// base fee of the pending block at the time the fees were chosen
+		base_fee_per_gas: U256,
+		max_fee_per_gas: U256,
+		max_priority_fee_per_gas: U256,
+	},
+}
+
+impl Fees {
+	/// highest price per gas the transaction can end up paying
+	pub fn max_gas_price(&self) -> U256 {
+		match *self {
+			Fees::Legacy { gas_price } => gas_price,
+			Fees::Eip1559 {
+				max_fee_per_gas, ..
+			} => max_fee_per_gas,
+		}
+	}
+
+	/// highest cost in wei of a transaction with `gas`
+	pub fn max_cost(&self, gas: U256) -> U256 {
+		gas.saturating_mul(self.max_gas_price())
+	}
+
+	/// price per gas the transaction is expected to pay.
+	/// for EIP-1559 transactions this is based on the base fee at the time the fees were chosen
+	/// since receipts don't contain the effective gas price.
+	pub fn expected_gas_price(&self) -> U256 {
+		match *self {
+			Fees::Legacy { gas_price } => gas_price,
+			Fees::Eip1559 {
+				base_fee_per_gas,
+				max_fee_per_gas,
+				max_priority_fee_per_gas,
+			} => base_fee_per_gas
+				.saturating_add(max_priority_fee_per_gas)
+				.min(max_fee_per_gas),
+		}
+	}
+}
+
+/// a transaction sent through `eth_sendTransaction` by an unlocked account
+#[derive(Debug, PartialEq, Clone)]
+pub struct Transaction {
+	pub from: Address,
+	/// `None` for contract creation
+	pub to: Option<Address>,
+	pub gas: U256,
+	pub fees: Fees,
+	pub data: Bytes,
+}
+
+/// the json-rpc representation of `Transaction`
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TransactionParams {
+	from: Address,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	to: Option<Address>,
+	gas: U256,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	gas_price: Option<U256>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	max_fee_per_gas: Option<U256>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	max_priority_fee_per_gas: Option<U256>,
+	#[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+	transaction_type: Option<U64>,
+	data: Bytes,
+}
+
+impl Transaction {
+	/// the params of an `eth_sendTransaction` request for this transaction
+	pub fn to_json(&self) -> serde_json::Value {
+		let mut params = TransactionParams {
+			from: self.from,
+			to: self.to,
+			gas: self.gas,
+			gas_price: None,
+			max_fee_per_gas: None,
+			max_priority_fee_per_gas: None,
+			transaction_type: None,
+			data: self.data.clone(),
+		};
+		match self.fees {
+			Fees::Legacy { gas_price } => params.gas_price = Some(gas_price),
+			Fees::Eip1559 {
+				max_fee_per_gas,
+				max_priority_fee_per_gas,
+				..
+			} => {
+				params.max_fee_per_gas = Some(max_fee_per_gas);
+				params.max_priority_fee_per_gas = Some(max_priority_fee_per_gas);
+				params.transaction_type = Some(2.into());
+			}
+		}
+		helpers::serialize(&params)
+	}
+
+	/// `eth_sendTransaction` future that resolves with the transaction hash
+	pub fn send<T: Transport>(&self, transport: &T) -> CallFuture<H256, T::Out> {
+		CallFuture::new(transport.execute("eth_sendTransaction", vec![self.to_json()]))
+	}
+
+	/// `eth_estimateGas` future that resolves with the gas the transaction
+	/// would use (at most `gas`)
+	pub fn estimate_gas<T: Transport>(&self, transport: &T) -> CallFuture<U256, T::Out> {
+		CallFuture::new(transport.execute("eth_estimateGas", vec![self.to_json()]))
+	}
+}
+
+/// response of `eth_feeHistory`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FeeHistory {
+	/// contains one more entry than blocks requested: the base fee of the pending block
+	base_fee_per_gas: Vec<U256>,
+	reward: Option<Vec<Vec<U256>>>,
+}
+
+/// whether a chain supports EIP-1559 type-2 transactions.
+/// unknown until the first `AsyncFees` on the chain resolves.
+/// `clone`d versions share the knowledge
+#[derive(Debug, Clone, Default)]
+pub struct Eip1559Support(Rc<Cell<Option<bool>>>);
+
+impl Eip1559Support {
+	pub fn get(&self) -> Option<bool> {
+		self.0.get()
+	}
+
+	fn set(&self, supported: bool) {
+		self.0.set(Some(supported));
+	}
+}
+
+/// whether `err` says that the node doesn't know `eth_feeHistory`
+fn is_fee_history_unsupported(err: &web3::Error) -> bool {
+	match *err {
+		web3::Error::Rpc(ref rpc_error) => {
+			rpc_error.code == jsonrpc_core::ErrorCode::MethodNotFound
+				|| rpc_error.message.to_lowercase().contains("not supported")
+		}
+		_ => false,
+	}
+}
+
+/// use `AsyncFees::new(transport, timeout, transaction_config, eip1559_support)` to get a `Future`
+/// that resolves with the `Fees` to use for a transaction.
+/// resolves with EIP-1559 fees if the chain supports them and with
+/// `transaction_config.gas_price` otherwise.
+/// once a chain is known not to support them `eth_feeHistory` isn't requested anymore.
+pub struct AsyncFees<T: Transport> {
+	/// `None` if the chain is known not to support EIP-1559
+	future: Option<Timeout<FromErr<CallFuture<FeeHistory, T::Out>, error::Error>>>,
+	transaction_config: TransactionConfig,
+	eip1559_support: Eip1559Support,
+}
+
+impl<T: Transport> AsyncFees<T> {
+	pub fn new(
+		transport: &T,
+		timeout: Duration,
+		transaction_config: &TransactionConfig,
+		eip1559_support: &Eip1559Support,
+	) -> Self {
+		let future = if eip1559_support.get() == Some(false) {
+			None
+		} else {
+			let inner_future = CallFuture::new(transport.execute(
+				"eth_feeHistory",
+				vec![
+					helpers::serialize(&U256::from(FEE_HISTORY_BLOCKS)),
+					helpers::serialize(&"latest"),
+					helpers::serialize(&vec![FEE_HISTORY_REWARD_PERCENTILE]),
+				],
+			))
+			.from_err();
+			Some(Timer::default().timeout(inner_future, timeout))
+		};
+		Self {
+			future,
+			transaction_config: transaction_config.clone(),
+			eip1559_support: eip1559_support.clone(),
+		}
+	}
+
+	fn legacy(&self) -> Fees {
+		Fees::Legacy {
+			gas_price: self.transaction_config.gas_price,
+		}
+	}
+}
+
+impl<T: Transport> Future for AsyncFees<T> {
+	type Item = Fees;
+	type Error = error::Error;
+
+	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+		let fee_history = match self.future.as_mut().map(|future| future.poll()) {
+			None => return Ok(Async::Ready(self.legacy())),
+			Some(Ok(Async::Ready(fee_history))) => fee_history,
+			Some(Ok(Async::NotReady)) => return Ok(Async::NotReady),
+			Some(Err(err)) => {
+				if let ErrorKind::Web3(ref web3_error) = *err.kind() {
+					if is_fee_history_unsupported(web3_error) {
+						info!("eth_feeHistory is not supported. using legacy transactions");
+						self.eip1559_support.set(false);
+						return Ok(Async::Ready(self.legacy()));
+					}
+				}
+				return Err(err).chain_err(|| "failed to fetch fee history");
+			}
+		};
+
+		let base_fee_per_gas = match fee_history.base_fee_per_gas.last() {
+			Some(base_fee_per_gas) if !base_fee_per_gas.is_zero() => *base_fee_per_gas,
+			// chain has not activated EIP-1559. it can activate it later
+			_ => return Ok(Async::Ready(self.legacy())),
+		};
+		self.eip1559_support.set(true);
+
+		let max_priority_fee_per_gas = self
+			.transaction_config
+			.max_priority_fee_per_gas
+			.unwrap_or_else(|| median_reward(&fee_history));
+		let max_fee_per_gas = self.transaction_config.max_fee_per_gas.unwrap_or_else(|| {
+			// leaves room for the base fee to double before the transaction is mined
+			base_fee_per_gas
+				.saturating_mul(2.into())
+				.saturating_add(max_priority_fee_per_gas)
+		});
+
+		Ok(Async::Ready(Fees::Eip1559 {
+			base_fee_per_gas,
+			max_fee_per_gas,
+			// the priority fee can't exceed the max fee
+			max_priority_fee_per_gas: max_priority_fee_per_gas.min(max_fee_per_gas),
+		}))
+	}
+}
+
+fn median_reward(fee_history: &FeeHistory) -> U256 {
+	let mut rewards = fee_history
+		.reward
+		.iter()
+		.flat_map(|blocks| blocks.iter())
+		.filter_map(|percentiles| percentiles.first().cloned())
+		.collect::<Vec<_>>();
+	if rewards.is_empty() {
+		return U256::zero();
+	}
+	rewards.sort();
+	rewards[rewards.len() / 2]
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tokio_core::reactor::Core;
+
+	#[test]
+	fn test_legacy_transaction_to_json() {
+		let transaction = Transaction {
+			from: "006b5dda44dc2606f07ad86c9190fb54fd905f6d".parse().unwrap(),
+			to: Some("0000000000000000000000000000000000000dd1".parse().unwrap()),
+			gas: 0xfd.into(),
+			fees: Fees::Legacy {
+				gas_price: 0xa0.into(),
+			},
+			data: vec![0x60].into(),
+		};
+
+		assert_eq!(
+			transaction.to_json(),
+			json!({
+				"data": "0x60",
+				"from": "0x006b5dda44dc2606f07ad86c9190fb54fd905f6d",
+				"gas": "0xfd",
+				"gasPrice": "0xa0",
+				"to": "0x0000000000000000000000000000000000000dd1",
+			})
+		);
+	}
+
+	#[test]
+	fn test_eip1559_transaction_to_json() {
+		let transaction = Transaction {
+			from: "006b5dda44dc2606f07ad86c9190fb54fd905f6d".parse().unwrap(),
+			to: None,
+			gas: 0xfd.into(),
+			fees: Fees::Eip1559 {
+				base_fee_per_gas: 0x10.into(),
+				max_fee_per_gas: 0x30.into(),
+				max_priority_fee_per_gas: 0x2.into(),
+			},
+			data: vec![0x60].into(),
+		};
+
+		assert_eq!(
+			transaction.to_json(),
+			json!({
+				"data": "0x60",
+				"from": "0x006b5dda44dc2606f07ad86c9190fb54fd905f6d",
+				"gas": "0xfd",
+				"maxFeePerGas": "0x30",
+				"maxPriorityFeePerGas": "0x2",
+				"type": "0x2",
+			})
+		);
+		assert_eq!(transaction.fees.max_gas_price(), 0x30.into());
+		assert_eq!(transaction.fees.expected_gas_price(), 0x12.into());
+	}
+
+	#[test]
+	fn test_async_fees() {
+		let fee_history_request = json!(["0x5", "latest", [50.0]]);
+		let transport = mock_transport!(
+			"eth_feeHistory" =>
+				req => fee_history_request.clone(),
+				res => json!({
+					"oldestBlock": "0x1000",
+					"baseFeePerGas": ["0x0", "0x0", "0x0", "0x0", "0x0", "0x0"],
+					"gasUsedRatio": [0.0, 0.0, 0.0, 0.0, 0.0],
+				});
+			"eth_feeHistory" =>
+				req => fee_history_request.clone(),
+				res => json!({
+					"oldestBlock": "0x1000",
+					"baseFeePerGas": ["0x8", "0x9", "0xa", "0x9", "0x8", "0xa"],
+					"gasUsedRatio": [0.5, 0.6, 0.4, 0.4, 0.6],
+					"reward": [["0x1"], ["0x3"], ["0x2"], ["0x5"], ["0x2"]],
+				});
+			"eth_feeHistory" =>
+				req => fee_history_request.clone(),
+				res => json!({
+					"oldestBlock": "0x1000",
+					"baseFeePerGas": ["0x8", "0x9", "0xa", "0x9", "0x8", "0xa"],
+					"gasUsedRatio": [0.5, 0.6, 0.4, 0.4, 0.6],
+					"reward": [["0x1"], ["0x3"], ["0x2"], ["0x5"], ["0x2"]],
+				});
+		);
+
+		let mut transaction_config = TransactionConfig {
+			gas: 0xfd.into(),
+			gas_price: 0xa0.into(),
+			max_fee_per_gas: None,
+			max_priority_fee_per_gas: None,
+		};
+
+		let eip1559_support = Eip1559Support::default();
+		let mut event_loop = Core::new().unwrap();
+		let fees = event_loop
+			.run(AsyncFees::new(
+				&transport,
+				Duration::from_secs(1),
+				&transaction_config,
+				&eip1559_support,
+			))
+			.unwrap();
+		assert_eq!(
+			fees,
+			Fees::Legacy {
+				gas_price: 0xa0.into()
+			}
+		);
+
+		let fees = event_loop
+			.run(AsyncFees::new(
+				&transport,
+				Duration::from_secs(1),
+				&transaction_config,
+				&eip1559_support,
+			))
+			.unwrap();
+		assert_eq!(
+			fees,
+			Fees::Eip1559 {
+				base_fee_per_gas: 0xa.into(),
+				max_fee_per_gas: 0x16.into(),
+				max_priority_fee_per_gas: 0x2.into(),
+			}
+		);
+
+		transaction_config.max_fee_per_gas = Some(0x10.into());
+		transaction_config.max_priority_fee_per_gas = Some(0x4.into());
+		let fees = event_loop
+			.run(AsyncFees::new(
+				&transport,
+				Duration::from_secs(1),
+				&transaction_config,
+				&eip1559_support,
+			))
+			.unwrap();
+		assert_eq!(
+			fees,
+			Fees::Eip1559 {
+				base_fee_per_gas: 0xa.into(),
+				max_fee_per_gas: 0x10.into(),
+				max_priority_fee_per_gas: 0x4.into(),
+			}
+		);
+
+		assert_eq!(eip1559_support.get(), Some(true));
+
+		assert_eq!(transport.actual_requests(), transport.expected_requests());
+	}
+
+	#[test]
+	fn test_async_fees_without_eip1559_support() {
+		let transport = ::MockTransport {
+			actual_requests: Default::default(),
+			expected_requests: vec![],
+			mock_responses: vec![],
+		};
+		let transaction_config = TransactionConfig {
+			gas: 0xfd.into(),
+			gas_price: 0xa0.into(),
+			max_fee_per_gas: None,
+			max_priority_fee_per_gas: None,
+		};
+		let eip1559_support = Eip1559Support::default();
+		eip1559_support.set(false);
+
+		let mut event_loop = Core::new().unwrap();
+		let fees = event_loop
+			.run(AsyncFees::new(
+				&transport,
+				Duration::from_secs(1),
+				&transaction_config,
+				&eip1559_support,
+			))
+			.unwrap();
+		assert_eq!(
+			fees,
+			Fees::Legacy {
+				gas_price: 0xa0.into()
+			}
+		);
+		assert_eq!(transport.actual_requests(), vec![]);
+	}
+
+	fn rpc_error(code: jsonrpc_core::ErrorCode, message: &str) -> web3::Error {
+		web3::Error::Rpc(jsonrpc_core::Error {
+			code,
+			message: message.into(),
+			data: None,
+		})
+	}
+
+	#[test]
+	fn test_is_fee_history_unsupported() {
+		assert!(is_fee_history_unsupported(&rpc_error(
+			jsonrpc_core::ErrorCode::MethodNotFound,
+			"the method eth_feeHistory does not exist/is not available"
+		)));
+		assert!(is_fee_history_unsupported(&rpc_error(
+			jsonrpc_core::ErrorCode::ServerError(-32000),
+			"method eth_feeHistory is not supported"
+		)));
+		assert!(!is_fee_history_unsupported(&rpc_error(
+			jsonrpc_core::ErrorCode::ServerError(-32000),
+			"header not found"
+		)));
+		assert!(!is_fee_history_unsupported(&rpc_error(
+			jsonrpc_core::ErrorCode::InternalError,
+			"internal error"
+		)));
+		assert!(!is_fee_history_unsupported(&web3::Error::Transport(
+			"connection refused".into()
+		)));
+	}
+}
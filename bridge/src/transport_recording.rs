@@ -0,0 +1,433 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity-Bridge.
+
+// Parity-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! recording of JSON-RPC exchanges to fixture files and replaying them.
+//!
+//! `RecordingTransport` wraps a real transport and appends every
+//! completed exchange as a line of JSON to a fixture file.
+//! `ReplayTransport` serves the exchanges of a fixture file back
+//! which turns a recorded run of the bridge into a deterministic test.
+//! it panics on requests that weren't recorded and is therefore only
+//! available in tests and with the `test-util` feature.
+
+use error::{self, ResultExt};
+#[cfg(any(test, feature = "test-util"))]
+use futures::future;
+use futures::{Async, Future, Poll};
+use jsonrpc_core::{self, Call, Params, Value};
+use serde_json;
+use std::cell::RefCell;
+#[cfg(any(test, feature = "test-util"))]
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+use web3::{self, Transport};
+
+/// a single JSON-RPC request and the response to it
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Exchange {
+	/// position of the request among all requests sent through the transport
+	pub index: usize,
+	pub method: String,
+	pub params: Vec<Value>,
+	pub response: Response,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+	Result(Value),
+	/// error returned by the node
+	Error(jsonrpc_core::Error),
+	/// any other error, for example a failed connection
+	TransportError(String),
+}
+
+impl Response {
+	fn from_result(result: &Result<Value, web3::Error>) -> Self {
+		match *result {
+			Ok(ref value) => Response::Result(value.clone()),
+			Err(web3::Error::Rpc(ref err)) => Response::Error(err.clone()),
+			Err(ref err) => Response::TransportError(format!("{:?}", err)),
+		}
+	}
+
+	#[cfg(any(test, feature = "test-util"))]
+	fn to_result(&self) -> Result<Value, web3::Error> {
+		match *self {
+			Response::Result(ref value) => Ok(value.clone()),
+			Response::Error(ref err) => Err(web3::Error::Rpc(err.clone())),
+			Response::TransportError(ref err) => Err(web3::Error::Transport(err.clone())),
+		}
+	}
+}
+
+/// reads the exchanges of a fixture file written by `RecordingTransport`
+/// in the order their requests were sent
+#[cfg(any(test, feature = "test-util"))]
+pub fn read_fixture<P: AsRef<Path>>(path: P) -> error::Result<Vec<Exchange>> {
+	let contents = fs::read_to_string(path.as_ref())
+		.chain_err(|| format!("cannot read fixture {:?}", path.as_ref()))?;
+	let mut exchanges = contents
+		.lines()
+		.filter(|line| !line.trim().is_empty())
+		.map(serde_json::from_str::<Exchange>)
+		.collect::<Result<Vec<_>, _>>()
+		.map_err(|err| format!("invalid fixture {:?}: {}", path.as_ref(), err))?;
+	// exchanges are written once they complete which isn't necessarily
+	// the order their requests were sent in
+	exchanges.sort_by_key(|exchange| exchange.index);
+	Ok(exchanges)
+}
+
+#[derive(Debug)]
+struct Recorder {
+	next_index: usize,
+	file: File,
+}
+
+impl Recorder {
+	fn record(&mut self, exchange: &Exchange) {
+		let line = serde_json::to_string(exchange).expect("Exchange always serializes; qed");
+		if let Err(err) = writeln!(self.file, "{}", line) {
+			warn!("failed to record exchange #{}: {}", exchange.index, err);
+		}
+	}
+}
+
+/// a `Transport` that appends every exchange with the wrapped transport
+/// to a fixture file.
+/// `clone`d versions write to the same file.
+#[derive(Debug, Clone)]
+pub struct RecordingTransport<T> {
+	inner: T,
+	recorder: Option<Rc<RefCell<Recorder>>>,
+}
+
+impl<T: Transport> RecordingTransport<T> {
+	/// record to the file at `path`. an existing file is truncated
+	pub fn new<P: AsRef<Path>>(inner: T, path: P) -> error::Result<Self> {
+		let file = OpenOptions::new()
+			.create(true)
+			.write(true)
+			.truncate(true)
+			.open(path.as_ref())
+			.chain_err(|| format!("cannot create fixture {:?}", path.as_ref()))?;
+		Ok(Self {
+			inner,
+			recorder: Some(Rc::new(RefCell::new(Recorder {
+				next_index: 0,
+				file,
+			}))),
+		})
+	}
+
+	/// forward to `inner` without recording anything
+	pub fn without_recording(inner: T) -> Self {
+		Self {
+			inner,
+			recorder: None,
+		}
+	}
+}
+
+impl<T: Transport> Transport for RecordingTransport<T> {
+	type Out = RecordingFuture<T::Out>;
+
+	fn prepare(&self, method: &str, params: Vec<Value>) -> (usize, Call) {
+		self.inner.prepare(method, params)
+	}
+
+	fn send(&self, id: usize, request: Call) -> Self::Out {
+		let pending = match (self.recorder.as_ref(), &request) {
+			(Some(recorder), &Call::MethodCall(ref call)) => {
+				let params = match call.params {
+					Params::Array(ref params) => params.clone(),
+					_ => vec![],
+				};
+				let index = {
+					let mut recorder = recorder.borrow_mut();
+					recorder.next_index += 1;
+					recorder.next_index - 1
+				};
+				Some(PendingExchange {
+					recorder: recorder.clone(),
+					index,
+					method: call.method.clone(),
+					params,
+				})
+			}
+			_ => None,
+		};
+		RecordingFuture {
+			future: self.inner.send(id, request),
+			pending,
+		}
+	}
+}
+
+struct PendingExchange {
+	recorder: Rc<RefCell<Recorder>>,
+	index: usize,
+	method: String,
+	params: Vec<Value>,
+}
+
+/// `Future` returned by `RecordingTransport` that records
+/// the exchange once the wrapped request completes
+pub struct RecordingFuture<F> {
+	future: F,
+	pending: Option<PendingExchange>,
+}
+
+impl<F: Future<Item = Value, Error = web3::Error>> Future for RecordingFuture<F> {
+	type Item = Value;
+	type Error = web3::Error;
+
+	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+		let result = match self.future.poll() {
+			Ok(Async::NotReady) => return Ok(Async::NotReady),
+			Ok(Async::Ready(value)) => Ok(value),
+			Err(err) => Err(err),
+		};
+		if let Some(pending) = self.pending.take() {
+			pending.recorder.borrow_mut().record(&Exchange {
+				index: pending.index,
+				method: pending.method,
+				params: pending.params,
+				response: Response::from_result(&result),
+			});
+		}
+		result.map(Async::Ready)
+	}
+}
+
+/// how `ReplayTransport` matches requests to recorded exchanges
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayOrder {
+	/// every request must equal the next recorded request
+	Strict,
+	/// every request is matched to the first unreplayed exchange
+	/// with the same method and params
+	ByRequest,
+}
+
+/// a `Transport` that serves the responses of recorded exchanges.
+/// panics on requests that weren't recorded.
+/// `clone`d versions share the replayed exchanges
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug, Clone)]
+pub struct ReplayTransport {
+	exchanges: Rc<Vec<Exchange>>,
+	order: ReplayOrder,
+	replayed: Rc<RefCell<Vec<bool>>>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl ReplayTransport {
+	pub fn new(exchanges: Vec<Exchange>, order: ReplayOrder) -> Self {
+		Self {
+			replayed: Rc::new(RefCell::new(vec![false; exchanges.len()])),
+			exchanges: Rc::new(exchanges),
+			order,
+		}
+	}
+
+	pub fn from_fixture<P: AsRef<Path>>(path: P, order: ReplayOrder) -> error::Result<Self> {
+		Ok(Self::new(read_fixture(path)?, order))
+	}
+
+	/// recorded exchanges whose requests haven't been sent (yet)
+	pub fn unreplayed(&self) -> Vec<Exchange> {
+		let replayed = self.replayed.borrow();
+		self.exchanges
+			.iter()
+			.zip(replayed.iter())
+			.filter(|&(_, replayed)| !replayed)
+			.map(|(exchange, _)| exchange.clone())
+			.collect()
+	}
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Transport for ReplayTransport {
+	type Out = web3::Result<Value>;
+
+	fn prepare(&self, method: &str, params: Vec<Value>) -> (usize, Call) {
+		let mut replayed = self.replayed.borrow_mut();
+		let position = match self.order {
+			ReplayOrder::Strict => {
+				let position = replayed
+					.iter()
+					.position(|replayed| !replayed)
+					.unwrap_or_else(|| {
+						panic!(
+							"{} exchanges recorded but at least one more request is being executed: {} {:?}",
+							self.exchanges.len(),
+							method,
+							params
+						)
+					});
+				let exchange = &self.exchanges[position];
+				assert_eq!(
+					exchange.method.as_str(),
+					method,
+					"invalid method called at request #{}",
+					exchange.index
+				);
+				assert_eq!(
+					exchange.params, params,
+					"invalid method params at request #{}",
+					exchange.index
+				);
+				position
+			}
+			ReplayOrder::ByRequest => self
+				.exchanges
+				.iter()
+				.zip(replayed.iter())
+				.position(|(exchange, &replayed)| {
+					!replayed && exchange.method == method && exchange.params == params
+				})
+				.unwrap_or_else(|| {
+					panic!(
+						"no unreplayed exchange recorded for request: {} {:?}",
+						method, params
+					)
+				}),
+		};
+		replayed[position] = true;
+
+		(
+			position,
+			web3::helpers::build_request(position, method, params),
+		)
+	}
+
+	fn send(&self, id: usize, _request: Call) -> Self::Out {
+		Box::new(future::done(self.exchanges[id].response.to_result()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use block_number_stream::{BlockNumberStream, BlockNumberStreamOptions};
+	use futures::Stream;
+	use std::time::Duration;
+	use tempfile;
+	use tokio_core::reactor::Core;
+
+	fn block_numbers<T: Transport>(transport: T) -> Vec<u64> {
+		let block_number_stream = BlockNumberStream::new(BlockNumberStreamOptions {
+			request_timeout: Duration::from_secs(1),
+			poll_interval: Duration::from_secs(0),
+			confirmations: 12,
+			transport,
+			after: 3,
+		});
+
+		let mut event_loop = Core::new().unwrap();
+		event_loop
+			.run(block_number_stream.take(3).collect())
+			.unwrap()
+	}
+
+	#[test]
+	fn test_record_then_replay() {
+		let transport = mock_transport!(
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x1011");
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x1012");
+			"eth_blockNumber" =>
+				req => json!([]),
+				res => json!("0x1015");
+		);
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("main.jsonl");
+
+		let recording = RecordingTransport::new(transport.clone(), &path).unwrap();
+		let recorded = block_numbers(recording);
+		assert_eq!(transport.actual_requests(), transport.expected_requests());
+
+		let exchanges = read_fixture(&path).unwrap();
+		assert_eq!(exchanges.len(), 3);
+		assert_eq!(exchanges[2].index, 2);
+		assert_eq!(exchanges[2].method, "eth_blockNumber");
+		assert_eq!(exchanges[2].response, Response::Result(json!("0x1015")));
+
+		let replay = ReplayTransport::from_fixture(&path, ReplayOrder::Strict).unwrap();
+		assert_eq!(block_numbers(replay.clone()), recorded);
+		assert_eq!(replay.unreplayed(), vec![]);
+	}
+
+	#[test]
+	fn test_replay_by_request() {
+		let exchanges = vec![
+			Exchange {
+				index: 0,
+				method: "eth_getBalance".into(),
+				params: vec![json!("0x0000000000000000000000000000000000000001")],
+				response: Response::Result(json!("0x1")),
+			},
+			Exchange {
+				index: 1,
+				method: "eth_blockNumber".into(),
+				params: vec![],
+				response: Response::Error(jsonrpc_core::Error::internal_error()),
+			},
+		];
+		let replay = ReplayTransport::new(exchanges.clone(), ReplayOrder::ByRequest);
+
+		let block_number = replay.execute("eth_blockNumber", vec![]).wait();
+		match block_number {
+			Err(web3::Error::Rpc(ref err)) => {
+				assert_eq!(*err, jsonrpc_core::Error::internal_error())
+			}
+			other => panic!("expected rpc error, got {:?}", other),
+		}
+		assert_eq!(replay.unreplayed(), vec![exchanges[0].clone()]);
+
+		let balance = replay
+			.execute(
+				"eth_getBalance",
+				vec![json!("0x0000000000000000000000000000000000000001")],
+			)
+			.wait()
+			.unwrap();
+		assert_eq!(balance, json!("0x1"));
+		assert_eq!(replay.unreplayed(), vec![]);
+	}
+
+	#[test]
+	#[should_panic(expected = "invalid method called at request #0")]
+	fn test_strict_replay_rejects_other_order() {
+		let exchanges = vec![Exchange {
+			index: 0,
+			method: "eth_getBalance".into(),
+			params: vec![],
+			response: Response::Result(json!("0x1")),
+		}];
+		let replay = ReplayTransport::new(exchanges, ReplayOrder::Strict);
+		let _ = replay.execute("eth_blockNumber", vec![]);
+	}
+}
@@ -19,6 +19,7 @@ extern crate env_logger;
 extern crate futures;
 #[macro_use]
 extern crate log;
+extern crate rustc_hex;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
@@ -28,25 +29,68 @@ extern crate web3;
 use docopt::Docopt;
 use futures::Stream;
 use std::env;
+use std::fs;
+use std::io::Write;
 use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio_core::reactor::Core;
 use web3::transports::http::Http;
+use web3::types::{Address, H256, U256};
 
-use bridge::config::Config;
-use bridge::database::{Database, TomlFileDatabase};
+use bridge::config::{Config, ConfigOverride, NodeConfig};
+use bridge::database::{
+	Database, Relay, RelayDirection, RescanCheckpointFile, State, TomlFileDatabase,
+};
 use bridge::error::{self, ResultExt};
+#[cfg(feature = "fault-injection")]
+use bridge::fault_injection::{FaultInjectingTransport, FaultInjectionOptions};
 use bridge::helpers::StreamExt;
+use bridge::transport_recording::RecordingTransport;
+use bridge::{ReconciledStatus, RelayOutcome, SendMessageProgress};
+use rustc_hex::FromHex;
 
 const MAX_PARALLEL_REQUESTS: usize = 10;
 
+/// how often the config file is checked for changes
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// number of blocks `reconcile` fetches the logs of per request.
+/// nodes refuse `eth_getLogs` requests over too many blocks
+const RECONCILE_BLOCK_WINDOW: u64 = 10_000;
+
 #[derive(Debug, Deserialize)]
 pub struct Args {
+	cmd_release: bool,
+	cmd_check_config: bool,
+	cmd_status: bool,
+	cmd_send_message: bool,
+	cmd_rescan: bool,
+	cmd_reconcile: bool,
+	cmd_costs: bool,
 	arg_config: PathBuf,
+	flag_set: Vec<String>,
 	arg_database: PathBuf,
+	arg_message_hash: Option<String>,
+	arg_tx_hash: Option<String>,
+	flag_to_side: bool,
+	flag_to_main: bool,
+	flag_from: Option<String>,
+	flag_recipient: Option<String>,
+	flag_data: String,
+	flag_gas: String,
+	flag_gas_price: Option<String>,
+	flag_timeout: u64,
+	flag_relay: Option<String>,
+	flag_to: Option<u64>,
+	flag_format: String,
+	flag_stuck_after: u64,
+	flag_output: Option<PathBuf>,
+	flag_log_format: String,
+	flag_record: Option<PathBuf>,
+	flag_inject_faults: Option<f64>,
 }
 
 fn main() {
-	let _ = env_logger::init();
 	let result = execute(env::args());
 
 	match result {
@@ -55,6 +99,32 @@ fn main() {
 	}
 }
 
+fn init_logger(format: &str) -> Result<(), error::Error> {
+	let mut builder = env_logger::Builder::from_default_env();
+	match format {
+		"text" => {}
+		"json" => {
+			bridge::relay_log::set_json(true);
+			builder.format(|buf, record| {
+				let timestamp = buf.timestamp().to_string();
+				writeln!(
+					buf,
+					"{}",
+					bridge::relay_log::json_line(
+						&timestamp,
+						record.level(),
+						record.target(),
+						&record.args().to_string(),
+					)
+				)
+			});
+		}
+		other => return Err(format!("unknown log format {}", other).into()),
+	}
+	let _ = builder.try_init();
+	Ok(())
+}
+
 fn print_err(err: error::Error) {
 	let message = err
 		.iter()
@@ -77,23 +147,113 @@ Parity-bridge
 	Commit: {}
 
 Usage:
-	parity-bridge --config <config> --database <database>
+	parity-bridge --config <config> --database <database> [--set=<override>]... [--log-format=<format>] [--record=<dir>] [--inject-faults=<probability>]
+	parity-bridge release --config <config> --database <database> [--set=<override>]... [--log-format=<format>] <message_hash>
+	parity-bridge check-config --config <config> --database <database> [--set=<override>]... [--log-format=<format>]
+	parity-bridge status --config <config> --database <database> [--set=<override>]... [--log-format=<format>] <tx_hash>
+	parity-bridge send-message --config <config> --database <database> [--set=<override>]... [--log-format=<format>] (--to-side | --to-main) --from=<address> --recipient=<address> [--data=<hex>] [--gas=<gas>] [--gas-price=<wei>] [--timeout=<seconds>]
+	parity-bridge rescan --config <config> --database <database> [--set=<override>]... [--log-format=<format>] --relay=<name> --from=<block> --to=<block>
+	parity-bridge reconcile --config <config> --database <database> [--set=<override>]... [--log-format=<format>] [--format=<format>] [--stuck-after=<seconds>] [--output=<path>]
+	parity-bridge costs --config <config> --database <database> [--set=<override>]... [--log-format=<format>]
 	parity-bridge -h | --help
 
 Options:
 	-h, --help           Display help message and exit.
+	--set=<override>     Override a config value. Example: `--set main.http=http://localhost:8545`.
+	                     Takes precedence over `PARITY_BRIDGE_*` environment variables
+	                     (example: `PARITY_BRIDGE_MAIN__HTTP`) which take precedence
+	                     over the config file.
+	--log-format=<format>  Format of the log. `text` or `json` [default: text].
+	                     With `json` every line is a JSON object. Lines logged
+	                     by relays carry the relay direction, origin tx hash,
+	                     message id, step, chain and resulting tx hash.
+	--record=<dir>       Record every JSON-RPC request to and response from
+	                     the main (side) node to `main.jsonl` (`side.jsonl`)
+	                     in this directory. The files can be replayed in tests
+	                     with `bridge::transport_recording::ReplayTransport`.
+	--inject-faults=<probability>  Inject faults into the JSON-RPC exchanges with
+	                     both nodes: every request times out, fails, is
+	                     answered out of order, sees an older block number,
+	                     reorged logs or a dropped transaction with this
+	                     probability. For staging only. Requires a build with
+	                     the `fault-injection` feature.
+	--to-side            Send the message on main to be relayed to side.
+	--to-main            Send the message on side to be relayed to main.
+	--from=<from>        Unlocked account that sends the message transaction
+	                     (send-message) or first block to rescan (rescan).
+	--recipient=<address>  Recipient of the message on the other chain.
+	--data=<hex>         Hex encoded data of the message [default: ].
+	--gas=<gas>          Gas of the message transaction [default: 200000].
+	--gas-price=<wei>    Gas price of the message transaction on chains without
+	                     EIP-1559. Defaults to the gas price of the relay
+	                     transactions the bridge sends on the same chain.
+	--timeout=<seconds>  How long to wait for the message to be accepted on
+	                     the other chain [default: 600].
+	--relay=<name>       Relay to rescan. One of `main_to_side_sign`,
+	                     `side_to_main_sign` and `side_to_main_signatures`.
+	--to=<block>         Last block to rescan.
+	--format=<format>    Format of the reconciliation report. `csv` or `json`
+	                     [default: csv].
+	--stuck-after=<seconds>  Messages not accepted on the other chain for longer
+	                     are reported as stuck [default: 3600].
+	--output=<path>      Write the reconciliation report to this file instead
+	                     of printing it.
+
+Commands:
+	release              Relay a message that was held because its estimated
+	                     cost exceeded `max_main_relay_cost`, then exit.
+	                     Stop the running bridge before using this.
+	check-config         Check the config for problems, connect to both chains,
+	                     verify chain ids, deployed contract code and the
+	                     balances of the authority, then exit.
+	status               Show how far the relay of the message sent in
+	                     transaction <tx_hash> on main or side has progressed,
+	                     then exit.
+	send-message         Send a message through the bridge, wait for it to
+	                     be accepted on the other chain while showing the
+	                     progress of the relay, then exit.
+	rescan               Run a relay over the logs in a range of past blocks,
+	                     then exit. Messages that have already been relayed
+	                     are skipped. Progress is persisted to
+	                     `<database>.rescan` so an interrupted rescan of the
+	                     same range resumes where it stopped.
+	                     Stop the running bridge before using this.
+	reconcile            Report every message sent on both chains since the
+	                     contracts were deployed as completed, pending or
+	                     stuck depending on whether and how long ago it was
+	                     accepted on the other chain, then exit.
+	costs                Show the gas this authority paid for relay
+	                     transactions per relay direction and per message
+	                     sender, then exit.
 "#,
 		env!("CARGO_PKG_VERSION"),
 		env!("GIT_HASH")
 	);
 
-	info!("Parsing cli arguments");
 	let args: Args = Docopt::new(usage)
 		.and_then(|d| d.argv(command).deserialize())
 		.map_err(|e| e.to_string())?;
 
+	init_logger(&args.flag_log_format)?;
+
 	info!("Loading config from {:?}", args.arg_config);
-	let config = Config::load(&args.arg_config)?;
+	let mut overrides = ConfigOverride::from_env(env::vars());
+	for flag in &args.flag_set {
+		overrides.push(ConfigOverride::from_flag(flag)?);
+	}
+	let config = Config::load_with_overrides(&args.arg_config, &overrides)?;
+
+	if !args.cmd_check_config {
+		let problems = config.validate();
+		if !problems.is_empty() {
+			return Err(format!(
+				"invalid config {:?}:\n  {}",
+				args.arg_config,
+				problems.join("\n  ")
+			)
+			.into());
+		}
+	}
 
 	info!("Starting event loop");
 	let mut event_loop = Core::new().unwrap();
@@ -130,12 +290,47 @@ Options:
 		)
 	})?;
 
+	let (main_transport, side_transport) = match args.flag_record {
+		Some(ref dir) => {
+			info!("Recording JSON-RPC exchanges to {:?}", dir);
+			fs::create_dir_all(dir).chain_err(|| format!("Cannot create directory {:?}", dir))?;
+			(
+				RecordingTransport::new(main_transport, dir.join("main.jsonl"))?,
+				RecordingTransport::new(side_transport, dir.join("side.jsonl"))?,
+			)
+		}
+		None => (
+			RecordingTransport::without_recording(main_transport),
+			RecordingTransport::without_recording(side_transport),
+		),
+	};
+
+	let (main_transport, side_transport) = (
+		inject_faults(main_transport, args.flag_inject_faults, 1)?,
+		inject_faults(side_transport, args.flag_inject_faults, 2)?,
+	);
+
 	info!("Loading database from {:?}", args.arg_database);
 	let mut database = TomlFileDatabase::from_path(&args.arg_database)?;
 
 	info!("Reading initial state from database");
 	let initial_state = database.read();
 
+	if args.cmd_costs {
+		return Ok(costs(&initial_state));
+	}
+
+	if args.cmd_check_config {
+		return check_config(
+			&mut event_loop,
+			&config,
+			&main_transport,
+			&side_transport,
+			initial_state.main_contract_address,
+			initial_state.side_contract_address,
+		);
+	}
+
 	let main_contract = bridge::MainContract::new(main_transport.clone(), &config, &initial_state);
 	event_loop
 		.run(main_contract.is_main_contract())
@@ -158,7 +353,147 @@ Options:
 		)
 		})?;
 
-	let bridge_stream = bridge::Bridge::new(initial_state, main_contract, side_contract);
+	verify_contract_code(
+		&mut event_loop,
+		"main",
+		&config.main,
+		&main_transport,
+		initial_state.main_contract_address,
+	)?;
+	verify_contract_code(
+		&mut event_loop,
+		"side",
+		&config.side,
+		&side_transport,
+		initial_state.side_contract_address,
+	)?;
+
+	if args.cmd_release {
+		let message_hash = args
+			.arg_message_hash
+			.expect("docopt requires <message_hash> with release; qed");
+		return release(
+			&mut event_loop,
+			&mut database,
+			main_contract,
+			side_contract,
+			&message_hash,
+		);
+	}
+
+	if args.cmd_status {
+		let tx_hash = args
+			.arg_tx_hash
+			.expect("docopt requires <tx_hash> with status; qed");
+		return status(
+			&mut event_loop,
+			&config,
+			main_contract,
+			side_contract,
+			&tx_hash,
+		);
+	}
+
+	if args.cmd_rescan {
+		let relay: Relay = args
+			.flag_relay
+			.as_ref()
+			.expect("docopt requires --relay with rescan; qed")
+			.parse()?;
+		let from_block = args
+			.flag_from
+			.as_ref()
+			.expect("docopt requires --from with rescan; qed");
+		let from_block: u64 = from_block
+			.parse()
+			.map_err(|_| format!("invalid block number {}", from_block))?;
+		let to_block = args.flag_to.expect("docopt requires --to with rescan; qed");
+		if from_block > to_block {
+			return Err(format!(
+				"--from={} must not be greater than --to={}",
+				from_block, to_block
+			)
+			.into());
+		}
+
+		info!(
+			"Rescanning {} in blocks {} to {}",
+			relay.name(),
+			from_block,
+			to_block
+		);
+		let checkpoint_file = RescanCheckpointFile::next_to_database(&args.arg_database);
+		let rescan_stream = bridge::Rescan::new(
+			checkpoint_file.read()?,
+			relay,
+			from_block,
+			to_block,
+			main_contract,
+			side_contract,
+			config.max_in_flight_relays,
+		);
+		let persisted_rescan_stream = rescan_stream.and_then(|progress| {
+			match progress {
+				bridge::RescanProgress::Relayed(checkpoint, outcome) => {
+					match outcome {
+						RelayOutcome::AlreadyRelayed => {}
+						outcome => {
+							// reread the database so that nothing but the outcome is written
+							let mut database = TomlFileDatabase::from_path(&args.arg_database)?;
+							let mut state = database.read();
+							bridge::record_outcome(&mut state, outcome);
+							database.write(&state)?;
+						}
+					}
+					checkpoint_file.write(&checkpoint)?;
+				}
+				bridge::RescanProgress::Complete => checkpoint_file.remove()?,
+			}
+			Ok(())
+		});
+		event_loop.run(persisted_rescan_stream.last())?;
+
+		return Ok(format!(
+			"Rescanned {} in blocks {} to {}",
+			relay.name(),
+			from_block,
+			to_block
+		));
+	}
+
+	if args.cmd_reconcile {
+		return reconcile(
+			&mut event_loop,
+			&config,
+			&initial_state,
+			main_contract,
+			side_contract,
+			&args,
+		);
+	}
+
+	if args.cmd_send_message {
+		return send_message(
+			&mut event_loop,
+			&config,
+			main_contract,
+			side_contract,
+			&args,
+		);
+	}
+
+	let bridge_stream = bridge::Bridge::new(
+		initial_state,
+		main_contract,
+		side_contract,
+		config.max_in_flight_relays,
+	)
+	.with_config_watcher(bridge::ConfigWatcher::new(
+		args.arg_config.clone(),
+		overrides,
+		config.clone(),
+		CONFIG_POLL_INTERVAL,
+	));
 	info!("Started polling logs");
 	let persisted_bridge_stream = bridge_stream.and_then(|state| {
 		database.write(&state)?;
@@ -170,3 +505,432 @@ Options:
 
 	Ok("Done".into())
 }
+
+/// relays a held message regardless of its cost and removes it from the held messages
+fn release<T: web3::Transport, D: Database>(
+	event_loop: &mut Core,
+	database: &mut D,
+	main_contract: bridge::MainContract<T>,
+	side_contract: bridge::SideContract<T>,
+	message_hash: &str,
+) -> Result<String, error::Error> {
+	let message_hash: H256 = message_hash
+		.trim_start_matches("0x")
+		.parse()
+		.map_err(|_| format!("invalid message hash {}", message_hash))?;
+
+	let mut state = database.read();
+	let held_message = state
+		.held_messages
+		.iter()
+		.find(|x| x.message_hash == message_hash)
+		.cloned()
+		.ok_or_else(|| format!("message {:?} is not held", message_hash))?;
+
+	info!("Releasing held message {:?}", message_hash);
+	let outcome = event_loop.run(bridge::SideToMainSignatures::release(
+		&held_message,
+		main_contract,
+		side_contract,
+	))?;
+
+	state
+		.held_messages
+		.retain(|x| x.message_hash != held_message.message_hash);
+	let result = match outcome {
+		RelayOutcome::Relayed(relay_cost) => {
+			let result = format!(
+				"Released message {:?} in transaction {:?}",
+				message_hash, relay_cost.transaction_hash
+			);
+			state.add_relay_cost(&relay_cost);
+			result
+		}
+		_ => format!("Message {:?} had already been relayed", message_hash),
+	};
+	database.write(&state)?;
+
+	Ok(result)
+}
+
+/// looks up the message sent in transaction `tx_hash` and how far its relay has progressed
+fn status<T: web3::Transport>(
+	event_loop: &mut Core,
+	config: &Config,
+	main_contract: bridge::MainContract<T>,
+	side_contract: bridge::SideContract<T>,
+	tx_hash: &str,
+) -> Result<String, error::Error> {
+	let tx_hash: H256 = tx_hash
+		.trim_start_matches("0x")
+		.parse()
+		.map_err(|_| format!("invalid transaction hash {}", tx_hash))?;
+
+	let status = event_loop.run(bridge::MessageStatusLookup::new(
+		main_contract,
+		side_contract,
+		config.authorities.accounts.clone(),
+		tx_hash,
+	))?;
+
+	Ok(status.to_string())
+}
+
+/// sends a message and prints the progress of its relay until it is accepted on the other chain
+fn send_message<T: web3::Transport>(
+	event_loop: &mut Core,
+	config: &Config,
+	main_contract: bridge::MainContract<T>,
+	side_contract: bridge::SideContract<T>,
+	args: &Args,
+) -> Result<String, error::Error> {
+	let sender = parse_address(
+		args.flag_from
+			.as_ref()
+			.expect("docopt requires --from with send-message; qed"),
+	)?;
+	let recipient = parse_address(
+		args.flag_recipient
+			.as_ref()
+			.expect("docopt requires --recipient with send-message; qed"),
+	)?;
+	let data = args
+		.flag_data
+		.trim_start_matches("0x")
+		.from_hex()
+		.map_err(|_| format!("invalid message data {}", args.flag_data))?;
+
+	let (direction, mut transaction_config, poll_interval) = if args.flag_to_side {
+		(
+			RelayDirection::MainToSide,
+			config.txs.withdraw_relay.clone(),
+			config.side.poll_interval,
+		)
+	} else {
+		(
+			RelayDirection::SideToMain,
+			config.txs.deposit_relay.clone(),
+			config.main.poll_interval,
+		)
+	};
+	transaction_config.gas =
+		U256::from_dec_str(&args.flag_gas).map_err(|_| format!("invalid gas {}", args.flag_gas))?;
+	if let Some(ref gas_price) = args.flag_gas_price {
+		transaction_config.gas_price = U256::from_dec_str(gas_price)
+			.map_err(|_| format!("invalid gas price {}", gas_price))?;
+	}
+
+	let timeout = Duration::from_secs(args.flag_timeout);
+	let stream = bridge::SendMessage::new(bridge::SendMessageOptions {
+		main: main_contract,
+		side: side_contract,
+		direction,
+		sender,
+		recipient,
+		data,
+		transaction_config,
+		authorities: config.authorities.accounts.clone(),
+		poll_interval,
+		timeout,
+	});
+
+	let started = Instant::now();
+	let last_progress = event_loop.run(
+		stream
+			.inspect(|progress| {
+				let elapsed = started.elapsed().as_secs();
+				match *progress {
+					SendMessageProgress::Sent(tx_hash) => {
+						println!("[{}s] sent message transaction {:?}", elapsed, tx_hash)
+					}
+					SendMessageProgress::Mined(ref receipt) => println!(
+						"[{}s] message transaction mined in block {:?}. following relay",
+						elapsed,
+						receipt.block_number.unwrap_or_default()
+					),
+					SendMessageProgress::Relaying(ref status) => {
+						println!("[{}s] {}", elapsed, status)
+					}
+				}
+			})
+			.last(),
+	)?;
+
+	match last_progress {
+		Some(SendMessageProgress::Relaying(ref status)) if status.is_accepted() => Ok(format!(
+			"Message was accepted on the other chain after {} seconds",
+			started.elapsed().as_secs()
+		)),
+		_ => Err("message relay ended before the message was accepted".into()),
+	}
+}
+
+/// wraps `transport` in a `FaultInjectingTransport` that injects every fault
+/// with `probability`. no faults are injected without a `probability`
+#[cfg(feature = "fault-injection")]
+fn inject_faults<T: web3::Transport>(
+	transport: T,
+	probability: Option<f64>,
+	seed: u64,
+) -> Result<FaultInjectingTransport<T>, error::Error> {
+	let probability = probability.unwrap_or(0.0);
+	if probability < 0.0 || probability > 1.0 {
+		return Err(format!("--inject-faults={} must be between 0 and 1", probability).into());
+	}
+	if probability > 0.0 {
+		warn!("Injecting faults with probability {}", probability);
+	}
+	Ok(FaultInjectingTransport::new(
+		transport,
+		FaultInjectionOptions {
+			seed,
+			timeout: probability,
+			rpc_error: probability,
+			dropped_transaction: probability,
+			reordered_response: probability,
+			block_number_regression: probability,
+			reorg: probability,
+			..FaultInjectionOptions::default()
+		},
+	))
+}
+
+#[cfg(not(feature = "fault-injection"))]
+fn inject_faults<T: web3::Transport>(
+	transport: T,
+	probability: Option<f64>,
+	_seed: u64,
+) -> Result<T, error::Error> {
+	if probability.is_some() {
+		return Err("--inject-faults requires a build with the `fault-injection` feature".into());
+	}
+	Ok(transport)
+}
+
+fn parse_address(address: &str) -> Result<Address, error::Error> {
+	address
+		.trim_start_matches("0x")
+		.parse()
+		.map_err(|_| format!("invalid address {}", address).into())
+}
+
+/// reports the messages sent on both chains since deployment and whether they were accepted
+fn reconcile<T: web3::Transport>(
+	event_loop: &mut Core,
+	config: &Config,
+	state: &State,
+	main_contract: bridge::MainContract<T>,
+	side_contract: bridge::SideContract<T>,
+	args: &Args,
+) -> Result<String, error::Error> {
+	let now = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_err(|_| "system time is before the unix epoch")?
+		.as_secs();
+	let report = event_loop.run(bridge::Reconciliation::new(bridge::ReconciliationOptions {
+		main: main_contract,
+		side: side_contract,
+		authorities: config.authorities.accounts.clone(),
+		main_from_block: state.main_deployed_at_block,
+		side_from_block: state.side_deployed_at_block,
+		block_window: RECONCILE_BLOCK_WINDOW,
+		stuck_after: Duration::from_secs(args.flag_stuck_after),
+		now,
+	}))?;
+
+	let serialized = match args.flag_format.as_str() {
+		"csv" => report.to_csv(),
+		"json" => report.to_json(),
+		format => return Err(format!("unknown report format {}", format).into()),
+	};
+	let summary = format!(
+		"{} messages: {} completed, {} pending, {} stuck",
+		report.messages.len(),
+		report.count(ReconciledStatus::Completed),
+		report.count(ReconciledStatus::Pending),
+		report.count(ReconciledStatus::Stuck)
+	);
+
+	match args.flag_output {
+		Some(ref path) => {
+			fs::write(path, serialized)
+				.chain_err(|| format!("cannot write reconciliation report to {:?}", path))?;
+			Ok(summary)
+		}
+		None => {
+			info!("{}", summary);
+			Ok(serialized)
+		}
+	}
+}
+
+/// formats the running relay cost totals by direction and by sender
+fn costs(state: &State) -> String {
+	let mut lines = Vec::new();
+	for (direction, total) in state.relay_cost_by_direction() {
+		lines.push(format!(
+			"total relay cost {}: {} wei",
+			direction.name(),
+			total
+		));
+	}
+	for total in &state.relay_cost_totals {
+		lines.push(format!(
+			"relay cost {} of messages from {:?}: {} wei in {} transactions",
+			total.direction.name(),
+			total.sender,
+			total.cost,
+			total.relays
+		));
+	}
+	if lines.is_empty() {
+		lines.push("no relay transactions sent yet".into());
+	}
+	lines.join("\n")
+}
+
+/// reports all problems with the config and the chains it points to
+fn check_config<T: web3::Transport>(
+	event_loop: &mut Core,
+	config: &Config,
+	main_transport: &T,
+	side_transport: &T,
+	main_contract_address: Address,
+	side_contract_address: Address,
+) -> Result<String, error::Error> {
+	let mut problems = config.validate();
+
+	check_chain(
+		event_loop,
+		"main",
+		&config.main,
+		main_transport,
+		main_contract_address,
+		config.address,
+		config.txs.withdraw_relay.max_cost(),
+		&mut problems,
+	);
+	check_chain(
+		event_loop,
+		"side",
+		&config.side,
+		side_transport,
+		side_contract_address,
+		config.address,
+		config
+			.txs
+			.deposit_relay
+			.max_cost()
+			.max(config.txs.withdraw_confirm.max_cost()),
+		&mut problems,
+	);
+
+	if !problems.is_empty() {
+		return Err(format!(
+			"found {} problem(s):\n  {}",
+			problems.len(),
+			problems.join("\n  ")
+		)
+		.into());
+	}
+
+	Ok("Config is OK".into())
+}
+
+/// checks chain id, deployed contract code and the balance of `authority`
+/// on the chain of `node`. `min_balance` is the cost of the most expensive
+/// relay transaction on the chain.
+fn check_chain<T: web3::Transport>(
+	event_loop: &mut Core,
+	name: &str,
+	node: &NodeConfig,
+	transport: &T,
+	contract_address: Address,
+	authority: Address,
+	min_balance: U256,
+	problems: &mut Vec<String>,
+) {
+	let web3 = web3::Web3::new(transport.clone());
+
+	if let Some(expected_chain_id) = node.chain_id {
+		let chain_id: Result<U256, _> = event_loop.run(web3::helpers::CallFuture::new(
+			transport.execute("eth_chainId", vec![]),
+		));
+		match chain_id {
+			Ok(ref chain_id) if *chain_id == expected_chain_id.into() => {
+				info!("{} chain id is {}", name, chain_id)
+			}
+			Ok(chain_id) => problems.push(format!(
+				"{}.chain_id = {} but the node at {} is connected to chain {}",
+				name, expected_chain_id, node.http, chain_id
+			)),
+			Err(err) => problems.push(format!(
+				"cannot fetch chain id from {} chain node at {}: {:?}",
+				name, node.http, err
+			)),
+		}
+	}
+
+	match verify_contract_code(event_loop, name, node, transport, contract_address) {
+		Ok(code_hash) => info!(
+			"{} contract code at {:?} has keccak256 {:?}",
+			name, contract_address, code_hash
+		),
+		Err(problem) => problems.push(problem),
+	}
+
+	match event_loop.run(web3.eth().balance(authority, None)) {
+		Ok(balance) if balance < min_balance => problems.push(format!(
+			"balance of authority {:?} on {} is {} wei which is less than the {} wei a single relay transaction can cost",
+			authority, name, balance, min_balance
+		)),
+		Ok(balance) => info!(
+			"balance of authority {:?} on {} is {} wei",
+			authority, name, balance
+		),
+		Err(err) => problems.push(format!(
+			"cannot fetch balance of authority {:?} from {} chain node at {}: {:?}",
+			authority, name, node.http, err
+		)),
+	}
+}
+
+/// fails if the code at `contract_address` was not deployed from `node.contract.bin`.
+/// returns the hash of the code otherwise
+fn verify_contract_code<T: web3::Transport>(
+	event_loop: &mut Core,
+	name: &str,
+	node: &NodeConfig,
+	transport: &T,
+	contract_address: Address,
+) -> Result<H256, String> {
+	let code = event_loop
+		.run(
+			web3::Web3::new(transport.clone())
+				.eth()
+				.code(contract_address, None),
+		)
+		.map_err(|err| {
+			format!(
+				"cannot fetch code of {} contract from node at {}: {:?}",
+				name, node.http, err
+			)
+		})?;
+
+	if code.0.is_empty() {
+		return Err(format!(
+			"there is no contract at {} contract address {:?}",
+			name, contract_address
+		));
+	}
+
+	let code_hash = bridge::helpers::keccak256(&code.0);
+	if !bridge::helpers::is_runtime_code_of(&code.0, &node.contract.bin.0) {
+		return Err(format!(
+			"code at {} contract address {:?} (keccak256 {:?}) was not deployed from the expected {} contract bytecode",
+			name, contract_address, code_hash, name
+		));
+	}
+
+	Ok(code_hash)
+}
@@ -23,3 +23,11 @@ use_contract!(main, "../compiled_contracts/Main.abi");
 use_contract!(side, "../compiled_contracts/Side.abi");
 #[cfg(feature = "integration-tests")]
 use_contract!(test, "../compiled_contracts/RecipientTest.abi");
+
+/// hex encoded creation code (without constructor arguments) of the contracts
+/// compiled by the build script
+pub const MAIN_BIN: &str = include_str!("../../compiled_contracts/Main.bin");
+pub const SIDE_BIN: &str = include_str!("../../compiled_contracts/Side.bin");
+/// hex encoded creation code of the contract the integration tests relay messages to
+#[cfg(feature = "integration-tests")]
+pub const RECIPIENT_TEST_BIN: &str = include_str!("../../compiled_contracts/RecipientTest.bin");
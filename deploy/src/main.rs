@@ -26,22 +26,38 @@ extern crate tokio_core;
 extern crate web3;
 
 use docopt::Docopt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::{env, fs};
 use tokio_core::reactor::Core;
 use web3::transports::http::Http;
 
-use bridge::config::Config;
+use bridge::config::{Config, ConfigOverride};
 use bridge::database::State;
-use bridge::deploy::{DeployMain, DeploySide};
+use bridge::deploy::{
+	main_creation_code, side_creation_code, DeployContract, DeployProgressFile, DeploymentInfo,
+	FindContractCreation,
+};
 use bridge::error::{self, ResultExt};
+use web3::types::{Address, H256};
 
 const MAX_PARALLEL_REQUESTS: usize = 10;
+/// used by commands that don't load a config
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Debug, Deserialize)]
 pub struct Args {
+	cmd_attach: bool,
+	cmd_verify: bool,
 	arg_config: PathBuf,
+	flag_set: Vec<String>,
+	flag_dry_run: bool,
+	flag_create2_salt: Option<String>,
+	flag_main: Option<String>,
+	flag_side: Option<String>,
+	flag_rpc: Option<String>,
 	arg_database: PathBuf,
+	arg_deployment_dir: Option<PathBuf>,
 }
 
 fn main() {
@@ -76,11 +92,47 @@ Parity-bridge
 	Commit: {}
 
 Usage:
-	parity-bridge-deploy --config <config> --database <database>
+	parity-bridge-deploy --config <config> --database <database> [--set=<override>]... [--dry-run] [--create2-salt=<salt>]
+	parity-bridge-deploy attach --config <config> --database <database> --main=<address> --side=<address> [--set=<override>]...
+	parity-bridge-deploy verify --rpc=<url> <deployment_dir>
 	parity-bridge-deploy -h | --help
 
 Options:
 	-h, --help           Display help message and exit.
+	--set=<override>     Override a config value. Example: `--set main.http=http://localhost:8545`.
+	                     Takes precedence over `PARITY_BRIDGE_*` environment variables
+	                     (example: `PARITY_BRIDGE_MAIN__HTTP`) which take precedence
+	                     over the config file.
+	--dry-run            Check the deployments without sending anything: print the transactions
+	                     and predicted contract addresses, check gas, balances and nonces
+	                     and write the deployment info directories.
+	--create2-salt=<salt>
+	                     Deploy both contracts through the CREATE3 proxy for this 32 byte salt
+	                     instead of sending contract creation transactions. The CREATE2 factory
+	                     0x4e59b44847b379578588920ca78fbf26c0b4956c creates the proxy from the salt
+	                     and the proxy creates the contract. The contract addresses then only
+	                     depend on the salt: both contracts get the same address, which is
+	                     known before deploying. Deploys the factory and the proxy if they are missing.
+	--main=<address>     Address of the deployed main contract.
+	--side=<address>     Address of the deployed side contract.
+	--rpc=<url>          HTTP JSON-RPC endpoint of a node of the chain the contract was deployed to.
+
+Commands:
+	attach               Write the database for contracts that were already deployed
+	                     (for example by another authority) instead of deploying new ones.
+	                     Verifies that both contracts are bridge contracts that were created
+	                     from the configured bytecode and authorities and finds the blocks
+	                     they were deployed in. Their state must be available on the nodes
+	                     (use archive nodes for older deployments).
+	verify               Check a deployment against the deployment info directory
+	                     (`deployment-main-*` or `deployment-side-*`) written when it was deployed:
+	                     compares the creation code recomputed from the directory with the input
+	                     of the creation transaction and the runtime code at the contract address.
+
+The progress of the deployments is stored next to the database
+(for `--database bridge.db` in `bridge.deploy-main.json` and `bridge.deploy-side.json`).
+Rerunning an interrupted deployment waits for pending deployment transactions
+and skips contracts that have already been deployed.
 "#,
 		env!("CARGO_PKG_VERSION"),
 		env!("GIT_HASH")
@@ -91,8 +143,25 @@ Options:
 		.and_then(|d| d.argv(command).deserialize())
 		.map_err(|e| e.to_string())?;
 
+	if args.cmd_verify {
+		return verify(
+			args.flag_rpc
+				.as_ref()
+				.expect("docopt requires --rpc with verify; qed"),
+			args.arg_deployment_dir
+				.as_ref()
+				.expect("docopt requires <deployment_dir> with verify; qed"),
+		);
+	}
+
 	info!(target: "parity-bridge-deploy", "Loading config");
-	let config = Config::load(args.arg_config)?;
+	let config = {
+		let mut overrides = ConfigOverride::from_env(env::vars());
+		for flag in &args.flag_set {
+			overrides.push(ConfigOverride::from_flag(flag)?);
+		}
+		Config::load_with_overrides(&args.arg_config, &overrides)?
+	};
 
 	info!(target: "parity-bridge-deploy", "Starting event loop");
 	let mut event_loop = Core::new().unwrap();
@@ -119,8 +188,56 @@ Options:
 	)
 	.chain_err(|| format!("Cannot connect to side at {}", config.side.http))?;
 
+	if args.cmd_attach {
+		let main_address = args
+			.flag_main
+			.as_ref()
+			.expect("docopt requires --main with attach; qed");
+		let side_address = args
+			.flag_side
+			.as_ref()
+			.expect("docopt requires --side with attach; qed");
+		let state = attach(
+			&mut event_loop,
+			&config,
+			main_transport,
+			side_transport,
+			parse_address(main_address)?,
+			parse_address(side_address)?,
+		)?;
+		info!(target: "parity-bridge-deploy", "\n\n{}\n", state);
+		state.write(fs::File::create(args.arg_database)?)?;
+		return Ok("Attached to deployed contracts".into());
+	}
+
+	let main_progress = DeployProgressFile::open(progress_path(&args.arg_database, "main"))?;
+	let side_progress = DeployProgressFile::open(progress_path(&args.arg_database, "side"))?;
+
+	let mut deploy_main = DeployContract::main(&config, main_transport, main_progress);
+	let mut deploy_side = DeployContract::side(&config, side_transport, side_progress);
+	if let Some(ref salt) = args.flag_create2_salt {
+		let salt = parse_salt(salt)?;
+		deploy_main = deploy_main.with_create2_salt(salt);
+		deploy_side = deploy_side.with_create2_salt(salt);
+		info!(
+			target: "parity-bridge-deploy",
+			"Deploying through the CREATE3 proxy for salt {:?}: MainBridge to {:?}, SideBridge to {:?}",
+			salt,
+			deploy_main
+				.create3_address()
+				.expect("deploy_main has a salt; qed"),
+			deploy_side
+				.create3_address()
+				.expect("deploy_side has a salt; qed")
+		);
+	}
+
+	if args.flag_dry_run {
+		return dry_run(&mut event_loop, &deploy_main, &deploy_side);
+	}
+
 	info!(target: "parity-bridge-deploy", "Deploying MainBridge contract");
-	let main_deployed = event_loop.run(DeployMain::new(config.clone(), main_transport))?;
+	let main_deployed = event_loop.run(deploy_main)?;
 	info!(target: "parity-bridge-deploy", "Successfully deployed MainBridge contract");
 
 	main_deployed.dump_info(format!(
@@ -129,7 +246,7 @@ Options:
 	))?;
 
 	info!(target: "parity-bridge-deploy", "Deploying SideBridge contract");
-	let side_deployed = event_loop.run(DeploySide::new(config.clone(), side_transport))?;
+	let side_deployed = event_loop.run(deploy_side)?;
 	info!(target: "parity-bridge-deploy", "Successfully deployed SideBridge contract");
 
 	side_deployed.dump_info(format!(
@@ -137,9 +254,208 @@ Options:
 		side_deployed.contract_address
 	))?;
 
-	let state = State::from_transaction_receipts(&main_deployed.receipt, &side_deployed.receipt);
+	// the receipts of contracts deployed through the CREATE3 proxy have no contract address
+	let state = State::from_deployments(
+		parse_address(&main_deployed.contract_address)?,
+		main_deployed
+			.receipt
+			.as_ref()
+			.expect("DeployContract resolves with a receipt; qed"),
+		parse_address(&side_deployed.contract_address)?,
+		side_deployed
+			.receipt
+			.as_ref()
+			.expect("DeployContract resolves with a receipt; qed"),
+	);
 	info!(target: "parity-bridge-deploy", "\n\n{}\n", state);
 	state.write(fs::File::create(args.arg_database)?)?;
 
 	Ok("Done".into())
 }
+
+fn parse_address(address: &str) -> Result<Address, error::Error> {
+	address
+		.trim_start_matches("0x")
+		.parse()
+		.map_err(|_| format!("invalid address {}", address).into())
+}
+
+fn parse_salt(salt: &str) -> Result<H256, error::Error> {
+	salt.trim_start_matches("0x")
+		.parse()
+		.map_err(|_| format!("invalid CREATE2 salt {}. expected 32 bytes of hex", salt).into())
+}
+
+/// initial state for the bridge contracts at `main_address` and `side_address`.
+/// fails unless they are bridge contracts that were created from the
+/// configured bytecode and authorities
+fn attach<T: web3::Transport>(
+	event_loop: &mut Core,
+	config: &Config,
+	main_transport: T,
+	side_transport: T,
+	main_address: Address,
+	side_address: Address,
+) -> Result<State, error::Error> {
+	let state = State {
+		main_contract_address: main_address,
+		side_contract_address: side_address,
+		..State::default()
+	};
+
+	let main_contract = bridge::MainContract::new(main_transport.clone(), config, &state);
+	let is_main_contract = event_loop
+		.run(main_contract.is_main_contract())
+		.chain_err(|| {
+			format!(
+				"call to main contract `is_main_bridge_contract` failed. is {:?} a bridge main contract?",
+				main_address
+			)
+		})?;
+	if !is_main_contract {
+		return Err(format!("{:?} is not a bridge main contract", main_address).into());
+	}
+
+	let side_contract = bridge::SideContract::new(side_transport.clone(), config, &state);
+	let is_side_contract = event_loop
+		.run(side_contract.is_side_contract())
+		.chain_err(|| {
+			format!(
+				"call to side contract `is_side_bridge_contract` failed. is {:?} a bridge side contract?",
+				side_address
+			)
+		})?;
+	if !is_side_contract {
+		return Err(format!("{:?} is not a bridge side contract", side_address).into());
+	}
+
+	info!(target: "parity-bridge-deploy", "Finding main contract creation");
+	let main_creation = event_loop.run(FindContractCreation::new(
+		main_transport,
+		config.main.request_timeout,
+		main_address,
+	))?;
+	main_creation.check_creation_code(
+		"main",
+		&config.main.contract.bin.0,
+		&main_creation_code(config),
+	)?;
+
+	info!(target: "parity-bridge-deploy", "Finding side contract creation");
+	let side_creation = event_loop.run(FindContractCreation::new(
+		side_transport,
+		config.side.request_timeout,
+		side_address,
+	))?;
+	side_creation.check_creation_code(
+		"side",
+		&config.side.contract.bin.0,
+		&side_creation_code(config),
+	)?;
+
+	Ok(State::from_deployments(
+		main_address,
+		&main_creation.receipt,
+		side_address,
+		&side_creation.receipt,
+	))
+}
+
+/// compares the deployment described in `deployment_dir` with the chain at `rpc`
+fn verify(rpc: &str, deployment_dir: &Path) -> Result<String, error::Error> {
+	let info = DeploymentInfo::read(deployment_dir)?;
+
+	let mut event_loop = Core::new().unwrap();
+	let transport = Http::with_event_loop(rpc, &event_loop.handle(), MAX_PARALLEL_REQUESTS)
+		.chain_err(|| format!("Cannot connect to {}", rpc))?;
+
+	let find_contract_creation = match info.transaction_hash {
+		Some(transaction_hash) => FindContractCreation::from_transaction(
+			transport.clone(),
+			REQUEST_TIMEOUT,
+			info.contract_address,
+			transaction_hash,
+		),
+		// written by a dry run
+		None => {
+			FindContractCreation::new(transport.clone(), REQUEST_TIMEOUT, info.contract_address)
+		}
+	};
+	let creation = event_loop.run(find_contract_creation)?;
+	let runtime_code = event_loop
+		.run(
+			web3::Web3::new(transport)
+				.eth()
+				.code(info.contract_address, None),
+		)
+		.map_err(|err| {
+			format!(
+				"Cannot fetch code of {:?}: {:?}",
+				info.contract_address, err
+			)
+		})?;
+
+	println!(
+		"{} contract {:?} created in transaction {:?} in block {}",
+		info.contract_name,
+		info.contract_address,
+		creation.receipt.transaction_hash,
+		creation
+			.receipt
+			.block_number
+			.map_or("?".to_string(), |block| block.to_string())
+	);
+
+	let problems = info.verify(&creation, &runtime_code.0);
+	if problems.is_empty() {
+		Ok(format!(
+			"Deployment matches {:?}: creation code and runtime code (keccak256 {:?}) were verified",
+			deployment_dir,
+			bridge::helpers::keccak256(&runtime_code.0)
+		))
+	} else {
+		Err(format!(
+			"Deployment does NOT match {:?}:\n{}",
+			deployment_dir,
+			problems.join("\n")
+		)
+		.into())
+	}
+}
+
+/// checks both deployments and writes their deployment info directories
+/// without sending anything
+fn dry_run<T: web3::Transport + Clone>(
+	event_loop: &mut Core,
+	deploy_main: &DeployContract<T>,
+	deploy_side: &DeployContract<T>,
+) -> Result<String, error::Error> {
+	let mut problems = Vec::new();
+	for (chain, future) in vec![
+		("main", deploy_main.dry_run()),
+		("side", deploy_side.dry_run()),
+	] {
+		let dry_run = event_loop.run(future)?;
+		println!("{}\n", dry_run);
+		dry_run.contract.dump_info(format!(
+			"deployment-{}-{}",
+			chain, dry_run.contract.contract_address
+		))?;
+		problems.extend(dry_run.problems());
+	}
+
+	if problems.is_empty() {
+		Ok("Dry run found no problems. Nothing was sent".into())
+	} else {
+		Err(format!(
+			"Dry run found problems. Nothing was sent:\n{}",
+			problems.join("\n")
+		)
+		.into())
+	}
+}
+
+/// file the progress of the deployment of the `chain` contract is stored in
+fn progress_path(database: &Path, chain: &str) -> PathBuf {
+	database.with_extension(format!("deploy-{}.json", chain))
+}